@@ -0,0 +1,414 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This example spins a single-node blockchain network and an adversarial client, which
+//! intentionally submits malformed transfers via HTTP API: transfers with a stale or
+//! non-existing `history_len`, transfers reusing proofs from another transfer, transfers
+//! of amounts below the minimum, and transfers with garbage instead of the encrypted opening.
+//! The example asserts that each malformed transfer fails with the expected
+//! `transactions::Error` code (as reported by the service debugger), or, if the node cannot
+//! detect the malformation, that the receiver rejects the transfer and it is rolled back.
+//! The example thus doubles as a test of the attack surface of the service.
+//!
+//! Run with
+//!
+//! ```shell
+//! cargo +nightly run --example adversary --features client
+//! ```
+//!
+//! The example panics if any of the attacks yields an unexpected outcome.
+
+extern crate exonum;
+#[macro_use]
+extern crate log;
+extern crate private_currency;
+extern crate rand;
+extern crate serde;
+extern crate tempdir;
+
+use exonum::{
+    blockchain::{GenesisConfig, TransactionErrorType, ValidatorKeys},
+    crypto::{gen_keypair_from_seed, CryptoHash, Hash, SecretKey, Seed, SEED_LENGTH},
+    node::{Node, NodeApiConfig, NodeConfig},
+    storage::{DbOptions, RocksDB},
+};
+use private_currency::{
+    api::{TrustAnchor, WalletQuery},
+    client::{ClientError, CurrencyClient},
+    crypto::{AmountProof, Commitment, SimpleRangeProof},
+    transactions::{Error, Transfer},
+    DebugEvent, DebuggerOptions, EncryptedData, Invariants, RejectedProof, SecretState,
+    Service as CurrencyService,
+};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use tempdir::TempDir;
+
+use std::{
+    env, process,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+const NODE_URL: &str = "http://127.0.0.1:8080";
+const TIME_LOCK: u32 = 5;
+const COMMIT_TIMEOUT_SECS: u64 = 10;
+const ROLLBACK_TIMEOUT_SECS: u64 = 60;
+
+fn node_config() -> NodeConfig {
+    let (consensus_public_key, consensus_secret_key) = exonum::crypto::gen_keypair();
+    let (service_public_key, service_secret_key) = exonum::crypto::gen_keypair();
+
+    let validator_keys = ValidatorKeys {
+        consensus_key: consensus_public_key,
+        service_key: service_public_key,
+    };
+    let genesis = GenesisConfig::new(vec![validator_keys].into_iter());
+
+    let api_address = "127.0.0.1:8080".parse().unwrap();
+    let api_cfg = NodeApiConfig {
+        public_api_address: Some(api_address),
+        ..Default::default()
+    };
+
+    let peer_address = "127.0.0.1:2000".parse().unwrap();
+
+    NodeConfig {
+        listen_address: peer_address,
+        service_public_key,
+        service_secret_key,
+        consensus_public_key,
+        consensus_secret_key,
+        genesis,
+        external_address: peer_address,
+        network: Default::default(),
+        connect_list: Default::default(),
+        api: api_cfg,
+        mempool: Default::default(),
+        services_configs: Default::default(),
+        database: Default::default(),
+    }
+}
+
+/// Fields of a transfer, which the adversary tampers with before signing the transfer.
+struct Forgery {
+    transfer: Transfer,
+    history_len: u64,
+    amount: Commitment,
+    amount_proof: AmountProof<'static>,
+    sufficient_balance_proof: SimpleRangeProof<'static>,
+    encrypted_data: EncryptedData,
+}
+
+impl Forgery {
+    /// Starts a forgery from the fields of an honestly created transfer.
+    fn from(transfer: &Transfer) -> Self {
+        Forgery {
+            transfer: transfer.clone(),
+            history_len: transfer.history_len(),
+            amount: transfer.amount(),
+            amount_proof: transfer.amount_proof().into_owned(),
+            sufficient_balance_proof: transfer.sufficient_balance_proof().into_owned(),
+            encrypted_data: transfer.encrypted_data(),
+        }
+    }
+
+    fn history_len(mut self, history_len: u64) -> Self {
+        self.history_len = history_len;
+        self
+    }
+
+    fn amount(mut self, amount: Commitment, amount_proof: AmountProof<'static>) -> Self {
+        self.amount = amount;
+        self.amount_proof = amount_proof;
+        self
+    }
+
+    fn encrypted_data(mut self, encrypted_data: EncryptedData) -> Self {
+        self.encrypted_data = encrypted_data;
+        self
+    }
+
+    fn sign(self, secret_key: &SecretKey) -> Transfer {
+        Transfer::new(
+            self.transfer.from(),
+            self.transfer.to(),
+            self.transfer.rollback_delay(),
+            self.history_len,
+            self.amount,
+            self.amount_proof,
+            self.sufficient_balance_proof,
+            self.encrypted_data,
+            secret_key,
+        )
+    }
+}
+
+/// Outcomes of transactions reported by the service debugger.
+struct Outcomes {
+    events: Receiver<DebugEvent>,
+    received: Vec<DebugEvent>,
+}
+
+impl Outcomes {
+    /// Returns the error code of a failed transaction.
+    ///
+    /// Transfers rejected because of an invalid proof are reported by the debugger
+    /// with a dedicated event; they fail with `IncorrectAmountProof` or `IncorrectProof`.
+    fn error_code(event: &DebugEvent, tx_hash: &Hash) -> Option<u8> {
+        match *event {
+            DebugEvent::ExecutionFailed {
+                tx_hash: ref hash,
+                error: TransactionErrorType::Code(code),
+                ..
+            } if hash == tx_hash => Some(code),
+            DebugEvent::ProofRejected {
+                ref transfer,
+                ref proof,
+                ..
+            } if transfer.hash() == *tx_hash => Some(match *proof {
+                RejectedProof::Amount => Error::IncorrectAmountProof as u8,
+                RejectedProof::SufficientBalance => Error::IncorrectProof as u8,
+            }),
+            _ => None,
+        }
+    }
+
+    fn is_rollback(event: &DebugEvent, tx_hash: &Hash) -> bool {
+        match *event {
+            DebugEvent::RolledBack { ref transfer, .. } => transfer.hash() == *tx_hash,
+            _ => false,
+        }
+    }
+
+    /// Waits for an event satisfying the predicate.
+    fn wait_for<F, T>(&mut self, timeout: Duration, mut predicate: F) -> Option<T>
+    where
+        F: FnMut(&DebugEvent) -> Option<T>,
+    {
+        if let Some(value) = self.received.iter().filter_map(&mut predicate).next() {
+            return Some(value);
+        }
+
+        let start = Instant::now();
+        loop {
+            let remaining = timeout.checked_sub(start.elapsed())?;
+            match self.events.recv_timeout(remaining) {
+                Ok(event) => {
+                    let value = predicate(&event);
+                    self.received.push(event);
+                    if value.is_some() {
+                        return value;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return None
+                }
+            }
+        }
+    }
+}
+
+/// Adversarial client.
+struct Adversary {
+    api: CurrencyClient,
+    outcomes: Outcomes,
+}
+
+impl Adversary {
+    /// Submits a transaction which is expected to be executed successfully.
+    fn submit_valid<T>(&self, description: &str, transaction: &T)
+    where
+        T: Serialize + CryptoHash,
+    {
+        let tx_hash = self.api.submit(transaction).expect("submit transaction");
+        self.api
+            .await_commit(&tx_hash, Duration::from_secs(COMMIT_TIMEOUT_SECS))
+            .unwrap_or_else(|e| panic!("{}: {}", description, e));
+        info!("{}: committed, tx_hash = {:?}", description, tx_hash);
+    }
+
+    /// Submits a transfer which is expected to fail with the specified error.
+    fn attack(&mut self, description: &str, transfer: &Transfer, expected: Error) {
+        let tx_hash = self.api.submit(transfer).expect("submit transfer");
+        match self
+            .api
+            .await_commit(&tx_hash, Duration::from_secs(COMMIT_TIMEOUT_SECS))
+        {
+            Err(ClientError::TransactionFailed(_)) => {}
+            other => panic!("{}: unexpected outcome {:?}", description, other),
+        }
+
+        let code = self
+            .outcomes
+            .wait_for(Duration::from_secs(COMMIT_TIMEOUT_SECS), |event| {
+                Outcomes::error_code(event, &tx_hash)
+            })
+            .unwrap_or_else(|| panic!("{}: no error reported by the debugger", description));
+        assert_eq!(
+            code, expected as u8,
+            "{}: expected {:?}, got error code {}",
+            description, expected, code
+        );
+        info!("{}: rejected with {:?}", description, expected);
+    }
+
+    fn sync(&self, state: &mut SecretState) {
+        let query = WalletQuery::new(*state.public_key(), state.history_len());
+        let proof = self.api.wallet_proof(&query).expect("wallet proof");
+        state.sync_with_proof(&proof).expect("sync with proof");
+    }
+}
+
+fn garbage(len: usize) -> Vec<u8> {
+    let mut rng = thread_rng();
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn main() {
+    env::set_var("RUST_LOG", "adversary=info");
+    exonum::helpers::init_logger().unwrap();
+
+    let node_cfg = node_config();
+    let trust_anchor = TrustAnchor::new(vec![node_cfg.consensus_public_key]);
+
+    let (service, debugger) = CurrencyService::debug(DebuggerOptions {
+        check_invariants: Invariants::all(),
+        ..DebuggerOptions::default()
+    });
+    let (events_tx, events) = mpsc::channel();
+    thread::spawn(move || {
+        for event in debugger {
+            if events_tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    thread::spawn(|| {
+        let dir = TempDir::new("exonum").expect("tempdir");
+        let db = RocksDB::open(dir.path(), &DbOptions::default()).expect("rocksdb");
+        let node = Node::new(db, vec![Box::new(service)], node_cfg, None);
+        info!("Starting a single node...");
+        node.run().unwrap();
+    });
+    thread::sleep(Duration::from_millis(2_000));
+
+    let mut adversary = Adversary {
+        api: CurrencyClient::new(NODE_URL, trust_anchor),
+        outcomes: Outcomes {
+            events,
+            received: vec![],
+        },
+    };
+
+    // The adversary needs its signing key to sign forged transfers.
+    let (mallory_key, mallory_sk) =
+        gen_keypair_from_seed(&Seed::new(thread_rng().gen::<[u8; SEED_LENGTH]>()));
+    let mut mallory = SecretState::from_keypair(mallory_key, mallory_sk.clone());
+    let mut bob = SecretState::with_random_keypair();
+    adversary.submit_valid("mallory's wallet", &mallory.create_wallet());
+    adversary.submit_valid("bob's wallet", &bob.create_wallet());
+    mallory.initialize();
+    bob.initialize();
+
+    // An honest transfer serves as the source of proofs for the attacks.
+    let stale_state = mallory.clone();
+    let honest = mallory.create_transfer(1_000, bob.public_key(), TIME_LOCK);
+    adversary.submit_valid("honest transfer", &honest);
+    let accept = bob
+        .verify_transfer(&honest)
+        .expect("verify honest transfer")
+        .accept;
+    adversary.submit_valid("accept for honest transfer", &accept);
+    adversary.sync(&mut mallory);
+
+    let mut stale = stale_state;
+    let stale_transfer = stale.create_transfer(500, bob.public_key(), TIME_LOCK);
+    adversary.attack(
+        "transfer with stale history_len",
+        &stale_transfer,
+        Error::OutdatedHistory,
+    );
+
+    let from_future = Forgery::from(&honest).history_len(100).sign(&mallory_sk);
+    adversary.attack(
+        "transfer referencing non-existing history",
+        &from_future,
+        Error::InvalidHistoryRef,
+    );
+
+    let (amount, _) = Commitment::new(1_000);
+    let reused_amount_proof = Forgery::from(&honest)
+        .amount(amount, honest.amount_proof().into_owned())
+        .sign(&mallory_sk);
+    adversary.attack(
+        "transfer reusing amount proof",
+        &reused_amount_proof,
+        Error::IncorrectAmountProof,
+    );
+
+    // The balance proof of the honest transfer is made against the balance before it.
+    let reused_balance_proof = Forgery::from(&honest)
+        .history_len(mallory.history_len())
+        .sign(&mallory_sk);
+    adversary.attack(
+        "transfer reusing balance proof",
+        &reused_balance_proof,
+        Error::IncorrectProof,
+    );
+
+    let (amount, opening) = Commitment::new(0);
+    let zero_amount_proof = SimpleRangeProof::prove(&opening).expect("prove amount");
+    let zero_amount_proof = AmountProof::new(zero_amount_proof, None);
+    let below_minimum = Forgery::from(&honest)
+        .amount(amount, zero_amount_proof)
+        .sign(&mallory_sk);
+    adversary.attack(
+        "transfer below minimum amount",
+        &below_minimum,
+        Error::IncorrectAmountProof,
+    );
+
+    // Garbage ciphertext cannot be detected by the node, but the receiver cannot decrypt
+    // the amount and does not accept the transfer, so it is rolled back.
+    let template = mallory.create_transfer(500, bob.public_key(), TIME_LOCK);
+    mallory.abort(&template.hash());
+    let encrypted_data = template.encrypted_data();
+    let garbage_ciphertext = Forgery::from(&template)
+        .encrypted_data(EncryptedData::new(
+            &garbage(encrypted_data.nonce().len()),
+            &garbage(encrypted_data.encrypted_data().len()),
+        ))
+        .sign(&mallory_sk);
+    adversary.submit_valid("transfer with garbage ciphertext", &garbage_ciphertext);
+    assert!(bob.verify_transfer(&garbage_ciphertext).is_none());
+    info!("bob has rejected transfer with garbage ciphertext");
+    let tx_hash = garbage_ciphertext.hash();
+    adversary
+        .outcomes
+        .wait_for(Duration::from_secs(ROLLBACK_TIMEOUT_SECS), |event| {
+            if Outcomes::is_rollback(event, &tx_hash) {
+                Some(())
+            } else {
+                None
+            }
+        })
+        .expect("transfer with garbage ciphertext is not rolled back");
+    info!("transfer with garbage ciphertext has been rolled back");
+
+    info!("All attacks have been repelled");
+    process::exit(0);
+}
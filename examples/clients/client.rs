@@ -12,22 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use exonum::{
-    api::node::public::explorer::TransactionQuery,
-    crypto::{CryptoHash, Hash, PublicKey},
-    explorer::TransactionInfo,
-};
+use exonum::crypto::{CryptoHash, Hash, PublicKey};
 use private_currency::{
-    api::{CheckedWalletProof, FullEvent, TrustAnchor, WalletProof, WalletQuery},
+    api::TrustAnchor,
+    client::{CurrencyClient, TransactionStatus},
     transactions::{Accept, CreateWallet, Transfer},
-    SecretState, CONFIG,
+    AcceptDecision, FileStore, SecretState, StateStore, StoredWallet, ThresholdPolicy,
+    Transactions, CONFIG,
 };
 use rand::{seq::sample_iter, thread_rng, Rng};
-use reqwest::Client as HttpClient;
 
 use std::{
     cmp,
     collections::HashSet,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
     thread,
     time::Duration,
@@ -37,6 +35,7 @@ use std::{
 pub struct ClientConfig {
     pub sleep_probability: f64,
     pub sleep_duration: Duration,
+    pub restart_probability: f64,
     pub time_lock: u32,
 }
 
@@ -44,16 +43,18 @@ pub struct ClientConfig {
 pub struct ClientEnv {
     keys: Arc<RwLock<HashSet<PublicKey>>>,
     trust_anchor: TrustAnchor,
+    data_dir: PathBuf,
 }
 
 impl ClientEnv {
-    pub fn new<I>(consensus_keys: I) -> Self
+    pub fn new<I>(consensus_keys: I, data_dir: &Path) -> Self
     where
         I: IntoIterator<Item = PublicKey>,
     {
         ClientEnv {
             keys: Arc::new(RwLock::new(HashSet::new())),
             trust_anchor: TrustAnchor::new(consensus_keys),
+            data_dir: data_dir.to_owned(),
         }
     }
 
@@ -78,11 +79,17 @@ impl ClientEnv {
 
     pub fn run(self, client_count: usize, config: ClientConfig) {
         let client_handles: Vec<_> = (0..client_count)
-            .map(|_| {
+            .map(|i| {
                 let env = self.clone();
+                let store = FileStore::new(self.data_dir.join(format!("client-{}.json", i)));
                 thread::spawn(move || {
-                    let client = Client::new(env, config);
-                    client.run();
+                    let mut client = Client::new(env.clone(), config, store.clone());
+                    loop {
+                        // `run()` returns when the client crashes; the client is then
+                        // restarted from the state persisted in the store.
+                        client.run();
+                        client = Client::restore(env.clone(), config, store.clone());
+                    }
                 })
             })
             .collect();
@@ -97,29 +104,27 @@ impl ClientEnv {
 
 #[derive(Debug)]
 struct Client {
-    state: SecretState,
-    http: HttpClient,
-    events: Vec<FullEvent>,
+    wallet: StoredWallet,
+    store: FileStore,
+    api: CurrencyClient,
     client_env: ClientEnv,
     unconfirmed_transfer: Option<Hash>,
     config: ClientConfig,
 }
 
 impl Client {
-    const WALLET_URL: &'static str =
-        "http://127.0.0.1:8080/api/services/private_currency/v1/wallet";
-    const TRANSACTION_URL: &'static str =
-        "http://127.0.0.1:8080/api/services/private_currency/v1/transaction";
-    const TX_STATUS_URL: &'static str = "http://127.0.0.1:8080/api/explorer/v1/transactions";
+    const NODE_URL: &'static str = "http://127.0.0.1:8080";
 
-    fn new(client_env: ClientEnv, config: ClientConfig) -> Self {
+    fn new(client_env: ClientEnv, config: ClientConfig, store: FileStore) -> Self {
         let state = SecretState::with_random_keypair();
         client_env.add(*state.public_key());
 
+        let wallet = StoredWallet::new(state);
+        store.save(&wallet).expect("save wallet");
         let client = Client {
-            state,
-            http: HttpClient::new(),
-            events: vec![],
+            wallet,
+            store,
+            api: CurrencyClient::new(Self::NODE_URL, client_env.trust_anchor.clone()),
             client_env,
             unconfirmed_transfer: None,
             config,
@@ -128,8 +133,37 @@ impl Client {
         client
     }
 
+    /// Restores a client after a crash from the state persisted in the store.
+    fn restore(client_env: ClientEnv, config: ClientConfig, store: FileStore) -> Self {
+        let wallet = store
+            .load()
+            .expect("load wallet")
+            .expect("wallet is not persisted");
+        // The outcome of the transfer sent before the crash is unknown; if it is still
+        // pending after the next sync, its status is polled as usual.
+        let unconfirmed_transfer = wallet
+            .state
+            .pending_transfers()
+            .next()
+            .map(|transfer| transfer.hash());
+
+        let client = Client {
+            wallet,
+            store,
+            api: CurrencyClient::new(Self::NODE_URL, client_env.trust_anchor.clone()),
+            client_env,
+            unconfirmed_transfer,
+            config,
+        };
+        client.log_info(&format!(
+            "restarted; resuming history at event #{}",
+            client.wallet.state.history_len()
+        ));
+        client
+    }
+
     fn tag(&self) -> String {
-        let key = self.state.public_key().as_ref();
+        let key = self.wallet.state.public_key().as_ref();
         format!("[{:02x}{:02x}{:02x}{:02x}]", key[0], key[1], key[2], key[3])
     }
 
@@ -142,89 +176,57 @@ impl Client {
     }
 
     fn poll_history(&mut self) -> Vec<Transfer> {
-        let query = WalletQuery {
-            key: *self.state.public_key(),
-            start_history_at: self.events.len() as u64,
-        };
-        let mut response = self
-            .http
-            .get(Self::WALLET_URL)
-            .query(&query)
-            .send()
-            .expect("query wallet status");
-
-        if response.status().is_success() {
-            let wallet_proof: WalletProof = response.json().expect("cannot parse response");
-            let CheckedWalletProof {
-                wallet,
-                history,
-                unaccepted_transfers,
-                ..
-            } = wallet_proof
-                .check(&self.client_env.trust_anchor, &query)
-                .unwrap();
-            let wallet = wallet.expect("wallet not found");
-
-            for event in history {
-                let old_balance = self.state.balance();
-
-                match event {
-                    FullEvent::CreateWallet(..) => {
-                        self.log_info("received event: `CreateWallet`");
-                        self.state.initialize();
-                    }
-                    FullEvent::Transfer(ref transfer) => {
-                        self.log_info(&format!(
-                            "received event: `Transfer`, tx_hash = {:?}",
-                            transfer.hash()
-                        ));
-                        self.state.transfer(transfer);
-                    }
-                    FullEvent::Rollback(ref transfer) => {
-                        self.log_info(&format!(
-                            "received event: `Rollback`, tx_hash = {:?}",
-                            transfer.hash()
-                        ));
-                        self.state.rollback(transfer);
-                    }
-                }
-
-                self.log_info(&format!(
-                    "updated balance: {} ({:+})",
-                    self.state.balance(),
-                    self.state.balance() as i64 - old_balance as i64,
-                ));
-                self.events.push(event);
+        // Events are requested starting from the first event not applied to the stored
+        // state, so no events are lost or applied twice across restarts.
+        let start = self.wallet.state.history_len();
+        let proof = match self.api.sync_wallet(&mut self.wallet, &self.store) {
+            Ok(proof) => proof,
+            Err(e) => {
+                self.log_error(&format!("cannot sync wallet: {}", e));
+                return vec![];
             }
+        };
 
-            assert!(self.state.corresponds_to(&wallet.info()));
-            unaccepted_transfers
-        } else {
-            self.log_error(&format!("unexpected response: {:?}", response));
-            vec![]
+        let applied = self.wallet.state.history_len() - start;
+        let ledger = self.wallet.state.ledger();
+        for entry in &ledger[ledger.len() - applied as usize..] {
+            self.log_info(&format!(
+                "received event #{}, tx_hash = {:?}; updated balance: {} ({:+})",
+                entry.index, entry.transaction_hash, entry.balance, entry.delta,
+            ));
         }
+
+        proof.unaccepted_transfers
     }
 
     fn accept_transfers(&self, transfers: &[Transfer]) {
-        let accepts = transfers.iter().flat_map(|transfer| {
-            if let Some(verified) = self.state.verify_transfer(transfer) {
-                self.log_info(&format!(
-                    "received transfer: {}, tx_hash = {:?}",
-                    verified.value(),
-                    transfer.hash()
-                ));
-                Some(verified.accept)
-            } else {
-                self.log_error(&format!(
-                    "received incorrect transfer, tx_hash = {:?}",
-                    transfer.hash()
-                ));
-                None
+        // Honest clients accept all incoming transfers.
+        let policy = ThresholdPolicy::accept_all();
+
+        for transfer in transfers {
+            match self.wallet.state.evaluate_transfer(transfer, &policy) {
+                Some((AcceptDecision::Accept, verified)) => {
+                    self.log_info(&format!(
+                        "received transfer: {}, tx_hash = {:?}",
+                        verified.value(),
+                        transfer.hash()
+                    ));
+                    self.send_accept(&verified.accept);
+                }
+                Some((decision, _)) => {
+                    self.log_info(&format!(
+                        "not accepting transfer ({:?}), tx_hash = {:?}",
+                        decision,
+                        transfer.hash()
+                    ));
+                }
+                None => {
+                    self.log_error(&format!(
+                        "received incorrect transfer, tx_hash = {:?}",
+                        transfer.hash()
+                    ));
+                }
             }
-        });
-
-        for accept in accepts {
-            self.send_accept(&accept);
         }
     }
 
@@ -233,14 +235,7 @@ impl Client {
             "sending `CreateWallet`, tx_hash = {:?}",
             create_wallet.hash()
         ));
-        let mut response = self
-            .http
-            .post(Self::TRANSACTION_URL)
-            .json(create_wallet)
-            .send()
-            .expect("send `CreateWallet`");
-        let response: Hash = response.json().expect("transaction hash");
-        assert_eq!(response, create_wallet.hash());
+        self.api.submit(create_wallet).expect("send `CreateWallet`");
     }
 
     fn send_transfer(&mut self, transfer: &Transfer, amount: u64) {
@@ -250,14 +245,13 @@ impl Client {
             transfer.to(),
             transfer.hash()
         ));
-        let mut response = self
-            .http
-            .post(Self::TRANSACTION_URL)
-            .json(transfer)
-            .send()
+        // The pending transfer is persisted before sending, so that the client recognizes
+        // the transfer in its history if it crashes before the transfer is committed,
+        // and can re-broadcast the transfer if it is lost by the node.
+        let tx = Transactions::Transfer(transfer.clone());
+        self.api
+            .submit_tracked(&tx, &mut self.wallet, &self.store)
             .expect("send `Transfer`");
-        let response: Hash = response.json().expect("transaction hash");
-        assert_eq!(response, transfer.hash());
         self.unconfirmed_transfer = Some(transfer.hash());
     }
 
@@ -268,34 +262,39 @@ impl Client {
             .expect("unconfirmed transfer");
         self.log_info(&format!("polling transfer status, tx_hash = {:?}", tx_hash));
 
-        let mut response = self
-            .http
-            .get(Self::TX_STATUS_URL)
-            .query(&TransactionQuery { hash: tx_hash })
-            .send()
-            .expect("transaction info");
-
-        if !response.status().is_success() {
-            self.log_error(&format!("transfer disappeared, tx_hash = {:?}", tx_hash));
-            self.unconfirmed_transfer = None;
-            return;
-        }
-
-        let response: TransactionInfo<Transfer> = response.json().expect("parse transaction info");
-        if let Some(committed) = response.as_committed() {
-            match committed.status() {
-                Ok(_) => {
-                    self.log_info(&format!("transfer committed, tx_hash = {:?}", tx_hash));
-                }
-                Err(e) => {
-                    self.log_error(&format!(
-                        "transfer failed, tx_hash = {:?}, reason: {}",
-                        tx_hash, e
-                    ));
-                }
+        // Failed transfers are aborted, and transfers lost by the node are re-broadcast.
+        let statuses = self
+            .api
+            .rebroadcast(&mut self.wallet, &self.store)
+            .expect("rebroadcast transactions");
+        let status = statuses
+            .into_iter()
+            .find(|(hash, _)| *hash == tx_hash)
+            .map(|(_, status)| status)
+            // The transfer is no longer tracked if it has been resolved before a restart.
+            .unwrap_or(TransactionStatus::Committed);
+
+        match status {
+            TransactionStatus::InPool => return,
+            TransactionStatus::Unknown => {
+                self.log_error(&format!(
+                    "transfer disappeared, re-broadcasting; tx_hash = {:?}",
+                    tx_hash
+                ));
+                return;
+            }
+            TransactionStatus::Committed => {
+                self.log_info(&format!("transfer committed, tx_hash = {:?}", tx_hash));
+            }
+            TransactionStatus::Failed(e) => {
+                // The transfer is already aborted by `rebroadcast`.
+                self.log_error(&format!(
+                    "transfer failed, tx_hash = {:?}, reason: {}",
+                    tx_hash, e
+                ));
             }
-            self.unconfirmed_transfer = None;
         }
+        self.unconfirmed_transfer = None;
     }
 
     fn send_accept(&self, accept: &Accept) {
@@ -304,16 +303,10 @@ impl Client {
             accept.transfer_id(),
             accept.hash()
         ));
-        let mut response = self
-            .http
-            .post(Self::TRANSACTION_URL)
-            .json(accept)
-            .send()
-            .expect("send `Accept`");
-        let response: Hash = response.json().expect("transaction hash");
-        assert_eq!(response, accept.hash());
+        self.api.submit(accept).expect("send `Accept`");
     }
 
+    /// Runs the client routine until the client crashes.
     fn run(mut self) {
         let mut rng = thread_rng();
         let mut sleep = move || {
@@ -323,9 +316,12 @@ impl Client {
         let config = self.config;
 
         let mut rng = thread_rng();
-        let create_wallet = self.state.create_wallet();
-        self.send_create_wallet(&create_wallet);
-        sleep();
+        if self.wallet.state.history_len() == 0 {
+            // Resubmitting the transaction after a restart is safe.
+            let create_wallet = self.wallet.state.create_wallet();
+            self.send_create_wallet(&create_wallet);
+            sleep();
+        }
 
         loop {
             // Update our state.
@@ -334,17 +330,28 @@ impl Client {
 
             if self.unconfirmed_transfer.is_some() {
                 self.poll_transfer_status();
-            } else if let Some(peer) = self.client_env.random_peer(self.state.public_key()) {
+            } else if let Some(peer) = self.client_env.random_peer(self.wallet.state.public_key()) {
                 // Create a transfer to a random wallet.
                 let amount = rng.gen_range(
                     CONFIG.min_transfer_amount,
-                    cmp::min(10_000, self.state.balance()),
+                    cmp::min(10_000, self.wallet.state.spendable_balance()),
                 );
-                let transfer = self.state.create_transfer(amount, &peer, config.time_lock);
-                self.send_transfer(&transfer, amount);
+                match self
+                    .wallet
+                    .state
+                    .try_create_transfer(amount, &peer, config.time_lock)
+                {
+                    Ok(transfer) => self.send_transfer(&transfer, amount),
+                    Err(e) => self.log_error(&format!("cannot create transfer: {}", e)),
+                }
             }
 
             sleep();
+            if rng.gen::<f64>() < config.restart_probability {
+                // Simulate a crash; all state not persisted in the store is lost.
+                self.log_info("crashing");
+                return;
+            }
             if rng.gen::<f64>() < config.sleep_probability {
                 // Simulate going offline for a while.
                 self.log_info("going offline");
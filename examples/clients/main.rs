@@ -19,10 +19,15 @@
 //! intentionally), but go "offline" periodically, thus potentially missing refund time-locks
 //! for incoming transfers.
 //!
+//! Each client persists its secret state together with the applied events from the wallet
+//! history to a file. Clients crash periodically, losing all state not persisted in the file;
+//! a crashed client is restarted from the file and resumes synchronization with the first
+//! event it has not applied yet.
+//!
 //! Run with
 //!
 //! ```shell
-//! cargo +nightly run --example clients -- <PARAMS>
+//! cargo +nightly run --example clients --features client -- <PARAMS>
 //! ```
 //!
 //! Use `-h` or `--help` to get param description.
@@ -33,7 +38,6 @@ extern crate exonum;
 extern crate log;
 extern crate private_currency;
 extern crate rand;
-extern crate reqwest;
 extern crate tempdir;
 
 use clap::{App, Arg};
@@ -43,7 +47,9 @@ use exonum::{
     node::{Node, NodeApiConfig, NodeConfig},
     storage::{DbOptions, RocksDB},
 };
-use private_currency::{DebugEvent, DebuggerOptions, Service as CurrencyService, CONFIG};
+use private_currency::{
+    DebugEvent, DebuggerOptions, Invariants, Service as CurrencyService, CONFIG,
+};
 use tempdir::TempDir;
 
 use std::{env, thread, time::Duration};
@@ -132,6 +138,22 @@ fn parse_client_config() -> (usize, ClientConfig) {
             }
             Ok(())
         });
+    let restart_probability = Arg::with_name("restart_probability")
+        .short("r")
+        .long("restart-prob")
+        .takes_value(true)
+        .value_name("PROB")
+        .default_value("0.05")
+        .help("Probability for a client to crash and restart at any iteration of its routine")
+        .validator(|s| {
+            let value: f64 = s
+                .parse()
+                .map_err(|_| "expected a floating-point number".to_string())?;
+            if value < 0.0 || value > 1.0 {
+                return Err("expected a number between 0 and 1".to_string());
+            }
+            Ok(())
+        });
     let time_lock = Arg::with_name("time_lock")
         .short("t")
         .long("timelock")
@@ -158,14 +180,16 @@ fn parse_client_config() -> (usize, ClientConfig) {
             "Demo launches a single-node blockchain network and a specified number \
              of clients. Each client then iterates the following routine: (1) receive updates \
              via `wallet` API endpoint; (2) create and broadcast a transfer to another client \
-             chosen randomly; (3) maybe go to sleep. \
+             chosen randomly; (3) maybe crash and restart from the state persisted on disk; \
+             (4) maybe go to sleep. \
              Regardless of sleep probability / sleep duration config, \
-             each client waits 2..3s on each iteration after step (3). \
+             each client waits 2..3s on each iteration after step (2). \
              The demo runs indefinitely; hit Ctrl+C (or an equivalent) to terminate.",
         )
         .arg(client_count)
         .arg(sleep_probability)
         .arg(sleep_duration)
+        .arg(restart_probability)
         .arg(time_lock)
         .get_matches();
 
@@ -191,6 +215,13 @@ fn parse_client_config() -> (usize, ClientConfig) {
     assert!(sleep_duration <= 60_000);
     let sleep_duration = Duration::from_millis(sleep_duration);
 
+    let restart_probability: f64 = matches
+        .value_of("restart_probability")
+        .expect("no `restart_probability` param")
+        .parse()
+        .expect("`restart_probability` cannot be parsed");
+    assert!(restart_probability >= 0.0 && restart_probability <= 1.0);
+
     let time_lock: u32 = matches
         .value_of("time_lock")
         .expect("no `time_lock` param")
@@ -200,6 +231,7 @@ fn parse_client_config() -> (usize, ClientConfig) {
     let config = ClientConfig {
         sleep_probability,
         sleep_duration,
+        restart_probability,
         time_lock,
     };
     (client_count, config)
@@ -214,7 +246,8 @@ fn main() {
     let consensus_keys = vec![node_cfg.consensus_public_key];
 
     let (service, debugger) = CurrencyService::debug(DebuggerOptions {
-        check_invariants: true,
+        check_invariants: Invariants::all(),
+        ..DebuggerOptions::default()
     });
     let debug_handle = thread::spawn(|| {
         for event in debugger {
@@ -228,6 +261,21 @@ fn main() {
                         height
                     );
                 }
+                DebugEvent::ExecutionFailed {
+                    tx_hash,
+                    error,
+                    description,
+                    height,
+                } => {
+                    warn!(
+                        "transaction {:?} failed at height {}: {:?} ({})",
+                        tx_hash,
+                        height,
+                        error,
+                        description.unwrap_or_default()
+                    );
+                }
+                other => debug!("{:?}", other),
             }
         }
     });
@@ -246,7 +294,8 @@ fn main() {
 
     thread::sleep(Duration::from_millis(2_000));
     info!("Starting clients with config {:?}", client_config);
-    let client_env = ClientEnv::new(consensus_keys);
+    let clients_dir = TempDir::new("clients").expect("tempdir");
+    let client_env = ClientEnv::new(consensus_keys, clients_dir.path());
     client_env.run(client_count, client_config);
     handle.join().unwrap();
     debug_handle.join().unwrap();
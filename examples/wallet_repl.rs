@@ -0,0 +1,329 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interactive wallet connected to a running node of the service.
+//!
+//! The wallet is kept in a JSON file; if the file does not exist, a new wallet is generated
+//! and registered on the blockchain. The wallet then reads commands from the standard input:
+//! checking the balance, sending transfers, listing and accepting incoming transfers,
+//! and printing the wallet history. Wallet proofs returned by the node are verified
+//! against the consensus keys of validators. Run with
+//!
+//! ```shell
+//! cargo +nightly run --example wallet_repl --features client -- \
+//!     --wallet alice.json --validators <KEYS>
+//! ```
+//!
+//! and type `help` to get the list of commands.
+
+extern crate clap;
+extern crate exonum;
+extern crate failure;
+extern crate private_currency;
+
+use clap::{App, Arg};
+use exonum::{
+    crypto::{CryptoHash, Hash, PublicKey},
+    encoding::serialize::{encode_hex, FromHex},
+};
+use failure::{err_msg, Error};
+use private_currency::{
+    api::TrustAnchor,
+    client::{AcceptanceDeadline, CurrencyClient},
+    FileStore, SecretState, StateStore, StoredWallet,
+};
+
+use std::{
+    io::{self, BufRead, Write},
+    time::Duration,
+};
+
+const COMMIT_TIMEOUT: u64 = 30;
+
+const HELP: &str = "\
+Commands:
+    balance                       synchronize the wallet and print its balance
+    send <KEY> <AMOUNT> [DELAY]   send a transfer (by default, with the rollback delay
+                                  recommended by the node)
+    incoming                      list incoming transfers waiting for acceptance
+    accept <N> | all              accept the incoming transfer #N from the last listing, or all
+    history                       print the wallet history
+    key                           print the public key of the wallet
+    help                          print this message
+    quit                          exit the wallet";
+
+/// Interactive wallet.
+struct Wallet {
+    client: CurrencyClient,
+    store: FileStore,
+    wallet: StoredWallet,
+    // Incoming transfers from the last listing.
+    incoming: Vec<AcceptanceDeadline>,
+}
+
+impl Wallet {
+    fn open(client: CurrencyClient, store: FileStore) -> Result<Self, Error> {
+        let stored = match store.load()? {
+            Some(stored) => stored,
+            None => StoredWallet::new(SecretState::with_random_keypair()),
+        };
+        let mut wallet = Wallet {
+            client,
+            store,
+            wallet: stored,
+            incoming: vec![],
+        };
+
+        if wallet.wallet.state.history_len() == 0 {
+            wallet.store.save(&wallet.wallet)?;
+            println!("registering wallet {}", encode_hex(wallet.public_key()));
+            // Resubmitting the transaction is safe if the wallet has been registered already.
+            let create_wallet = wallet.wallet.state.create_wallet();
+            let tx_hash = wallet.client.submit(&create_wallet)?;
+            wallet.await_commit(&tx_hash)?;
+        }
+        wallet.sync()?;
+        Ok(wallet)
+    }
+
+    fn public_key(&self) -> &PublicKey {
+        self.wallet.state.public_key()
+    }
+
+    fn await_commit(&self, tx_hash: &Hash) -> Result<(), Error> {
+        self.client
+            .await_commit(tx_hash, Duration::from_secs(COMMIT_TIMEOUT))
+            .map_err(Error::from)
+    }
+
+    /// Synchronizes the wallet and updates the list of incoming transfers.
+    fn sync(&mut self) -> Result<(), Error> {
+        let proof = self.client.sync_wallet(&mut self.wallet, &self.store)?;
+        self.incoming = self.client.acceptance_deadlines(&proof)?;
+        Ok(())
+    }
+
+    fn balance(&mut self) -> Result<(), Error> {
+        self.sync()?;
+        let state = &self.wallet.state;
+        println!(
+            "balance: {}, spendable: {}, incoming transfers: {}",
+            state.balance(),
+            state.spendable_balance(),
+            self.incoming.len()
+        );
+        Ok(())
+    }
+
+    fn send(&mut self, args: &[&str]) -> Result<(), Error> {
+        let (receiver, amount, delay) = match *args {
+            [receiver, amount] => (receiver, amount, None),
+            [receiver, amount, delay] => (receiver, amount, Some(delay)),
+            _ => return Err(err_msg("usage: send <KEY> <AMOUNT> [DELAY]")),
+        };
+        let receiver = PublicKey::from_hex(receiver)
+            .map_err(|e| err_msg(format!("invalid key {}: {}", receiver, e)))?;
+        let amount: u64 = amount
+            .parse()
+            .map_err(|_| err_msg(format!("invalid amount: {}", amount)))?;
+        let delay = match delay {
+            Some(delay) => delay
+                .parse()
+                .map_err(|_| err_msg(format!("invalid rollback delay: {}", delay)))?,
+            None => {
+                let recommendation = self.client.recommended_rollback_delay(&receiver)?;
+                println!(
+                    "using recommended rollback delay: {} {:?}",
+                    recommendation.rollback_delay, recommendation.unit
+                );
+                recommendation.rollback_delay
+            }
+        };
+
+        self.sync()?;
+        let transfer = self
+            .wallet
+            .state
+            .try_create_transfer(amount, &receiver, delay)?;
+        // The transfer is recorded as pending, so it is reconciled during the following syncs.
+        self.store.save(&self.wallet)?;
+        let tx_hash = self.client.submit(&transfer)?;
+        println!("sent transfer {}", encode_hex(&tx_hash));
+        if let Err(e) = self.await_commit(&tx_hash) {
+            self.wallet.state.abort(&tx_hash);
+            self.store.save(&self.wallet)?;
+            return Err(e);
+        }
+        self.sync()?;
+        println!(
+            "transfer committed; it will be rolled back unless accepted within {} blocks",
+            delay
+        );
+        Ok(())
+    }
+
+    fn list_incoming(&mut self) -> Result<(), Error> {
+        self.sync()?;
+        if self.incoming.is_empty() {
+            println!("no incoming transfers");
+        }
+        for (i, deadline) in self.incoming.iter().enumerate() {
+            let amount = match self.wallet.state.verify_transfer(&deadline.transfer) {
+                Some(verified) => verified.value().to_string(),
+                None => "cannot be decrypted".to_owned(),
+            };
+            println!(
+                "#{}: {} from {}, rolled back in {} blocks",
+                i,
+                amount,
+                encode_hex(deadline.transfer.from()),
+                deadline.blocks_left
+            );
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self, args: &[&str]) -> Result<(), Error> {
+        let indices: Vec<usize> = match *args {
+            ["all"] => (0..self.incoming.len()).collect(),
+            [index] => vec![index
+                .parse()
+                .map_err(|_| err_msg(format!("invalid transfer number: {}", index)))?],
+            _ => return Err(err_msg("usage: accept <N> | all")),
+        };
+
+        for index in indices {
+            let transfer = &self
+                .incoming
+                .get(index)
+                .ok_or_else(|| err_msg(format!("no incoming transfer #{}", index)))?
+                .transfer;
+            let verified = self
+                .wallet
+                .state
+                .verify_transfer(transfer)
+                .ok_or_else(|| err_msg(format!("transfer #{} cannot be decrypted", index)))?;
+            let tx_hash = self.client.submit(&verified.accept)?;
+            self.await_commit(&tx_hash)?;
+            println!(
+                "accepted transfer #{} ({}), tx_hash = {}",
+                index,
+                verified.value(),
+                encode_hex(&transfer.hash())
+            );
+        }
+        self.balance()
+    }
+
+    fn history(&self) {
+        for entry in self.wallet.state.ledger() {
+            println!(
+                "#{}: {:+} -> {}, tx_hash = {}",
+                entry.index,
+                entry.delta,
+                entry.balance,
+                encode_hex(&entry.transaction_hash)
+            );
+        }
+    }
+
+    fn execute(&mut self, line: &str) -> Result<bool, Error> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(true),
+        };
+
+        match command {
+            "balance" => self.balance()?,
+            "send" => self.send(args)?,
+            "incoming" => self.list_incoming()?,
+            "accept" => self.accept(args)?,
+            "history" => self.history(),
+            "key" => println!("{}", encode_hex(self.public_key())),
+            "help" => println!("{}", HELP),
+            "quit" | "exit" => return Ok(false),
+            _ => println!("unknown command `{}`; type `help` for help", command),
+        }
+        Ok(true)
+    }
+}
+
+fn main() {
+    let matches = App::new("wallet_repl")
+        .about("Interactive wallet for the private cryptocurrency service")
+        .arg(
+            Arg::with_name("wallet")
+                .long("wallet")
+                .short("w")
+                .takes_value(true)
+                .value_name("FILE")
+                .required(true)
+                .help("Path to the wallet file; a new wallet is created if it does not exist"),
+        )
+        .arg(
+            Arg::with_name("node")
+                .long("node")
+                .takes_value(true)
+                .value_name("URL")
+                .default_value("http://127.0.0.1:8080")
+                .help("Base URL of the public API of the node"),
+        )
+        .arg(
+            Arg::with_name("validators")
+                .long("validators")
+                .takes_value(true)
+                .value_name("KEYS")
+                .required(true)
+                .use_delimiter(true)
+                .help("Comma-separated hex-encoded consensus keys of all validators"),
+        )
+        .get_matches();
+
+    let keys: Vec<_> = matches
+        .values_of("validators")
+        .expect("required argument")
+        .map(|key| PublicKey::from_hex(key).expect("invalid validator key"))
+        .collect();
+    let client = CurrencyClient::new(matches.value_of("node").unwrap(), TrustAnchor::new(keys));
+    // The wallet is locked, so that it cannot be used by another REPL concurrently.
+    let store = FileStore::locked(matches.value_of("wallet").expect("required argument"))
+        .unwrap_or_else(|e| {
+            eprintln!("error: cannot open wallet: {}", e);
+            ::std::process::exit(1);
+        });
+    let mut wallet = Wallet::open(client, store).unwrap_or_else(|e| {
+        eprintln!("error: cannot open wallet: {}", e);
+        ::std::process::exit(1);
+    });
+    println!(
+        "wallet {}; type `help` for help",
+        encode_hex(wallet.public_key())
+    );
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().expect("flush stdout");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).expect("read stdin") == 0 {
+            break;
+        }
+        match wallet.execute(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+}
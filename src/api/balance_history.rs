@@ -0,0 +1,334 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proofs of historical wallet balances.
+
+use exonum::{
+    api,
+    blockchain::{Block, Schema as CoreSchema},
+    crypto::{Hash, PublicKey, Signature},
+    helpers::Height,
+    storage::Snapshot,
+};
+
+use super::{
+    EventAnchor, FullEvent, ProofDescription, TrustAnchor, VerifyError, WalletProof, WalletQuery,
+};
+use crypto::Commitment;
+use storage::{Schema, Wallet};
+
+/// Query for the `balance_history` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceHistoryQuery {
+    /// Public key of the wallet.
+    pub key: PublicKey,
+    /// First height of the range.
+    pub since: Height,
+    /// Last height of the range (inclusive). If not specified, the range ends
+    /// with the latest committed block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<Height>,
+    /// Hash of a recently committed block. Required together with `signature`
+    /// if the service is configured to [require signed queries].
+    ///
+    /// [require signed queries]: ::Service::require_signed_queries()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<Hash>,
+    /// Signature of the wallet owner, which is the same as for a [`WalletQuery`]
+    /// with `start_history_at` equal to zero.
+    ///
+    /// [`WalletQuery`]: self::WalletQuery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl BalanceHistoryQuery {
+    /// Creates an unsigned query.
+    pub fn new(key: PublicKey, since: Height, until: Option<Height>) -> Self {
+        BalanceHistoryQuery {
+            key,
+            since,
+            until,
+            challenge: None,
+            signature: None,
+        }
+    }
+
+    /// Converts this query into a query for the embedded wallet proof.
+    fn wallet_query(&self, start_history_at: u64) -> WalletQuery {
+        WalletQuery {
+            challenge: self.challenge,
+            signature: self.signature,
+            ..WalletQuery::new(self.key, start_history_at)
+        }
+    }
+
+    /// Checks that the query is signed by the wallet owner.
+    pub(super) fn authenticate<T: AsRef<dyn Snapshot>>(
+        &self,
+        snapshot: T,
+    ) -> Result<(), api::Error> {
+        self.wallet_query(0).authenticate(snapshot)
+    }
+}
+
+/// Cryptographically authenticated proof of commitments to all balances a wallet has had
+/// within a range of heights.
+///
+/// # Theory
+///
+/// Wallet history is ordered by the height at which events occur, and each event changes
+/// the balance commitment by a committed amount (or does not change it at all). Hence,
+/// commitments to past balances can be restored from the current balance commitment
+/// by undoing events in the history, latest first. The proof contains
+///
+/// - a [`WalletProof`] for the latest block, which includes all events starting from
+///   the `start` index
+/// - an anchor of the event at `start`, i.e., the block header, in which the transaction
+///   corresponding to the event was committed, and a proof that the transaction
+///   is included into the block. If the anchor block precedes `since`, the balance
+///   after the event at `start` was in effect at `since`
+/// - if the range ends before the latest block, an anchor of the first event at `end`
+///   occurring after the range. Since transactions are committed no later than events
+///   caused by them, an anchor block following `until` proves that the event
+///   has occurred after the range
+///
+/// Thus, balances after events `start..end` cover all balances of the wallet
+/// within the range.
+///
+/// Incoming transfers, rollbacks and stream payments occur later than their transactions
+/// are committed, so they are never used as the `start` event.
+///
+/// [`WalletProof`]: self::WalletProof
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BalanceHistoryProof {
+    start: u64,
+    end: u64,
+    wallet_proof: WalletProof,
+    start_anchor: EventAnchor,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end_anchor: Option<EventAnchor>,
+}
+
+/// Balances of a wallet obtained after checking a `BalanceHistoryProof`.
+#[derive(Debug)]
+pub struct CheckedBalanceHistory {
+    /// Latest block, against which the proof is verified.
+    pub block: Block,
+    /// General information about the wallet.
+    pub wallet: Wallet,
+    /// Index of the event in the wallet history, after which the first
+    /// of `balances` is in effect.
+    pub start: u64,
+    /// Commitments to the wallet balances after events `start`, `start + 1`, etc.
+    /// The commitments cover all balances of the wallet within the requested range.
+    pub balances: Vec<Commitment>,
+}
+
+/// Returns the change of the balance of the wallet with the specified key caused
+/// by the event, as a pair of the committed amount and a flag whether the amount is credited
+/// to the wallet. Returns `None` if the event does not change the balance.
+///
+/// The event is assumed to be proven to belong to the wallet history, so that embedded
+/// transactions are valid.
+pub(crate) fn balance_change(event: &FullEvent, key: &PublicKey) -> Option<(Commitment, bool)> {
+    const INVALID: &str = "embedded transaction of a committed event";
+
+    Some(match event {
+        FullEvent::CreateWallet(..)
+        | FullEvent::Acknowledgment(..)
+        | FullEvent::TransferRequest(..) => return None,
+        FullEvent::Transfer(transfer) => (transfer.amount(), transfer.to() == key),
+        FullEvent::Rollback(transfer) => (transfer.amount(), true),
+        FullEvent::BridgeLock(lock) => (lock.amount(), false),
+        FullEvent::BridgeRelease(release) => {
+            (release.lock_transaction().expect(INVALID).amount(), true)
+        }
+        FullEvent::DisputeOpened(dispute) => (
+            dispute.transfer_transaction().expect(INVALID).amount(),
+            false,
+        ),
+        FullEvent::DisputeResolved(resolution) => (
+            resolution.transfer_transaction().expect(INVALID).amount(),
+            true,
+        ),
+        FullEvent::StreamOpened(stream) => (stream.total_amount(), false),
+        FullEvent::StreamPayment(stream) => (stream.rate(), true),
+        FullEvent::StreamClosed(close) => (close.refunded_amount().expect(INVALID), true),
+        FullEvent::Lock(lock) => (lock.amount(), false),
+        FullEvent::Unlock(unlock) => (unlock.lock_transaction().expect(INVALID).amount(), true),
+        FullEvent::Rerandomization(rerandomize) => (rerandomize.blinding(), true),
+    })
+}
+
+/// Does the event occur at the same height as its transaction is committed?
+fn occurs_with_transaction(event: &FullEvent, key: &PublicKey) -> bool {
+    match event {
+        FullEvent::Transfer(transfer) => transfer.from() == key,
+        FullEvent::Rollback(..) | FullEvent::StreamPayment(..) => false,
+        _ => true,
+    }
+}
+
+impl BalanceHistoryProof {
+    /// Creates a new proof based on a given storage snapshot.
+    pub(super) fn new<T: AsRef<dyn Snapshot>>(
+        snapshot: T,
+        query: &BalanceHistoryQuery,
+    ) -> api::Result<Self> {
+        let history_len = Schema::new(&snapshot)
+            .wallet(&query.key)
+            .ok_or_else(|| api::Error::NotFound("wallet not found".to_owned()))?
+            .history_len();
+        let core_schema = CoreSchema::new(&snapshot);
+        if let Some(until) = query.until {
+            if until < query.since {
+                return Err(api::Error::BadRequest(
+                    "`until` precedes `since`".to_owned(),
+                ));
+            }
+            if until > core_schema.height() {
+                return Err(api::Error::BadRequest(
+                    "`until` exceeds the blockchain height".to_owned(),
+                ));
+            }
+        }
+
+        let transaction_height = |event: &FullEvent| {
+            core_schema
+                .transactions_locations()
+                .get(&event.transaction_hash())
+                .expect("TxLocation")
+                .block_height()
+        };
+        let events: Vec<_> = (0..history_len)
+            .map(|index| FullEvent::at(&snapshot, &query.key, index))
+            .collect();
+
+        let start = (1..history_len)
+            .rev()
+            .find(|&index| {
+                let event = &events[index as usize];
+                occurs_with_transaction(event, &query.key)
+                    && transaction_height(event) < query.since
+            })
+            .unwrap_or(0);
+        let end = query.until.and_then(|until| {
+            (start + 1..history_len)
+                .find(|&index| transaction_height(&events[index as usize]) > until)
+        });
+
+        let start_anchor = EventAnchor::new(&snapshot, &events[start as usize].transaction_hash());
+        let end_anchor = end
+            .map(|index| EventAnchor::new(&snapshot, &events[index as usize].transaction_hash()));
+        Ok(BalanceHistoryProof {
+            start,
+            end: end.unwrap_or(history_len),
+            wallet_proof: WalletProof::new(&snapshot, &query.wallet_query(start)),
+            start_anchor,
+            end_anchor,
+        })
+    }
+
+    /// Returns the index of the event in the wallet history, after which the first
+    /// proven balance is in effect.
+    pub(crate) fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the index of the first event after the proven balances.
+    pub(crate) fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Returns events included into the proof. The events are not verified.
+    pub(crate) fn unchecked_history(&self) -> &[FullEvent] {
+        self.wallet_proof.unchecked_history()
+    }
+
+    /// Checks the proof. Fails with [`VerifyError::RangeNotCovered`] if the balances
+    /// in the proof do not cover the range specified in the query.
+    ///
+    /// [`VerifyError::RangeNotCovered`]: self::VerifyError::RangeNotCovered
+    pub fn check(
+        &self,
+        trust_anchor: &TrustAnchor,
+        query: &BalanceHistoryQuery,
+    ) -> Result<CheckedBalanceHistory, VerifyError> {
+        let checked = self
+            .wallet_proof
+            .check(trust_anchor, &query.wallet_query(self.start))?;
+        let wallet = checked
+            .wallet
+            .ok_or(VerifyError::MissingKey(ProofDescription::Wallet))?;
+        let history = checked.history;
+        if self.start >= self.end || self.end > wallet.history_len() {
+            return Err(VerifyError::RangeNotCovered);
+        }
+        let event_at = |index: u64| &history[(index - self.start) as usize];
+
+        let start_event = event_at(self.start);
+        let start_height = self
+            .start_anchor
+            .check(trust_anchor, &start_event.transaction_hash())?;
+        if self.start > 0
+            && (!occurs_with_transaction(start_event, &query.key) || start_height >= query.since)
+        {
+            return Err(VerifyError::RangeNotCovered);
+        }
+
+        if self.end < wallet.history_len() {
+            let end_anchor = self
+                .end_anchor
+                .as_ref()
+                .ok_or(VerifyError::RangeNotCovered)?;
+            let end_height =
+                end_anchor.check(trust_anchor, &event_at(self.end).transaction_hash())?;
+            if query.until.map_or(true, |until| end_height <= until) {
+                return Err(VerifyError::RangeNotCovered);
+            }
+        } else if query
+            .until
+            .map_or(false, |until| checked.block.height() < until)
+        {
+            return Err(VerifyError::RangeNotCovered);
+        }
+
+        // Restore balances by undoing events from the latest one.
+        let mut balance = wallet.balance();
+        let mut balances = Vec::with_capacity((self.end - self.start) as usize);
+        for index in (self.start..wallet.history_len()).rev() {
+            if index < self.end {
+                balances.push(balance.clone());
+            }
+            if index > self.start {
+                if let Some((amount, credit)) = balance_change(event_at(index), &query.key) {
+                    balance = if credit {
+                        &balance - &amount
+                    } else {
+                        &balance + &amount
+                    };
+                }
+            }
+        }
+        balances.reverse();
+
+        Ok(CheckedBalanceHistory {
+            block: checked.block,
+            wallet,
+            start: self.start,
+            balances,
+        })
+    }
+}
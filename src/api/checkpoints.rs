@@ -0,0 +1,214 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proofs of history checkpoints.
+
+use exonum::{
+    api,
+    blockchain::{Block, Blockchain, Schema as CoreSchema},
+    crypto::{CryptoHash, Hash, PublicKey, Signature},
+    storage::{MapProof, Snapshot},
+};
+
+use super::{FullEvent, ProofDescription, TrustAnchor, VerifyError, WalletProof, WalletQuery};
+use storage::{HistoryCheckpoint, Schema, Wallet};
+use SERVICE_ID;
+
+/// Query for the `checkpoints` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointQuery {
+    /// Public key of the wallet.
+    pub key: PublicKey,
+    /// Index of the first requested checkpoint. A returning client should specify
+    /// the index of the latest checkpoint it has verified before.
+    pub since: u64,
+    /// Hash of a recently committed block. Required together with `signature`
+    /// if the service is configured to [require signed queries].
+    ///
+    /// [require signed queries]: ::Service::require_signed_queries()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<Hash>,
+    /// Signature of the wallet owner, which is the same as for a [`WalletQuery`]
+    /// with `start_history_at` equal to zero.
+    ///
+    /// [`WalletQuery`]: self::WalletQuery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl CheckpointQuery {
+    /// Creates an unsigned query.
+    pub fn new(key: PublicKey, since: u64) -> Self {
+        CheckpointQuery {
+            key,
+            since,
+            challenge: None,
+            signature: None,
+        }
+    }
+
+    /// Converts this query into a query for the embedded wallet proof.
+    fn wallet_query(&self, start_history_at: u64) -> WalletQuery {
+        WalletQuery {
+            challenge: self.challenge,
+            signature: self.signature,
+            ..WalletQuery::new(self.key, start_history_at)
+        }
+    }
+
+    /// Checks that the query is signed by the wallet owner.
+    pub(super) fn authenticate<T: AsRef<dyn Snapshot>>(
+        &self,
+        snapshot: T,
+    ) -> Result<(), api::Error> {
+        self.wallet_query(0).authenticate(snapshot)
+    }
+}
+
+/// Cryptographically authenticated proof of the history checkpoints of a wallet recorded
+/// since a certain checkpoint, together with the history events after the latest checkpoint.
+///
+/// # Theory
+///
+/// Each time the length of a wallet history reaches a multiple of [`CHECKPOINT_INTERVAL`],
+/// the service records a [`HistoryCheckpoint`] with the history length and Merkle root,
+/// and the hash of the previous checkpoint. The latest checkpoint of each wallet is stored
+/// in a table directly committed to by the service state hash. The proof contains
+///
+/// - a [`WalletProof`] for the latest block, which includes all events after
+///   the latest checkpoint
+/// - a `MapProof` from the block state hash to the latest checkpoint of the wallet
+/// - checkpoints starting from the requested one, each of which is connected to the next one
+///   via its hash
+///
+/// A client, which has verified a checkpoint during a previous visit, compares it with
+/// the first checkpoint in the proof. If they match, the history has only been appended to
+/// since the visit, and the client does not need to download and verify events covered
+/// by intermediate checkpoints.
+///
+/// [`CHECKPOINT_INTERVAL`]: ::storage::CHECKPOINT_INTERVAL
+/// [`HistoryCheckpoint`]: ::storage::HistoryCheckpoint
+/// [`WalletProof`]: self::WalletProof
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckpointProof {
+    wallet_proof: WalletProof,
+    checkpoint_table_proof: MapProof<Hash, Hash>,
+    latest_checkpoint_proof: MapProof<PublicKey, HistoryCheckpoint>,
+    checkpoints: Vec<HistoryCheckpoint>,
+}
+
+/// Information about a wallet obtained after checking a `CheckpointProof`.
+#[derive(Debug)]
+pub struct CheckedCheckpointProof {
+    /// Latest block, against which the proof is verified.
+    pub block: Block,
+    /// General information about the wallet.
+    pub wallet: Wallet,
+    /// Checkpoints starting from the requested one, oldest first. The last checkpoint
+    /// is the latest checkpoint of the wallet. The caller should check that the first
+    /// checkpoint matches the one it has verified before.
+    pub checkpoints: Vec<HistoryCheckpoint>,
+    /// Events in the wallet history after the latest checkpoint.
+    pub tail: Vec<FullEvent>,
+}
+
+impl CheckpointProof {
+    /// Creates a new proof based on a given storage snapshot.
+    pub(super) fn new<T: AsRef<dyn Snapshot>>(
+        snapshot: T,
+        query: &CheckpointQuery,
+    ) -> api::Result<Self> {
+        let schema = Schema::new(&snapshot);
+        if !schema.wallets().contains(&query.key) {
+            return Err(api::Error::NotFound("wallet not found".to_owned()));
+        }
+        let history_checkpoints = schema.history_checkpoints(&query.key);
+        if query.since >= history_checkpoints.len() {
+            return Err(api::Error::NotFound("checkpoint not found".to_owned()));
+        }
+        let checkpoints: Vec<_> = history_checkpoints.iter_from(query.since).collect();
+        let tail_start = checkpoints.last().expect("checkpoint").history_len();
+
+        let core_schema = CoreSchema::new(&snapshot);
+        Ok(CheckpointProof {
+            wallet_proof: WalletProof::new(&snapshot, &query.wallet_query(tail_start)),
+            checkpoint_table_proof: core_schema.get_proof_to_service_table(SERVICE_ID, 3),
+            latest_checkpoint_proof: schema.latest_checkpoints().get_proof(query.key),
+            checkpoints,
+        })
+    }
+
+    /// Checks the proof. Fails with [`VerifyError::KeyMismatch`] if the checkpoints
+    /// in the proof do not form a hash chain ending with the latest checkpoint of the wallet.
+    ///
+    /// [`VerifyError::KeyMismatch`]: self::VerifyError::KeyMismatch
+    pub fn check(
+        &self,
+        trust_anchor: &TrustAnchor,
+        query: &CheckpointQuery,
+    ) -> Result<CheckedCheckpointProof, VerifyError> {
+        const DESCRIPTION: ProofDescription = ProofDescription::Checkpoint;
+
+        let latest = self
+            .checkpoints
+            .last()
+            .ok_or(VerifyError::KeyMismatch(DESCRIPTION))?;
+        let checked = self
+            .wallet_proof
+            .check(trust_anchor, &query.wallet_query(latest.history_len()))?;
+        let wallet = checked
+            .wallet
+            .ok_or(VerifyError::MissingKey(ProofDescription::Wallet))?;
+
+        let checkpoints_hash: Option<Hash> = WalletProof::check_map_proof_with_single_key(
+            self.checkpoint_table_proof.clone(),
+            *checked.block.state_hash(),
+            &Blockchain::service_table_unique_key(SERVICE_ID, 3),
+            ProofDescription::CheckpointsTable,
+        )?;
+        let checkpoints_hash =
+            checkpoints_hash.ok_or(VerifyError::MissingKey(ProofDescription::CheckpointsTable))?;
+        let proven_latest: Option<HistoryCheckpoint> =
+            WalletProof::check_map_proof_with_single_key(
+                self.latest_checkpoint_proof.clone(),
+                checkpoints_hash,
+                &query.key,
+                DESCRIPTION,
+            )?;
+        let proven_latest = proven_latest.ok_or(VerifyError::MissingKey(DESCRIPTION))?;
+
+        if proven_latest != *latest || latest.history_len() > wallet.history_len() {
+            return Err(VerifyError::KeyMismatch(DESCRIPTION));
+        }
+        if latest.history_len() == wallet.history_len()
+            && latest.history_hash() != wallet.history_hash()
+        {
+            return Err(VerifyError::KeyMismatch(DESCRIPTION));
+        }
+        for pair in self.checkpoints.windows(2) {
+            if *pair[1].prev_checkpoint() != pair[0].hash()
+                || pair[1].history_len() <= pair[0].history_len()
+            {
+                return Err(VerifyError::KeyMismatch(DESCRIPTION));
+            }
+        }
+
+        Ok(CheckedCheckpointProof {
+            block: checked.block,
+            wallet,
+            checkpoints: self.checkpoints.clone(),
+            tail: checked.history,
+        })
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proofs of wallet inactivity.
+
+use exonum::{
+    api,
+    blockchain::Block,
+    crypto::{Hash, PublicKey, Signature},
+    helpers::Height,
+    storage::Snapshot,
+};
+
+use super::{
+    EventAnchor, FullEvent, ProofDescription, TrustAnchor, VerifyError, WalletProof, WalletQuery,
+};
+use storage::{Schema, Wallet};
+
+/// Query for the `inactivity` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InactivityQuery {
+    /// Public key of the wallet.
+    pub key: PublicKey,
+    /// First height of the range, in which the wallet should have no outgoing payments.
+    /// The range ends with the latest committed block.
+    pub since: Height,
+    /// Hash of a recently committed block. Required together with `signature`
+    /// if the service is configured to [require signed queries].
+    ///
+    /// [require signed queries]: ::Service::require_signed_queries()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<Hash>,
+    /// Signature of the wallet owner, which is the same as for a [`WalletQuery`]
+    /// with `start_history_at` equal to zero.
+    ///
+    /// [`WalletQuery`]: self::WalletQuery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl InactivityQuery {
+    /// Creates an unsigned query.
+    pub fn new(key: PublicKey, since: Height) -> Self {
+        InactivityQuery {
+            key,
+            since,
+            challenge: None,
+            signature: None,
+        }
+    }
+
+    /// Converts this query into a query for the embedded wallet proof.
+    fn wallet_query(&self, last_send_index: u64) -> WalletQuery {
+        WalletQuery {
+            challenge: self.challenge,
+            signature: self.signature,
+            ..WalletQuery::new(self.key, 0)
+        }
+        .with_history_indices(vec![last_send_index])
+    }
+
+    /// Checks that the query is signed by the wallet owner.
+    pub(super) fn authenticate<T: AsRef<dyn Snapshot>>(
+        &self,
+        snapshot: T,
+    ) -> Result<(), api::Error> {
+        self.wallet_query(0).authenticate(snapshot)
+    }
+}
+
+/// Cryptographically authenticated proof that a wallet has made no outgoing payments
+/// since a certain height, e.g., that a cold-storage wallet has stayed cold.
+///
+/// # Theory
+///
+/// Each wallet records the index of the latest event in its history, which has decreased
+/// its balance ([`last_send_index`]). Such events include outgoing transfers, locks, opened
+/// payment streams and disputes against transfers accepted by the wallet. The proof
+/// contains
+///
+/// - a [`WalletProof`] for the latest block, which includes the event at `last_send_index`
+/// - the block header, in which the transaction corresponding to this event was committed,
+///   together with authorizing `Precommit`s
+/// - a proof that the transaction is included into the block
+///
+/// If the block precedes the requested height, the wallet has made no outgoing payments
+/// from this height up to the latest block.
+///
+/// [`last_send_index`]: ::storage::Wallet::last_send_index()
+/// [`WalletProof`]: self::WalletProof
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InactivityProof {
+    last_send_index: u64,
+    wallet_proof: WalletProof,
+    last_send_anchor: EventAnchor,
+}
+
+/// Information about wallet inactivity obtained after checking an `InactivityProof`.
+#[derive(Debug)]
+pub struct CheckedInactivityProof {
+    /// Latest block, which ends the range of inactivity.
+    pub block: Block,
+    /// General information about the wallet.
+    pub wallet: Wallet,
+    /// Latest event decreasing the balance of the wallet.
+    pub last_send: FullEvent,
+    /// Height of the block, in which the transaction corresponding to `last_send`
+    /// was committed.
+    pub last_send_height: Height,
+}
+
+impl InactivityProof {
+    /// Creates a new proof based on a given storage snapshot. Returns `None` if the wallet
+    /// does not exist.
+    pub(super) fn new<T: AsRef<dyn Snapshot>>(
+        snapshot: T,
+        query: &InactivityQuery,
+    ) -> Option<Self> {
+        let last_send_index = Schema::new(&snapshot).wallet(&query.key)?.last_send_index();
+        let wallet_proof = WalletProof::new(&snapshot, &query.wallet_query(last_send_index));
+        let tx_hash = FullEvent::at(&snapshot, &query.key, last_send_index).transaction_hash();
+        let last_send_anchor = EventAnchor::new(&snapshot, &tx_hash);
+
+        Some(InactivityProof {
+            last_send_index,
+            wallet_proof,
+            last_send_anchor,
+        })
+    }
+
+    /// Checks the proof. Fails with [`VerifyError::RecentActivity`] if the wallet has
+    /// made an outgoing payment at or after `query.since`.
+    ///
+    /// [`VerifyError::RecentActivity`]: self::VerifyError::RecentActivity
+    pub fn check(
+        &self,
+        trust_anchor: &TrustAnchor,
+        query: &InactivityQuery,
+    ) -> Result<CheckedInactivityProof, VerifyError> {
+        let checked = self
+            .wallet_proof
+            .check(trust_anchor, &query.wallet_query(self.last_send_index))?;
+        let wallet = checked
+            .wallet
+            .ok_or(VerifyError::MissingKey(ProofDescription::Wallet))?;
+        if wallet.last_send_index() != self.last_send_index || checked.history.len() != 1 {
+            return Err(VerifyError::KeyMismatch(ProofDescription::History));
+        }
+        let last_send = checked.history.into_iter().next().expect("event");
+
+        let last_send_height = self
+            .last_send_anchor
+            .check(trust_anchor, &last_send.transaction_hash())?;
+        if last_send_height >= query.since {
+            return Err(VerifyError::RecentActivity(last_send_height));
+        }
+        Ok(CheckedInactivityProof {
+            block: checked.block,
+            wallet,
+            last_send,
+            last_send_height,
+        })
+    }
+}
@@ -0,0 +1,1372 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! HTTP API for the service.
+
+use byteorder::{ByteOrder, LittleEndian};
+use exonum::{
+    api::{self, ServiceApiState},
+    blockchain::{Block, BlockProof, Blockchain, Schema as CoreSchema, Transaction},
+    crypto::{self as exonum_crypto, CryptoHash, Hash, PublicKey, SecretKey, Signature},
+    helpers::Height,
+    storage::{
+        proof_list_index::ListProofError,
+        proof_map_index::{MapProofError, ProofMapKey},
+        ListProof, MapProof, Snapshot, StorageValue,
+    },
+};
+
+use serde_json::Value;
+
+use std::{collections::HashSet, fmt};
+
+use super::{Config, TransferFilter, SERVICE_ID};
+use secrets::EncryptedData;
+use storage::{
+    maybe_accept_with_note, maybe_back_up_transfer, maybe_bridge_lock, maybe_bridge_release,
+    maybe_close_stream, maybe_create_wallet, maybe_lock, maybe_open_dispute,
+    maybe_request_transfer, maybe_rerandomize, maybe_resolve_dispute, maybe_stream_transfer,
+    maybe_transfer, maybe_unlock, Event, EventTag, Schema, Wallet,
+};
+use transactions::{
+    AcceptWithNote, BackUpTransfer, CloseStream, CreateWallet, CryptoTransactions, Lock,
+    LockForBridge, OpenDispute, ReleaseFromBridge, RequestTransfer, Rerandomize, ResolveDispute,
+    StreamTransfer, Transfer, Unlock,
+};
+
+pub use self::balance_history::{BalanceHistoryProof, BalanceHistoryQuery, CheckedBalanceHistory};
+pub use self::checkpoints::{CheckedCheckpointProof, CheckpointProof, CheckpointQuery};
+pub use self::inactivity::{CheckedInactivityProof, InactivityProof, InactivityQuery};
+pub use self::receipt::{CheckedReceiptProof, ReceiptProof, ReceiptQuery};
+pub use self::rollback_delay::{
+    RollbackDelayQuery, RollbackDelayRecommendation, DEFAULT_ROLLBACK_DELAY,
+};
+pub use self::schema::schema;
+pub use self::supply::{CheckedSupplyProof, SupplyProof, UnsupportedSupplyState};
+pub use utils::{BlockVerifyError, TrustAnchor};
+
+pub(crate) use self::balance_history::balance_change;
+
+mod balance_history;
+mod checkpoints;
+mod inactivity;
+mod receipt;
+mod rollback_delay;
+mod schema;
+mod stream;
+mod supply;
+
+/// HTTP API for the private cryptocurrency service.
+#[derive(Debug)]
+pub enum Api {}
+
+/// Maximum age of a block (measured in block height relative to the latest committed block)
+/// that can be used as a challenge in a signed [`WalletQuery`].
+///
+/// [`WalletQuery`]: self::WalletQuery
+pub const MAX_CHALLENGE_AGE: u64 = 50;
+
+/// Query for the `wallet` endpoint.
+///
+/// # Authentication
+///
+/// If the service is configured to [require signed queries], the query must contain
+/// a `challenge` (hash of a recently committed block) and a `signature` of the query
+/// created with the secret key of the wallet. Such queries can be created with
+/// [`SecretState::wallet_query()`].
+///
+/// [require signed queries]: ::Service::require_signed_queries()
+/// [`SecretState::wallet_query()`]: ::SecretState::wallet_query()
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletQuery {
+    /// Public key of the account to check.
+    pub key: PublicKey,
+    /// The starting index for the user’s list of events.
+    pub start_history_at: u64,
+    /// Hash of a recently committed block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<Hash>,
+    /// Ed25519 signature of the query created with the secret key of the wallet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+    /// Indices of the requested events in the wallet history. If set, `start_history_at`
+    /// is ignored, and the history is proven with a compact proof covering only
+    /// the requested events. Indices are serialized as a comma-separated list.
+    ///
+    /// The indices are not covered by the query signature; they can only narrow
+    /// the information disclosed about the wallet.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "history_indices"
+    )]
+    pub history_indices: Option<Vec<u64>>,
+    /// Tags of the requested events. If set, only the events with one of the specified tags
+    /// are included into the response among the events requested with `start_history_at`
+    /// or `history_indices`. Tags are serialized as a comma-separated list of their
+    /// numeric values.
+    ///
+    /// The proof shows that each returned event belongs to the wallet history and has
+    /// a requested tag, but it cannot show that no matching events are omitted.
+    /// Like `history_indices`, the tags are not covered by the query signature.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "event_tags")]
+    pub event_tags: Option<Vec<EventTag>>,
+}
+
+/// Query for the `settings` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsQuery {
+    /// Public key of the wallet.
+    pub key: PublicKey,
+}
+
+/// Settings of a wallet recorded with [`UpdateSettings`] transactions.
+///
+/// [`UpdateSettings`]: ::transactions::UpdateSettings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletSettings {
+    /// Minimum rollback delay of incoming transfers preferred by the wallet owner;
+    /// zero if there is no preference.
+    pub min_rollback_delay: u32,
+}
+
+/// Query for the `unaccepted_filter` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferFilterQuery {
+    /// Public key of the wallet.
+    pub key: PublicKey,
+}
+
+/// Query for the `transfer_request` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferRequestQuery {
+    /// Hash of the `RequestTransfer` transaction.
+    pub id: Hash,
+}
+
+/// Query for the `transfer_backup` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferBackupQuery {
+    /// Hash of the backed up transfer.
+    pub id: Hash,
+}
+
+/// Status of a transfer requested with a [`RequestTransfer`] transaction.
+///
+/// [`RequestTransfer`]: ::transactions::RequestTransfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TransferRequestStatus {
+    /// The request is not committed, or its execution has failed.
+    Unknown,
+    /// The request has not been paid yet.
+    Pending,
+    /// The request has been paid with a transfer.
+    Paid {
+        /// Hash of the transfer paying the request.
+        transfer_id: Hash,
+        /// Whether the transfer has been accepted by the requester.
+        accepted: bool,
+    },
+}
+
+/// Serialization of `WalletQuery::history_indices` compatible with query strings.
+mod history_indices {
+    use serde::{de::Error as DeError, Deserialize, Deserializer, Serializer};
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::trivially_copy_pass_by_ref))]
+    pub fn serialize<S: Serializer>(
+        indices: &Option<Vec<u64>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let indices = indices.as_ref().expect("skipped if `None`");
+        let indices: Vec<_> = indices.iter().map(ToString::to_string).collect();
+        serializer.serialize_str(&indices.join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u64>>, D::Error> {
+        let indices = match Option::<String>::deserialize(deserializer)? {
+            Some(indices) => indices,
+            None => return Ok(None),
+        };
+        if indices.is_empty() {
+            return Ok(Some(vec![]));
+        }
+        indices
+            .split(',')
+            .map(|index| index.parse().map_err(D::Error::custom))
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+mod event_tags {
+    use serde::{de::Error as DeError, Deserialize, Deserializer, Serializer};
+
+    use storage::EventTag;
+
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::trivially_copy_pass_by_ref))]
+    pub fn serialize<S: Serializer>(
+        tags: &Option<Vec<EventTag>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let tags = tags.as_ref().expect("skipped if `None`");
+        let tags: Vec<_> = tags.iter().map(|&tag| (tag as u8).to_string()).collect();
+        serializer.serialize_str(&tags.join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<EventTag>>, D::Error> {
+        let tags = match Option::<String>::deserialize(deserializer)? {
+            Some(tags) => tags,
+            None => return Ok(None),
+        };
+        if tags.is_empty() {
+            return Ok(Some(vec![]));
+        }
+        tags.split(',')
+            .map(|tag| {
+                let tag: u8 = tag.parse().map_err(D::Error::custom)?;
+                EventTag::from_u8(tag)
+                    .ok_or_else(|| D::Error::custom(format!("unknown event tag: {}", tag)))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+impl WalletQuery {
+    /// Domain separator for signed queries.
+    const DOMAIN_SEPARATOR: &'static [u8] = b"exonum.private_currency.wallet_query";
+
+    /// Creates an unsigned query.
+    pub fn new(key: PublicKey, start_history_at: u64) -> Self {
+        WalletQuery {
+            key,
+            start_history_at,
+            challenge: None,
+            signature: None,
+            history_indices: None,
+            event_tags: None,
+        }
+    }
+
+    /// Requests only the events with the specified indices in the wallet history.
+    /// See [`history_indices`](#structfield.history_indices) for details.
+    pub fn with_history_indices<I>(mut self, indices: I) -> Self
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        self.history_indices = Some(indices.into_iter().collect());
+        self
+    }
+
+    /// Requests only the events with the specified tags.
+    /// See [`event_tags`](#structfield.event_tags) for details.
+    pub fn with_event_tags<I>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = EventTag>,
+    {
+        self.event_tags = Some(tags.into_iter().collect());
+        self
+    }
+
+    /// Checks whether an event with the specified numeric tag passes the tag filter
+    /// of the query.
+    fn matches_tag(&self, tag: u8) -> bool {
+        self.event_tags
+            .as_ref()
+            .map_or(true, |tags| tags.iter().any(|&t| t as u8 == tag))
+    }
+
+    /// Returns the indices of history events, which should be included into a response
+    /// given the history length. The tag filter is not applied.
+    fn requested_history(&self, history_len: u64) -> Vec<u64> {
+        match self.history_indices {
+            Some(ref indices) => {
+                let mut indices: Vec<_> = indices
+                    .iter()
+                    .cloned()
+                    .filter(|&index| index < history_len)
+                    .collect();
+                indices.sort();
+                indices.dedup();
+                indices
+            }
+            None => (self.start_history_at..history_len).collect(),
+        }
+    }
+
+    /// Creates a query signed with the specified secret key.
+    pub(crate) fn signed(
+        key: PublicKey,
+        start_history_at: u64,
+        challenge: Hash,
+        secret_key: &SecretKey,
+    ) -> Self {
+        let message = Self::message_to_sign(&key, start_history_at, &challenge);
+        WalletQuery {
+            key,
+            start_history_at,
+            challenge: Some(challenge),
+            signature: Some(exonum_crypto::sign(&message, secret_key)),
+            history_indices: None,
+            event_tags: None,
+        }
+    }
+
+    /// Serializes query data covered by the signature.
+    fn message_to_sign(key: &PublicKey, start_history_at: u64, challenge: &Hash) -> Vec<u8> {
+        let mut message = Self::DOMAIN_SEPARATOR.to_vec();
+        message.extend_from_slice(key.as_ref());
+        let mut start_bytes = [0_u8; 8];
+        LittleEndian::write_u64(&mut start_bytes, start_history_at);
+        message.extend_from_slice(&start_bytes);
+        message.extend_from_slice(challenge.as_ref());
+        message
+    }
+
+    /// Checks that the query is signed by the wallet key and that its challenge
+    /// references a recent block.
+    fn authenticate<T: AsRef<dyn Snapshot>>(&self, snapshot: T) -> Result<(), api::Error> {
+        let (challenge, signature) = match (self.challenge, self.signature) {
+            (Some(challenge), Some(signature)) => (challenge, signature),
+            _ => return Err(api::Error::Unauthorized),
+        };
+
+        let message = Self::message_to_sign(&self.key, self.start_history_at, &challenge);
+        if !exonum_crypto::verify(&signature, &message, &self.key) {
+            return Err(api::Error::Unauthorized);
+        }
+
+        let core_schema = CoreSchema::new(snapshot);
+        let challenge_height = core_schema
+            .blocks()
+            .get(&challenge)
+            .ok_or(api::Error::Unauthorized)?
+            .height();
+        if core_schema.height().0 > challenge_height.0 + MAX_CHALLENGE_AGE {
+            return Err(api::Error::Unauthorized);
+        }
+        Ok(())
+    }
+}
+
+/// Event changing balance of a wallet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "kebab-case")]
+pub enum FullEvent {
+    /// Event corresponding to wallet creation. There is only one such event in wallet history -
+    /// the very first one.
+    CreateWallet(CreateWallet),
+
+    /// Transfer to or from the wallet.
+    ///
+    /// Note that outgoing transfers are recorded in the sender’s history immediately after
+    /// the commitment. The incoming transfers, on the other hand, need to be [`Accept`]ed.
+    ///
+    /// [`Accept`]: ::transactions::Accept
+    Transfer(Transfer),
+
+    /// Rolled-back transfer returning the funds to the sender.
+    Rollback(Transfer),
+
+    /// Funds locked by the wallet owner to be released in another deployment of the service.
+    /// See the [`bridge`](::bridge) module for details.
+    BridgeLock(LockForBridge),
+
+    /// Funds released to the wallet after being locked in another deployment of the service.
+    /// The event references the relayer vote that has completed the release.
+    BridgeRelease(ReleaseFromBridge),
+
+    /// Acknowledgment of an outgoing transfer by its receiver. The acknowledgment is recorded
+    /// in the sender’s history once the transfer is accepted; it does not change the balance.
+    /// The note can be decrypted with [`SecretState::read_note()`].
+    ///
+    /// [`SecretState::read_note()`]: ::SecretState::read_note()
+    Acknowledgment(AcceptWithNote),
+
+    /// Funds of an accepted incoming transfer moved into the disputed escrow after
+    /// the sender of the transfer has opened a dispute. See the [`dispute`](::dispute) module
+    /// for details.
+    DisputeOpened(OpenDispute),
+
+    /// Funds released from the disputed escrow to the wallet by the arbiter: either refunded
+    /// to the sender of the disputed transfer, or returned to its receiver.
+    DisputeResolved(ResolveDispute),
+
+    /// Funds locked by the wallet owner for a payment stream.
+    StreamOpened(StreamTransfer),
+
+    /// Payment of an incoming stream. The event references the stream; the paid amount
+    /// is the [`rate`] of the stream.
+    ///
+    /// [`rate`]: ::transactions::StreamTransfer::rate()
+    StreamPayment(StreamTransfer),
+
+    /// Cancelled payments of an outgoing stream refunded to the wallet after the stream
+    /// has been closed early.
+    StreamClosed(CloseStream),
+
+    /// Funds moved from the wallet balance to the locked balance.
+    Lock(Lock),
+
+    /// Locked funds returned to the wallet balance.
+    Unlock(Unlock),
+
+    /// Transfer requested from the wallet. The event does not change the balance;
+    /// the wallet owner may answer it with [`SecretState::pay_transfer_request()`].
+    ///
+    /// [`SecretState::pay_transfer_request()`]: ::SecretState::pay_transfer_request()
+    TransferRequest(RequestTransfer),
+
+    /// Balance commitment replaced with a fresh commitment to the same value.
+    /// The opening of the added blinding can be decrypted by the wallet owner.
+    Rerandomization(Rerandomize),
+}
+
+impl FullEvent {
+    /// Converts `Event` into its full form by loading the transaction data
+    /// from the provided snapshot.
+    fn from<T: AsRef<dyn Snapshot>>(event: &Event, snapshot: T) -> Self {
+        let id = event.transaction_hash();
+        match event.tag() {
+            tag if tag == EventTag::CreateWallet as u8 => {
+                FullEvent::CreateWallet(maybe_create_wallet(snapshot, id).expect("CreateWallet"))
+            }
+            tag if tag == EventTag::Transfer as u8 => {
+                FullEvent::Transfer(maybe_transfer(snapshot, id).expect("Transfer"))
+            }
+            tag if tag == EventTag::Rollback as u8 => {
+                FullEvent::Rollback(maybe_transfer(snapshot, id).expect("Transfer"))
+            }
+            tag if tag == EventTag::BridgeLock as u8 => {
+                FullEvent::BridgeLock(maybe_bridge_lock(snapshot, id).expect("LockForBridge"))
+            }
+            tag if tag == EventTag::BridgeRelease as u8 => FullEvent::BridgeRelease(
+                maybe_bridge_release(snapshot, id).expect("ReleaseFromBridge"),
+            ),
+            tag if tag == EventTag::Acknowledgment as u8 => FullEvent::Acknowledgment(
+                maybe_accept_with_note(snapshot, id).expect("AcceptWithNote"),
+            ),
+            tag if tag == EventTag::DisputeOpened as u8 => {
+                FullEvent::DisputeOpened(maybe_open_dispute(snapshot, id).expect("OpenDispute"))
+            }
+            tag if tag == EventTag::DisputeResolved as u8 => FullEvent::DisputeResolved(
+                maybe_resolve_dispute(snapshot, id).expect("ResolveDispute"),
+            ),
+            tag if tag == EventTag::StreamOpened as u8 => FullEvent::StreamOpened(
+                maybe_stream_transfer(snapshot, id).expect("StreamTransfer"),
+            ),
+            tag if tag == EventTag::StreamPayment as u8 => FullEvent::StreamPayment(
+                maybe_stream_transfer(snapshot, id).expect("StreamTransfer"),
+            ),
+            tag if tag == EventTag::StreamClosed as u8 => {
+                FullEvent::StreamClosed(maybe_close_stream(snapshot, id).expect("CloseStream"))
+            }
+            tag if tag == EventTag::Lock as u8 => {
+                FullEvent::Lock(maybe_lock(snapshot, id).expect("Lock"))
+            }
+            tag if tag == EventTag::Unlock as u8 => {
+                FullEvent::Unlock(maybe_unlock(snapshot, id).expect("Unlock"))
+            }
+            tag if tag == EventTag::TransferRequest as u8 => FullEvent::TransferRequest(
+                maybe_request_transfer(snapshot, id).expect("RequestTransfer"),
+            ),
+            tag if tag == EventTag::Rerandomization as u8 => {
+                FullEvent::Rerandomization(maybe_rerandomize(snapshot, id).expect("Rerandomize"))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Loads an event with the specified index from the wallet history.
+    pub(crate) fn at<T: AsRef<dyn Snapshot>>(snapshot: T, key: &PublicKey, index: u64) -> Self {
+        let event = Schema::new(&snapshot)
+            .history_index(key)
+            .get(index)
+            .expect("event");
+        FullEvent::from(&event, &snapshot)
+    }
+
+    fn tag(&self) -> EventTag {
+        match self {
+            FullEvent::CreateWallet(..) => EventTag::CreateWallet,
+            FullEvent::Transfer(..) => EventTag::Transfer,
+            FullEvent::Rollback(..) => EventTag::Rollback,
+            FullEvent::BridgeLock(..) => EventTag::BridgeLock,
+            FullEvent::BridgeRelease(..) => EventTag::BridgeRelease,
+            FullEvent::Acknowledgment(..) => EventTag::Acknowledgment,
+            FullEvent::DisputeOpened(..) => EventTag::DisputeOpened,
+            FullEvent::DisputeResolved(..) => EventTag::DisputeResolved,
+            FullEvent::StreamOpened(..) => EventTag::StreamOpened,
+            FullEvent::StreamPayment(..) => EventTag::StreamPayment,
+            FullEvent::StreamClosed(..) => EventTag::StreamClosed,
+            FullEvent::Lock(..) => EventTag::Lock,
+            FullEvent::Unlock(..) => EventTag::Unlock,
+            FullEvent::TransferRequest(..) => EventTag::TransferRequest,
+            FullEvent::Rerandomization(..) => EventTag::Rerandomization,
+        }
+    }
+
+    /// Does this event correspond to a given storage-form event?
+    fn corresponds_to(&self, event: &Event) -> bool {
+        if self.tag() as u8 != event.tag() {
+            return false;
+        }
+
+        self.transaction_hash() == *event.transaction_hash()
+    }
+
+    /// Returns the hash of the transaction that has caused this event.
+    pub fn transaction_hash(&self) -> Hash {
+        match self {
+            FullEvent::CreateWallet(tx) => tx.hash(),
+            FullEvent::Transfer(tx) => tx.hash(),
+            FullEvent::Rollback(tx) => tx.hash(),
+            FullEvent::BridgeLock(tx) => tx.hash(),
+            FullEvent::BridgeRelease(tx) => tx.hash(),
+            FullEvent::Acknowledgment(tx) => tx.hash(),
+            FullEvent::DisputeOpened(tx) => tx.hash(),
+            FullEvent::DisputeResolved(tx) => tx.hash(),
+            FullEvent::StreamOpened(tx) => tx.hash(),
+            FullEvent::StreamPayment(tx) => tx.hash(),
+            FullEvent::StreamClosed(tx) => tx.hash(),
+            FullEvent::Lock(tx) => tx.hash(),
+            FullEvent::Unlock(tx) => tx.hash(),
+            FullEvent::TransferRequest(tx) => tx.hash(),
+            FullEvent::Rerandomization(tx) => tx.hash(),
+        }
+    }
+}
+
+/// Cryptographically authenticated proof of the state for a single wallet.
+///
+/// The proof contains several parts:
+///
+/// - Block header together with authorizing `Precommit`s
+/// - Proof connecting the block header with the wallets table in the storage,
+///   and then with a particular wallet.
+/// - Proof connecting the block header with the profiles table, and then with the encrypted
+///   profile of the wallet (or its absence). If profiles are not activated yet, the proof
+///   shows that the profiles table is absent from the state.
+/// - Information about new events in the wallet history and unaccepted incoming transfers,
+///   if any.
+///
+/// # Proof of absence
+///
+/// The proof can also be used to prove the absence of a wallet. In this case, the last part
+/// of the proof (history and unaccepted transfers) is empty.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletProof {
+    block_proof: BlockProof,
+    wallet_table_proof: MapProof<Hash, Hash>,
+    wallet_proof: MapProof<PublicKey, Wallet>,
+    profile_table_proof: MapProof<Hash, Hash>,
+    profile_proof: MapProof<PublicKey, EncryptedData>,
+    #[serde(flatten)]
+    wallet_contents: Option<WalletContentsProof>,
+}
+
+/// Information about wallet state useful for a client, obtained after checking a `WalletProof`.
+#[derive(Debug)]
+pub struct CheckedWalletProof {
+    /// Block information.
+    pub block: Block,
+
+    /// General information about the wallet.
+    pub wallet: Option<Wallet>,
+
+    /// Encrypted profile of the wallet, which can be decrypted with
+    /// [`SecretState::profile()`] or [`WatchWallet::profile()`]. `None` if the profile
+    /// has not been set.
+    ///
+    /// [`SecretState::profile()`]: ::SecretState::profile()
+    /// [`WatchWallet::profile()`]: ::WatchWallet::profile()
+    pub profile: Option<EncryptedData>,
+
+    /// New events concerning the wallet. The event with index `0` corresponds to an event
+    /// at index `query.start_history_at` in the wallet history, and so on.
+    ///
+    /// If the query specifies [`history_indices`], the events correspond to the requested
+    /// indices less than the history length, in the ascending order. If the query specifies
+    /// [`event_tags`], only the requested events with matching tags are included,
+    /// in the ascending order of their indices.
+    ///
+    /// If [`wallet`](#structfield.wallet) is `None`, the `history` is empty.
+    ///
+    /// [`history_indices`]: self::WalletQuery::history_indices
+    /// [`event_tags`]: self::WalletQuery::event_tags
+    pub history: Vec<FullEvent>,
+
+    /// Unaccepted incoming transfers for the wallet.
+    ///
+    /// If [`wallet`](#structfield.wallet) is `None`, the `unaccepted_transfers` vector is empty.
+    pub unaccepted_transfers: Vec<Transfer>,
+}
+
+/// Part of a `WalletProof` related to auxiliary tables (wallet history and unaccepted transfers).
+// This struct is inlined into the parent, so it’s not public.
+#[derive(Debug, Serialize, Deserialize)]
+struct WalletContentsProof {
+    history: Vec<FullEvent>,
+    unaccepted_transfers: Vec<Transfer>,
+    history_proof: Option<ListProof<Event>>,
+    unaccepted_transfers_proof: MapProof<Hash, ()>,
+}
+
+/// Error during `WalletProof` verification.
+#[derive(Debug, Fail)]
+pub enum VerifyError {
+    /// Error verifying block header.
+    #[fail(display = "block verification failed: {}", _0)]
+    Block(#[fail(cause)] BlockVerifyError),
+
+    /// Error verifying one of `MapProof`s included into the wallet proof.
+    #[fail(
+        display = "verifying `MapProof` for {} failed: {}",
+        proof_description, error
+    )]
+    MapProof {
+        /// Cause of the verification failure.
+        #[fail(cause)]
+        error: MapProofError,
+        /// Description of the proof where an error has occurred.
+        proof_description: ProofDescription,
+    },
+
+    /// Error verifying one of `ListProof`s included into the wallet proof.
+    #[fail(
+        display = "verifying `ListProof` for {} failed: {:?}",
+        proof_description, error
+    )]
+    ListProof {
+        /// Cause of the verification failure.
+        error: ListProofError,
+        /// Description of the proof where an error has occurred.
+        proof_description: ProofDescription,
+    },
+
+    /// A `ListProof` or `MapProof` is disconnected from its parent. In other words, the root hash
+    /// of the index restored from the proof does not match one obtained from other proof data.
+    #[fail(display = "Merkle proof for {} is disconnected from parent", _0)]
+    ProofDisconnect(ProofDescription),
+
+    /// A `ListProof` or `MapProof` does not prove presence or absence of a key,
+    /// which it is expected to prove.
+    #[fail(display = "Merkle proof for {} misses expected key", _0)]
+    MissingKey(ProofDescription),
+
+    /// A Merkle proof proves existence of keys that do not match the plain data included into
+    /// to the proof.
+    ///
+    /// For example, this error could occur if the proof mentions 3 new events in wallet history,
+    /// but the corresponding `ListProof` includes only 2 of these events.
+    #[fail(display = "Merkle proof and entries for {} do not match", _0)]
+    KeyMismatch(ProofDescription),
+
+    /// The proof shows existence of the requested wallet, but the events and unaccepted transfers
+    /// are missing from the proof.
+    #[fail(display = "missing wallet contents")]
+    NoContents,
+
+    /// The sum of commitments to wallet balances and pending transfers does not correspond
+    /// to the total supply of tokens.
+    #[fail(display = "committed balances do not match total supply")]
+    SupplyMismatch,
+
+    /// The token supply cannot be proven in the state of the service.
+    #[fail(display = "supply cannot be proven: {}", _0)]
+    UnsupportedSupply(UnsupportedSupplyState),
+
+    /// The wallet has made an outgoing payment at the specified height, which is within
+    /// the range of an [`InactivityQuery`](self::InactivityQuery).
+    #[fail(display = "wallet has made an outgoing payment at height {}", _0)]
+    RecentActivity(Height),
+
+    /// Balances proven by a [`BalanceHistoryProof`](self::BalanceHistoryProof) do not cover
+    /// the requested range of heights.
+    #[fail(display = "proven balances do not cover the requested range")]
+    RangeNotCovered,
+}
+
+/// Description of a part of a `WalletProof`.
+///
+/// Used in [`VerifyError`](VerifyError).
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum ProofDescription {
+    /// `MapProof` from the `state_hash` mentioned in the block header, to the wallets table.
+    WalletsTable,
+    /// `MapProof` from the wallets table to a specific wallet.
+    Wallet,
+    /// `ListProof` for wallet history.
+    History,
+    /// `MapProof` for unaccepted transfers.
+    UnacceptedTransfers,
+    /// `MapProof` from the `state_hash` mentioned in the block header, to the profiles table.
+    ProfilesTable,
+    /// `MapProof` from the profiles table to the profile of a specific wallet.
+    Profile,
+    /// `MapProof` from the `state_hash` mentioned in the block header, to the receipt
+    /// roots table.
+    ReceiptsTable,
+    /// `MapProof` from the receipt roots table to the receipts of a specific wallet.
+    Receipts,
+    /// `MapProof` from the receipts of a wallet to a specific receipt.
+    Receipt,
+    /// `MapProof` from the `state_hash` mentioned in the block header, to the table
+    /// of latest history checkpoints.
+    CheckpointsTable,
+    /// History checkpoints of a specific wallet.
+    Checkpoint,
+    /// `ListProof` for transactions in a block.
+    BlockTransactions,
+}
+
+impl fmt::Display for ProofDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ProofDescription::*;
+
+        match self {
+            WalletsTable => f.write_str("wallets table"),
+            Wallet => f.write_str("wallet"),
+            History => f.write_str("history"),
+            UnacceptedTransfers => f.write_str("unaccepted transfers"),
+            ProfilesTable => f.write_str("profiles table"),
+            Profile => f.write_str("profile"),
+            ReceiptsTable => f.write_str("receipt roots table"),
+            Receipts => f.write_str("receipts"),
+            Receipt => f.write_str("receipt"),
+            CheckpointsTable => f.write_str("checkpoints table"),
+            Checkpoint => f.write_str("history checkpoints"),
+            BlockTransactions => f.write_str("block transactions"),
+        }
+    }
+}
+
+impl From<BlockVerifyError> for VerifyError {
+    fn from(e: BlockVerifyError) -> Self {
+        VerifyError::Block(e)
+    }
+}
+
+impl WalletProof {
+    /// Creates a new proof based on a given storage snapshot.
+    fn new<T: AsRef<dyn Snapshot>>(snapshot: T, query: &WalletQuery) -> Self {
+        let (mut proof, history_indices) = Self::without_history(&snapshot, query);
+        if let Some(ref mut contents) = proof.wallet_contents {
+            contents.history = history_indices
+                .into_iter()
+                .map(|index| FullEvent::at(&snapshot, &query.key, index))
+                .collect();
+        }
+        proof
+    }
+
+    /// Creates a new proof without events in the wallet history. Returns the proof together
+    /// with indices of history events that need to be added to it.
+    fn without_history<T: AsRef<dyn Snapshot>>(
+        snapshot: T,
+        query: &WalletQuery,
+    ) -> (Self, Vec<u64>) {
+        let core_schema = CoreSchema::new(&snapshot);
+        let block_proof = core_schema
+            .block_and_precommits(core_schema.height())
+            .expect("BlockProof");
+        let wallet_table_proof = core_schema.get_proof_to_service_table(SERVICE_ID, 0);
+        let profile_table_proof = core_schema.get_proof_to_service_table(SERVICE_ID, 1);
+
+        let schema = Schema::new(&snapshot);
+        let wallets = schema.wallets();
+
+        let (wallet_contents, history_indices) = if wallets.contains(&query.key) {
+            let (contents, indices) = WalletContentsProof::without_history(&snapshot, query);
+            (Some(contents), indices)
+        } else {
+            (None, vec![])
+        };
+        let proof = WalletProof {
+            block_proof,
+            wallet_table_proof,
+            wallet_proof: wallets.get_proof(query.key),
+            profile_table_proof,
+            profile_proof: schema.profiles().get_proof(query.key),
+            wallet_contents,
+        };
+        (proof, history_indices)
+    }
+
+    /// Checks if a `MapProof` contains a specified key.
+    ///
+    /// # Return value
+    ///
+    /// - If the proof is correct and contains the key, the method returns `Ok(Some(_))`.
+    /// - If the proof (correctly) proves absence of the key, the method returns `Ok(None)`.
+    /// - Otherwise, we return an `Err(_)`.
+    fn check_map_proof_with_single_key<K, V>(
+        proof: MapProof<K, V>,
+        expected_hash: Hash,
+        key: &K,
+        proof_description: ProofDescription,
+    ) -> Result<Option<V>, VerifyError>
+    where
+        K: ProofMapKey + Eq,
+        V: StorageValue + Clone,
+    {
+        let checked = proof.check().map_err(|error| VerifyError::MapProof {
+            error,
+            proof_description,
+        })?;
+        if checked.merkle_root() != expected_hash {
+            return Err(VerifyError::ProofDisconnect(proof_description));
+        }
+        let (_, value) = checked
+            .all_entries()
+            .into_iter()
+            .find(|&(k, _)| k == key)
+            .ok_or_else(|| VerifyError::MissingKey(proof_description))?;
+        Ok(value.cloned())
+    }
+
+    /// Checks the proof, returning information contained in the proof that might be
+    /// interesting to client applications.
+    pub fn check(
+        &self,
+        trust_anchor: &TrustAnchor,
+        query: &WalletQuery,
+    ) -> Result<CheckedWalletProof, VerifyError> {
+        // First, verify the block proof.
+        trust_anchor.verify_block_proof(&self.block_proof)?;
+        self.check_contents(query)
+    }
+
+    /// Checks the proof using anchors in the `trust_anchor` instead of validator signatures.
+    ///
+    /// `subsequent_blocks` are block headers connecting the block referenced in the proof
+    /// to an anchored block. See [`TrustAnchor::verify_anchored_block()`] for details.
+    ///
+    /// [`TrustAnchor::verify_anchored_block()`]: self::TrustAnchor::verify_anchored_block()
+    pub fn check_anchored(
+        &self,
+        trust_anchor: &TrustAnchor,
+        query: &WalletQuery,
+        subsequent_blocks: &[Block],
+    ) -> Result<CheckedWalletProof, VerifyError> {
+        trust_anchor.verify_anchored_block(&self.block_proof.block, subsequent_blocks)?;
+        self.check_contents(query)
+    }
+
+    /// Checks the part of the proof following the block header.
+    /// Returns wallet history events included into the proof. The events are not verified.
+    pub(crate) fn unchecked_history(&self) -> &[FullEvent] {
+        self.wallet_contents
+            .as_ref()
+            .map_or(&[][..], |contents| &contents.history[..])
+    }
+
+    fn check_contents(&self, query: &WalletQuery) -> Result<CheckedWalletProof, VerifyError> {
+        // Verify proof for wallets table.
+        let wallets_hash: Option<Hash> = Self::check_map_proof_with_single_key(
+            self.wallet_table_proof.clone(),
+            *self.block_proof.block.state_hash(),
+            &Blockchain::service_table_unique_key(SERVICE_ID, 0),
+            ProofDescription::WalletsTable,
+        )?;
+        // The key corresponding to the wallets table cannot be missing.
+        let wallets_hash =
+            wallets_hash.ok_or(VerifyError::MissingKey(ProofDescription::WalletsTable))?;
+
+        // Verify proof for the wallet.
+        let wallet: Option<Wallet> = Self::check_map_proof_with_single_key(
+            self.wallet_proof.clone(),
+            wallets_hash,
+            &query.key,
+            ProofDescription::Wallet,
+        )?;
+
+        // Verify proofs for the profiles table and the wallet profile.
+        let profiles_hash: Option<Hash> = Self::check_map_proof_with_single_key(
+            self.profile_table_proof.clone(),
+            *self.block_proof.block.state_hash(),
+            &Blockchain::service_table_unique_key(SERVICE_ID, 1),
+            ProofDescription::ProfilesTable,
+        )?;
+        // The profiles table is committed to only after the wire version introducing
+        // profiles is activated; before that, no wallet can have a profile.
+        let profile: Option<EncryptedData> = match profiles_hash {
+            Some(profiles_hash) => Self::check_map_proof_with_single_key(
+                self.profile_proof.clone(),
+                profiles_hash,
+                &query.key,
+                ProofDescription::Profile,
+            )?,
+            None => None,
+        };
+
+        if let Some(ref wallet) = wallet {
+            if let Some(ref wallet_contents) = self.wallet_contents {
+                let (history, unaccepted_transfers) = wallet_contents.check(wallet, query)?;
+                Ok(CheckedWalletProof {
+                    block: self.block_proof.block.clone(),
+                    wallet: Some(wallet.clone()),
+                    profile,
+                    history,
+                    unaccepted_transfers,
+                })
+            } else {
+                return Err(VerifyError::NoContents);
+            }
+        } else {
+            // No wallet.
+            Ok(CheckedWalletProof {
+                block: self.block_proof.block.clone(),
+                wallet: None,
+                profile,
+                history: vec![],
+                unaccepted_transfers: vec![],
+            })
+        }
+    }
+}
+
+impl WalletContentsProof {
+    /// Creates a new proof based on a given storage snapshot. History events are not loaded;
+    /// instead, their indices are returned together with the proof.
+    fn without_history<T: AsRef<dyn Snapshot>>(
+        snapshot: T,
+        query: &WalletQuery,
+    ) -> (Self, Vec<u64>) {
+        let schema = Schema::new(&snapshot);
+
+        // Get indices of requested events in wallet history...
+        let history_index = schema.history_index(&query.key);
+        let mut indices = query.requested_history(history_index.len());
+        if query.event_tags.is_some() {
+            indices
+                .retain(|&index| query.matches_tag(history_index.get(index).expect("event").tag()));
+        }
+        // ...and the corresponding proof.
+        let history_proof = match (indices.first(), indices.last()) {
+            (None, _) | (_, None) => None,
+            (Some(&first), Some(&last)) if last - first + 1 == indices.len() as u64 => {
+                Some(history_index.get_range_proof(first, last + 1))
+            }
+            (Some(&first), Some(&last)) => {
+                // Merge proofs for individual events; if this fails, fall back
+                // to the proof for the entire range.
+                let mut multiproof = Some(history_index.get_proof(first));
+                for &index in &indices[1..] {
+                    multiproof = multiproof
+                        .and_then(|proof| merge_list_proofs(proof, history_index.get_proof(index)));
+                }
+                Some(multiproof.unwrap_or_else(|| history_index.get_range_proof(first, last + 1)))
+            }
+        };
+
+        // Get hashes of unaccepted transfers.
+        let unaccepted_transfers: Vec<_> = schema
+            .unaccepted_transfers(&query.key)
+            .into_iter()
+            .collect();
+        // ...and the corresponding proof.
+        let unaccepted_transfers_proof = schema
+            .unaccepted_transfers_index(&query.key)
+            .get_multiproof(unaccepted_transfers.iter().cloned());
+        let unaccepted_transfers: Vec<_> = unaccepted_transfers
+            .into_iter()
+            .map(|hash| maybe_transfer(&snapshot, &hash).expect("Transfer"))
+            .collect();
+
+        let proof = WalletContentsProof {
+            history: vec![],
+            history_proof,
+            unaccepted_transfers,
+            unaccepted_transfers_proof,
+        };
+        (proof, indices)
+    }
+
+    /// Checks the proof.
+    ///
+    /// # Return value
+    ///
+    /// Vectors with new events in wallet history and unaccepted incoming transfers.
+    fn check(
+        &self,
+        wallet: &Wallet,
+        query: &WalletQuery,
+    ) -> Result<(Vec<FullEvent>, Vec<Transfer>), VerifyError> {
+        // Verify wallet history.
+        let proof_description = ProofDescription::History;
+        let history_proof = self.history_proof.as_ref();
+        let tx_hashes = if let Some(proof) = history_proof {
+            proof
+                .validate(*wallet.history_hash(), wallet.history_len())
+                .map_err(|error| VerifyError::ListProof {
+                    error,
+                    proof_description,
+                })?
+        } else {
+            vec![]
+        };
+
+        // The proof may cover more events than requested if it could not be compacted.
+        let expected_indices = query.requested_history(wallet.history_len());
+        let tx_hashes: Vec<_> = tx_hashes
+            .into_iter()
+            .filter(|&(index, event)| {
+                expected_indices.binary_search(&index).is_ok() && query.matches_tag(event.tag())
+            })
+            .collect();
+        // With a tag filter, indices of matching events are not known in advance.
+        if (query.event_tags.is_none() && tx_hashes.len() != expected_indices.len())
+            || tx_hashes.len() != self.history.len()
+        {
+            return Err(VerifyError::KeyMismatch(proof_description));
+        }
+        let stored_events = tx_hashes.into_iter().map(|(_, stored_event)| stored_event);
+        for (stored_event, event) in stored_events.zip(&self.history) {
+            if !event.corresponds_to(stored_event) {
+                return Err(VerifyError::KeyMismatch(proof_description));
+            }
+        }
+
+        // Verify unaccepted transfers.
+        let proof_description = ProofDescription::UnacceptedTransfers;
+        let transfer_hashes: HashSet<_> = self
+            .unaccepted_transfers
+            .iter()
+            .map(|tx| tx.hash())
+            .collect();
+
+        let checked = self
+            .unaccepted_transfers_proof
+            .clone()
+            .check()
+            .map_err(|error| VerifyError::MapProof {
+                error,
+                proof_description,
+            })?;
+        if checked.merkle_root() != *wallet.unaccepted_transfers_hash() {
+            return Err(VerifyError::ProofDisconnect(proof_description));
+        }
+
+        let hashes_in_proof: HashSet<_> = checked
+            .entries()
+            .into_iter()
+            .map(|(&hash, _)| hash)
+            .collect();
+        if transfer_hashes != hashes_in_proof {
+            return Err(VerifyError::KeyMismatch(proof_description));
+        }
+
+        Ok((self.history.clone(), self.unaccepted_transfers.clone()))
+    }
+}
+
+/// Merges two proofs for the same list into a proof covering the entries of both.
+/// Returns `None` if the proofs have incompatible structure.
+fn merge_list_proofs<V>(first: ListProof<V>, second: ListProof<V>) -> Option<ListProof<V>> {
+    use exonum::storage::ListProof::*;
+
+    fn merge_boxed<V>(
+        first: Box<ListProof<V>>,
+        second: Box<ListProof<V>>,
+    ) -> Option<Box<ListProof<V>>> {
+        merge_list_proofs(*first, *second).map(Box::new)
+    }
+
+    Some(match (first, second) {
+        (Leaf(value), Leaf(_)) => Leaf(value),
+        (Full(left, right), Full(other_left, other_right)) => Full(
+            merge_boxed(left, other_left)?,
+            merge_boxed(right, other_right)?,
+        ),
+        (Full(left, right), Left(other, _)) | (Left(other, _), Full(left, right)) => {
+            Full(merge_boxed(left, other)?, right)
+        }
+        (Full(left, right), Right(_, other)) | (Right(_, other), Full(left, right)) => {
+            Full(left, merge_boxed(right, other)?)
+        }
+        (Left(left, right_hash), Left(other, _)) => Left(merge_boxed(left, other)?, right_hash),
+        (Left(left, _), Right(_, right)) | (Right(_, right), Left(left, _)) => Full(left, right),
+        (Right(left_hash, right), Right(_, other)) => Right(left_hash, merge_boxed(right, other)?),
+        _ => return None,
+    })
+}
+
+/// Proof that the transaction corresponding to an event in the wallet history
+/// is committed in a certain block.
+#[derive(Debug, Serialize, Deserialize)]
+struct EventAnchor {
+    block_proof: BlockProof,
+    transaction_proof: ListProof<Hash>,
+}
+
+impl EventAnchor {
+    /// Creates an anchor for a committed transaction.
+    fn new<T: AsRef<dyn Snapshot>>(snapshot: T, tx_hash: &Hash) -> Self {
+        let core_schema = CoreSchema::new(&snapshot);
+        let location = core_schema
+            .transactions_locations()
+            .get(tx_hash)
+            .expect("TxLocation");
+        let block_proof = core_schema
+            .block_and_precommits(location.block_height())
+            .expect("BlockProof");
+        let transaction_proof = core_schema
+            .block_transactions(location.block_height())
+            .get_proof(location.position_in_block());
+        EventAnchor {
+            block_proof,
+            transaction_proof,
+        }
+    }
+
+    /// Checks that the transaction is included into the anchor block, and returns
+    /// the height of the block.
+    fn check(&self, trust_anchor: &TrustAnchor, tx_hash: &Hash) -> Result<Height, VerifyError> {
+        trust_anchor.verify_block_proof(&self.block_proof)?;
+        let block = &self.block_proof.block;
+        let proof_description = ProofDescription::BlockTransactions;
+        let tx_hashes = self
+            .transaction_proof
+            .validate(*block.tx_hash(), u64::from(block.tx_count()))
+            .map_err(|error| VerifyError::ListProof {
+                error,
+                proof_description,
+            })?;
+        if !tx_hashes.iter().any(|&(_, hash)| hash == tx_hash) {
+            return Err(VerifyError::KeyMismatch(proof_description));
+        }
+        Ok(block.height())
+    }
+}
+
+// Required for conversions in `Service::wire`.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_pass_by_value))]
+impl Api {
+    /// Returns information about a single wallet. The information is supported with
+    /// cryptographic proofs, allowing client applications to minimize trust in their server
+    /// peers.
+    pub fn wallet(state: &ServiceApiState, query: WalletQuery) -> api::Result<WalletProof> {
+        let snapshot = state.snapshot();
+        Ok(WalletProof::new(snapshot, &query))
+    }
+
+    /// Same as [`wallet`](#method.wallet), but requires the query to be signed
+    /// by the wallet owner. The endpoint is used instead of `wallet` if the service is
+    /// configured to [require signed queries](::Service::require_signed_queries()).
+    pub fn authenticated_wallet(
+        state: &ServiceApiState,
+        query: WalletQuery,
+    ) -> api::Result<WalletProof> {
+        let snapshot = state.snapshot();
+        query.authenticate(&snapshot)?;
+        Ok(WalletProof::new(snapshot, &query))
+    }
+
+    /// Returns a proof that hidden balances of all wallets do not exceed the total supply
+    /// of tokens.
+    ///
+    /// This is an expensive operation; the proof size is linear in the number of wallets.
+    /// The endpoint responds with an error if the supply cannot be proven in the current
+    /// state of the service; see [`UnsupportedSupplyState`].
+    ///
+    /// [`UnsupportedSupplyState`]: ::api::UnsupportedSupplyState
+    pub fn supply(state: &ServiceApiState, _query: ()) -> api::Result<SupplyProof> {
+        let snapshot = state.snapshot();
+        SupplyProof::new(snapshot).map_err(|e| api::Error::BadRequest(e.to_string()))
+    }
+
+    /// Returns a proof that the wallet has made no outgoing payments since
+    /// the specified height.
+    pub fn inactivity(
+        state: &ServiceApiState,
+        query: InactivityQuery,
+    ) -> api::Result<InactivityProof> {
+        InactivityProof::new(state.snapshot(), &query)
+            .ok_or_else(|| api::Error::NotFound("wallet not found".to_owned()))
+    }
+
+    /// Same as [`inactivity`](#method.inactivity), but requires the query to be signed
+    /// by the wallet owner. The endpoint is used instead of `inactivity` if the service
+    /// is configured to [require signed queries](::Service::require_signed_queries()).
+    pub fn authenticated_inactivity(
+        state: &ServiceApiState,
+        query: InactivityQuery,
+    ) -> api::Result<InactivityProof> {
+        let snapshot = state.snapshot();
+        query.authenticate(&snapshot)?;
+        InactivityProof::new(snapshot, &query)
+            .ok_or_else(|| api::Error::NotFound("wallet not found".to_owned()))
+    }
+
+    /// Returns a proof that the transfer with the specified hash sent or received
+    /// by the wallet has been accepted.
+    pub fn receipt(state: &ServiceApiState, query: ReceiptQuery) -> api::Result<ReceiptProof> {
+        ReceiptProof::new(state.snapshot(), &query)
+            .ok_or_else(|| api::Error::NotFound("receipt not found".to_owned()))
+    }
+
+    /// Same as [`receipt`](#method.receipt), but requires the query to be signed
+    /// by the wallet owner. The endpoint is used instead of `receipt` if the service
+    /// is configured to [require signed queries](::Service::require_signed_queries()).
+    pub fn authenticated_receipt(
+        state: &ServiceApiState,
+        query: ReceiptQuery,
+    ) -> api::Result<ReceiptProof> {
+        let snapshot = state.snapshot();
+        query.authenticate(&snapshot)?;
+        ReceiptProof::new(snapshot, &query)
+            .ok_or_else(|| api::Error::NotFound("receipt not found".to_owned()))
+    }
+
+    /// Returns history checkpoints of the wallet starting from the specified one,
+    /// together with the events after the latest checkpoint.
+    pub fn checkpoints(
+        state: &ServiceApiState,
+        query: CheckpointQuery,
+    ) -> api::Result<CheckpointProof> {
+        CheckpointProof::new(state.snapshot(), &query)
+    }
+
+    /// Same as [`checkpoints`](#method.checkpoints), but requires the query to be signed
+    /// by the wallet owner. The endpoint is used instead of `checkpoints` if the service
+    /// is configured to [require signed queries](::Service::require_signed_queries()).
+    pub fn authenticated_checkpoints(
+        state: &ServiceApiState,
+        query: CheckpointQuery,
+    ) -> api::Result<CheckpointProof> {
+        let snapshot = state.snapshot();
+        query.authenticate(&snapshot)?;
+        CheckpointProof::new(snapshot, &query)
+    }
+
+    /// Returns commitments to all balances of the wallet within the specified range
+    /// of heights.
+    pub fn balance_history(
+        state: &ServiceApiState,
+        query: BalanceHistoryQuery,
+    ) -> api::Result<BalanceHistoryProof> {
+        BalanceHistoryProof::new(state.snapshot(), &query)
+    }
+
+    /// Same as [`balance_history`](#method.balance_history), but requires the query
+    /// to be signed by the wallet owner. The endpoint is used instead of `balance_history`
+    /// if the service is configured to [require signed queries](::Service::require_signed_queries()).
+    pub fn authenticated_balance_history(
+        state: &ServiceApiState,
+        query: BalanceHistoryQuery,
+    ) -> api::Result<BalanceHistoryProof> {
+        let snapshot = state.snapshot();
+        query.authenticate(&snapshot)?;
+        BalanceHistoryProof::new(snapshot, &query)
+    }
+
+    /// Returns the actual configuration of the service.
+    ///
+    /// The configuration is not covered by a proof; clients should obtain
+    /// it from a trusted source if possible.
+    pub fn config(state: &ServiceApiState, _query: ()) -> api::Result<Config> {
+        Ok(Schema::new(state.snapshot()).config())
+    }
+
+    /// Returns settings of a wallet.
+    ///
+    /// Settings are not covered by a proof, similar to the [configuration](#method.config).
+    pub fn settings(state: &ServiceApiState, query: SettingsQuery) -> api::Result<WalletSettings> {
+        let schema = Schema::new(state.snapshot());
+        Ok(WalletSettings {
+            min_rollback_delay: schema.min_rollback_delay(&query.key),
+        })
+    }
+
+    /// Recommends a rollback delay for a transfer to the specified receiver based
+    /// on the acceptance latencies of the receiver and the observed block interval.
+    pub fn rollback_delay(
+        state: &ServiceApiState,
+        query: RollbackDelayQuery,
+    ) -> api::Result<RollbackDelayRecommendation> {
+        Ok(RollbackDelayRecommendation::new(state.snapshot(), &query))
+    }
+
+    /// Returns the Bloom filter of unaccepted incoming transfers of a wallet. The filter is empty
+    /// if the wallet does not exist or has no unaccepted transfers.
+    ///
+    /// The filter is not covered by a proof. It does not require a signed query even
+    /// if the service is configured to [require signed queries], since recipients
+    /// of transfers are public.
+    ///
+    /// [require signed queries]: ::Service::require_signed_queries()
+    pub fn unaccepted_filter(
+        state: &ServiceApiState,
+        query: TransferFilterQuery,
+    ) -> api::Result<TransferFilter> {
+        Ok(Schema::new(state.snapshot()).transfer_filter(&query.key))
+    }
+
+    /// Returns the status of a transfer request. The status is not supported with proofs.
+    pub fn transfer_request(
+        state: &ServiceApiState,
+        query: TransferRequestQuery,
+    ) -> api::Result<TransferRequestStatus> {
+        let schema = Schema::new(state.snapshot());
+        let status = if let Some(transfer_id) = schema.transfer_request_payment(&query.id) {
+            TransferRequestStatus::Paid {
+                transfer_id,
+                accepted: schema.transfer_acceptance_height(&transfer_id).is_some(),
+            }
+        } else if schema.is_transfer_request_pending(&query.id) {
+            TransferRequestStatus::Pending
+        } else {
+            TransferRequestStatus::Unknown
+        };
+        Ok(status)
+    }
+
+    /// Returns the `BackUpTransfer` transaction publishing the backup copy of the opening
+    /// for a transfer, or `None` if no backup has been published. The backup is not covered
+    /// by a proof; its holder checks it against the transfer amount on decryption.
+    pub fn transfer_backup(
+        state: &ServiceApiState,
+        query: TransferBackupQuery,
+    ) -> api::Result<Option<BackUpTransfer>> {
+        let snapshot = state.snapshot();
+        let backup_id = Schema::new(&snapshot).transfer_backup(&query.id);
+        Ok(backup_id.map(|id| maybe_back_up_transfer(&snapshot, &id).expect("BackUpTransfer")))
+    }
+
+    /// Returns JSON schemas describing the wire format of API queries, responses
+    /// and transactions.
+    pub fn schema(_state: &ServiceApiState, _query: ()) -> api::Result<Value> {
+        Ok(schema().clone())
+    }
+
+    /// Accepts transactions for processing.
+    pub fn transaction(state: &ServiceApiState, tx: CryptoTransactions) -> api::Result<Hash> {
+        use exonum::node::TransactionSend;
+
+        let tx: Box<dyn Transaction> = tx.into();
+        let tx_hash = tx.hash();
+        state
+            .sender()
+            .send(tx)
+            .map(|()| tx_hash)
+            .map_err(|e| e.into())
+    }
+}
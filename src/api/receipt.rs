@@ -0,0 +1,201 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proofs of settled payments.
+
+use exonum::{
+    api,
+    blockchain::{Block, BlockProof, Blockchain, Schema as CoreSchema},
+    crypto::{CryptoHash, Hash, PublicKey, Signature},
+    storage::{MapProof, Snapshot},
+};
+
+use super::{ProofDescription, TrustAnchor, VerifyError, WalletProof, WalletQuery};
+use storage::{maybe_transfer, Receipt, Schema};
+use transactions::Transfer;
+use SERVICE_ID;
+
+/// Query for the `receipt` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptQuery {
+    /// Public key of the wallet, which has sent or received the transfer.
+    pub key: PublicKey,
+    /// Hash of the `Transfer` transaction.
+    pub transfer_id: Hash,
+    /// Hash of a recently committed block. Required together with `signature`
+    /// if the service is configured to [require signed queries].
+    ///
+    /// [require signed queries]: ::Service::require_signed_queries()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<Hash>,
+    /// Signature of the wallet owner, which is the same as for a [`WalletQuery`]
+    /// with `start_history_at` equal to zero.
+    ///
+    /// [`WalletQuery`]: self::WalletQuery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl ReceiptQuery {
+    /// Creates an unsigned query.
+    pub fn new(key: PublicKey, transfer_id: Hash) -> Self {
+        ReceiptQuery {
+            key,
+            transfer_id,
+            challenge: None,
+            signature: None,
+        }
+    }
+
+    /// Converts this query into a wallet query with the same authentication.
+    fn wallet_query(&self) -> WalletQuery {
+        WalletQuery {
+            challenge: self.challenge,
+            signature: self.signature,
+            ..WalletQuery::new(self.key, 0)
+        }
+    }
+
+    /// Checks that the query is signed by the wallet owner.
+    pub(super) fn authenticate<T: AsRef<dyn Snapshot>>(
+        &self,
+        snapshot: T,
+    ) -> Result<(), api::Error> {
+        self.wallet_query().authenticate(snapshot)
+    }
+}
+
+/// Cryptographically authenticated proof that a transfer has been accepted, i.e.,
+/// that the payment is settled.
+///
+/// # Theory
+///
+/// After a transfer is accepted, the service records a [`Receipt`] with the hashes
+/// of the transfer and the accepting transaction, and the acceptance height, both
+/// for the sender and for the receiver of the transfer. Receipts of each wallet are stored
+/// in a Merkelized map, the root of which is recorded in the receipt roots table
+/// directly committed to by the service state hash. The proof contains
+///
+/// - the latest block header, together with authorizing `Precommit`s
+/// - a `MapProof` from the block state hash to the receipt roots table
+/// - a `MapProof` from the table to the receipt root of the wallet
+/// - a `MapProof` from the root to the receipt of the transfer
+/// - the transfer itself, signed by the sender
+///
+/// Unlike [`WalletProof`]s, the proof size does not depend on the length of the wallet
+/// history. Either party of the transfer can export the proof.
+///
+/// Receipts are recorded only for transfers accepted after the [wire version] 5
+/// is activated.
+///
+/// [`Receipt`]: ::storage::Receipt
+/// [wire version]: ::Config::wire_version
+/// [`WalletProof`]: self::WalletProof
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReceiptProof {
+    block_proof: BlockProof,
+    receipt_table_proof: MapProof<Hash, Hash>,
+    receipt_root_proof: MapProof<PublicKey, Hash>,
+    receipt_proof: MapProof<Hash, Receipt>,
+    transfer: Transfer,
+}
+
+/// Information about a settled payment obtained after checking a `ReceiptProof`.
+#[derive(Debug)]
+pub struct CheckedReceiptProof {
+    /// Latest block, against which the proof is verified.
+    pub block: Block,
+    /// Receipt of the transfer.
+    pub receipt: Receipt,
+    /// Accepted transfer.
+    pub transfer: Transfer,
+}
+
+impl ReceiptProof {
+    /// Creates a new proof based on a given storage snapshot. Returns `None` if there is
+    /// no receipt for the transfer in the wallet.
+    pub(super) fn new<T: AsRef<dyn Snapshot>>(snapshot: T, query: &ReceiptQuery) -> Option<Self> {
+        let schema = Schema::new(&snapshot);
+        let receipts = schema.receipts(&query.key);
+        if !receipts.contains(&query.transfer_id) {
+            return None;
+        }
+
+        let core_schema = CoreSchema::new(&snapshot);
+        let block_proof = core_schema
+            .block_and_precommits(core_schema.height())
+            .expect("BlockProof");
+        Some(ReceiptProof {
+            block_proof,
+            receipt_table_proof: core_schema.get_proof_to_service_table(SERVICE_ID, 2),
+            receipt_root_proof: schema.receipt_roots().get_proof(query.key),
+            receipt_proof: receipts.get_proof(query.transfer_id),
+            transfer: maybe_transfer(&snapshot, &query.transfer_id).expect("Transfer"),
+        })
+    }
+
+    /// Checks the proof. Fails with [`VerifyError::MissingKey`] if the proof shows
+    /// that there is no receipt for the transfer.
+    ///
+    /// [`VerifyError::MissingKey`]: self::VerifyError::MissingKey
+    pub fn check(
+        &self,
+        trust_anchor: &TrustAnchor,
+        query: &ReceiptQuery,
+    ) -> Result<CheckedReceiptProof, VerifyError> {
+        trust_anchor.verify_block_proof(&self.block_proof)?;
+
+        let roots_hash: Option<Hash> = WalletProof::check_map_proof_with_single_key(
+            self.receipt_table_proof.clone(),
+            *self.block_proof.block.state_hash(),
+            &Blockchain::service_table_unique_key(SERVICE_ID, 2),
+            ProofDescription::ReceiptsTable,
+        )?;
+        let roots_hash =
+            roots_hash.ok_or(VerifyError::MissingKey(ProofDescription::ReceiptsTable))?;
+
+        let receipts_hash: Option<Hash> = WalletProof::check_map_proof_with_single_key(
+            self.receipt_root_proof.clone(),
+            roots_hash,
+            &query.key,
+            ProofDescription::Receipts,
+        )?;
+        let receipts_hash =
+            receipts_hash.ok_or(VerifyError::MissingKey(ProofDescription::Receipts))?;
+
+        let receipt: Option<Receipt> = WalletProof::check_map_proof_with_single_key(
+            self.receipt_proof.clone(),
+            receipts_hash,
+            &query.transfer_id,
+            ProofDescription::Receipt,
+        )?;
+        let receipt = receipt.ok_or(VerifyError::MissingKey(ProofDescription::Receipt))?;
+
+        // The transfer must be the one mentioned in the receipt, and must involve
+        // the queried wallet.
+        let transfer_id = self.transfer.hash();
+        if transfer_id != query.transfer_id
+            || *receipt.transfer_id() != transfer_id
+            || (*self.transfer.from() != query.key && *self.transfer.to() != query.key)
+        {
+            return Err(VerifyError::KeyMismatch(ProofDescription::Receipt));
+        }
+
+        Ok(CheckedReceiptProof {
+            block: self.block_proof.block.clone(),
+            receipt,
+            transfer: self.transfer.clone(),
+        })
+    }
+}
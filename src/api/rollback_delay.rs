@@ -0,0 +1,132 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recommendations of rollback delays for transfers.
+
+use exonum::{
+    blockchain::Schema as CoreSchema, crypto::PublicKey, helpers::Height, storage::Snapshot,
+};
+
+use std::{cmp, time::UNIX_EPOCH};
+
+use storage::Schema;
+use DelayUnit;
+
+/// Rollback delay in blocks recommended for receivers without recorded acceptances.
+pub const DEFAULT_ROLLBACK_DELAY: u32 = 10;
+/// Number of latest acceptances of the receiver taken into account.
+const LATENCY_SAMPLES: u64 = 32;
+/// Number of latest blocks used to estimate the block interval.
+const INTERVAL_SAMPLES: u64 = 16;
+/// Block interval assumed if it cannot be estimated, in milliseconds.
+const DEFAULT_BLOCK_INTERVAL_MS: u64 = 1_000;
+
+/// Query for the `rollback_delay` endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackDelayQuery {
+    /// Public key of the receiver of the planned transfer.
+    pub receiver: PublicKey,
+}
+
+/// Rollback delay recommended for a transfer to a specific receiver.
+///
+/// The recommendation is not covered by a proof, similar to the
+/// [configuration](::api::Api::config()).
+///
+/// # Algorithm
+///
+/// If the receiver has accepted transfers before, the recommended delay is twice
+/// the maximum acceptance latency among its latest 32 acceptances. Otherwise,
+/// [`DEFAULT_ROLLBACK_DELAY`] is recommended. The delay is then raised to the minimum
+/// rollback delay [preferred](::transactions::UpdateSettings) by the receiver
+/// and clamped to the bounds in the service configuration.
+///
+/// If the configuration measures delays in seconds, the delay in blocks is converted
+/// to seconds using the block interval estimated from the timestamps of `Precommit`s
+/// for the latest 16 blocks. Like the timestamps, the estimate is not authenticated.
+///
+/// [`DEFAULT_ROLLBACK_DELAY`]: self::DEFAULT_ROLLBACK_DELAY
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollbackDelayRecommendation {
+    /// Recommended rollback delay, which can be used as is in a transfer.
+    pub rollback_delay: u32,
+    /// Unit of the rollback delay.
+    pub unit: DelayUnit,
+    /// Estimated interval between blocks in milliseconds, or `None` if there are not
+    /// enough blocks for the estimate.
+    pub block_interval_ms: Option<u64>,
+    /// Maximum acceptance latency (in blocks) among the latest acceptances
+    /// of the receiver, or `None` if the receiver has not accepted any transfers.
+    pub max_acceptance_latency: Option<u64>,
+}
+
+impl RollbackDelayRecommendation {
+    /// Computes a recommendation based on a given storage snapshot.
+    pub(super) fn new<T: AsRef<dyn Snapshot>>(snapshot: T, query: &RollbackDelayQuery) -> Self {
+        let schema = Schema::new(&snapshot);
+        let config = schema.config();
+
+        let latencies = schema.acceptance_latencies(&query.receiver);
+        let start = latencies.len().saturating_sub(LATENCY_SAMPLES);
+        let max_acceptance_latency = latencies.iter_from(start).max();
+        let delay_in_blocks =
+            max_acceptance_latency.map_or(u64::from(DEFAULT_ROLLBACK_DELAY), |latency| 2 * latency);
+
+        let block_interval_ms = block_interval_ms(&snapshot);
+        let delay = match config.rollback_delay_unit {
+            DelayUnit::Blocks => delay_in_blocks,
+            DelayUnit::Seconds => {
+                let interval = block_interval_ms.unwrap_or(DEFAULT_BLOCK_INTERVAL_MS);
+                (delay_in_blocks * interval + 999) / 1_000
+            }
+        };
+        let bounds = &config.rollback_delay_bounds;
+        let delay = cmp::max(delay, u64::from(schema.min_rollback_delay(&query.receiver)));
+        let delay = cmp::min(delay, u64::from(bounds.end - 1)) as u32;
+        let delay = cmp::max(delay, bounds.start);
+
+        RollbackDelayRecommendation {
+            rollback_delay: delay,
+            unit: config.rollback_delay_unit,
+            block_interval_ms,
+            max_acceptance_latency,
+        }
+    }
+}
+
+/// Estimates the block interval from the median `Precommit` timestamps of the latest blocks.
+fn block_interval_ms<T: AsRef<dyn Snapshot>>(snapshot: T) -> Option<u64> {
+    let core_schema = CoreSchema::new(&snapshot);
+    let height = core_schema.height().0;
+    let start = height.saturating_sub(INTERVAL_SAMPLES).max(1);
+    if start >= height {
+        return None;
+    }
+
+    let block_time_ms = |height: u64| -> Option<u64> {
+        let proof = core_schema.block_and_precommits(Height(height))?;
+        let mut times: Vec<_> = proof
+            .precommits
+            .iter()
+            .filter_map(|precommit| precommit.time().duration_since(UNIX_EPOCH).ok())
+            .map(|time| time.as_secs() * 1_000 + u64::from(time.subsec_millis()))
+            .collect();
+        times.sort();
+        times.get(times.len() / 2).cloned()
+    };
+    let first = block_time_ms(start)?;
+    let last = block_time_ms(height)?;
+    last.checked_sub(first)
+        .map(|elapsed| elapsed / (height - start))
+}
@@ -0,0 +1,571 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Machine-readable JSON schemas for the wire format of the service.
+//!
+//! The schemas follow [JSON Schema draft 07] and describe the JSON forms of API queries
+//! and responses, as well as transactions accepted by the service. The schema document
+//! is assembled once, on the first access, and is served by the `v1/schema` endpoint.
+//!
+//! [JSON Schema draft 07]: https://json-schema.org/specification-links.html#draft-7
+
+use serde_json::Value;
+
+use crypto::SimpleRangeProof;
+use SERVICE_ID;
+
+lazy_static! {
+    static ref SCHEMA: Value = build_schema();
+}
+
+/// Returns the JSON schema document describing the wire format of the service.
+///
+/// Individual types are described in the `definitions` section of the document.
+pub fn schema() -> &'static Value {
+    &SCHEMA
+}
+
+/// Schema of a hex-encoded byte string with the specified length in bytes.
+fn hex(byte_len: usize, description: &str) -> Value {
+    json!({
+        "type": "string",
+        "pattern": format!("^[0-9a-f]{{{}}}$", 2 * byte_len),
+        "description": description,
+    })
+}
+
+/// Schema of an `u64` value serialized within Exonum transactions and storage values.
+/// Such values are encoded as decimal strings in order to be compatible with JavaScript.
+fn exonum_u64(description: &str) -> Value {
+    json!({
+        "type": "string",
+        "pattern": "^[0-9]{1,20}$",
+        "description": description,
+    })
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/definitions/{}", name) })
+}
+
+/// Schema of a signed transaction with the specified message ID and body definition.
+///
+/// Message IDs are assigned by the `transactions!` macro in the order of transaction
+/// declaration.
+fn transaction(message_id: u16, body: &str) -> Value {
+    json!({
+        "type": "object",
+        "required": ["protocol_version", "service_id", "message_id", "body", "signature"],
+        "properties": {
+            "protocol_version": { "type": "integer", "const": 0 },
+            "service_id": { "type": "integer", "const": SERVICE_ID },
+            "message_id": { "type": "integer", "const": message_id },
+            "body": reference(body),
+            "signature": reference("Signature"),
+        },
+    })
+}
+
+fn build_schema() -> Value {
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "private_currency",
+        "definitions": {
+            "PublicKey": hex(32, "Ed25519 public key"),
+            "Signature": hex(64, "Ed25519 signature"),
+            "Hash": hex(32, "SHA-256 digest"),
+            "Commitment": hex(32, "Pedersen commitment (compressed Ristretto point)"),
+            "SimpleRangeProof": {
+                "type": "array",
+                "description": "Bulletproof serialized as a sequence of Ristretto \
+                                scalars and compressed points",
+                "items": hex(32, "scalar or compressed point"),
+                "minItems": SimpleRangeProof::ELEMENTS_SIZE,
+                "maxItems": SimpleRangeProof::ELEMENTS_SIZE,
+            },
+            "AmountProof": {
+                "description": "range proof for the lower bound on the transferred amount, \
+                                optionally followed by a range proof for the upper bound",
+                "oneOf": [
+                    reference("SimpleRangeProof"),
+                    {
+                        "type": "array",
+                        "items": hex(32, "scalar or compressed point"),
+                        "minItems": 2 * SimpleRangeProof::ELEMENTS_SIZE,
+                        "maxItems": 2 * SimpleRangeProof::ELEMENTS_SIZE,
+                    },
+                ],
+            },
+            "EncryptedData": {
+                "type": "object",
+                "required": ["nonce", "encrypted_data"],
+                "properties": {
+                    "nonce": hex(24, "nonce for the `box` routine"),
+                    "encrypted_data": {
+                        "type": "string",
+                        "pattern": "^([0-9a-f]{2})*$",
+                        "description": "data encrypted with the `box` routine",
+                    },
+                },
+            },
+
+            "CreateWalletBody": {
+                "type": "object",
+                "required": ["key"],
+                "properties": {
+                    "key": reference("PublicKey"),
+                },
+            },
+            "TransferBody": {
+                "type": "object",
+                "required": [
+                    "from", "to", "rollback_delay", "history_len", "amount",
+                    "amount_proof", "sufficient_balance_proof", "encrypted_data",
+                ],
+                "properties": {
+                    "from": reference("PublicKey"),
+                    "to": reference("PublicKey"),
+                    "rollback_delay": { "type": "integer", "minimum": 0 },
+                    "history_len": exonum_u64("length of the sender’s wallet history"),
+                    "amount": reference("Commitment"),
+                    "amount_proof": reference("AmountProof"),
+                    "sufficient_balance_proof": reference("SimpleRangeProof"),
+                    "encrypted_data": reference("EncryptedData"),
+                },
+            },
+            "AcceptBody": {
+                "type": "object",
+                "required": ["receiver", "transfer_id"],
+                "properties": {
+                    "receiver": reference("PublicKey"),
+                    "transfer_id": reference("Hash"),
+                },
+            },
+            "LockForBridgeBody": {
+                "type": "object",
+                "required": [
+                    "from", "to", "destination", "history_len", "amount",
+                    "amount_proof", "sufficient_balance_proof", "encrypted_data",
+                ],
+                "properties": {
+                    "from": reference("PublicKey"),
+                    "to": reference("PublicKey"),
+                    "destination": reference("Hash"),
+                    "history_len": exonum_u64("length of the sender’s wallet history"),
+                    "amount": reference("Commitment"),
+                    "amount_proof": reference("SimpleRangeProof"),
+                    "sufficient_balance_proof": reference("SimpleRangeProof"),
+                    "encrypted_data": reference("EncryptedData"),
+                },
+            },
+            "ReleaseFromBridgeBody": {
+                "type": "object",
+                "required": ["relayer", "lock"],
+                "properties": {
+                    "relayer": reference("PublicKey"),
+                    "lock": {
+                        "type": "string",
+                        "pattern": "^([0-9a-f]{2})*$",
+                        "description": "hex-encoded binary message of a `LockForBridge` \
+                                        transaction",
+                    },
+                },
+            },
+            "AcceptWithNoteBody": {
+                "type": "object",
+                "required": ["receiver", "transfer_id", "note"],
+                "properties": {
+                    "receiver": reference("PublicKey"),
+                    "transfer_id": reference("Hash"),
+                    "note": reference("EncryptedData"),
+                },
+            },
+            "UpdateSettingsBody": {
+                "type": "object",
+                "required": ["key", "min_rollback_delay", "seed"],
+                "properties": {
+                    "key": reference("PublicKey"),
+                    "min_rollback_delay": { "type": "integer", "minimum": 0 },
+                    "seed": exonum_u64("arbitrary number making the transaction unique"),
+                },
+            },
+            "OpenDisputeBody": {
+                "type": "object",
+                "required": ["sender", "transfer", "seed"],
+                "properties": {
+                    "sender": reference("PublicKey"),
+                    "transfer": {
+                        "type": "string",
+                        "pattern": "^([0-9a-f]{2})*$",
+                        "description": "hex-encoded binary message of the disputed `Transfer`",
+                    },
+                    "seed": exonum_u64("arbitrary number making the transaction unique"),
+                },
+            },
+            "ResolveDisputeBody": {
+                "type": "object",
+                "required": ["arbiter", "dispute", "refund"],
+                "properties": {
+                    "arbiter": reference("PublicKey"),
+                    "dispute": {
+                        "type": "string",
+                        "pattern": "^([0-9a-f]{2})*$",
+                        "description": "hex-encoded binary message of an `OpenDispute` \
+                                        transaction",
+                    },
+                    "refund": { "type": "boolean" },
+                },
+            },
+            "StreamTransferBody": {
+                "type": "object",
+                "required": [
+                    "from", "to", "duration", "history_len", "rate",
+                    "rate_proof", "sufficient_balance_proof", "encrypted_data",
+                ],
+                "properties": {
+                    "from": reference("PublicKey"),
+                    "to": reference("PublicKey"),
+                    "duration": { "type": "integer", "minimum": 1 },
+                    "history_len": exonum_u64("length of the sender’s wallet history"),
+                    "rate": reference("Commitment"),
+                    "rate_proof": reference("SimpleRangeProof"),
+                    "sufficient_balance_proof": reference("SimpleRangeProof"),
+                    "encrypted_data": reference("EncryptedData"),
+                },
+            },
+            "CloseStreamBody": {
+                "type": "object",
+                "required": ["author", "stream", "duration"],
+                "properties": {
+                    "author": reference("PublicKey"),
+                    "stream": {
+                        "type": "string",
+                        "pattern": "^([0-9a-f]{2})*$",
+                        "description": "hex-encoded binary message of the closed \
+                                        `StreamTransfer`",
+                    },
+                    "duration": { "type": "integer", "minimum": 0 },
+                },
+            },
+            "LockBody": {
+                "type": "object",
+                "required": [
+                    "owner", "unlock_height", "history_len", "amount",
+                    "amount_proof", "sufficient_balance_proof", "encrypted_data",
+                ],
+                "properties": {
+                    "owner": reference("PublicKey"),
+                    "unlock_height": exonum_u64("height from which the funds can be unlocked"),
+                    "history_len": exonum_u64("length of the owner’s wallet history"),
+                    "amount": reference("Commitment"),
+                    "amount_proof": reference("SimpleRangeProof"),
+                    "sufficient_balance_proof": reference("SimpleRangeProof"),
+                    "encrypted_data": reference("EncryptedData"),
+                },
+            },
+            "UnlockBody": {
+                "type": "object",
+                "required": ["owner", "lock", "seed"],
+                "properties": {
+                    "owner": reference("PublicKey"),
+                    "lock": {
+                        "type": "string",
+                        "pattern": "^([0-9a-f]{2})*$",
+                        "description": "hex-encoded binary message of the `Lock` transaction",
+                    },
+                    "seed": exonum_u64("arbitrary number making the transaction unique"),
+                },
+            },
+            "SetProfileBody": {
+                "type": "object",
+                "required": ["key", "profile", "seed"],
+                "properties": {
+                    "key": reference("PublicKey"),
+                    "profile": reference("EncryptedData"),
+                    "seed": exonum_u64("arbitrary number making the transaction unique"),
+                },
+            },
+            "RequestTransferBody": {
+                "type": "object",
+                "required": ["requester", "payer", "amount", "encrypted_data", "seed"],
+                "properties": {
+                    "requester": reference("PublicKey"),
+                    "payer": reference("PublicKey"),
+                    "amount": reference("Commitment"),
+                    "encrypted_data": reference("EncryptedData"),
+                    "seed": exonum_u64("arbitrary number making the transaction unique"),
+                },
+            },
+            "RerandomizeBody": {
+                "type": "object",
+                "required": ["owner", "blinding", "equality_proof", "encrypted_data", "seed"],
+                "properties": {
+                    "owner": reference("PublicKey"),
+                    "blinding": reference("Commitment"),
+                    "equality_proof": {
+                        "type": "string",
+                        "pattern": "^[0-9a-f]{128}$",
+                        "description": "hex-encoded proof that the balance commitment \
+                                        is rerandomized to the same value",
+                    },
+                    "encrypted_data": reference("EncryptedData"),
+                    "seed": exonum_u64("arbitrary number making the transaction unique"),
+                },
+            },
+            "BackUpTransferBody": {
+                "type": "object",
+                "required": ["from", "holder", "transfer_id", "encrypted_data", "seed"],
+                "properties": {
+                    "from": reference("PublicKey"),
+                    "holder": reference("PublicKey"),
+                    "transfer_id": reference("Hash"),
+                    "encrypted_data": reference("EncryptedData"),
+                    "seed": exonum_u64("arbitrary number making the transaction unique"),
+                },
+            },
+            "CreateWallet": transaction(0, "CreateWalletBody"),
+            "Transfer": transaction(1, "TransferBody"),
+            "Accept": transaction(2, "AcceptBody"),
+            "LockForBridge": transaction(3, "LockForBridgeBody"),
+            "ReleaseFromBridge": transaction(4, "ReleaseFromBridgeBody"),
+            "AcceptWithNote": transaction(5, "AcceptWithNoteBody"),
+            "UpdateSettings": transaction(6, "UpdateSettingsBody"),
+            "OpenDispute": transaction(7, "OpenDisputeBody"),
+            "ResolveDispute": transaction(8, "ResolveDisputeBody"),
+            "StreamTransfer": transaction(9, "StreamTransferBody"),
+            "CloseStream": transaction(10, "CloseStreamBody"),
+            "Lock": transaction(11, "LockBody"),
+            "Unlock": transaction(12, "UnlockBody"),
+            "SetProfile": transaction(13, "SetProfileBody"),
+            "RequestTransfer": transaction(14, "RequestTransferBody"),
+            "Rerandomize": transaction(15, "RerandomizeBody"),
+            "BackUpTransfer": transaction(16, "BackUpTransferBody"),
+            "Transaction": {
+                "description": "transaction accepted by the `v1/transaction` endpoint",
+                "oneOf": [
+                    reference("CreateWallet"),
+                    reference("Transfer"),
+                    reference("Accept"),
+                    reference("LockForBridge"),
+                    reference("ReleaseFromBridge"),
+                    reference("AcceptWithNote"),
+                    reference("UpdateSettings"),
+                    reference("OpenDispute"),
+                    reference("ResolveDispute"),
+                    reference("StreamTransfer"),
+                    reference("CloseStream"),
+                    reference("Lock"),
+                    reference("Unlock"),
+                    reference("SetProfile"),
+                    reference("RequestTransfer"),
+                    reference("Rerandomize"),
+                    reference("BackUpTransfer"),
+                ],
+            },
+
+            "WalletQuery": {
+                "type": "object",
+                "description": "query string for the `v1/wallet` endpoint",
+                "required": ["key", "start_history_at"],
+                "properties": {
+                    "key": reference("PublicKey"),
+                    "start_history_at": { "type": "integer", "minimum": 0 },
+                    "challenge": reference("Hash"),
+                    "signature": reference("Signature"),
+                    "history_indices": {
+                        "type": "string",
+                        "description": "comma-separated indices of requested history events",
+                        "pattern": "^([0-9]+(,[0-9]+)*)?$",
+                    },
+                    "event_tags": {
+                        "type": "string",
+                        "description": "comma-separated numeric tags of requested history events",
+                        "pattern": "^([0-9]+(,[0-9]+)*)?$",
+                    },
+                },
+            },
+            "Wallet": {
+                "type": "object",
+                "required": [
+                    "public_key", "balance", "history_len", "last_send_index",
+                    "history_hash", "unaccepted_transfers_hash", "locked_balance",
+                ],
+                "properties": {
+                    "public_key": reference("PublicKey"),
+                    "balance": reference("Commitment"),
+                    "history_len": exonum_u64("number of entries in the wallet history"),
+                    "last_send_index": exonum_u64("index of the last outgoing transfer"),
+                    "history_hash": reference("Hash"),
+                    "unaccepted_transfers_hash": reference("Hash"),
+                    "locked_balance": reference("Commitment"),
+                },
+            },
+            "FullEvent": {
+                "oneOf": [
+                    {
+                        "type": "object",
+                        "required": ["CreateWallet"],
+                        "properties": { "CreateWallet": reference("CreateWallet") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["Transfer"],
+                        "properties": { "Transfer": reference("Transfer") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["Rollback"],
+                        "properties": { "Rollback": reference("Transfer") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["BridgeLock"],
+                        "properties": { "BridgeLock": reference("LockForBridge") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["BridgeRelease"],
+                        "properties": { "BridgeRelease": reference("ReleaseFromBridge") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["Acknowledgment"],
+                        "properties": { "Acknowledgment": reference("AcceptWithNote") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["DisputeOpened"],
+                        "properties": { "DisputeOpened": reference("OpenDispute") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["DisputeResolved"],
+                        "properties": { "DisputeResolved": reference("ResolveDispute") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["StreamOpened"],
+                        "properties": { "StreamOpened": reference("StreamTransfer") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["StreamPayment"],
+                        "properties": { "StreamPayment": reference("StreamTransfer") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["StreamClosed"],
+                        "properties": { "StreamClosed": reference("CloseStream") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["Lock"],
+                        "properties": { "Lock": reference("Lock") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["Unlock"],
+                        "properties": { "Unlock": reference("Unlock") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["TransferRequest"],
+                        "properties": { "TransferRequest": reference("RequestTransfer") },
+                        "additionalProperties": false,
+                    },
+                    {
+                        "type": "object",
+                        "required": ["Rerandomization"],
+                        "properties": { "Rerandomization": reference("Rerandomize") },
+                        "additionalProperties": false,
+                    },
+                ],
+            },
+            "WalletProof": {
+                "type": "object",
+                "description": "response of the `v1/wallet` endpoint; Merkle proofs are \
+                                described by the Exonum core documentation",
+                "required": [
+                    "block_proof",
+                    "wallet_table_proof",
+                    "wallet_proof",
+                    "profile_table_proof",
+                    "profile_proof",
+                ],
+                "properties": {
+                    "block_proof": { "type": "object" },
+                    "wallet_table_proof": { "type": "object" },
+                    "wallet_proof": { "type": "object" },
+                    "profile_table_proof": { "type": "object" },
+                    "profile_proof": { "type": "object" },
+                    "history": {
+                        "type": "array",
+                        "items": reference("FullEvent"),
+                    },
+                    "unaccepted_transfers": {
+                        "type": "array",
+                        "items": reference("Transfer"),
+                    },
+                    "history_proof": {
+                        "oneOf": [{ "type": "null" }, { "type": "object" }],
+                    },
+                    "unaccepted_transfers_proof": { "type": "object" },
+                },
+                "additionalProperties": false,
+            },
+        },
+    })
+}
+
+#[test]
+fn schema_references_are_resolved() {
+    fn collect_refs<'a>(value: &'a Value, refs: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get("$ref") {
+                    refs.push(reference);
+                }
+                map.values().for_each(|value| collect_refs(value, refs));
+            }
+            Value::Array(values) => values.iter().for_each(|value| collect_refs(value, refs)),
+            _ => {}
+        }
+    }
+
+    let schema = schema();
+    let mut refs = vec![];
+    collect_refs(schema, &mut refs);
+    assert!(!refs.is_empty());
+    for reference in refs {
+        let name = reference.trim_start_matches("#/definitions/");
+        assert!(
+            schema["definitions"].get(name).is_some(),
+            "unresolved reference {}",
+            reference
+        );
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streaming serialization of wallet proofs.
+
+use exonum::storage::Snapshot;
+use serde_json::{self, Value};
+
+use std::io::{self, Write};
+
+use super::{FullEvent, WalletProof, WalletQuery};
+
+impl WalletProof {
+    /// Writes the JSON presentation of the proof for the query into the writer.
+    ///
+    /// Events in the wallet history, which constitute the bulk of the proof for wallets
+    /// with long histories, are loaded from the snapshot and written one at a time,
+    /// so the proof is never buffered in memory as a whole. The output can be deserialized
+    /// into a `WalletProof` in the same way as the response of the `v1/wallet` endpoint.
+    ///
+    /// The HTTP API of the service cannot use this method, since the endpoints exposed
+    /// by Exonum services return fully serializable values; it is intended for nodes
+    /// and tools with their own transport (e.g., writing proofs to files or sockets).
+    pub fn write_json<T, W>(snapshot: T, query: &WalletQuery, mut writer: W) -> io::Result<()>
+    where
+        T: AsRef<dyn Snapshot>,
+        W: Write,
+    {
+        let (proof, history_indices) = WalletProof::without_history(&snapshot, query);
+        let mut fields = match serde_json::to_value(&proof)? {
+            Value::Object(fields) => fields,
+            _ => unreachable!("`WalletProof` is serialized as an object"),
+        };
+        // The history is absent if the wallet does not exist.
+        if fields.remove("history").is_none() {
+            return serde_json::to_writer(writer, &fields).map_err(From::from);
+        }
+
+        writer.write_all(b"{\"history\":[")?;
+        for (i, index) in history_indices.into_iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b",")?;
+            }
+            let event = FullEvent::at(&snapshot, &query.key, index);
+            serde_json::to_writer(&mut writer, &event)?;
+        }
+        writer.write_all(b"]")?;
+
+        for (name, value) in &fields {
+            writer.write_all(b",")?;
+            serde_json::to_writer(&mut writer, name)?;
+            writer.write_all(b":")?;
+            serde_json::to_writer(&mut writer, value)?;
+        }
+        writer.write_all(b"}")?;
+        writer.flush()
+    }
+}
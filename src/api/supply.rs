@@ -0,0 +1,276 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proofs of supply conservation.
+
+use exonum::{
+    blockchain::{Block, BlockProof, Blockchain, Schema as CoreSchema},
+    crypto::{CryptoHash, Hash, PublicKey},
+    storage::{
+        proof_map_index::ProofMapKey, Database, MapProof, MemoryDB, ProofMapIndex, Snapshot,
+        StorageValue,
+    },
+};
+
+use std::collections::HashMap;
+
+use super::{ProofDescription, TrustAnchor, VerifyError, WalletProof};
+use crypto::Commitment;
+use storage::{maybe_rerandomize, maybe_transfer, Schema, Wallet};
+use transactions::{Rerandomize, Transfer};
+use {Config, CONFIG, SERVICE_ID};
+
+/// Cryptographically authenticated proof that the hidden balances of all wallets
+/// do not exceed the total supply of tokens.
+///
+/// # Theory
+///
+/// Each wallet receives [`initial_balance`] tokens on creation; afterwards, tokens are
+/// only moved among wallets. The commitment to the transferred amount is subtracted from
+/// the sender’s balance and is eventually added either to the receiver’s balance
+/// or back to the sender’s one. Locked funds are moved between the balance and the locked
+/// balance of the same wallet. Hence, the sum of commitments to balances and locked balances
+/// of all wallets and to amounts of all pending transfers must equal a commitment to
+/// `initial_balance * wallets_count` with zero blinding factor. Anyone can check this equality
+/// without knowing individual balances.
+///
+/// [`Rerandomize`] transactions add commitments to zero to balances. The proof includes
+/// all committed rerandomizations, and their commitments are subtracted from the sum.
+/// Since the equality proof in each rerandomization shows that its commitment is
+/// a commitment to zero, the included rerandomizations do not need to be authenticated.
+///
+/// The proof includes all wallets, all pending transfers and all rerandomizations, so its size
+/// is linear in the number of wallets.
+///
+/// # Limitations
+///
+/// Funds held in the escrow of open [disputes](::dispute) and the undelivered remainder
+/// of active payment streams are neither in the balance of a wallet nor in a pending
+/// transfer, and are not covered by the Merkelized state. Funds moved through
+/// the [bridge](::bridge) change the total supply of the deployment. Thus, a proof
+/// cannot be created while there are open disputes or active streams, and cannot be checked
+/// if the bridge is enabled; in these cases, an [`UnsupportedSupplyState`] error
+/// is returned.
+///
+/// [`initial_balance`]: ::Config::initial_balance
+/// [`Rerandomize`]: ::transactions::Rerandomize
+/// [`UnsupportedSupplyState`]: enum.UnsupportedSupplyState.html
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SupplyProof {
+    block_proof: BlockProof,
+    wallet_table_proof: MapProof<Hash, Hash>,
+    wallets: Vec<Wallet>,
+    pending_transfers: Vec<Transfer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rerandomizations: Vec<Rerandomize>,
+}
+
+/// Service state preventing the token supply from being proven with a [`SupplyProof`].
+///
+/// [`SupplyProof`]: struct.SupplyProof.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Fail)]
+pub enum UnsupportedSupplyState {
+    /// Funds are held in the escrow of open disputes.
+    #[fail(display = "funds are held in the escrow of open disputes")]
+    DisputedEscrow,
+    /// Funds are reserved for payments of active streams.
+    #[fail(display = "funds are reserved for active payment streams")]
+    ActiveStreams,
+    /// The bridge is enabled, so funds may be moved to or from other deployments.
+    #[fail(display = "funds may be moved through the bridge")]
+    Bridge,
+}
+
+/// Information about the token supply obtained after checking a `SupplyProof`.
+#[derive(Debug)]
+pub struct CheckedSupplyProof {
+    /// Block information.
+    pub block: Block,
+    /// Number of wallets in the system.
+    pub wallets_count: u64,
+    /// Number of pending (not yet accepted or rolled back) transfers.
+    pub pending_transfers_count: u64,
+    /// Sum of commitments to the balances of all wallets.
+    pub total_balance: Commitment,
+    /// Sum of commitments to the locked balances of all wallets.
+    pub total_locked: Commitment,
+    /// Sum of commitments to the amounts of all pending transfers.
+    pub total_pending: Commitment,
+    /// Total supply of tokens.
+    pub total_supply: u64,
+}
+
+/// Computes the Merkle root of a `ProofMapIndex` with the specified entries.
+fn map_root<K, V, I>(entries: I) -> Hash
+where
+    K: ProofMapKey,
+    V: StorageValue,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let db = MemoryDB::new();
+    let mut fork = db.fork();
+    let mut index = ProofMapIndex::new("supply_proof.index", &mut fork);
+    for (key, value) in entries {
+        index.put(&key, value);
+    }
+    index.merkle_root()
+}
+
+impl SupplyProof {
+    /// Creates a new proof based on a given storage snapshot.
+    pub(super) fn new<T: AsRef<dyn Snapshot>>(snapshot: T) -> Result<Self, UnsupportedSupplyState> {
+        {
+            let schema = Schema::new(&snapshot);
+            if schema.config().bridge.is_some() {
+                return Err(UnsupportedSupplyState::Bridge);
+            } else if schema.has_disputed_escrow() {
+                return Err(UnsupportedSupplyState::DisputedEscrow);
+            } else if schema.has_active_streams() {
+                return Err(UnsupportedSupplyState::ActiveStreams);
+            }
+        }
+
+        let core_schema = CoreSchema::new(&snapshot);
+        let block_proof = core_schema
+            .block_and_precommits(core_schema.height())
+            .expect("BlockProof");
+        let wallet_table_proof = core_schema.get_proof_to_service_table(SERVICE_ID, 0);
+
+        let schema = Schema::new(&snapshot);
+        let wallets: Vec<_> = schema.wallets().values().collect();
+        let pending_transfers = wallets
+            .iter()
+            .flat_map(|wallet| schema.unaccepted_transfers(wallet.public_key()))
+            .map(|hash| maybe_transfer(&snapshot, &hash).expect("Transfer"))
+            .collect();
+        let rerandomizations = schema
+            .rerandomizations()
+            .iter()
+            .map(|hash| maybe_rerandomize(&snapshot, &hash).expect("Rerandomize"))
+            .collect();
+
+        Ok(SupplyProof {
+            block_proof,
+            wallet_table_proof,
+            wallets,
+            pending_transfers,
+            rerandomizations,
+        })
+    }
+
+    /// Checks the proof, assuming that the service uses the [default configuration](::CONFIG).
+    pub fn check(&self, trust_anchor: &TrustAnchor) -> Result<CheckedSupplyProof, VerifyError> {
+        self.check_with_config(trust_anchor, &CONFIG)
+    }
+
+    /// Checks the proof with the specified service configuration.
+    pub fn check_with_config(
+        &self,
+        trust_anchor: &TrustAnchor,
+        config: &Config,
+    ) -> Result<CheckedSupplyProof, VerifyError> {
+        if config.bridge.is_some() {
+            return Err(VerifyError::UnsupportedSupply(
+                UnsupportedSupplyState::Bridge,
+            ));
+        }
+        trust_anchor.verify_block_proof(&self.block_proof)?;
+
+        let wallets_hash: Option<Hash> = WalletProof::check_map_proof_with_single_key(
+            self.wallet_table_proof.clone(),
+            *self.block_proof.block.state_hash(),
+            &Blockchain::service_table_unique_key(SERVICE_ID, 0),
+            ProofDescription::WalletsTable,
+        )?;
+        let wallets_hash =
+            wallets_hash.ok_or(VerifyError::MissingKey(ProofDescription::WalletsTable))?;
+
+        // Check that the proof contains *all* wallets.
+        let wallet_entries = self
+            .wallets
+            .iter()
+            .map(|wallet| (*wallet.public_key(), wallet.clone()));
+        if map_root(wallet_entries) != wallets_hash {
+            return Err(VerifyError::ProofDisconnect(ProofDescription::Wallet));
+        }
+
+        // Check that the proof contains all pending transfers.
+        let mut transfers_by_receiver: HashMap<PublicKey, Vec<Hash>> = HashMap::new();
+        for transfer in &self.pending_transfers {
+            transfers_by_receiver
+                .entry(*transfer.to())
+                .or_insert_with(Vec::new)
+                .push(transfer.hash());
+        }
+        for wallet in &self.wallets {
+            let hashes = transfers_by_receiver
+                .remove(wallet.public_key())
+                .unwrap_or_default();
+            let root = map_root(hashes.into_iter().map(|hash| (hash, ())));
+            if root != *wallet.unaccepted_transfers_hash() {
+                return Err(VerifyError::ProofDisconnect(
+                    ProofDescription::UnacceptedTransfers,
+                ));
+            }
+        }
+        if !transfers_by_receiver.is_empty() {
+            // Some transfers are addressed to unknown wallets.
+            return Err(VerifyError::KeyMismatch(
+                ProofDescription::UnacceptedTransfers,
+            ));
+        }
+
+        let zero = Commitment::with_no_blinding(0);
+        let total_balance = self
+            .wallets
+            .iter()
+            .fold(zero.clone(), |acc, wallet| acc + wallet.balance());
+        let total_locked = self
+            .wallets
+            .iter()
+            .fold(zero.clone(), |acc, wallet| acc + wallet.locked_balance());
+        let total_pending = self
+            .pending_transfers
+            .iter()
+            .fold(zero.clone(), |acc, transfer| acc + transfer.amount());
+
+        // Rerandomizations only add commitments to zero, which is checked by their proofs.
+        if !self.rerandomizations.iter().all(Rerandomize::verify_proof) {
+            return Err(VerifyError::SupplyMismatch);
+        }
+        let total_rerandomization = self
+            .rerandomizations
+            .iter()
+            .fold(zero, |acc, rerandomize| acc + rerandomize.blinding());
+
+        let wallets_count = self.wallets.len() as u64;
+        let total_supply = wallets_count
+            .checked_mul(config.initial_balance)
+            .ok_or(VerifyError::SupplyMismatch)?;
+        let total = &(&(&total_balance + &total_locked) + &total_pending) - &total_rerandomization;
+        if total != Commitment::with_no_blinding(total_supply) {
+            return Err(VerifyError::SupplyMismatch);
+        }
+
+        Ok(CheckedSupplyProof {
+            block: self.block_proof.block.clone(),
+            wallets_count,
+            pending_transfers_count: self.pending_transfers.len() as u64,
+            total_balance,
+            total_locked,
+            total_pending,
+            total_supply,
+        })
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Command-line wallet for the service.
+//!
+//! The wallet is kept in a JSON file managed by `FileStore`. Transactions are printed
+//! to the standard output in their JSON form, so they can be created on an offline machine
+//! and submitted from another one.
+//!
+//! The wallet file is locked while a command is running, so that concurrent commands
+//! cannot create conflicting transfers. If the CLI is killed, the lock (a file with
+//! the `lock` extension next to the wallet file) needs to be removed manually. Run with
+//!
+//! ```shell
+//! cargo +nightly run --features cli --bin private-currency-cli -- <SUBCOMMAND>
+//! ```
+//!
+//! Use `-h` or `--help` to get the list of subcommands and their params.
+
+extern crate clap;
+extern crate exonum;
+extern crate failure;
+extern crate private_currency;
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use exonum::{
+    crypto::PublicKey,
+    encoding::serialize::{encode_hex, FromHex},
+};
+use failure::{err_msg, Error};
+use private_currency::{
+    api::{TrustAnchor, WalletProof, WalletQuery},
+    client::CurrencyClient,
+    transactions::Transfer,
+    FileStore, SecretState, StateStore, StoredWallet, Transactions,
+};
+use serde::Serialize;
+
+use std::{fs, process};
+
+fn wallet_arg() -> Arg<'static, 'static> {
+    Arg::with_name("wallet")
+        .long("wallet")
+        .short("w")
+        .takes_value(true)
+        .value_name("FILE")
+        .required(true)
+        .help("Path to the wallet file")
+}
+
+fn node_arg() -> Arg<'static, 'static> {
+    Arg::with_name("node")
+        .long("node")
+        .takes_value(true)
+        .value_name("URL")
+        .default_value("http://127.0.0.1:8080")
+        .help("Base URL of the public API of the node")
+}
+
+fn validators_arg() -> Arg<'static, 'static> {
+    Arg::with_name("validators")
+        .long("validators")
+        .takes_value(true)
+        .value_name("KEYS")
+        .required(true)
+        .use_delimiter(true)
+        .help("Comma-separated hex-encoded consensus keys of all validators")
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("private-currency-cli")
+        .about("Wallet for the private cryptocurrency service")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("keygen")
+                .about("Generates a new wallet and prints its CreateWallet transaction")
+                .arg(wallet_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Updates the wallet with verified events from the node")
+                .arg(wallet_arg())
+                .arg(node_arg())
+                .arg(validators_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("transfer")
+                .about("Creates and signs a Transfer transaction offline")
+                .arg(wallet_arg())
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("KEY")
+                        .required(true)
+                        .help("Hex-encoded public key of the receiver"),
+                )
+                .arg(
+                    Arg::with_name("amount")
+                        .long("amount")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Transferred amount"),
+                )
+                .arg(
+                    Arg::with_name("rollback_delay")
+                        .long("rollback-delay")
+                        .takes_value(true)
+                        .value_name("DELAY")
+                        .default_value("50")
+                        .help("Rollback delay of the transfer"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("decrypt")
+                .about(
+                    "Decrypts an incoming transfer and prints its amount \
+                     and the Accept transaction",
+                )
+                .arg(wallet_arg())
+                .arg(
+                    Arg::with_name("transfer")
+                        .long("transfer")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Path to the JSON of the Transfer transaction"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-proof")
+                .about("Verifies a wallet proof downloaded from the v1/wallet endpoint")
+                .arg(
+                    Arg::with_name("proof")
+                        .long("proof")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Path to the JSON of the wallet proof"),
+                )
+                .arg(validators_arg())
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Hex-encoded public key of the wallet"),
+                )
+                .arg(
+                    Arg::with_name("start_history_at")
+                        .long("start-history-at")
+                        .takes_value(true)
+                        .value_name("INDEX")
+                        .default_value("0")
+                        .help("Index of the first requested history event"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("submit")
+                .about("Submits a signed transaction to the node")
+                .arg(node_arg())
+                .arg(
+                    Arg::with_name("transaction")
+                        .long("transaction")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .required(true)
+                        .help("Path to the JSON of the transaction"),
+                ),
+        )
+}
+
+fn parse<T: ::std::str::FromStr>(args: &ArgMatches, name: &str) -> Result<T, Error> {
+    let value = args.value_of(name).expect("required argument");
+    value
+        .parse()
+        .map_err(|_| err_msg(format!("invalid value for `{}`: {}", name, value)))
+}
+
+fn parse_key(value: &str) -> Result<PublicKey, Error> {
+    PublicKey::from_hex(value).map_err(|e| err_msg(format!("invalid key {}: {}", value, e)))
+}
+
+fn trust_anchor(args: &ArgMatches) -> Result<TrustAnchor, Error> {
+    let keys = args
+        .values_of("validators")
+        .expect("required argument")
+        .map(parse_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(TrustAnchor::new(keys))
+}
+
+fn load_wallet(args: &ArgMatches) -> Result<(FileStore, StoredWallet), Error> {
+    let store = FileStore::locked(args.value_of("wallet").expect("required argument"))?;
+    let wallet = store.load()?.ok_or_else(|| {
+        err_msg(format!(
+            "wallet file {} does not exist",
+            store.path().display()
+        ))
+    })?;
+    Ok((store, wallet))
+}
+
+fn read_file(args: &ArgMatches, name: &str) -> Result<String, Error> {
+    let path = args.value_of(name).expect("required argument");
+    fs::read_to_string(path).map_err(|e| err_msg(format!("cannot read {}: {}", path, e)))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Error> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn keygen(args: &ArgMatches) -> Result<(), Error> {
+    let store = FileStore::new(args.value_of("wallet").expect("required argument"));
+    if store.path().exists() {
+        return Err(err_msg(format!(
+            "wallet file {} already exists",
+            store.path().display()
+        )));
+    }
+    let wallet = StoredWallet::new(SecretState::with_random_keypair());
+    store.save(&wallet)?;
+    eprintln!("public key: {}", encode_hex(wallet.state.public_key()));
+    print_json(&wallet.state.create_wallet())
+}
+
+fn sync(args: &ArgMatches) -> Result<(), Error> {
+    let (store, mut wallet) = load_wallet(args)?;
+    let client = CurrencyClient::new(args.value_of("node").unwrap(), trust_anchor(args)?);
+    let proof = client.sync_wallet(&mut wallet, &store)?;
+    println!(
+        "height: {}, balance: {}, history length: {}",
+        proof.block.height(),
+        wallet.state.balance(),
+        wallet.state.history_len()
+    );
+    Ok(())
+}
+
+fn transfer(args: &ArgMatches) -> Result<(), Error> {
+    let (store, mut wallet) = load_wallet(args)?;
+    let receiver = parse_key(args.value_of("to").expect("required argument"))?;
+    let transfer = wallet.state.try_create_transfer(
+        parse(args, "amount")?,
+        &receiver,
+        parse(args, "rollback_delay")?,
+    )?;
+    // The transfer is recorded as pending, so it is reconciled during the following syncs.
+    store.save(&wallet)?;
+    print_json(&transfer)
+}
+
+fn decrypt(args: &ArgMatches) -> Result<(), Error> {
+    let (_, wallet) = load_wallet(args)?;
+    let transfer: Transfer = serde_json::from_str(&read_file(args, "transfer")?)?;
+    let verified = wallet
+        .state
+        .verify_transfer(&transfer)
+        .ok_or_else(|| err_msg("transfer cannot be decrypted or verified by the wallet"))?;
+    eprintln!("amount: {}", verified.value());
+    print_json(&verified.accept)
+}
+
+fn verify_proof(args: &ArgMatches) -> Result<(), Error> {
+    let proof: WalletProof = serde_json::from_str(&read_file(args, "proof")?)?;
+    let key = parse_key(args.value_of("key").expect("required argument"))?;
+    let query = WalletQuery::new(key, parse(args, "start_history_at")?);
+    let checked = proof.check(&trust_anchor(args)?, &query)?;
+    print_json(&json!({
+        "block": checked.block,
+        "wallet": checked.wallet,
+        "history": checked.history,
+        "unaccepted_transfers": checked.unaccepted_transfers,
+    }))
+}
+
+fn submit(args: &ArgMatches) -> Result<(), Error> {
+    let transaction = Transactions::from_json(&read_file(args, "transaction")?)?;
+    // Submitting transactions does not involve proof verification.
+    let client = CurrencyClient::new(args.value_of("node").unwrap(), TrustAnchor::new(vec![]));
+    let hash = match transaction {
+        Transactions::CreateWallet(ref tx) => client.submit(tx)?,
+        Transactions::Transfer(ref tx) => client.submit(tx)?,
+        Transactions::Accept(ref tx) => client.submit(tx)?,
+        Transactions::LockForBridge(ref tx) => client.submit(tx)?,
+        Transactions::ReleaseFromBridge(ref tx) => client.submit(tx)?,
+        Transactions::AcceptWithNote(ref tx) => client.submit(tx)?,
+        Transactions::UpdateSettings(ref tx) => client.submit(tx)?,
+        Transactions::OpenDispute(ref tx) => client.submit(tx)?,
+        Transactions::ResolveDispute(ref tx) => client.submit(tx)?,
+        Transactions::StreamTransfer(ref tx) => client.submit(tx)?,
+        Transactions::CloseStream(ref tx) => client.submit(tx)?,
+        Transactions::Lock(ref tx) => client.submit(tx)?,
+        Transactions::Unlock(ref tx) => client.submit(tx)?,
+        Transactions::SetProfile(ref tx) => client.submit(tx)?,
+        Transactions::RequestTransfer(ref tx) => client.submit(tx)?,
+        Transactions::Rerandomize(ref tx) => client.submit(tx)?,
+        Transactions::BackUpTransfer(ref tx) => client.submit(tx)?,
+    };
+    println!("{}", encode_hex(&hash));
+    Ok(())
+}
+
+fn main() {
+    let matches = app().get_matches();
+    let result = match matches.subcommand() {
+        ("keygen", Some(args)) => keygen(args),
+        ("sync", Some(args)) => sync(args),
+        ("transfer", Some(args)) => transfer(args),
+        ("decrypt", Some(args)) => decrypt(args),
+        ("verify-proof", Some(args)) => verify_proof(args),
+        ("submit", Some(args)) => submit(args),
+        _ => unreachable!("subcommand is required"),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Migrates the storage of the service in a node database to the latest version.
+//! The node must be stopped during the migration.
+//!
+//! Run with
+//!
+//! ```shell
+//! cargo +nightly run --bin private-currency-migrate -- <DB_PATH> [--dry-run]
+//! ```
+
+extern crate exonum;
+extern crate private_currency;
+
+use exonum::storage::{DbOptions, RocksDB};
+use private_currency::migrations::{self, STORAGE_VERSION};
+
+use std::{env, process};
+
+const USAGE: &str = "usage: private-currency-migrate <DB_PATH> [--dry-run]";
+
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let (path, dry_run) = match args.as_slice() {
+        [path] => (path, false),
+        [path, flag] if flag == "--dry-run" => (path, true),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let db = RocksDB::open(path, &DbOptions::default()).unwrap_or_else(|e| {
+        eprintln!("cannot open database at {}: {}", path, e);
+        process::exit(1);
+    });
+    match migrations::migrate(&db, dry_run) {
+        Ok(ref report) if report.is_noop() => {
+            println!("storage is up to date (version {})", STORAGE_VERSION);
+        }
+        Ok(report) => {
+            println!(
+                "migrated storage from version {} to {}{}",
+                report.from,
+                report.to,
+                if dry_run { " (dry run)" } else { "" }
+            );
+            if report.changes_state_hash() {
+                println!(
+                    "re-encoded {} wallet(s); the service state hash has changed",
+                    report.reencoded_wallets
+                );
+                println!("previous service state hash: {:?}", report.prev_state_hash);
+            }
+            println!("service state hash: {:?}", report.state_hash);
+        }
+        Err(e) => {
+            eprintln!("migration failed: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bridge moving funds between deployments of the service.
+//!
+//! Two deployments of the service (e.g., staging and production networks) can be connected
+//! by a bridge if their configurations specify [`BridgeConfig`]s with different network
+//! identifiers. Funds are moved in two steps:
+//!
+//! 1. The owner of a wallet in the source deployment commits a [`LockForBridge`] transaction
+//!    (created with [`SecretState::create_bridge_lock()`]), which specifies the receiver
+//!    and the network identifier of the destination deployment. The locked amount
+//!    is subtracted from the sender’s balance in the same way as for a `Transfer`,
+//!    but is never rolled back.
+//! 2. Relayers fetch the sender’s wallet proof from the source deployment, check it
+//!    against the trust anchor of the source network, and submit [`ReleaseFromBridge`]
+//!    votes embedding the lock to the destination deployment (see [`Relayer`]).
+//!    Once [`threshold`] votes from the relayers listed in the configuration
+//!    of the destination deployment are committed, the locked amount is credited
+//!    to the receiver.
+//!
+//! The commitment to the amount and the encrypted opening are carried over from the lock,
+//! so the amount remains hidden, and the receiver decrypts it in the same way as
+//! for an incoming transfer. A lock can be released only once, and only in the deployment
+//! it is addressed to; locks addressed to the deployment they are submitted to are rejected,
+//! so a lock cannot be replayed as a debit in the destination.
+//!
+//! # Limitations
+//!
+//! - The destination deployment trusts relayers to only vote for committed locks;
+//!   the threshold should be chosen accordingly. The destination checks the signature
+//!   of the lock author, but cannot check that the lock is committed in the source.
+//! - The receiver needs to have a wallet in the destination deployment; releases to
+//!   unregistered wallets fail and may be resubmitted after the wallet is created.
+//! - The total supply of tokens in each deployment changes by the amounts moved through
+//!   the bridge, so [supply proofs](::api::SupplyProof) are not supported for deployments
+//!   with the bridge enabled.
+//!
+//! [`BridgeConfig`]: struct.BridgeConfig.html
+//! [`LockForBridge`]: ::transactions::LockForBridge
+//! [`ReleaseFromBridge`]: ::transactions::ReleaseFromBridge
+//! [`SecretState::create_bridge_lock()`]: ::SecretState::create_bridge_lock()
+//! [`Relayer`]: struct.Relayer.html
+//! [`threshold`]: struct.BridgeConfig.html#structfield.threshold
+
+use exonum::{
+    crypto::{Hash, PublicKey, SecretKey},
+    messages::{Message, MessageBuffer},
+};
+
+use api::{FullEvent, TrustAnchor, VerifyError, WalletProof, WalletQuery};
+use transactions::{LockForBridge, ReleaseFromBridge};
+
+/// Bridge configuration of a deployment of the service, stored in the
+/// [service configuration](::Config::bridge).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BridgeConfig {
+    /// Identifier of this deployment. Locks are addressed to deployments by their identifiers;
+    /// e.g., the hash of the genesis block can be used.
+    pub network_id: Hash,
+    /// Ed25519 keys of relayers authorized to release locks in this deployment.
+    pub relayers: Vec<PublicKey>,
+    /// Number of relayer votes necessary to release a lock.
+    pub threshold: u32,
+}
+
+impl BridgeConfig {
+    /// Checks if the configuration is valid, that is, the threshold is positive
+    /// and does not exceed the number of relayers.
+    pub fn is_valid(&self) -> bool {
+        self.threshold > 0 && self.threshold as usize <= self.relayers.len()
+    }
+}
+
+/// Bridge relayer moving locks from a source deployment of the service to the destination one.
+///
+/// The relayer should run an instance for each destination deployment; it fetches proofs
+/// for wallets in the source deployment (e.g., with the HTTP client) and submits the produced
+/// votes to the destination deployment.
+#[derive(Debug, Clone)]
+pub struct Relayer {
+    public_key: PublicKey,
+    secret_key: SecretKey,
+    source: TrustAnchor,
+    destination: Hash,
+}
+
+impl Relayer {
+    /// Creates a relayer with the specified keypair, the trust anchor of the source deployment
+    /// and the network identifier of the destination deployment.
+    pub fn new(
+        public_key: PublicKey,
+        secret_key: SecretKey,
+        source: TrustAnchor,
+        destination: Hash,
+    ) -> Self {
+        Relayer {
+            public_key,
+            secret_key,
+            source,
+            destination,
+        }
+    }
+
+    /// Returns the public key of the relayer.
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Checks a wallet proof obtained from the source deployment and produces votes
+    /// to release locks from the proven history that are addressed to the destination
+    /// deployment.
+    ///
+    /// Votes for locks which are already released are rejected by the destination
+    /// deployment, so the relayer may keep track of processed history indices in order
+    /// not to resubmit them.
+    pub fn votes(
+        &self,
+        proof: &WalletProof,
+        query: &WalletQuery,
+    ) -> Result<Vec<ReleaseFromBridge>, VerifyError> {
+        let checked = proof.check(&self.source, query)?;
+        let votes = checked
+            .history
+            .iter()
+            .filter_map(|event| match event {
+                FullEvent::BridgeLock(lock) if *lock.from() == query.key => Some(lock),
+                _ => None,
+            })
+            .filter(|lock| *lock.destination() == self.destination)
+            .map(|lock| self.vote(lock))
+            .collect();
+        Ok(votes)
+    }
+
+    /// Produces a vote to release the lock without checking that the lock is committed
+    /// in the source deployment.
+    pub fn vote(&self, lock: &LockForBridge) -> ReleaseFromBridge {
+        let buffer: &MessageBuffer = lock.raw();
+        ReleaseFromBridge::new(&self.public_key, buffer.as_ref(), &self.secret_key)
+    }
+}
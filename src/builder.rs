@@ -0,0 +1,213 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Builder for the service.
+
+use debug::DebuggerProbe;
+use verification::Verifier;
+use {Config, Debugger, DebuggerOptions, Invariants, Metrics, Service, CONFIG};
+
+/// Capacity of the channel between the service and the debugger.
+const DEBUGGER_CHANNEL_SIZE: usize = 16;
+
+/// Builder for a [`Service`] allowing to toggle optional features of the service.
+///
+/// All features are disabled by default, and the default [configuration](::CONFIG)
+/// is used.
+///
+/// The builder has no toggle for the frequency of garbage collection, since the service
+/// does not run periodic garbage collection: data committed to by the state hash
+/// is kept by all nodes, and auxiliary data which is no longer needed (e.g., entries
+/// of the rollback index) is removed when the corresponding transaction or rollback
+/// is processed.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate private_currency;
+/// use private_currency::{DebuggerOptions, ServiceBuilder};
+///
+/// let (service, debugger) = ServiceBuilder::new()
+///     .require_signed_queries(true)
+///     .check_invariants(true)
+///     .metrics(true)
+///     .verification_threads(2)
+///     .debugger(DebuggerOptions::default())
+///     .build();
+/// assert!(debugger.is_some());
+/// assert!(service.metrics().is_some());
+/// # drop(service);
+/// ```
+///
+/// [`Service`]: struct.Service.html
+#[derive(Debug, Clone)]
+pub struct ServiceBuilder {
+    config: Config,
+    signed_queries: bool,
+    invariants: Invariants,
+    metrics: bool,
+    #[cfg(feature = "webhooks")]
+    webhooks: bool,
+    debugger: Option<DebuggerOptions>,
+    verification_threads: usize,
+}
+
+impl Default for ServiceBuilder {
+    fn default() -> Self {
+        ServiceBuilder {
+            config: CONFIG,
+            signed_queries: false,
+            invariants: Invariants::empty(),
+            metrics: false,
+            #[cfg(feature = "webhooks")]
+            webhooks: false,
+            debugger: None,
+            verification_threads: 0,
+        }
+    }
+}
+
+impl ServiceBuilder {
+    /// Creates a builder with all features disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the configuration of the service. See [`Service::with_config()`] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is [invalid](::Config::is_valid()).
+    ///
+    /// [`Service::with_config()`]: struct.Service.html#method.with_config
+    pub fn config(mut self, config: Config) -> Self {
+        assert!(config.is_valid(), "invalid service configuration");
+        self.config = config;
+        self
+    }
+
+    /// Toggles whether queries to the `v1/wallet` endpoint need to be signed
+    /// by the wallet owner. See [`Service::require_signed_queries()`] for details.
+    ///
+    /// [`Service::require_signed_queries()`]: struct.Service.html#method.require_signed_queries
+    pub fn require_signed_queries(mut self, enabled: bool) -> Self {
+        self.signed_queries = enabled;
+        self
+    }
+
+    /// Toggles checking service invariants after each committed block. Violations
+    /// of invariants are logged at the `ERROR` level and counted in [metrics](#method.metrics)
+    /// (if enabled). If a debugger is attached to the service, violations are also reported
+    /// to it as [`DebugEvent::InvariantsViolated`]. A violation does not stop the node.
+    ///
+    /// This is an expensive operation; it is *at least* linear w.r.t. the number of
+    /// wallets in the system.
+    ///
+    /// Enabling this option enables all checks; use [`invariants()`](#method.invariants)
+    /// to select checks.
+    ///
+    /// [`DebugEvent::InvariantsViolated`]: enum.DebugEvent.html#variant.InvariantsViolated
+    pub fn check_invariants(mut self, enabled: bool) -> Self {
+        self.invariants = if enabled {
+            Invariants::all()
+        } else {
+            Invariants::empty()
+        };
+        self
+    }
+
+    /// Selects service invariants checked after each committed block.
+    pub fn invariants(mut self, invariants: Invariants) -> Self {
+        self.invariants = invariants;
+        self
+    }
+
+    /// Toggles collection of [metrics](struct.Metrics.html), which are available
+    /// via [`Service::metrics()`] and the `v1/metrics` endpoint of the private HTTP API.
+    ///
+    /// [`Service::metrics()`]: struct.Service.html#method.metrics
+    pub fn metrics(mut self, enabled: bool) -> Self {
+        self.metrics = enabled;
+        self
+    }
+
+    /// Toggles [webhook notifications](webhooks/index.html) about wallet events.
+    /// Webhooks are registered via the private HTTP API or [`Service::webhooks()`].
+    ///
+    /// [`Service::webhooks()`]: struct.Service.html#method.webhooks
+    #[cfg(feature = "webhooks")]
+    pub fn webhooks(mut self, enabled: bool) -> Self {
+        self.webhooks = enabled;
+        self
+    }
+
+    /// Attaches a debugger to the service. The debugger is returned from
+    /// [`build()`](#method.build).
+    ///
+    /// The debugger has high associated performance penalty; use for debugging only.
+    pub fn debugger(mut self, options: DebuggerOptions) -> Self {
+        self.debugger = Some(options);
+        self
+    }
+
+    /// Sets the number of background threads verifying amount proofs of transfers
+    /// entering the node. With zero threads (the default), proofs are verified
+    /// on the thread receiving transactions. In either case, verified proofs are memoized,
+    /// so that their verification is skipped when transfers are executed.
+    ///
+    /// Background verification does not influence transaction outcomes: if a proof
+    /// has not been verified by the time the transfer is executed, it is verified
+    /// during execution.
+    pub fn verification_threads(mut self, threads: usize) -> Self {
+        self.verification_threads = threads;
+        self
+    }
+
+    /// Builds the service. Returns the service together with the debugger attached to it,
+    /// if the debugger is enabled.
+    pub fn build(self) -> (Service, Option<Debugger>) {
+        let invariants = self.invariants
+            | self
+                .debugger
+                .as_ref()
+                .map_or(Invariants::empty(), |options| options.check_invariants);
+        let (probe, debugger) = if let Some(ref options) = self.debugger {
+            let (probe, debugger) =
+                DebuggerProbe::create_channel(DEBUGGER_CHANNEL_SIZE, options.wallets.clone());
+            (Some(probe), Some(debugger))
+        } else {
+            (None, None)
+        };
+
+        let service = Service {
+            debugger_probe: probe,
+            signed_queries: self.signed_queries,
+            invariants,
+            metrics: if self.metrics {
+                Some(Metrics::default())
+            } else {
+                None
+            },
+            #[cfg(feature = "webhooks")]
+            webhooks: if self.webhooks {
+                Some(Default::default())
+            } else {
+                None
+            },
+            verifier: Verifier::with_threads(self.verification_threads),
+            config: self.config,
+        };
+        (service, debugger)
+    }
+}
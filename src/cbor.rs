@@ -0,0 +1,283 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact CBOR encoding of proofs and transactions for constrained clients.
+//!
+//! The encoding mirrors the [JSON format](::wire) of the HTTP API: a value is serialized
+//! to JSON, and the resulting document is written as CBOR, with every string consisting
+//! of an even number of lowercase hex digits written as a CBOR byte string. Keys, hashes,
+//! signatures, commitments and range proofs thus take half the space of their hex forms;
+//! since most of a [`WalletProof`] consists of such data, the encoded proof is roughly
+//! half as large as its JSON form.
+//!
+//! Decoding converts byte strings back to lowercase hex, so the encoding is lossless
+//! for any JSON document, including decimal strings that happen to be valid hex.
+//!
+//! The HTTP API of Exonum services only speaks JSON, so the encoding is intended for nodes
+//! and gateways with their own transport, in the same way as [`WalletProof::write_json()`].
+//! For example, a gateway may convert proofs returned by the `v1/wallet` endpoint
+//! with [`to_vec()`], and decode transactions submitted by clients with
+//! [`transaction_from_slice()`] before passing them to the `v1/transaction` endpoint.
+//!
+//! This module requires the `cbor` crate feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate private_currency;
+//! use private_currency::{cbor, SecretState, Transactions};
+//!
+//! # fn main() {
+//! let alice = SecretState::with_random_keypair();
+//! let tx = Transactions::CreateWallet(alice.create_wallet());
+//! let bytes = cbor::transaction_to_vec(&tx).unwrap();
+//! assert!(bytes.len() < tx.to_json().len());
+//! let decoded = cbor::transaction_from_slice(&bytes).unwrap();
+//! assert_eq!(decoded.hash(), tx.hash());
+//! # }
+//! ```
+//!
+//! [`WalletProof`]: ::api::WalletProof
+//! [`WalletProof::write_json()`]: ::api::WalletProof::write_json()
+//! [`to_vec()`]: fn.to_vec.html
+//! [`transaction_from_slice()`]: fn.transaction_from_slice.html
+
+use exonum::encoding::serialize::{encode_hex, FromHex};
+use serde::{
+    de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_cbor;
+use serde_json::{self, Map, Number, Value};
+
+use std::fmt;
+
+use transactions::CryptoTransactions;
+
+/// Error encoding or decoding CBOR data.
+#[derive(Debug, Fail)]
+pub enum CborError {
+    /// The value cannot be converted to or from its JSON form.
+    #[fail(display = "invalid JSON form: {}", _0)]
+    Json(#[cause] serde_json::Error),
+
+    /// The data is not valid CBOR, or cannot be written as CBOR.
+    #[fail(display = "invalid CBOR: {}", _0)]
+    Cbor(#[cause] serde_cbor::error::Error),
+}
+
+impl From<serde_json::Error> for CborError {
+    fn from(e: serde_json::Error) -> Self {
+        CborError::Json(e)
+    }
+}
+
+impl From<serde_cbor::error::Error> for CborError {
+    fn from(e: serde_cbor::error::Error) -> Self {
+        CborError::Cbor(e)
+    }
+}
+
+/// Encodes a value in the compact CBOR form.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, CborError> {
+    let value = serde_json::to_value(value)?;
+    serde_cbor::to_vec(&Compact(&value)).map_err(From::from)
+}
+
+/// Decodes a value from the compact CBOR form.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CborError> {
+    let Expanded(value) = serde_cbor::from_slice(bytes)?;
+    serde_json::from_value(value).map_err(From::from)
+}
+
+/// Encodes a transaction of the service in the compact CBOR form.
+pub fn transaction_to_vec(tx: &CryptoTransactions) -> Result<Vec<u8>, CborError> {
+    let value: Value = serde_json::from_str(&tx.to_json())?;
+    to_vec(&value)
+}
+
+/// Decodes a transaction of the service from the compact CBOR form. The signature
+/// of the transaction is not verified.
+pub fn transaction_from_slice(bytes: &[u8]) -> Result<CryptoTransactions, CborError> {
+    from_slice(bytes)
+}
+
+/// Checks whether a string is written as a byte string in the compact form.
+fn is_hex(s: &str) -> bool {
+    s.len() % 2 == 0
+        && s.bytes().all(|c| match c {
+            b'0'..=b'9' | b'a'..=b'f' => true,
+            _ => false,
+        })
+}
+
+/// JSON value serialized in the compact form.
+struct Compact<'a>(&'a Value);
+
+impl<'a> Serialize for Compact<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Number(n) => {
+                if let Some(n) = n.as_u64() {
+                    serializer.serialize_u64(n)
+                } else if let Some(n) = n.as_i64() {
+                    serializer.serialize_i64(n)
+                } else {
+                    serializer.serialize_f64(n.as_f64().expect("JSON number"))
+                }
+            }
+            Value::String(s) if is_hex(s) => {
+                let bytes = Vec::<u8>::from_hex(s).expect("checked hex");
+                serializer.serialize_bytes(&bytes)
+            }
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&Compact(item))?;
+                }
+                seq.end()
+            }
+            Value::Object(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, &Compact(value))?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// JSON value deserialized from the compact form.
+struct Expanded(Value);
+
+impl<'de> Deserialize<'de> for Expanded {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ExpandedVisitor).map(Expanded)
+    }
+}
+
+struct ExpandedVisitor;
+
+impl<'de> Visitor<'de> for ExpandedVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("compact CBOR form of a JSON value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Number::from_f64(value)
+            .map(Value::Number)
+            .ok_or_else(|| E::custom("non-finite number"))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Value, E> {
+        Ok(Value::String(encode_hex(value)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(Expanded(item)) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut fields = Map::new();
+        while let Some((name, Expanded(value))) = map.next_entry::<String, _>()? {
+            fields.insert(name, value);
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+#[test]
+fn compact_roundtrip() {
+    let value = json!({
+        "key": "0123456789abcdef",
+        "history_len": "10",
+        "odd": "abc",
+        "upper": "ABCD",
+        "tag": "Transfer",
+        "empty": "",
+        "numbers": [0, -1, 1.5, 18_446_744_073_709_551_615_u64],
+        "flags": [true, false, null],
+        "nested": { "proof": ["00ff", "ff00"] },
+    });
+    let bytes = to_vec(&value).unwrap();
+    let decoded: Value = from_slice(&bytes).unwrap();
+    assert_eq!(decoded, value);
+}
+
+#[test]
+fn compact_transactions() {
+    use exonum::crypto::gen_keypair;
+    use SecretState;
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let (receiver, _) = gen_keypair();
+    let transfer = state.create_transfer(1_000, &receiver, 10);
+    let tx = CryptoTransactions::Transfer(transfer.clone());
+
+    let bytes = transaction_to_vec(&tx).unwrap();
+    // Most of the transfer consists of hex-encoded data.
+    assert!(bytes.len() * 3 < tx.to_json().len() * 2);
+    let restored = transaction_from_slice(&bytes).unwrap();
+    assert_eq!(restored.hash(), transfer.hash());
+
+    match transaction_from_slice(&bytes[..bytes.len() - 1]) {
+        Err(CborError::Cbor(..)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let not_a_transaction = to_vec(&json!({ "body": "00" })).unwrap();
+    match transaction_from_slice(&not_a_transaction) {
+        Err(CborError::Json(..)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
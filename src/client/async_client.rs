@@ -0,0 +1,201 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Non-blocking HTTP client.
+
+use exonum::{
+    api::node::public::explorer::TransactionQuery,
+    crypto::{CryptoHash, Hash, PublicKey},
+    explorer::TransactionInfo,
+};
+use futures::{
+    future::{self, Either},
+    stream, Future, Stream,
+};
+use reqwest::{
+    async::{Client as HttpClient, Response},
+    StatusCode,
+};
+use serde::Serialize;
+use serde_json::Value;
+use tokio_timer::Delay;
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::{explorer_url, service_url, ClientError, TransactionStatus};
+use api::{CheckedWalletProof, FullEvent, TrustAnchor, WalletProof, WalletQuery};
+
+/// Non-blocking HTTP client for the service.
+///
+/// The client has the same functionality as [`CurrencyClient`], but returns futures
+/// instead of blocking the current thread. This allows to track many wallets on a single
+/// event loop. The futures need to be executed on a Tokio runtime.
+///
+/// [`CurrencyClient`]: struct.CurrencyClient.html
+#[derive(Debug, Clone)]
+pub struct AsyncCurrencyClient {
+    http: HttpClient,
+    base_url: Arc<str>,
+    trust_anchor: Arc<TrustAnchor>,
+    poll_interval: Duration,
+}
+
+fn check_status(response: Response) -> Result<Response, ClientError> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(ClientError::UnexpectedStatus(response.status()))
+    }
+}
+
+impl AsyncCurrencyClient {
+    /// Creates a client connecting to the node with the specified base URL of the public API
+    /// (e.g., `http://127.0.0.1:8080`).
+    ///
+    /// The wallet history is polled every 250 ms by default.
+    pub fn new(base_url: &str, trust_anchor: TrustAnchor) -> Self {
+        AsyncCurrencyClient {
+            http: HttpClient::new(),
+            base_url: base_url.trim_end_matches('/').into(),
+            trust_anchor: Arc::new(trust_anchor),
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+
+    /// Sets the interval between requests to the node in [`events`](#method.events).
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Returns the trust anchor used by the client.
+    pub fn trust_anchor(&self) -> &TrustAnchor {
+        &self.trust_anchor
+    }
+
+    /// Retrieves information about a wallet and checks the returned proof.
+    pub fn wallet_proof(
+        &self,
+        query: WalletQuery,
+    ) -> impl Future<Item = CheckedWalletProof, Error = ClientError> {
+        let trust_anchor = Arc::clone(&self.trust_anchor);
+        self.http
+            .get(&service_url(&self.base_url, "v1/wallet"))
+            .query(&query)
+            .send()
+            .map_err(ClientError::from)
+            .and_then(check_status)
+            .and_then(|mut response| response.json::<WalletProof>().map_err(ClientError::from))
+            .and_then(move |proof| {
+                proof
+                    .check(&trust_anchor, &query)
+                    .map_err(ClientError::from)
+            })
+    }
+
+    /// Submits a transaction to the node.
+    ///
+    /// The future resolves to the hash of the transaction acknowledged by the node.
+    pub fn submit<T>(&self, transaction: &T) -> impl Future<Item = Hash, Error = ClientError>
+    where
+        T: Serialize + CryptoHash,
+    {
+        let expected = transaction.hash();
+        self.http
+            .post(&service_url(&self.base_url, "v1/transaction"))
+            .json(transaction)
+            .send()
+            .map_err(ClientError::from)
+            .and_then(check_status)
+            .and_then(|mut response| response.json::<Hash>().map_err(ClientError::from))
+            .and_then(move |actual| {
+                if actual == expected {
+                    Ok(actual)
+                } else {
+                    Err(ClientError::HashMismatch { expected, actual })
+                }
+            })
+    }
+
+    /// Retrieves the status of a transaction.
+    pub fn transaction_status(
+        &self,
+        tx_hash: &Hash,
+    ) -> impl Future<Item = TransactionStatus, Error = ClientError> {
+        self.http
+            .get(&explorer_url(&self.base_url))
+            .query(&TransactionQuery { hash: *tx_hash })
+            .send()
+            .map_err(ClientError::from)
+            .and_then(|response| {
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Either::A(future::ok(TransactionStatus::Unknown));
+                }
+                let status = future::result(check_status(response)).and_then(|mut response| {
+                    response
+                        .json::<TransactionInfo<Value>>()
+                        .map_err(ClientError::from)
+                        .map(|info| TransactionStatus::from_info(&info))
+                });
+                Either::B(status)
+            })
+    }
+
+    /// Returns an infinite stream of events from the history of the specified wallet,
+    /// starting from the event with index `start_history_at`.
+    ///
+    /// Each stream item is a pair of the event index in the wallet history and the event
+    /// itself. The wallet history is polled periodically; if the wallet does not exist yet,
+    /// the stream waits until it is created. Each response of the node is checked as
+    /// a [`WalletProof`]. The stream terminates on the first error.
+    ///
+    /// [`WalletProof`]: ::api::WalletProof
+    pub fn events(
+        &self,
+        key: PublicKey,
+        start_history_at: u64,
+    ) -> impl Stream<Item = (u64, FullEvent), Error = ClientError> {
+        let client = self.clone();
+        stream::unfold(start_history_at, move |next_index| {
+            let poll_interval = client.poll_interval;
+            let events = client
+                .wallet_proof(WalletQuery::new(key, next_index))
+                .and_then(move |proof| {
+                    let events: Vec<_> = proof
+                        .history
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, event)| (next_index + i as u64, event))
+                        .collect();
+                    let next_index = next_index + events.len() as u64;
+
+                    if events.is_empty() {
+                        // Wait before polling the node again.
+                        let delay = Delay::new(Instant::now() + poll_interval)
+                            .map_err(ClientError::Timer)
+                            .map(move |()| (events, next_index));
+                        Either::A(delay)
+                    } else {
+                        Either::B(future::ok((events, next_index)))
+                    }
+                });
+            Some(events)
+        })
+        .map(stream::iter_ok::<_, ClientError>)
+        .flatten()
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Acceptance deadlines for incoming transfers.
+
+use exonum::helpers::Height;
+
+use transactions::Transfer;
+
+/// Deadline for accepting an incoming transfer, after which the transfer is rolled back
+/// to the sender.
+#[derive(Debug, Clone)]
+pub struct AcceptanceDeadline {
+    /// Unaccepted incoming transfer.
+    pub transfer: Transfer,
+    /// Height of the block including the transfer.
+    pub committed_at: Height,
+    /// Height at which the transfer is rolled back, equal to `committed_at` plus
+    /// the rollback delay of the transfer. To be safe, the `Accept` transaction
+    /// for the transfer should be committed in a block with a lesser height.
+    pub rollback_height: Height,
+    /// Number of blocks remaining until the rollback height, counted from the block
+    /// against which the wallet proof was checked.
+    pub blocks_left: u64,
+}
+
+impl AcceptanceDeadline {
+    pub(super) fn new(transfer: Transfer, committed_at: Height, current_height: Height) -> Self {
+        let rollback_height = Height(committed_at.0 + u64::from(transfer.rollback_delay()));
+        AcceptanceDeadline {
+            transfer,
+            committed_at,
+            rollback_height,
+            blocks_left: rollback_height.0.saturating_sub(current_height.0),
+        }
+    }
+
+    /// Checks if the transfer expires within the specified number of blocks.
+    pub fn expires_within(&self, blocks: u64) -> bool {
+        self.blocks_left <= blocks
+    }
+}
+
+#[test]
+fn deadline_is_computed_from_rollback_delay() {
+    use exonum::crypto::gen_keypair;
+    use SecretState;
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let (receiver, _) = gen_keypair();
+    let transfer = state.create_transfer(1_000, &receiver, 10);
+
+    let deadline = AcceptanceDeadline::new(transfer.clone(), Height(5), Height(8));
+    assert_eq!(deadline.rollback_height, Height(15));
+    assert_eq!(deadline.blocks_left, 7);
+    assert!(deadline.expires_within(7));
+    assert!(!deadline.expires_within(6));
+
+    let deadline = AcceptanceDeadline::new(transfer, Height(5), Height(20));
+    assert_eq!(deadline.blocks_left, 0);
+}
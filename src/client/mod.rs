@@ -0,0 +1,785 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! HTTP client for the service.
+//!
+//! The client is available if the crate is compiled with the `client` feature.
+//! A non-blocking [`AsyncCurrencyClient`] built on futures is additionally available
+//! with the `async-client` feature.
+//!
+//! [`AsyncCurrencyClient`]: struct.AsyncCurrencyClient.html
+
+use exonum::{
+    api::node::public::explorer::TransactionQuery,
+    crypto::{CryptoHash, Hash, PublicKey},
+    explorer::TransactionInfo,
+};
+use reqwest::{Client as HttpClient, Error as HttpError, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use api::{
+    BalanceHistoryProof, BalanceHistoryQuery, CheckedCheckpointProof, CheckedInactivityProof,
+    CheckedReceiptProof, CheckedWalletProof, CheckpointProof, CheckpointQuery, InactivityProof,
+    InactivityQuery, ReceiptProof, ReceiptQuery, RollbackDelayQuery, RollbackDelayRecommendation,
+    SettingsQuery, TransferBackupQuery, TransferFilterQuery, TransferRequestQuery,
+    TransferRequestStatus, TrustAnchor, VerifyError, WalletProof, WalletQuery, WalletSettings,
+};
+use transactions::{BackUpTransfer, Transfer};
+use {
+    AcceptDecision, AcceptPolicy, Config, SecretState, StateStore, StoreError, StoredWallet,
+    SyncError, Transactions, TransferFilter, VerifiedTransfer, SERVICE_NAME,
+};
+
+#[cfg(feature = "async-client")]
+pub use self::async_client::AsyncCurrencyClient;
+pub use self::deadline::AcceptanceDeadline;
+pub use self::observer::{WalletObserver, WalletSubscription};
+pub use self::retry::RetryPolicy;
+
+#[cfg(feature = "async-client")]
+mod async_client;
+mod deadline;
+mod observer;
+mod retry;
+
+/// Error that can occur during communication with an Exonum node.
+#[derive(Debug, Fail)]
+pub enum ClientError {
+    /// Error on the HTTP level (e.g., the node is unreachable).
+    #[fail(display = "HTTP error: {}", _0)]
+    Http(#[cause] HttpError),
+
+    /// The node has responded with an unexpected HTTP status.
+    #[fail(display = "unexpected HTTP status: {}", _0)]
+    UnexpectedStatus(StatusCode),
+
+    /// The node has acknowledged a transaction with a hash different from the submitted one.
+    #[fail(display = "node returned unexpected transaction hash")]
+    HashMismatch {
+        /// Hash of the submitted transaction.
+        expected: Hash,
+        /// Hash returned by the node.
+        actual: Hash,
+    },
+
+    /// A proof returned by the node is invalid.
+    #[fail(display = "invalid proof: {}", _0)]
+    Verify(#[cause] VerifyError),
+
+    /// The transaction is unknown to the node; it may have been evicted from the memory pool.
+    #[fail(display = "transaction is unknown to the node")]
+    UnknownTransaction,
+
+    /// The transaction has been committed, but its execution has failed.
+    #[fail(display = "transaction execution failed: {}", _0)]
+    TransactionFailed(String),
+
+    /// The transaction has not been committed within the specified timeout.
+    #[fail(display = "timed out waiting for transaction commitment")]
+    Timeout,
+
+    /// The local wallet state cannot be synchronized with the blockchain.
+    #[fail(display = "cannot synchronize wallet: {}", _0)]
+    Sync(#[cause] SyncError),
+
+    /// The wallet cannot be persisted.
+    #[fail(display = "cannot persist wallet: {}", _0)]
+    Store(#[cause] StoreError),
+
+    /// Error in the timer driving the asynchronous client.
+    #[cfg(feature = "async-client")]
+    #[fail(display = "timer error: {}", _0)]
+    Timer(#[cause] ::tokio_timer::Error),
+}
+
+impl From<HttpError> for ClientError {
+    fn from(e: HttpError) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+impl From<SyncError> for ClientError {
+    fn from(e: SyncError) -> Self {
+        ClientError::Sync(e)
+    }
+}
+
+impl From<StoreError> for ClientError {
+    fn from(e: StoreError) -> Self {
+        ClientError::Store(e)
+    }
+}
+
+impl From<VerifyError> for ClientError {
+    fn from(e: VerifyError) -> Self {
+        ClientError::Verify(e)
+    }
+}
+
+/// Status of a transaction as reported by the blockchain explorer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionStatus {
+    /// The transaction is unknown to the node.
+    Unknown,
+    /// The transaction is in the memory pool of the node.
+    InPool,
+    /// The transaction is committed and has been executed successfully.
+    Committed,
+    /// The transaction is committed, but its execution has failed. The payload
+    /// is the description of the execution error.
+    Failed(String),
+}
+
+fn service_url(base_url: &str, endpoint: &str) -> String {
+    format!("{}/api/services/{}/{}", base_url, SERVICE_NAME, endpoint)
+}
+
+fn explorer_url(base_url: &str) -> String {
+    format!("{}/api/explorer/v1/transactions", base_url)
+}
+
+impl TransactionStatus {
+    fn from_info(info: &TransactionInfo<Value>) -> Self {
+        match info.as_committed() {
+            None => TransactionStatus::InPool,
+            Some(committed) => match committed.status() {
+                Ok(()) => TransactionStatus::Committed,
+                Err(e) => TransactionStatus::Failed(e.to_string()),
+            },
+        }
+    }
+}
+
+/// HTTP client for the service.
+///
+/// The client is a thin wrapper around the HTTP API of Exonum nodes, which checks
+/// wallet proofs against a [`TrustAnchor`].
+///
+/// Requests failing with a transient error are retried according to the [`RetryPolicy`]
+/// of the client. If the client is connected to several nodes, a failed request is retried
+/// with the next node, which is then used for subsequent requests. Since wallet proofs
+/// are checked against the trust anchor, the nodes do not need to be trusted.
+///
+/// [`TrustAnchor`]: ::api::TrustAnchor
+/// [`RetryPolicy`]: struct.RetryPolicy.html
+#[derive(Debug, Clone)]
+pub struct CurrencyClient {
+    http: HttpClient,
+    base_urls: Vec<String>,
+    current_node: Arc<AtomicUsize>,
+    retry_policy: RetryPolicy,
+    trust_anchor: TrustAnchor,
+    poll_interval: Duration,
+}
+
+impl CurrencyClient {
+    /// Creates a client connecting to the node with the specified base URL of the public API
+    /// (e.g., `http://127.0.0.1:8080`).
+    ///
+    /// Transaction status is polled every 250 ms by default. Failed requests are retried
+    /// according to `RetryPolicy::default()`.
+    pub fn new(base_url: &str, trust_anchor: TrustAnchor) -> Self {
+        Self::with_failover(&[base_url], trust_anchor)
+    }
+
+    /// Creates a client connecting to several nodes with the specified base URLs.
+    /// Requests are sent to the first node until it fails, after which the client
+    /// switches to the next node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base_urls` is empty.
+    pub fn with_failover<S: AsRef<str>>(base_urls: &[S], trust_anchor: TrustAnchor) -> Self {
+        assert!(!base_urls.is_empty(), "no node URLs specified");
+        CurrencyClient {
+            http: HttpClient::new(),
+            base_urls: base_urls
+                .iter()
+                .map(|url| url.as_ref().trim_end_matches('/').to_owned())
+                .collect(),
+            current_node: Arc::new(AtomicUsize::new(0)),
+            retry_policy: RetryPolicy::default(),
+            trust_anchor,
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+
+    /// Sets the policy of retrying failed requests.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the timeout for each HTTP request. A timed out request is considered failed
+    /// and is retried according to the retry policy. By default, requests time out
+    /// after 30 seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client cannot be initialized (e.g., if the TLS backend
+    /// cannot be loaded).
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.http = HttpClient::builder()
+            .timeout(timeout)
+            .build()
+            .expect("cannot initialize HTTP client");
+        self
+    }
+
+    /// Sets the interval between transaction status requests in
+    /// [`await_commit`](#method.await_commit).
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Returns the trust anchor used by the client.
+    pub fn trust_anchor(&self) -> &TrustAnchor {
+        &self.trust_anchor
+    }
+
+    /// Returns the base URL of the node currently used by the client.
+    pub fn current_node(&self) -> &str {
+        let index = self.current_node.load(Ordering::Relaxed) % self.base_urls.len();
+        &self.base_urls[index]
+    }
+
+    fn check_status(response: Response) -> Result<Response, ClientError> {
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(ClientError::UnexpectedStatus(response.status()))
+        }
+    }
+
+    /// Performs a request, retrying it on transient errors and switching to the next node
+    /// after each failure.
+    fn request<T, F>(&self, mut f: F) -> Result<T, ClientError>
+    where
+        F: FnMut(&str) -> Result<T, ClientError>,
+    {
+        let max_attempts = self.retry_policy.max_attempts.max(1);
+        let mut attempt = 0;
+        loop {
+            let node = self.current_node.load(Ordering::Relaxed);
+            let base_url = &self.base_urls[node % self.base_urls.len()];
+            match f(base_url) {
+                Err(ref e) if e.is_transient() && attempt + 1 < max_attempts => {
+                    // Switch to the next node unless another thread has already done so.
+                    self.current_node.compare_and_swap(
+                        node,
+                        (node + 1) % self.base_urls.len(),
+                        Ordering::Relaxed,
+                    );
+                    thread::sleep(self.retry_policy.backoff(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Retrieves information about a wallet and checks the returned proof.
+    pub fn wallet_proof(&self, query: &WalletQuery) -> Result<CheckedWalletProof, ClientError> {
+        let proof: WalletProof = self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/wallet"))
+                .query(query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })?;
+        proof
+            .check(&self.trust_anchor, query)
+            .map_err(ClientError::from)
+    }
+
+    /// Retrieves a proof that a wallet has made no outgoing payments since the height
+    /// specified in the query, and checks it.
+    pub fn inactivity_proof(
+        &self,
+        query: &InactivityQuery,
+    ) -> Result<CheckedInactivityProof, ClientError> {
+        let proof: InactivityProof = self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/inactivity"))
+                .query(query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })?;
+        proof
+            .check(&self.trust_anchor, query)
+            .map_err(ClientError::from)
+    }
+
+    /// Retrieves a proof that a transfer sent or received by a wallet has been accepted,
+    /// and checks it.
+    pub fn receipt_proof(&self, query: &ReceiptQuery) -> Result<CheckedReceiptProof, ClientError> {
+        let proof: ReceiptProof = self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/receipt"))
+                .query(query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })?;
+        proof
+            .check(&self.trust_anchor, query)
+            .map_err(ClientError::from)
+    }
+
+    /// Retrieves history checkpoints of a wallet starting from the one specified
+    /// in the query, together with the events after the latest checkpoint, and checks
+    /// the returned proof.
+    pub fn checkpoint_proof(
+        &self,
+        query: &CheckpointQuery,
+    ) -> Result<CheckedCheckpointProof, ClientError> {
+        let proof: CheckpointProof = self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/checkpoints"))
+                .query(query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })?;
+        proof
+            .check(&self.trust_anchor, query)
+            .map_err(ClientError::from)
+    }
+
+    /// Retrieves commitments to the balances of a wallet within the range of heights
+    /// specified in the query. The proof is checked before being returned, so that
+    /// it can be used to [prove a balance floor](::SecretState::prove_balance_floor()).
+    pub fn balance_history_proof(
+        &self,
+        query: &BalanceHistoryQuery,
+    ) -> Result<BalanceHistoryProof, ClientError> {
+        let proof: BalanceHistoryProof = self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/balance_history"))
+                .query(query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })?;
+        proof.check(&self.trust_anchor, query)?;
+        Ok(proof)
+    }
+
+    /// Retrieves the actual configuration of the service.
+    ///
+    /// The configuration is not covered by a proof, so a malicious node may return
+    /// an incorrect configuration. Since the configuration is only used to check constraints
+    /// on created transfers, this may at worst lead to transfers rejected by the blockchain.
+    pub fn config(&self) -> Result<Config, ClientError> {
+        self.request(|base_url| {
+            let response = self.http.get(&service_url(base_url, "v1/config")).send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })
+    }
+
+    /// Retrieves settings of the wallet with the specified key, such as the minimum
+    /// rollback delay of incoming transfers. Transfers to the wallet with a lesser
+    /// rollback delay are rejected by the blockchain.
+    ///
+    /// Similar to the [configuration](#method.config), the settings are not covered
+    /// by a proof.
+    pub fn settings(&self, key: &PublicKey) -> Result<WalletSettings, ClientError> {
+        let query = SettingsQuery { key: *key };
+        self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/settings"))
+                .query(&query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })
+    }
+
+    /// Retrieves a rollback delay recommended for a transfer to the specified receiver.
+    /// Similar to the [settings](#method.settings), the recommendation is not covered
+    /// by a proof.
+    pub fn recommended_rollback_delay(
+        &self,
+        receiver: &PublicKey,
+    ) -> Result<RollbackDelayRecommendation, ClientError> {
+        let query = RollbackDelayQuery {
+            receiver: *receiver,
+        };
+        self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/rollback_delay"))
+                .query(&query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })
+    }
+
+    /// Retrieves the Bloom filter of unaccepted incoming transfers of the wallet with
+    /// the specified key. Similar to the [settings](#method.settings), the filter
+    /// is not covered by a proof.
+    ///
+    /// The filter is a cheap way to poll for incoming transfers: if it is
+    /// [covered](::TransferFilter::is_covered_by()) by the transfers known to the client,
+    /// a [wallet proof](#method.wallet_proof) is unlikely to contain anything new.
+    pub fn unaccepted_filter(&self, key: &PublicKey) -> Result<TransferFilter, ClientError> {
+        let query = TransferFilterQuery { key: *key };
+        self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/unaccepted_filter"))
+                .query(&query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })
+    }
+
+    /// Retrieves the status of a transfer requested with a `RequestTransfer` transaction
+    /// with the specified hash. Similar to the [settings](#method.settings), the status
+    /// is not covered by a proof.
+    pub fn transfer_request_status(
+        &self,
+        request_id: &Hash,
+    ) -> Result<TransferRequestStatus, ClientError> {
+        let query = TransferRequestQuery { id: *request_id };
+        self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/transfer_request"))
+                .query(&query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })
+    }
+
+    /// Retrieves the `BackUpTransfer` transaction publishing the backup copy of the opening
+    /// for the transfer with the specified hash. The backup is not covered by a proof;
+    /// use [`SecretState::backup_opening()`] to decrypt and check it.
+    ///
+    /// [`SecretState::backup_opening()`]: ::SecretState::backup_opening()
+    pub fn transfer_backup(
+        &self,
+        transfer_id: &Hash,
+    ) -> Result<Option<BackUpTransfer>, ClientError> {
+        let query = TransferBackupQuery { id: *transfer_id };
+        self.request(|base_url| {
+            let response = self
+                .http
+                .get(&service_url(base_url, "v1/transfer_backup"))
+                .query(&query)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })
+    }
+
+    /// Synchronizes a wallet with the blockchain and saves it to the store if any new events
+    /// have been applied.
+    ///
+    /// The wallet history is queried starting from the first event not applied
+    /// to the wallet yet, so it is safe to call this method after a restart with a wallet
+    /// [loaded](::StateStore::load()) from the store.
+    ///
+    /// # Return value
+    ///
+    /// Returns the verified wallet proof. Events from the proof are already applied
+    /// to the wallet.
+    pub fn sync_wallet<S>(
+        &self,
+        wallet: &mut StoredWallet,
+        store: &S,
+    ) -> Result<CheckedWalletProof, ClientError>
+    where
+        S: StateStore + ?Sized,
+    {
+        let query = WalletQuery::new(*wallet.state.public_key(), wallet.state.history_len());
+        let proof = self.wallet_proof(&query)?;
+        if wallet.state.sync_with_proof(&proof)? > 0 {
+            wallet.events.extend_from_slice(&proof.history);
+            store.save(wallet)?;
+        }
+        Ok(proof)
+    }
+
+    /// Submits a transaction to the node.
+    ///
+    /// # Return value
+    ///
+    /// Returns the hash of the transaction acknowledged by the node.
+    pub fn submit<T>(&self, transaction: &T) -> Result<Hash, ClientError>
+    where
+        T: Serialize + CryptoHash,
+    {
+        self.post_transaction(transaction, transaction.hash())
+    }
+
+    /// Posts the JSON form of a transaction to the node and checks the acknowledged hash.
+    fn post_transaction<T: Serialize>(
+        &self,
+        transaction: &T,
+        expected: Hash,
+    ) -> Result<Hash, ClientError> {
+        // Resubmitting a transaction is safe: the node ignores transactions
+        // it already knows about.
+        let actual: Hash = self.request(|base_url| {
+            let response = self
+                .http
+                .post(&service_url(base_url, "v1/transaction"))
+                .json(transaction)
+                .send()?;
+            Self::check_status(response)?
+                .json()
+                .map_err(ClientError::from)
+        })?;
+        if actual == expected {
+            Ok(actual)
+        } else {
+            Err(ClientError::HashMismatch { expected, actual })
+        }
+    }
+
+    /// Submits a transaction to the node, recording it as [unconfirmed] in the wallet.
+    /// The wallet is saved to the store before the transaction is submitted, so that
+    /// the transaction can be [re-broadcast](#method.rebroadcast) after a client restart.
+    ///
+    /// [unconfirmed]: ::StoredWallet::unconfirmed
+    pub fn submit_tracked<S>(
+        &self,
+        transaction: &Transactions,
+        wallet: &mut StoredWallet,
+        store: &S,
+    ) -> Result<Hash, ClientError>
+    where
+        S: StateStore + ?Sized,
+    {
+        if wallet.track(transaction) {
+            store.save(wallet)?;
+        }
+        let json: Value =
+            serde_json::from_str(&transaction.to_json()).expect("JSON form of a transaction");
+        self.post_transaction(&json, transaction.hash())
+    }
+
+    /// Checks the status of [unconfirmed] transactions of the wallet and re-broadcasts
+    /// transactions unknown to the node. A transaction may be lost by the node,
+    /// for example, if the node has been restarted or the transaction has been evicted
+    /// from the memory pool.
+    ///
+    /// Committed transactions are no longer tracked. Transactions which have failed
+    /// to execute are no longer tracked either; pending transfers among them are
+    /// [aborted](::SecretState::abort()). The wallet is saved to the store if it
+    /// has been changed.
+    ///
+    /// # Return value
+    ///
+    /// Returns the hashes of checked transactions together with their statuses
+    /// before re-broadcasting. Transactions with the `Unknown` status have been re-broadcast.
+    ///
+    /// [unconfirmed]: ::StoredWallet::unconfirmed
+    pub fn rebroadcast<S>(
+        &self,
+        wallet: &mut StoredWallet,
+        store: &S,
+    ) -> Result<Vec<(Hash, TransactionStatus)>, ClientError>
+    where
+        S: StateStore + ?Sized,
+    {
+        let mut statuses = Vec::with_capacity(wallet.unconfirmed.len());
+        let mut still_unconfirmed = Vec::with_capacity(wallet.unconfirmed.len());
+        for unconfirmed in &wallet.unconfirmed {
+            let status = self.transaction_status(&unconfirmed.hash)?;
+            match status {
+                TransactionStatus::Committed => {}
+                TransactionStatus::Failed(_) => {
+                    wallet.state.abort(&unconfirmed.hash);
+                }
+                TransactionStatus::InPool => still_unconfirmed.push(unconfirmed.clone()),
+                TransactionStatus::Unknown => {
+                    // Records are written by the client itself, so they are expected
+                    // to be well-formed; a corrupted record cannot be re-broadcast
+                    // and is dropped.
+                    if let Ok(transaction) = unconfirmed.transaction() {
+                        let json: Value = serde_json::from_str(&transaction.to_json())
+                            .expect("JSON form of a transaction");
+                        self.post_transaction(&json, unconfirmed.hash)?;
+                        still_unconfirmed.push(unconfirmed.clone());
+                    }
+                }
+            }
+            statuses.push((unconfirmed.hash, status));
+        }
+
+        if still_unconfirmed.len() != wallet.unconfirmed.len() {
+            wallet.unconfirmed = still_unconfirmed;
+            store.save(wallet)?;
+        }
+        Ok(statuses)
+    }
+
+    /// Processes incoming transfers according to the specified policy. `Accept` transactions
+    /// for automatically accepted transfers are submitted to the node; rejected transfers
+    /// and transfers that cannot be verified are ignored.
+    ///
+    /// # Return value
+    ///
+    /// Returns transfers requiring manual approval. To accept such a transfer, submit
+    /// its `accept` transaction.
+    pub fn accept_transfers<P>(
+        &self,
+        state: &SecretState,
+        transfers: &[Transfer],
+        policy: &P,
+    ) -> Result<Vec<VerifiedTransfer>, ClientError>
+    where
+        P: AcceptPolicy + ?Sized,
+    {
+        let mut awaiting_approval = vec![];
+        for transfer in transfers {
+            match state.evaluate_transfer(transfer, policy) {
+                Some((AcceptDecision::Accept, verified)) => {
+                    self.submit(&verified.accept)?;
+                }
+                Some((AcceptDecision::ManualApproval, verified)) => {
+                    awaiting_approval.push(verified);
+                }
+                Some((AcceptDecision::Reject, _)) | None => {}
+            }
+        }
+        Ok(awaiting_approval)
+    }
+
+    /// Retrieves information about a transaction from the blockchain explorer.
+    /// Returns `None` if the transaction is unknown to the node.
+    fn transaction_info(
+        &self,
+        tx_hash: &Hash,
+    ) -> Result<Option<TransactionInfo<Value>>, ClientError> {
+        self.request(|base_url| {
+            let response = self
+                .http
+                .get(&explorer_url(base_url))
+                .query(&TransactionQuery { hash: *tx_hash })
+                .send()?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            Self::check_status(response)?
+                .json()
+                .map(Some)
+                .map_err(ClientError::from)
+        })
+    }
+
+    /// Retrieves the status of a transaction.
+    pub fn transaction_status(&self, tx_hash: &Hash) -> Result<TransactionStatus, ClientError> {
+        Ok(self
+            .transaction_info(tx_hash)?
+            .map_or(TransactionStatus::Unknown, |info| {
+                TransactionStatus::from_info(&info)
+            }))
+    }
+
+    /// Computes acceptance deadlines for unaccepted incoming transfers from a wallet proof.
+    ///
+    /// The heights of blocks including the transfers are retrieved from the blockchain
+    /// explorer. Unlike the contents of the wallet proof, these heights are not verified
+    /// against the trust anchor.
+    ///
+    /// # Return value
+    ///
+    /// Returns deadlines sorted by the rollback height, so that the most urgent transfers
+    /// come first.
+    pub fn acceptance_deadlines(
+        &self,
+        proof: &CheckedWalletProof,
+    ) -> Result<Vec<AcceptanceDeadline>, ClientError> {
+        let current_height = proof.block.height();
+        let mut deadlines = Vec::with_capacity(proof.unaccepted_transfers.len());
+        for transfer in &proof.unaccepted_transfers {
+            let committed_at = self
+                .transaction_info(&transfer.hash())?
+                .as_ref()
+                .and_then(|info| info.as_committed())
+                .map(|committed| committed.location().block_height())
+                .ok_or(ClientError::UnknownTransaction)?;
+            deadlines.push(AcceptanceDeadline::new(
+                transfer.clone(),
+                committed_at,
+                current_height,
+            ));
+        }
+        deadlines.sort_by_key(|deadline| deadline.rollback_height);
+        Ok(deadlines)
+    }
+
+    /// Waits until the transaction is committed to the blockchain.
+    ///
+    /// # Return value
+    ///
+    /// Returns `Ok(())` if the transaction has been committed and executed successfully.
+    /// An error is returned if the transaction execution has failed, the transaction is unknown
+    /// to the node, or the transaction has not been committed within `timeout`.
+    pub fn await_commit(&self, tx_hash: &Hash, timeout: Duration) -> Result<(), ClientError> {
+        let start = Instant::now();
+        loop {
+            match self.transaction_status(tx_hash)? {
+                TransactionStatus::Committed => return Ok(()),
+                TransactionStatus::Failed(description) => {
+                    return Err(ClientError::TransactionFailed(description));
+                }
+                TransactionStatus::Unknown => return Err(ClientError::UnknownTransaction),
+                TransactionStatus::InPool => {}
+            }
+
+            if start.elapsed() >= timeout {
+                return Err(ClientError::Timeout);
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Notifications about changes of a wallet.
+
+use exonum::crypto::{CryptoHash, Hash};
+
+use std::{collections::HashSet, thread};
+
+use super::{AcceptanceDeadline, ClientError, CurrencyClient};
+use api::{CheckedWalletProof, FullEvent};
+use transactions::Transfer;
+use {LedgerEntry, SecretState, StateStore, StoredWallet, VerifiedTransfer};
+
+/// Observer of changes of a wallet.
+///
+/// All methods have no-op default implementations, so an observer only needs to implement
+/// methods for the changes it is interested in. Observers are driven
+/// by a [`WalletSubscription`].
+///
+/// [`WalletSubscription`]: struct.WalletSubscription.html
+pub trait WalletObserver {
+    /// Called when a new incoming transfer awaiting acceptance is discovered.
+    /// The transfer is already verified; to accept it, submit `verified.accept`
+    /// to the blockchain.
+    ///
+    /// Transfers that cannot be verified (e.g., because the encrypted amount cannot
+    /// be decrypted) are not reported.
+    fn on_transfer_received(&mut self, _transfer: &Transfer, _verified: &VerifiedTransfer) {}
+
+    /// Called when an outgoing transfer is rolled back, i.e., the funds return to the wallet
+    /// because the receiver has not accepted the transfer in time. `index` is the index
+    /// of the rollback event in the wallet history.
+    fn on_rollback(&mut self, _index: u64, _transfer: &Transfer) {}
+
+    /// Called for each event in the wallet history changing the balance of the wallet.
+    /// Changes are reported in the order of events, after `on_rollback` notifications.
+    fn on_balance_change(&mut self, _entry: &LedgerEntry) {}
+
+    /// Called on each poll for every unaccepted incoming transfer expiring within
+    /// the number of blocks specified with [`WalletSubscription::with_expiry_warnings`].
+    /// Transfers are reported in the order of their deadlines.
+    ///
+    /// [`WalletSubscription::with_expiry_warnings`]: struct.WalletSubscription.html#method.with_expiry_warnings
+    fn on_transfer_expiring(&mut self, _deadline: &AcceptanceDeadline) {}
+}
+
+/// Subscription to changes of a wallet, which notifies a [`WalletObserver`] about them.
+///
+/// The subscription keeps track of incoming transfers that have been reported,
+/// so that each transfer is reported only once.
+///
+/// [`WalletObserver`]: trait.WalletObserver.html
+#[derive(Debug)]
+pub struct WalletSubscription<O> {
+    observer: O,
+    known_transfers: HashSet<Hash>,
+    expiry_warning_blocks: Option<u64>,
+}
+
+impl<O: WalletObserver> WalletSubscription<O> {
+    /// Creates a subscription with the specified observer.
+    pub fn new(observer: O) -> Self {
+        WalletSubscription {
+            observer,
+            known_transfers: HashSet::new(),
+            expiry_warning_blocks: None,
+        }
+    }
+
+    /// Enables notifications about unaccepted incoming transfers expiring within
+    /// the specified number of blocks. Computing deadlines requires an additional request
+    /// to the node for each unaccepted transfer on each poll.
+    pub fn with_expiry_warnings(mut self, blocks: u64) -> Self {
+        self.expiry_warning_blocks = Some(blocks);
+        self
+    }
+
+    /// Returns a reference to the observer.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Returns a mutable reference to the observer.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Converts the subscription into the observer.
+    pub fn into_observer(self) -> O {
+        self.observer
+    }
+
+    /// Synchronizes a wallet with the blockchain using [`CurrencyClient::sync_wallet`],
+    /// and notifies the observer about the changes.
+    ///
+    /// [`CurrencyClient::sync_wallet`]: struct.CurrencyClient.html#method.sync_wallet
+    pub fn poll<S>(
+        &mut self,
+        client: &CurrencyClient,
+        wallet: &mut StoredWallet,
+        store: &S,
+    ) -> Result<CheckedWalletProof, ClientError>
+    where
+        S: StateStore + ?Sized,
+    {
+        let start_index = wallet.state.history_len();
+        let proof = client.sync_wallet(wallet, store)?;
+        let new_events = &wallet.events[start_index as usize..];
+        self.notify(
+            &wallet.state,
+            start_index,
+            new_events,
+            &proof.unaccepted_transfers,
+        );
+
+        if let Some(blocks) = self.expiry_warning_blocks {
+            let deadlines = client.acceptance_deadlines(&proof)?;
+            for deadline in deadlines.iter().filter(|d| d.expires_within(blocks)) {
+                self.observer.on_transfer_expiring(deadline);
+            }
+        }
+        Ok(proof)
+    }
+
+    /// Polls the wallet with the poll interval of the client until an error occurs.
+    pub fn run<S>(
+        &mut self,
+        client: &CurrencyClient,
+        wallet: &mut StoredWallet,
+        store: &S,
+    ) -> ClientError
+    where
+        S: StateStore + ?Sized,
+    {
+        loop {
+            if let Err(e) = self.poll(client, wallet, store) {
+                return e;
+            }
+            thread::sleep(client.poll_interval);
+        }
+    }
+
+    /// Notifies the observer about events applied to `state` starting from `start_index`,
+    /// and about unaccepted incoming transfers.
+    fn notify(
+        &mut self,
+        state: &SecretState,
+        start_index: u64,
+        new_events: &[FullEvent],
+        unaccepted_transfers: &[Transfer],
+    ) {
+        for (i, event) in new_events.iter().enumerate() {
+            if let FullEvent::Rollback(ref transfer) = *event {
+                self.observer.on_rollback(start_index + i as u64, transfer);
+            }
+        }
+        for entry in state.ledger().iter().filter(|e| e.index >= start_index) {
+            self.observer.on_balance_change(entry);
+        }
+
+        let mut known_transfers = HashSet::with_capacity(unaccepted_transfers.len());
+        for transfer in unaccepted_transfers {
+            let hash = transfer.hash();
+            if !self.known_transfers.contains(&hash) {
+                if let Some(verified) = state.verify_transfer(transfer) {
+                    self.observer.on_transfer_received(transfer, &verified);
+                }
+            }
+            known_transfers.insert(hash);
+        }
+        // Forget about transfers that have been accepted or rolled back.
+        self.known_transfers = known_transfers;
+    }
+}
+
+#[test]
+fn observer_is_notified_once() {
+    #[derive(Debug, Default)]
+    struct Log {
+        received: Vec<u64>,
+        rollbacks: Vec<u64>,
+        balances: Vec<u64>,
+    }
+
+    impl WalletObserver for Log {
+        fn on_transfer_received(&mut self, _: &Transfer, verified: &VerifiedTransfer) {
+            self.received.push(verified.value());
+        }
+
+        fn on_rollback(&mut self, index: u64, _: &Transfer) {
+            self.rollbacks.push(index);
+        }
+
+        fn on_balance_change(&mut self, entry: &LedgerEntry) {
+            self.balances.push(entry.balance);
+        }
+    }
+
+    let mut alice = SecretState::with_random_keypair();
+    alice.initialize();
+    let mut bob = SecretState::with_random_keypair();
+    bob.initialize();
+    let transfer = alice.create_transfer(1_000, bob.public_key(), 10);
+    let initial_balance = alice.balance();
+
+    let mut subscription = WalletSubscription::new(Log::default());
+    let unaccepted = vec![transfer.clone()];
+    subscription.notify(&bob, 1, &[], &unaccepted);
+    subscription.notify(&bob, 1, &[], &unaccepted);
+    assert_eq!(subscription.observer().received, vec![1_000]);
+
+    let events = vec![
+        FullEvent::Transfer(transfer.clone()),
+        FullEvent::Rollback(transfer),
+    ];
+    for (i, event) in events.iter().enumerate() {
+        alice.apply_event(1 + i as u64, event).unwrap();
+    }
+    let mut subscription = WalletSubscription::new(Log::default());
+    subscription.notify(&alice, 1, &events, &[]);
+    let log = subscription.into_observer();
+    assert_eq!(log.rollbacks, vec![2]);
+    assert_eq!(log.balances, vec![initial_balance - 1_000, initial_balance]);
+}
@@ -0,0 +1,94 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retry policy for HTTP requests.
+
+use std::{cmp, time::Duration};
+
+use super::ClientError;
+
+/// Policy of retrying failed requests to Exonum nodes.
+///
+/// Only transient errors are retried: failures on the HTTP level (e.g., an unreachable node
+/// or a request timeout) and server errors (HTTP statuses `5xx`). Before each retry,
+/// the client waits for a delay, which is doubled after each attempt (i.e., the client
+/// uses exponential backoff). If the client is connected to several nodes, it also
+/// switches to the next node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts for a single request, including the first attempt.
+    /// Values less than 1 are treated as 1.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between retries.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries requests.
+    pub fn no_retries() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the delay before the retry following the attempt with the specified
+    /// zero-based index.
+    pub(super) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1_u32.checked_shl(attempt).unwrap_or(u32::max_value());
+        let backoff = self
+            .initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff);
+        cmp::min(backoff, self.max_backoff)
+    }
+}
+
+impl ClientError {
+    /// Checks if the error is transient, i.e., the failed request may succeed
+    /// if retried.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ClientError::Http(e) => !e.is_serialization(),
+            ClientError::UnexpectedStatus(status) => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn exponential_backoff() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(1),
+    };
+    assert_eq!(policy.backoff(0), Duration::from_millis(100));
+    assert_eq!(policy.backoff(1), Duration::from_millis(200));
+    assert_eq!(policy.backoff(3), Duration::from_millis(800));
+    assert_eq!(policy.backoff(4), Duration::from_secs(1));
+    assert_eq!(policy.backoff(40), Duration::from_secs(1));
+}
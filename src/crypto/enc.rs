@@ -16,12 +16,33 @@
 //! in the `sodiumoxide` crate.
 
 pub(crate) use sodiumoxide::crypto::box_::{
-    gen_nonce, open, open_precomputed, precompute, seal, Nonce,
+    open, open_precomputed, precompute, seal, seal_precomputed, Nonce, PrecomputedKey, NONCEBYTES,
 };
 pub use sodiumoxide::crypto::box_::{PublicKey, SecretKey};
+// Password-based key derivation and symmetric encryption used to store secrets at rest.
+pub(crate) use sodiumoxide::crypto::{pwhash, secretbox};
 
 use exonum::crypto::{x25519, PublicKey as VerifyingKey, SecretKey as SigningKey};
 
+use std::collections::HashMap;
+
+use super::random_bytes;
+
+/// Generates a nonce for the `box` routine with the [crate RNG](::crypto::with_seeded_rng()).
+pub(crate) fn gen_nonce() -> Nonce {
+    Nonce::from_slice(&random_bytes(NONCEBYTES)).expect("nonce")
+}
+
+/// Generates a nonce for the `secretbox` routine with the crate RNG.
+pub(crate) fn gen_secretbox_nonce() -> secretbox::Nonce {
+    secretbox::Nonce::from_slice(&random_bytes(secretbox::NONCEBYTES)).expect("nonce")
+}
+
+/// Generates a salt for password-based key derivation with the crate RNG.
+pub(crate) fn gen_salt() -> pwhash::Salt {
+    pwhash::Salt::from_slice(&random_bytes(pwhash::SALTBYTES)).expect("salt")
+}
+
 /// Converts an Ed25519 keypair into the Curve25519 keypair.
 pub(crate) fn keypair_from_ed25519(pk: VerifyingKey, sk: SigningKey) -> (PublicKey, SecretKey) {
     let (pk, sk) = x25519::into_x25519_keypair(pk, sk).expect("ed25519 -> curve25519");
@@ -37,6 +58,75 @@ pub(crate) fn pk_from_ed25519(pk: VerifyingKey) -> PublicKey {
     PublicKey::from_slice(pk.as_ref()).expect("curve25519 group element")
 }
 
+/// Bounded cache of shared keys precomputed for counterparties, which evicts the least
+/// recently used key when full.
+///
+/// The cache is keyed by Ed25519 keys of counterparties, so it also saves the conversion
+/// of the keys into Curve25519 ones.
+pub(crate) struct KeyCache {
+    capacity: usize,
+    // Counter incremented on each access; used to find the least recently used entry.
+    clock: u64,
+    entries: HashMap<VerifyingKey, (PrecomputedKey, u64)>,
+}
+
+impl KeyCache {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "cache capacity must be positive");
+        KeyCache {
+            capacity,
+            clock: 0,
+            entries: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the shared key for the specified counterparty, computing it if necessary.
+    pub fn shared_key(&mut self, counterparty: &VerifyingKey, sk: &SecretKey) -> PrecomputedKey {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(counterparty) {
+            entry.1 = self.clock;
+            return entry.0.clone();
+        }
+
+        if self.entries.len() >= self.capacity {
+            let oldest = *self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key)
+                .expect("empty cache");
+            self.entries.remove(&oldest);
+        }
+        let key = precompute(&pk_from_ed25519(*counterparty), sk);
+        self.entries
+            .insert(*counterparty, (key.clone(), self.clock));
+        key
+    }
+
+    #[cfg(test)]
+    fn contains(&self, counterparty: &VerifyingKey) -> bool {
+        self.entries.contains_key(counterparty)
+    }
+}
+
+#[test]
+fn key_cache_evicts_least_recently_used_keys() {
+    let (pk, sk) = exonum::crypto::gen_keypair();
+    let (_, sk) = keypair_from_ed25519(pk, sk);
+    let keys: Vec<_> = (0..3).map(|_| exonum::crypto::gen_keypair().0).collect();
+
+    let mut cache = KeyCache::new(2);
+    let key = cache.shared_key(&keys[0], &sk);
+    assert_eq!(key, precompute(&pk_from_ed25519(keys[0]), &sk));
+    cache.shared_key(&keys[1], &sk);
+    // Touch the first key, so that the second one is evicted.
+    cache.shared_key(&keys[0], &sk);
+    cache.shared_key(&keys[2], &sk);
+    assert!(cache.contains(&keys[0]));
+    assert!(!cache.contains(&keys[1]));
+    assert!(cache.contains(&keys[2]));
+}
+
 #[test]
 fn encryption_keys_can_be_created_from_signing_keys() {
     use sodiumoxide::crypto::box_::gen_keypair;
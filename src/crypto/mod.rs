@@ -23,17 +23,39 @@
 //! by "transferring" negative amount to somebody), and that the sender has enough tokens to
 //! perform the transfer.
 //!
+//! [`VectorCommitment`]s commit to several values at once (e.g., a transfer amount together
+//! with a fee) under distinct generators; a [`VectorRangeProof`] asserts that all committed
+//! values are non-negative. These primitives are not used by the service transactions yet,
+//! but can serve as a building block for structured confidential payments.
+//!
 //! # Public-key encryption
 //!
 //! [`enc`](::crypto::enc) module re-exports necessary primitives to [encrypt data](::EncryptedData)
 //! within `Transfer`s.
 //!
+//! # Randomness
+//!
+//! Blinding factors, range proofs, encryption nonces and generated keys use the thread-local
+//! RNG from the `rand` crate. Deployments can route all this randomness through another
+//! cryptographically secure RNG (e.g., an audited DRBG or a hardware RNG) by installing
+//! an RNG factory with [`set_rng_factory()`](::crypto::set_rng_factory()) at startup.
+//! For reproducible transactions (e.g., in tests), the RNG can be replaced with a seeded one
+//! using [`with_seeded_rng()`](::crypto::with_seeded_rng()).
+//!
 //! [`Commitment`]: ::crypto::Commitment
 //! [`SimpleRangeProof`]: ::crypto::SimpleRangeProof
+//! [`VectorCommitment`]: ::crypto::VectorCommitment
+//! [`VectorRangeProof`]: ::crypto::VectorRangeProof
 //! [`Transfer`]: ::transactions::Transfer
 
 pub mod enc;
 mod proofs;
+mod rng;
 mod serialization;
 
-pub use self::proofs::{Commitment, Opening, SimpleRangeProof};
+pub use self::proofs::{
+    AmountProof, Commitment, EqualityProof, Opening, SimpleRangeProof, VectorCommitment,
+    VectorOpening, VectorRangeProof,
+};
+pub(crate) use self::rng::{random_bytes, CrateRng};
+pub use self::rng::{set_rng_factory, with_seeded_rng, RngFactoryError};
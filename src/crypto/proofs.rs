@@ -19,17 +19,77 @@ use byteorder::{ByteOrder, LittleEndian};
 use curve25519::{
     ristretto::{CompressedRistretto, RistrettoPoint},
     scalar::Scalar,
+    traits::Identity,
 };
+use exonum::crypto::hash;
 use merlin::Transcript;
-use rand::thread_rng;
 
-use std::ops;
+use super::CrateRng;
+
+use std::{borrow::Cow, cmp, collections::HashMap, ops, sync::Mutex};
+
+/// Maximum number of decompressed points in `DECOMPRESSED_POINTS`.
+const DECOMPRESSED_POINTS_CAPACITY: usize = 1_024;
 
 lazy_static! {
     /// Pedersen commitment generators.
     static ref PEDERSEN_GENS: PedersenGens = PedersenGens::default();
     /// Bulletproof generators used in `SimpleRangeProof`s.
     static ref BULLETPROOF_GENS: BulletproofGens = BulletproofGens::new(SimpleRangeProof::BITS, 1);
+    /// Bulletproof generators used in `VectorRangeProof`s.
+    static ref AGGREGATED_BULLETPROOF_GENS: BulletproofGens =
+        BulletproofGens::new(SimpleRangeProof::BITS, VectorCommitment::MAX_LEN);
+    /// Value generators for `VectorCommitment`s.
+    static ref VECTOR_GENS: Vec<RistrettoPoint> = vector_generators(VectorCommitment::MAX_LEN);
+    /// Recently decompressed commitments. The same commitments (e.g., wallet balances
+    /// and transfer amounts) are usually deserialized many times when processing a block,
+    /// and point decompression is relatively expensive.
+    static ref DECOMPRESSED_POINTS: Mutex<PointCache> =
+        Mutex::new(PointCache::new(DECOMPRESSED_POINTS_CAPACITY));
+}
+
+/// LRU cache of decompressed Ristretto points keyed by their compressed form.
+#[derive(Debug)]
+struct PointCache {
+    points: HashMap<[u8; 32], (RistrettoPoint, u64)>,
+    capacity: usize,
+    /// Logical time of the latest access to the cache.
+    clock: u64,
+}
+
+impl PointCache {
+    fn new(capacity: usize) -> Self {
+        PointCache {
+            points: HashMap::with_capacity(capacity),
+            capacity,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, bytes: &[u8; 32]) -> Option<RistrettoPoint> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.points.get_mut(bytes).map(|entry| {
+            entry.1 = clock;
+            entry.0
+        })
+    }
+
+    fn insert(&mut self, bytes: [u8; 32], point: RistrettoPoint) {
+        if self.points.len() >= self.capacity && !self.points.contains_key(&bytes) {
+            // Evictions are rare compared to lookups, so a linear scan is fine.
+            let least_recent = self
+                .points
+                .iter()
+                .min_by_key(|(_, &(_, last_used))| last_used)
+                .map(|(bytes, _)| *bytes);
+            if let Some(least_recent) = least_recent {
+                self.points.remove(&least_recent);
+            }
+        }
+        self.clock += 1;
+        self.points.insert(bytes, (point, self.clock));
+    }
 }
 
 /// Pedersen commitment to an integer value.
@@ -67,7 +127,7 @@ lazy_static! {
 ///
 /// It is possible to add and subtract Pedersen commitments; the result is a commitment to the
 /// sum / difference of corresponding values. This fact is what allows using commitments in
-/// private currency.
+/// private currency. Likewise, a commitment can be multiplied by a public integer.
 ///
 /// # Implementation details
 ///
@@ -108,7 +168,7 @@ impl Commitment {
     ///
     /// Returns the created commitment and the corresponding opening for it.
     pub fn new(value: u64) -> (Self, Opening) {
-        let blinding = Scalar::random(&mut thread_rng());
+        let blinding = Scalar::random(&mut CrateRng);
         let opening = Opening::new(value, blinding);
         (Self::from_opening(&opening), opening)
     }
@@ -133,10 +193,23 @@ impl Commitment {
             return None;
         }
 
-        let compressed_point = CompressedRistretto::from_slice(slice);
-        compressed_point
-            .decompress()
-            .map(|point| Commitment { inner: point })
+        let mut bytes = [0_u8; 32];
+        bytes.copy_from_slice(slice);
+        let cached = DECOMPRESSED_POINTS
+            .lock()
+            .expect("point cache is poisoned")
+            .get(&bytes);
+        if let Some(point) = cached {
+            return Some(Commitment { inner: point });
+        }
+
+        // The cache is not locked during decompression.
+        let point = CompressedRistretto(bytes).decompress()?;
+        DECOMPRESSED_POINTS
+            .lock()
+            .expect("point cache is poisoned")
+            .insert(bytes, point);
+        Some(Commitment { inner: point })
     }
 
     /// Serializes this commitment to bytes.
@@ -200,6 +273,18 @@ impl ops::SubAssign for Commitment {
     }
 }
 
+/// Multiplication by a public integer, which commits to the product of the committed value
+/// and the integer.
+impl<'a> ops::Mul<u64> for &'a Commitment {
+    type Output = Commitment;
+
+    fn mul(self, rhs: u64) -> Commitment {
+        Commitment {
+            inner: self.inner * Scalar::from(rhs),
+        }
+    }
+}
+
 #[test]
 fn commitment_arithmetic() {
     let (comm1, opening1) = Commitment::new(100);
@@ -209,6 +294,37 @@ fn commitment_arithmetic() {
     let (comm1, opening1) = Commitment::new(1234);
     let (comm2, opening2) = Commitment::new(234);
     assert!((comm1 - comm2).verify(&(opening1 - opening2)));
+
+    let (comm, opening) = Commitment::new(25);
+    assert!((&comm * 4).verify(&(&opening * 4)));
+    assert_eq!((&opening * 4).value, 100);
+    assert!((&comm * 0).verify(&Opening::with_no_blinding(0)));
+}
+
+#[test]
+fn point_cache_evicts_least_recently_used_points() {
+    let points: Vec<_> = (0..3)
+        .map(|i| {
+            let commitment = Commitment::with_no_blinding(i);
+            let mut bytes = [0_u8; 32];
+            bytes.copy_from_slice(&commitment.to_bytes());
+            (bytes, commitment.inner)
+        })
+        .collect();
+
+    let mut cache = PointCache::new(2);
+    cache.insert(points[0].0, points[0].1);
+    cache.insert(points[1].0, points[1].1);
+    assert_eq!(cache.get(&points[0].0), Some(points[0].1));
+    cache.insert(points[2].0, points[2].1);
+    assert_eq!(cache.get(&points[1].0), None);
+    assert_eq!(cache.get(&points[0].0), Some(points[0].1));
+    assert_eq!(cache.get(&points[2].0), Some(points[2].1));
+
+    // Cached deserialization returns the same commitment.
+    let bytes = Commitment::new(42).0.to_bytes();
+    let commitment = Commitment::from_slice(&bytes).unwrap();
+    assert_eq!(Commitment::from_slice(&bytes), Some(commitment));
 }
 
 /// Opening for a Pedersen commitment.
@@ -219,8 +335,8 @@ fn commitment_arithmetic() {
 ///
 /// # Arithmetic
 ///
-/// Akin to `Commitment`s, openings can be added and subtracted. If an over/underflow occurs
-/// when adding or subtracting committed values, a panic is raised.
+/// Akin to `Commitment`s, openings can be added, subtracted and multiplied by a public integer.
+/// If an over/underflow occurs in the arithmetic on committed values, a panic is raised.
 ///
 /// # Implementation details
 ///
@@ -237,7 +353,7 @@ pub struct Opening {
 
 impl Opening {
     /// Size of a serialized opening.
-    const BYTE_SIZE: usize = 40;
+    pub(crate) const BYTE_SIZE: usize = 40;
 
     pub(crate) fn new(value: u64, blinding: Scalar) -> Self {
         Opening { value, blinding }
@@ -332,6 +448,17 @@ impl ops::SubAssign for Opening {
     }
 }
 
+impl<'a> ops::Mul<u64> for &'a Opening {
+    type Output = Opening;
+
+    fn mul(self, rhs: u64) -> Opening {
+        Opening {
+            value: self.value.checked_mul(rhs).expect("integer overflow"),
+            blinding: self.blinding * Scalar::from(rhs),
+        }
+    }
+}
+
 /// Range proof for a single value in the range `[0; 1 << 64)`.
 ///
 /// # Theory
@@ -343,9 +470,24 @@ impl ops::SubAssign for Opening {
 ///
 /// # Implementation details
 ///
-/// We use the [`bulletproofs`] crate to implement proofs. The crate allows to efficiently prove
-/// several values at once, but this capability is not used as of now. Generators for proofs
-/// are initialized for a single party with `Self::BITS` range capacity.
+/// We use the [`bulletproofs`] crate to implement proofs. Generators for proofs
+/// are initialized for a single party with `Self::BITS` range capacity; proving several
+/// values at once is implemented separately by [`VectorRangeProof`].
+///
+/// Proofs embedded into transactions are deserialized lazily: when a transaction is parsed,
+/// only the length of a proof is checked, and the proof is decoded when it is verified.
+/// Thus, nodes do not pay decoding costs for transactions they never execute, and a proof
+/// which cannot be decoded simply fails verification. Proofs obtained from transaction
+/// accessors (e.g., [`Transfer::amount_proof()`]) borrow their bytes from the transaction
+/// message, so accessing a proof does not copy it.
+///
+/// Proofs in `Transfer` transactions are verified one at a time. Verification is moved
+/// off the execution path where possible: a proof is verified in `Transaction::verify`
+/// when the transaction enters the node, and the result is memoized, so execution
+/// only consults the memoized result (see the `verification` module). Batch verification
+/// is not implemented, since the `bulletproofs` version we use does not expose batch
+/// verification of independent range proofs (aggregated proofs need to be created jointly
+/// by the provers), and transactions enter the node one by one.
 ///
 /// # Examples
 ///
@@ -361,12 +503,16 @@ impl ops::SubAssign for Opening {
 /// [Bulletproofs]: https://eprint.iacr.org/2017/1066.pdf
 /// [`bulletproofs`]: https://doc.dalek.rs/bulletproofs/
 /// [committed]: self::Commitment
+/// [`Transfer::amount_proof()`]: ::transactions::Transfer::amount_proof()
+/// [`VectorRangeProof`]: ::crypto::VectorRangeProof
 #[derive(Debug, Clone)]
-pub struct SimpleRangeProof {
-    inner: RangeProof,
+pub struct SimpleRangeProof<'a> {
+    /// Serialized proof. The bytes may not correspond to a valid proof
+    /// if the proof is read from a transaction.
+    bytes: Cow<'a, [u8]>,
 }
 
-impl SimpleRangeProof {
+impl<'a> SimpleRangeProof<'a> {
     /// Number of variable bits in the committed value: `64`. The range
     /// to which the value must belong is `[0, 1 << BITS)`.
     pub const BITS: usize = 64;
@@ -388,32 +534,61 @@ impl SimpleRangeProof {
     /// In this case, `None` is returned.
     ///
     /// [impl]: https://doc.dalek.rs/bulletproofs/struct.RangeProof.html#method.prove_single
-    pub fn prove(opening: &Opening) -> Option<Self> {
+    pub fn prove(opening: &Opening) -> Option<SimpleRangeProof<'static>> {
         let mut transcript = Transcript::new(Self::DOMAIN_SEPARATOR);
-        let (proof, _) = RangeProof::prove_single(
+        let (proof, _) = RangeProof::prove_single_with_rng(
             &BULLETPROOF_GENS,
             &PEDERSEN_GENS,
             &mut transcript,
             opening.value,
             &opening.blinding,
             Self::BITS,
+            &mut CrateRng,
         )
         .ok()?;
 
-        Some(SimpleRangeProof { inner: proof })
-    }
-
-    /// Attempts to deserialize this proof from a byte slice.
-    pub fn from_slice(slice: &[u8]) -> Option<Self> {
         Some(SimpleRangeProof {
-            inner: RangeProof::from_bytes(slice).ok()?,
+            bytes: Cow::Owned(proof.to_bytes()),
         })
     }
 
-    /// Verifies this proof with respect to the given committed value.
+    /// Attempts to deserialize this proof from a byte slice. The proof borrows
+    /// the slice; use [`into_owned()`](#method.into_owned) to detach it.
+    pub fn from_slice(slice: &'a [u8]) -> Option<Self> {
+        RangeProof::from_bytes(slice).ok()?;
+        Some(Self::from_slice_unchecked(slice))
+    }
+
+    /// Creates a proof borrowing a byte slice without decoding it. The proof is decoded
+    /// during verification.
+    pub(crate) fn from_slice_unchecked(slice: &'a [u8]) -> Self {
+        debug_assert_eq!(slice.len(), Self::ELEMENTS_SIZE * 32);
+        SimpleRangeProof {
+            bytes: Cow::Borrowed(slice),
+        }
+    }
+
+    /// Converts this proof into one owning its bytes.
+    pub fn into_owned(self) -> SimpleRangeProof<'static> {
+        SimpleRangeProof {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+        }
+    }
+
+    /// Returns the serialized proof without copying it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Verifies this proof with respect to the given committed value. Returns `false`
+    /// if the proof cannot be decoded.
     pub fn verify(&self, commitment: &Commitment) -> bool {
+        let proof = match RangeProof::from_bytes(&self.bytes) {
+            Ok(proof) => proof,
+            Err(_) => return false,
+        };
         let mut transcript = Transcript::new(Self::DOMAIN_SEPARATOR);
-        self.inner
+        proof
             .verify_single(
                 &BULLETPROOF_GENS,
                 &PEDERSEN_GENS,
@@ -426,7 +601,7 @@ impl SimpleRangeProof {
 
     /// Serializes this proof into bytes.
     pub fn to_bytes(&self) -> Vec<u8> {
-        self.inner.to_bytes()
+        self.bytes.to_vec()
     }
 }
 
@@ -456,7 +631,7 @@ fn commitments_produced_by_bulletproofs_are_as_expected() {
 
 #[test]
 fn range_proof_serialized_size_is_as_expected() {
-    use rand::Rng;
+    use rand::{thread_rng, Rng};
 
     let mut rng = thread_rng();
     for _ in 0..5 {
@@ -473,3 +648,870 @@ fn incorrect_proofs_do_not_verify() {
     let (commitment2, _) = Commitment::new(54321);
     assert!(!proof.verify(&commitment2));
 }
+
+#[test]
+fn undecodable_proofs_do_not_verify() {
+    // Scalars in the proof are not canonical.
+    let bytes = vec![0xff; SimpleRangeProof::ELEMENTS_SIZE * 32];
+    assert!(SimpleRangeProof::from_slice(&bytes).is_none());
+
+    let proof = SimpleRangeProof::from_slice_unchecked(&bytes);
+    assert!(!proof.verify(&Commitment::with_no_blinding(0)));
+    assert_eq!(proof.to_bytes(), bytes);
+}
+
+/// Proof that a committed transfer amount lies within the bounds set by the service
+/// configuration.
+///
+/// The proof consists of a [`SimpleRangeProof`] for the difference between the amount
+/// and the minimum transfer amount, optionally followed by a [`SimpleRangeProof`]
+/// for the difference between the [maximum transfer amount] and the amount. The upper-bound
+/// proof is only required if the maximum amount is set; without it, the proof is serialized
+/// exactly as a single `SimpleRangeProof`.
+///
+/// # Examples
+///
+/// ```
+/// # use private_currency::crypto::{AmountProof, Commitment, Opening, SimpleRangeProof};
+/// let (commitment, opening) = Commitment::new(42);
+/// let lower = SimpleRangeProof::prove(&(&opening - &Opening::with_no_blinding(10))).unwrap();
+/// let upper = SimpleRangeProof::prove(&(&Opening::with_no_blinding(100) - &opening)).unwrap();
+/// let proof = AmountProof::new(lower, Some(upper));
+/// assert!(proof.verify(&commitment, 10, Some(100)));
+/// assert!(!proof.verify(&commitment, 10, Some(41)));
+/// ```
+///
+/// [`SimpleRangeProof`]: self::SimpleRangeProof
+/// [maximum transfer amount]: ::Config::max_transfer_amount
+#[derive(Debug, Clone)]
+pub struct AmountProof<'a> {
+    /// Serialized lower-bound proof, optionally followed by the upper-bound one.
+    bytes: Cow<'a, [u8]>,
+}
+
+impl<'a> AmountProof<'a> {
+    /// Size of a single range proof in the serialized form.
+    const PROOF_LEN: usize = SimpleRangeProof::ELEMENTS_SIZE * 32;
+
+    /// Creates a proof from the lower-bound proof and an optional upper-bound proof.
+    pub fn new(
+        lower_bound: SimpleRangeProof,
+        upper_bound: Option<SimpleRangeProof>,
+    ) -> AmountProof<'static> {
+        let mut bytes = lower_bound.to_bytes();
+        if let Some(upper_bound) = upper_bound {
+            bytes.extend_from_slice(upper_bound.as_bytes());
+        }
+        AmountProof {
+            bytes: Cow::Owned(bytes),
+        }
+    }
+
+    /// Checks whether a proof with the specified number of group elements has a valid size.
+    pub(crate) fn is_valid_size(elements: usize) -> bool {
+        elements == SimpleRangeProof::ELEMENTS_SIZE
+            || elements == 2 * SimpleRangeProof::ELEMENTS_SIZE
+    }
+
+    /// Creates a proof borrowing a byte slice without decoding it. The proofs are decoded
+    /// during verification.
+    pub(crate) fn from_slice_unchecked(slice: &'a [u8]) -> Self {
+        debug_assert!(Self::is_valid_size(slice.len() / 32));
+        AmountProof {
+            bytes: Cow::Borrowed(slice),
+        }
+    }
+
+    /// Converts this proof into one owning its bytes.
+    pub fn into_owned(self) -> AmountProof<'static> {
+        AmountProof {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+        }
+    }
+
+    /// Returns the proof that the amount is not less than the minimum one.
+    pub fn lower_bound(&self) -> SimpleRangeProof {
+        SimpleRangeProof::from_slice_unchecked(&self.bytes[..Self::PROOF_LEN])
+    }
+
+    /// Returns the proof that the amount does not exceed the maximum one, if present.
+    pub fn upper_bound(&self) -> Option<SimpleRangeProof> {
+        if self.bytes.len() > Self::PROOF_LEN {
+            Some(SimpleRangeProof::from_slice_unchecked(
+                &self.bytes[Self::PROOF_LEN..],
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Verifies this proof with respect to the committed amount and the bounds on it.
+    /// If `max_amount` is `None`, the upper-bound proof is not checked.
+    pub fn verify(&self, amount: &Commitment, min_amount: u64, max_amount: Option<u64>) -> bool {
+        let min_amount = Commitment::with_no_blinding(min_amount);
+        if !self.lower_bound().verify(&(amount - &min_amount)) {
+            return false;
+        }
+        match max_amount {
+            Some(max_amount) => {
+                let max_amount = Commitment::with_no_blinding(max_amount);
+                self.upper_bound()
+                    .map_or(false, |proof| proof.verify(&(&max_amount - amount)))
+            }
+            None => true,
+        }
+    }
+
+    /// Returns the serialized proof without copying it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Serializes this proof into bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.to_vec()
+    }
+}
+
+#[test]
+fn amount_proofs() {
+    let (commitment, opening) = Commitment::new(42);
+    let lower = SimpleRangeProof::prove(&(&opening - &Opening::with_no_blinding(10))).unwrap();
+    let proof = AmountProof::new(lower.clone(), None);
+    assert_eq!(proof.as_bytes(), lower.as_bytes());
+    assert!(proof.upper_bound().is_none());
+    assert!(proof.verify(&commitment, 10, None));
+    assert!(!proof.verify(&commitment, 11, None));
+    assert!(!proof.verify(&commitment, 10, Some(100)));
+
+    let upper = SimpleRangeProof::prove(&(&Opening::with_no_blinding(100) - &opening)).unwrap();
+    let proof = AmountProof::new(lower, Some(upper));
+    assert!(proof.verify(&commitment, 10, None));
+    assert!(proof.verify(&commitment, 10, Some(100)));
+    assert!(!proof.verify(&commitment, 10, Some(99)));
+
+    let (other_commitment, _) = Commitment::new(42);
+    assert!(!proof.verify(&other_commitment, 10, Some(100)));
+}
+
+/// Zero-knowledge proof that two [`Commitment`]s commit to the same value.
+///
+/// # Theory
+///
+/// If commitments `C1 = x*G + r1*H` and `C2 = x*G + r2*H` commit to the same value `x`,
+/// their difference `D = C2 - C1 = (r2 - r1)*H` is a multiple of the blinding generator.
+/// The proof is a non-interactive [Schnorr proof] of knowledge of the discrete logarithm
+/// of `D` with respect to `H`; since the discrete logarithm of `G` with respect to `H`
+/// is unknown, the prover cannot produce it for commitments to different values.
+///
+/// The Fiat–Shamir challenge is derived from `D` and the nonce commitment with SHA-256;
+/// thus, the proof does not reveal the committed value or the blinding factors.
+///
+/// # Examples
+///
+/// ```
+/// # use private_currency::crypto::{Commitment, EqualityProof};
+/// let (commitment, opening) = Commitment::new(42);
+/// let (other_commitment, other_opening) = Commitment::new(42);
+/// let proof = EqualityProof::prove(&opening, &other_opening).unwrap();
+/// assert!(proof.verify(&commitment, &other_commitment));
+/// ```
+///
+/// [Schnorr proof]: https://en.wikipedia.org/wiki/Proof_of_knowledge#Schnorr_protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EqualityProof {
+    nonce_commitment: CompressedRistretto,
+    response: Scalar,
+}
+
+impl EqualityProof {
+    /// Size of the byte representation of the proof.
+    pub(crate) const BYTE_LEN: usize = 64;
+
+    /// Domain separator for the proof.
+    const DOMAIN_SEPARATOR: &'static [u8] = b"exonum.private_currency.equality";
+
+    fn challenge(difference: &RistrettoPoint, nonce_commitment: &CompressedRistretto) -> Scalar {
+        let mut bytes = Self::DOMAIN_SEPARATOR.to_vec();
+        bytes.extend_from_slice(difference.compress().as_bytes());
+        bytes.extend_from_slice(nonce_commitment.as_bytes());
+        let mut challenge = [0_u8; 32];
+        challenge.copy_from_slice(hash(&bytes).as_ref());
+        Scalar::from_bytes_mod_order(challenge)
+    }
+
+    /// Creates a proof that commitments produced from `opening` and `other` commit
+    /// to the same value. Returns `None` if the values in the openings differ.
+    pub fn prove(opening: &Opening, other: &Opening) -> Option<Self> {
+        if opening.value != other.value {
+            return None;
+        }
+
+        let witness = other.blinding - opening.blinding;
+        let difference = witness * PEDERSEN_GENS.B_blinding;
+        let nonce = Scalar::random(&mut CrateRng);
+        let nonce_commitment = (nonce * PEDERSEN_GENS.B_blinding).compress();
+        let challenge = Self::challenge(&difference, &nonce_commitment);
+        Some(EqualityProof {
+            nonce_commitment,
+            response: nonce + challenge * witness,
+        })
+    }
+
+    /// Verifies that `commitment` and `other` commit to the same value.
+    pub fn verify(&self, commitment: &Commitment, other: &Commitment) -> bool {
+        let nonce_commitment = match self.nonce_commitment.decompress() {
+            Some(point) => point,
+            None => return false,
+        };
+        let difference = other.inner - commitment.inner;
+        let challenge = Self::challenge(&difference, &self.nonce_commitment);
+        self.response * PEDERSEN_GENS.B_blinding == nonce_commitment + challenge * difference
+    }
+
+    /// Attempts to deserialize a proof from a byte slice.
+    pub fn from_slice(slice: &[u8]) -> Option<Self> {
+        if slice.len() != Self::BYTE_LEN {
+            return None;
+        }
+
+        let mut point_bytes = [0_u8; 32];
+        point_bytes.copy_from_slice(&slice[..32]);
+        let mut scalar_bytes = [0_u8; 32];
+        scalar_bytes.copy_from_slice(&slice[32..]);
+        Some(EqualityProof {
+            nonce_commitment: CompressedRistretto(point_bytes),
+            response: Scalar::from_canonical_bytes(scalar_bytes)?,
+        })
+    }
+
+    /// Serializes this proof to bytes.
+    ///
+    /// # Implementation details
+    ///
+    /// The proof is serialized as a compressed Ristretto point (the nonce commitment)
+    /// followed by a scalar (the response), i.e., 64 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.nonce_commitment.as_bytes().to_vec();
+        bytes.extend_from_slice(self.response.as_bytes());
+        bytes
+    }
+}
+
+#[test]
+fn equality_proofs() {
+    let (commitment, opening) = Commitment::new(1_000);
+    let (other_commitment, other_opening) = Commitment::new(1_000);
+    let proof = EqualityProof::prove(&opening, &other_opening).expect("prove");
+    assert!(proof.verify(&commitment, &other_commitment));
+    assert!(!proof.verify(&other_commitment, &commitment));
+
+    let proof = EqualityProof::from_slice(&proof.to_bytes()).expect("from_slice");
+    assert!(proof.verify(&commitment, &other_commitment));
+
+    let (different_commitment, different_opening) = Commitment::new(999);
+    assert!(EqualityProof::prove(&opening, &different_opening).is_none());
+    assert!(!proof.verify(&commitment, &different_commitment));
+}
+
+/// Derives value generators for vector commitments. The first generator coincides
+/// with the value generator of `Commitment`s; the others are obtained by hashing
+/// to the Ristretto group, so their discrete logarithms are unknown.
+fn vector_generators(len: usize) -> Vec<RistrettoPoint> {
+    const DOMAIN_SEPARATOR: &[u8] = b"exonum.private_currency.vector_gens";
+
+    let mut generators = vec![PEDERSEN_GENS.B];
+    for i in 1..len {
+        let mut uniform_bytes = [0_u8; 64];
+        for (half, chunk) in uniform_bytes.chunks_mut(32).enumerate() {
+            let mut bytes = DOMAIN_SEPARATOR.to_vec();
+            bytes.push(i as u8);
+            bytes.push(half as u8);
+            chunk.copy_from_slice(hash(&bytes).as_ref());
+        }
+        generators.push(RistrettoPoint::from_uniform_bytes(&uniform_bytes));
+    }
+    generators
+}
+
+/// Pedersen commitment to a small vector of integer values.
+///
+/// # Theory
+///
+/// A vector commitment generalizes a [`Commitment`] to several values `x_1, ..., x_n`
+/// committed under distinct generators:
+///
+/// ```text
+/// Comm(x_1, ..., x_n; r) = x_1*G_1 + ... + x_n*G_n + rH.
+/// ```
+///
+/// The commitment has the same size as a commitment to a single value, and retains
+/// its hiding and binding properties; in particular, a party cannot swap values
+/// among components. Vector commitments can be added and subtracted component-wise,
+/// e.g., to split a committed payment into an amount, a fee and a tax.
+///
+/// # Implementation details
+///
+/// `G_1` and `H` are the generators used by [`Commitment`]s, so a commitment to a vector
+/// with a single value is equal to the ordinary commitment to this value.
+/// The remaining generators are derived by hashing to the Ristretto group. The length
+/// of vectors is limited by [`MAX_LEN`].
+///
+/// Vectors of different lengths are treated as padded with zeros, so a commitment
+/// does not reveal the length of the vector.
+///
+/// # Examples
+///
+/// ```
+/// # use private_currency::crypto::{Commitment, VectorCommitment};
+/// let (commitment, opening) = VectorCommitment::new(&[1_000, 10, 50]);
+/// assert_eq!(opening.values, vec![1_000, 10, 50]);
+/// assert!(commitment.verify(&opening));
+///
+/// let (fee, fee_opening) = VectorCommitment::new(&[0, 5]);
+/// let commitment = commitment - fee;
+/// let opening = opening - fee_opening;
+/// assert_eq!(opening.values, vec![1_000, 5, 50]);
+/// assert!(commitment.verify(&opening));
+///
+/// // Single-value vectors are committed in the same way as ordinary values.
+/// let (commitment, opening) = VectorCommitment::new(&[42]);
+/// assert_eq!(Commitment::from(commitment), Commitment::from_opening(&opening.into()));
+/// ```
+///
+/// [`Commitment`]: self::Commitment
+/// [`MAX_LEN`]: #associatedconstant.MAX_LEN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorCommitment {
+    inner: RistrettoPoint,
+}
+
+impl VectorCommitment {
+    /// Maximum number of values in a committed vector: `8`.
+    pub const MAX_LEN: usize = 8;
+
+    /// Creates a commitment with a randomly chosen blinding.
+    ///
+    /// # Return value
+    ///
+    /// Returns the created commitment and the corresponding opening for it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` is empty or longer than [`MAX_LEN`](#associatedconstant.MAX_LEN).
+    pub fn new(values: &[u64]) -> (Self, VectorOpening) {
+        let blinding = Scalar::random(&mut CrateRng);
+        let opening = VectorOpening::new(values.to_vec(), blinding);
+        (Self::from_opening(&opening), opening)
+    }
+
+    /// Creates a commitment from the given opening.
+    pub fn from_opening(opening: &VectorOpening) -> Self {
+        let inner = opening.values.iter().zip(VECTOR_GENS.iter()).fold(
+            opening.blinding * PEDERSEN_GENS.B_blinding,
+            |acc, (&value, gen)| acc + Scalar::from(value) * gen,
+        );
+        VectorCommitment { inner }
+    }
+
+    /// Attempts to deserialize a commitment from byte slice.
+    pub fn from_slice(slice: &[u8]) -> Option<Self> {
+        Commitment::from_slice(slice).map(Self::from)
+    }
+
+    /// Serializes this commitment to bytes.
+    ///
+    /// # Implementation details
+    ///
+    /// The commitment is serialized in the same way as a [`Commitment`](self::Commitment),
+    /// i.e., as a single compressed Ristretto point (32 bytes).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.inner.compress().as_bytes().to_vec()
+    }
+
+    /// Verifies if this commitment corresponds to the provided opening.
+    pub fn verify(&self, opening: &VectorOpening) -> bool {
+        *self == Self::from_opening(opening)
+    }
+}
+
+/// Interprets a commitment to a single value as a vector commitment.
+impl From<Commitment> for VectorCommitment {
+    fn from(commitment: Commitment) -> Self {
+        VectorCommitment {
+            inner: commitment.inner,
+        }
+    }
+}
+
+/// Interprets a vector commitment as a commitment to its first value. The conversion
+/// is meaningful only for vectors with a single value.
+impl From<VectorCommitment> for Commitment {
+    fn from(commitment: VectorCommitment) -> Self {
+        Commitment {
+            inner: commitment.inner,
+        }
+    }
+}
+
+impl ops::Add for VectorCommitment {
+    type Output = VectorCommitment;
+
+    fn add(self, rhs: Self) -> VectorCommitment {
+        VectorCommitment {
+            inner: self.inner + rhs.inner,
+        }
+    }
+}
+
+impl<'a, 'b> ops::Add<&'b VectorCommitment> for &'a VectorCommitment {
+    type Output = VectorCommitment;
+
+    fn add(self, rhs: &'b VectorCommitment) -> VectorCommitment {
+        VectorCommitment {
+            inner: self.inner + rhs.inner,
+        }
+    }
+}
+
+impl ops::Sub for VectorCommitment {
+    type Output = VectorCommitment;
+
+    fn sub(self, rhs: Self) -> VectorCommitment {
+        VectorCommitment {
+            inner: self.inner - rhs.inner,
+        }
+    }
+}
+
+impl<'a, 'b> ops::Sub<&'b VectorCommitment> for &'a VectorCommitment {
+    type Output = VectorCommitment;
+
+    fn sub(self, rhs: &'b VectorCommitment) -> VectorCommitment {
+        VectorCommitment {
+            inner: self.inner - rhs.inner,
+        }
+    }
+}
+
+/// Opening for a [`VectorCommitment`].
+///
+/// # Arithmetic
+///
+/// Similar to [`Opening`]s, vector openings can be added and subtracted component-wise.
+/// The shorter of the operands is padded with zeros. If an over/underflow occurs
+/// in any component, a panic is raised.
+///
+/// [`VectorCommitment`]: self::VectorCommitment
+/// [`Opening`]: self::Opening
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorOpening {
+    /// Committed values.
+    pub values: Vec<u64>,
+    blinding: Scalar,
+}
+
+impl VectorOpening {
+    fn new(values: Vec<u64>, blinding: Scalar) -> Self {
+        assert!(!values.is_empty(), "committed vector is empty");
+        assert!(
+            values.len() <= VectorCommitment::MAX_LEN,
+            "committed vector is too long"
+        );
+        VectorOpening { values, blinding }
+    }
+
+    #[doc(hidden)] // useful only in tests
+    pub fn with_no_blinding(values: &[u64]) -> Self {
+        VectorOpening::new(values.to_vec(), Scalar::zero())
+    }
+
+    /// Combines the values of two openings component-wise.
+    fn zip_values<F>(&self, rhs: &Self, op: F) -> Vec<u64>
+    where
+        F: Fn(u64, u64) -> u64,
+    {
+        let len = cmp::max(self.values.len(), rhs.values.len());
+        (0..len)
+            .map(|i| {
+                let lhs = self.values.get(i).cloned().unwrap_or(0);
+                let rhs = rhs.values.get(i).cloned().unwrap_or(0);
+                op(lhs, rhs)
+            })
+            .collect()
+    }
+}
+
+/// Converts an opening of a single value into a vector opening.
+impl From<Opening> for VectorOpening {
+    fn from(opening: Opening) -> Self {
+        VectorOpening::new(vec![opening.value], opening.blinding)
+    }
+}
+
+/// Converts a vector opening into an opening of its first value.
+///
+/// # Panics
+///
+/// Panics if the vector has more than one value.
+impl From<VectorOpening> for Opening {
+    fn from(opening: VectorOpening) -> Self {
+        assert_eq!(opening.values.len(), 1, "vector opening has several values");
+        Opening::new(opening.values[0], opening.blinding)
+    }
+}
+
+impl ops::Add for VectorOpening {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        VectorOpening {
+            values: self.zip_values(&rhs, |x, y| x.checked_add(y).expect("integer overflow")),
+            blinding: self.blinding + rhs.blinding,
+        }
+    }
+}
+
+impl ops::Sub for VectorOpening {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        VectorOpening {
+            values: self.zip_values(&rhs, |x, y| x.checked_sub(y).expect("integer underflow")),
+            blinding: self.blinding - rhs.blinding,
+        }
+    }
+}
+
+#[test]
+fn vector_commitment_arithmetic() {
+    let (comm1, opening1) = VectorCommitment::new(&[100, 1, 2]);
+    let (comm2, opening2) = VectorCommitment::new(&[200, 3]);
+    let sum = opening1.clone() + opening2.clone();
+    assert_eq!(sum.values, vec![300, 4, 2]);
+    assert!((&comm1 + &comm2).verify(&sum));
+    let difference = opening1.clone() - opening2;
+    assert_eq!(difference.values, vec![0, 0, 2]);
+    assert!((comm1.clone() - comm2).verify(&difference));
+
+    // Values cannot be permuted.
+    assert!(!comm1.verify(&VectorOpening::new(vec![1, 100, 2], opening1.blinding)));
+    // Trailing zeros do not change the commitment.
+    assert!(comm1.verify(&VectorOpening::new(vec![100, 1, 2, 0], opening1.blinding)));
+
+    let (commitment, opening) = Commitment::new(42);
+    assert!(VectorCommitment::from(commitment).verify(&opening.into()));
+    let bytes = comm1.to_bytes();
+    assert_eq!(VectorCommitment::from_slice(&bytes), Some(comm1));
+}
+
+/// Aggregated range proof for all values committed in a [`VectorCommitment`].
+///
+/// # Theory
+///
+/// The proof asserts that each value in the committed vector belongs to the range
+/// `[0; 1 << 64)`, without revealing the values. It consists of
+///
+/// - ordinary [`Commitment`]s to each value, with fresh blinding factors
+/// - an aggregated [Bulletproof] for these commitments, which is considerably smaller
+///   than separate [`SimpleRangeProof`]s
+/// - a non-interactive [Schnorr-type proof] that the vector commitment and the value
+///   commitments commit to the same values. The Fiat–Shamir challenge is derived
+///   with SHA-256, similar to [`EqualityProof`]s.
+///
+/// The value commitments are hiding, so the proof reveals only the length of the vector.
+///
+/// # Implementation details
+///
+/// The `bulletproofs` crate requires the number of aggregated values to be a power of two.
+/// If necessary, the vector is padded with zeros committed with zero blinding factors,
+/// so that the padding does not need to be included into the proof.
+///
+/// # Examples
+///
+/// ```
+/// # use private_currency::crypto::{VectorCommitment, VectorRangeProof};
+/// let (commitment, opening) = VectorCommitment::new(&[1_000, 10, 50]);
+/// let proof = VectorRangeProof::prove(&opening).unwrap();
+/// assert_eq!(proof.len(), 3);
+/// assert!(proof.verify(&commitment));
+/// ```
+///
+/// [`VectorCommitment`]: self::VectorCommitment
+/// [`Commitment`]: self::Commitment
+/// [`SimpleRangeProof`]: self::SimpleRangeProof
+/// [`EqualityProof`]: self::EqualityProof
+/// [Bulletproof]: https://eprint.iacr.org/2017/1066.pdf
+/// [Schnorr-type proof]: https://en.wikipedia.org/wiki/Proof_of_knowledge#Schnorr_protocol
+#[derive(Debug, Clone)]
+pub struct VectorRangeProof {
+    /// Commitments to the individual values.
+    value_commitments: Vec<CompressedRistretto>,
+    /// Nonce commitments of the linking proof: one per value, followed by the one
+    /// for the vector commitment.
+    nonce_commitments: Vec<CompressedRistretto>,
+    /// Responses for the committed values.
+    value_responses: Vec<Scalar>,
+    /// Responses for the blinding factors of the value commitments, followed by the response
+    /// for the blinding factor of the vector commitment.
+    blinding_responses: Vec<Scalar>,
+    /// Serialized aggregated range proof for `value_commitments`.
+    range_proof: Vec<u8>,
+}
+
+impl VectorRangeProof {
+    /// Domain separator for the proof.
+    const DOMAIN_SEPARATOR: &'static [u8] = b"exonum.private_currency.vector";
+
+    fn challenge(
+        commitment: &VectorCommitment,
+        value_commitments: &[CompressedRistretto],
+        nonce_commitments: &[CompressedRistretto],
+    ) -> Scalar {
+        let mut bytes = Self::DOMAIN_SEPARATOR.to_vec();
+        bytes.extend_from_slice(commitment.inner.compress().as_bytes());
+        for point in value_commitments.iter().chain(nonce_commitments) {
+            bytes.extend_from_slice(point.as_bytes());
+        }
+        let mut challenge = [0_u8; 32];
+        challenge.copy_from_slice(hash(&bytes).as_ref());
+        Scalar::from_bytes_mod_order(challenge)
+    }
+
+    /// Creates a proof for the values in the specified opening.
+    ///
+    /// # Return value
+    ///
+    /// This method may fail along the lines of the [underlying implementation][impl].
+    /// In this case, `None` is returned.
+    ///
+    /// [impl]: https://doc.dalek.rs/bulletproofs/struct.RangeProof.html#method.prove_multiple
+    pub fn prove(opening: &VectorOpening) -> Option<Self> {
+        let len = opening.values.len();
+        let mut values = opening.values.clone();
+        values.resize(len.next_power_of_two(), 0);
+        let mut blindings: Vec<_> = (0..len).map(|_| Scalar::random(&mut CrateRng)).collect();
+        blindings.resize(values.len(), Scalar::zero());
+
+        let mut transcript = Transcript::new(Self::DOMAIN_SEPARATOR);
+        let (range_proof, mut value_commitments) = RangeProof::prove_multiple_with_rng(
+            &AGGREGATED_BULLETPROOF_GENS,
+            &PEDERSEN_GENS,
+            &mut transcript,
+            &values,
+            &blindings,
+            SimpleRangeProof::BITS,
+            &mut CrateRng,
+        )
+        .ok()?;
+        value_commitments.truncate(len);
+
+        let value_nonces: Vec<_> = (0..len).map(|_| Scalar::random(&mut CrateRng)).collect();
+        let blinding_nonces: Vec<_> = (0..=len).map(|_| Scalar::random(&mut CrateRng)).collect();
+        let mut nonce_commitments: Vec<_> = value_nonces
+            .iter()
+            .zip(&blinding_nonces)
+            .map(|(&value, &blinding)| {
+                (value * PEDERSEN_GENS.B + blinding * PEDERSEN_GENS.B_blinding).compress()
+            })
+            .collect();
+        let vector_nonce = value_nonces.iter().zip(VECTOR_GENS.iter()).fold(
+            blinding_nonces[len] * PEDERSEN_GENS.B_blinding,
+            |acc, (&nonce, gen)| acc + nonce * gen,
+        );
+        nonce_commitments.push(vector_nonce.compress());
+
+        let commitment = VectorCommitment::from_opening(opening);
+        let challenge = Self::challenge(&commitment, &value_commitments, &nonce_commitments);
+        let value_responses = value_nonces
+            .iter()
+            .zip(&opening.values)
+            .map(|(&nonce, &value)| nonce + challenge * Scalar::from(value))
+            .collect();
+        let blinding_responses = blinding_nonces
+            .iter()
+            .zip(blindings[..len].iter().chain(Some(&opening.blinding)))
+            .map(|(&nonce, &blinding)| nonce + challenge * blinding)
+            .collect();
+
+        Some(VectorRangeProof {
+            value_commitments,
+            nonce_commitments,
+            value_responses,
+            blinding_responses,
+            range_proof: range_proof.to_bytes(),
+        })
+    }
+
+    /// Returns the number of values covered by the proof.
+    pub fn len(&self) -> usize {
+        self.value_commitments.len()
+    }
+
+    /// Checks whether the proof covers no values. Always returns `false` for proofs
+    /// created with [`prove()`](#method.prove) or decoded with [`from_slice()`](#method.from_slice).
+    pub fn is_empty(&self) -> bool {
+        self.value_commitments.is_empty()
+    }
+
+    /// Verifies this proof with respect to the given vector commitment.
+    pub fn verify(&self, commitment: &VectorCommitment) -> bool {
+        let len = self.len();
+        let challenge =
+            Self::challenge(commitment, &self.value_commitments, &self.nonce_commitments);
+
+        let decompress = |points: &[CompressedRistretto]| -> Option<Vec<RistrettoPoint>> {
+            points.iter().map(CompressedRistretto::decompress).collect()
+        };
+        let (value_commitments, nonce_commitments) = match (
+            decompress(&self.value_commitments),
+            decompress(&self.nonce_commitments),
+        ) {
+            (Some(values), Some(nonces)) => (values, nonces),
+            _ => return false,
+        };
+
+        let responses = self.value_responses.iter().zip(&self.blinding_responses);
+        let points = nonce_commitments.iter().zip(&value_commitments);
+        for ((&value, &blinding), (&nonce, &value_commitment)) in responses.zip(points) {
+            let lhs = value * PEDERSEN_GENS.B + blinding * PEDERSEN_GENS.B_blinding;
+            if lhs != nonce + challenge * value_commitment {
+                return false;
+            }
+        }
+        let lhs = self.value_responses.iter().zip(VECTOR_GENS.iter()).fold(
+            self.blinding_responses[len] * PEDERSEN_GENS.B_blinding,
+            |acc, (&response, gen)| acc + response * gen,
+        );
+        if lhs != nonce_commitments[len] + challenge * commitment.inner {
+            return false;
+        }
+
+        let range_proof = match RangeProof::from_bytes(&self.range_proof) {
+            Ok(proof) => proof,
+            Err(_) => return false,
+        };
+        let mut padded_commitments = self.value_commitments.clone();
+        padded_commitments.resize(
+            len.next_power_of_two(),
+            RistrettoPoint::identity().compress(),
+        );
+        let mut transcript = Transcript::new(Self::DOMAIN_SEPARATOR);
+        range_proof
+            .verify_multiple(
+                &AGGREGATED_BULLETPROOF_GENS,
+                &PEDERSEN_GENS,
+                &mut transcript,
+                &padded_commitments,
+                SimpleRangeProof::BITS,
+            )
+            .is_ok()
+    }
+
+    /// Attempts to deserialize a proof from a byte slice.
+    pub fn from_slice(slice: &[u8]) -> Option<Self> {
+        let (&len, rest) = slice.split_first()?;
+        let len = usize::from(len);
+        if len == 0 || len > VectorCommitment::MAX_LEN || rest.len() < (4 * len + 2) * 32 {
+            return None;
+        }
+
+        let (value_commitments, rest) = split_chunks(rest, len);
+        let (nonce_commitments, rest) = split_chunks(rest, len + 1);
+        let (value_responses, rest) = split_chunks(rest, len);
+        let (blinding_responses, rest) = split_chunks(rest, len + 1);
+        let value_responses = value_responses
+            .into_iter()
+            .map(Scalar::from_canonical_bytes)
+            .collect::<Option<_>>()?;
+        let blinding_responses = blinding_responses
+            .into_iter()
+            .map(Scalar::from_canonical_bytes)
+            .collect::<Option<_>>()?;
+        RangeProof::from_bytes(rest).ok()?;
+
+        Some(VectorRangeProof {
+            value_commitments: value_commitments
+                .into_iter()
+                .map(CompressedRistretto)
+                .collect(),
+            nonce_commitments: nonce_commitments
+                .into_iter()
+                .map(CompressedRistretto)
+                .collect(),
+            value_responses,
+            blinding_responses,
+            range_proof: rest.to_vec(),
+        })
+    }
+
+    /// Serializes this proof to bytes.
+    ///
+    /// # Implementation details
+    ///
+    /// The proof is serialized as the number of values `n` (1 byte), followed by
+    /// `n` value commitments, `n + 1` nonce commitments, `n` value responses and
+    /// `n + 1` blinding responses (32 bytes each), and the aggregated range proof.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.len() as u8];
+        for point in self.value_commitments.iter().chain(&self.nonce_commitments) {
+            bytes.extend_from_slice(point.as_bytes());
+        }
+        for scalar in self.value_responses.iter().chain(&self.blinding_responses) {
+            bytes.extend_from_slice(scalar.as_bytes());
+        }
+        bytes.extend_from_slice(&self.range_proof);
+        bytes
+    }
+}
+
+/// Splits `count` 32-byte chunks off the start of a slice, which must be long enough.
+fn split_chunks(slice: &[u8], count: usize) -> (Vec<[u8; 32]>, &[u8]) {
+    let (head, tail) = slice.split_at(count * 32);
+    let chunks = head
+        .chunks(32)
+        .map(|chunk| {
+            let mut bytes = [0_u8; 32];
+            bytes.copy_from_slice(chunk);
+            bytes
+        })
+        .collect();
+    (chunks, tail)
+}
+
+#[test]
+fn vector_range_proofs() {
+    for len in 1..=VectorCommitment::MAX_LEN {
+        let values: Vec<_> = (0..len as u64)
+            .map(|i| 1_000 * i + u64::max_value() / 2)
+            .collect();
+        let (commitment, opening) = VectorCommitment::new(&values);
+        let proof = VectorRangeProof::prove(&opening).expect("prove");
+        assert_eq!(proof.len(), len);
+        assert!(proof.verify(&commitment));
+
+        let proof = VectorRangeProof::from_slice(&proof.to_bytes()).expect("from_slice");
+        assert!(proof.verify(&commitment));
+    }
+
+    let (commitment, opening) = VectorCommitment::new(&[1_000, 10, 50]);
+    let proof = VectorRangeProof::prove(&opening).expect("prove");
+    let (other_commitment, _) = VectorCommitment::new(&[1_000, 10, 50]);
+    assert!(!proof.verify(&other_commitment));
+    let (fee, _) = VectorCommitment::new(&[0, 10]);
+    assert!(!proof.verify(&(&commitment - &fee)));
+
+    // Swapping value commitments breaks the linking proof.
+    let mut swapped = proof.clone();
+    swapped.value_commitments.swap(0, 1);
+    assert!(!swapped.verify(&commitment));
+
+    let bytes = proof.to_bytes();
+    assert!(VectorRangeProof::from_slice(&bytes[..bytes.len() - 1]).is_none());
+    assert!(VectorRangeProof::from_slice(&bytes[..1]).is_none());
+    let mut tampered = bytes;
+    tampered[0] = 2;
+    assert!(
+        VectorRangeProof::from_slice(&tampered).map_or(true, |proof| !proof.verify(&commitment))
+    );
+}
@@ -0,0 +1,267 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Source of randomness for blinding factors, range proofs, encryption nonces and keys.
+
+use rand::{prng::ChaChaRng, thread_rng, CryptoRng, Error as RandError, RngCore, SeedableRng};
+
+use std::{cell::RefCell, mem, sync::RwLock};
+
+/// Factory of per-thread RNGs installed with `set_rng_factory()`.
+type RngFactory = Box<dyn Fn() -> Box<dyn RngCore> + Send + Sync>;
+
+lazy_static! {
+    static ref RNG_FACTORY: RwLock<Option<RngFactory>> = RwLock::new(None);
+}
+
+thread_local! {
+    /// Deterministic RNG installed with `with_seeded_rng()` on the current thread.
+    static SEEDED_RNG: RefCell<Option<ChaChaRng>> = RefCell::new(None);
+    /// RNG created for the current thread by the factory installed with `set_rng_factory()`.
+    static FACTORY_RNG: RefCell<Option<Box<dyn RngCore>>> = RefCell::new(None);
+}
+
+/// Error installing an RNG factory with [`set_rng_factory()`].
+///
+/// [`set_rng_factory()`]: fn.set_rng_factory.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Fail)]
+pub enum RngFactoryError {
+    /// An RNG factory is already installed.
+    #[fail(display = "RNG factory is already installed")]
+    AlreadySet,
+}
+
+/// Configures the source of randomness for the crate (blinding factors of commitments,
+/// range proofs, encryption nonces, and generated keys), e.g., to route all entropy through
+/// an audited DRBG or a hardware RNG instead of the thread-local RNG from the `rand` crate.
+///
+/// `factory` is called at most once per thread, on the first use of randomness by the crate
+/// after the factory is installed; the created RNG is then used for all randomness
+/// on the thread. The factory can be installed only once per process, preferably
+/// at the start of the program.
+///
+/// RNGs installed with [`with_seeded_rng()`] take precedence over the factory.
+///
+/// # Security
+///
+/// The produced RNGs must be cryptographically secure, which is reflected by
+/// the `CryptoRng` bound. If the RNG is a DRBG, it must be seeded independently for each
+/// thread, e.g., from the OS entropy source; otherwise, blinding factors and nonces
+/// will repeat among threads.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate private_currency;
+/// # extern crate rand;
+/// use private_currency::{crypto, SecretState};
+/// use rand::{prng::ChaChaRng, FromEntropy};
+///
+/// # fn main() {
+/// crypto::set_rng_factory(ChaChaRng::from_entropy).unwrap();
+/// // All randomness below is drawn from a ChaCha20 RNG seeded from the OS.
+/// let _alice = SecretState::with_random_keypair();
+/// // The factory cannot be replaced.
+/// assert!(crypto::set_rng_factory(ChaChaRng::from_entropy).is_err());
+/// # }
+/// ```
+///
+/// [`with_seeded_rng()`]: fn.with_seeded_rng.html
+pub fn set_rng_factory<F, R>(factory: F) -> Result<(), RngFactoryError>
+where
+    F: Fn() -> R + Send + Sync + 'static,
+    R: RngCore + CryptoRng + 'static,
+{
+    let mut installed = RNG_FACTORY.write().expect("RNG factory lock is poisoned");
+    if installed.is_some() {
+        return Err(RngFactoryError::AlreadySet);
+    }
+    let factory: RngFactory = Box::new(move || Box::new(factory()));
+    *installed = Some(factory);
+    Ok(())
+}
+
+/// Runs the closure with all randomness used by the crate on the current thread
+/// (blinding factors of commitments, range proofs, encryption nonces, and generated keys)
+/// drawn from a ChaCha20 RNG seeded with `seed`.
+///
+/// Since Ed25519 signatures are deterministic, transactions created within the closure
+/// are reproducible byte-for-byte, which is useful for integration tests and for reproducing
+/// transactions when debugging divergences among nodes. Calls may be nested; the previous
+/// RNG is restored when the closure exits, even if it panics.
+///
+/// # Security
+///
+/// Everything produced within the closure can be recomputed by anyone knowing the seed.
+/// Never use this function with production wallets.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate private_currency;
+/// use private_currency::{crypto::with_seeded_rng, SecretState};
+///
+/// # fn main() {
+/// let create_transfer = || {
+///     let mut alice = SecretState::with_random_keypair();
+///     alice.initialize();
+///     let bob = SecretState::with_random_keypair();
+///     alice.create_transfer(1_000, bob.public_key(), 10)
+/// };
+/// let transfer = with_seeded_rng([1; 32], create_transfer);
+/// assert_eq!(transfer, with_seeded_rng([1; 32], create_transfer));
+/// # }
+/// ```
+pub fn with_seeded_rng<F, R>(seed: [u8; 32], f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct Restore(Option<ChaChaRng>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            SEEDED_RNG.with(|rng| *rng.borrow_mut() = previous);
+        }
+    }
+
+    let previous = SEEDED_RNG
+        .with(|rng| mem::replace(&mut *rng.borrow_mut(), Some(ChaChaRng::from_seed(seed))));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Handle to the RNG used by the crate: the seeded RNG installed with [`with_seeded_rng()`]
+/// if any, the RNG created by the factory installed with [`set_rng_factory()`] if any,
+/// or the thread-local RNG from the `rand` crate otherwise.
+///
+/// [`with_seeded_rng()`]: fn.with_seeded_rng.html
+/// [`set_rng_factory()`]: fn.set_rng_factory.html
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CrateRng;
+
+impl CrateRng {
+    fn with<F, R>(f: F) -> R
+    where
+        F: FnOnce(&mut dyn RngCore) -> R,
+    {
+        SEEDED_RNG.with(|rng| match *rng.borrow_mut() {
+            Some(ref mut rng) => f(rng),
+            None => Self::with_factory_rng(f),
+        })
+    }
+
+    fn with_factory_rng<F, R>(f: F) -> R
+    where
+        F: FnOnce(&mut dyn RngCore) -> R,
+    {
+        FACTORY_RNG.with(|rng| {
+            let mut rng = rng.borrow_mut();
+            if rng.is_none() {
+                let factory = RNG_FACTORY.read().expect("RNG factory lock is poisoned");
+                *rng = factory.as_ref().map(|factory| factory());
+            }
+            match *rng {
+                Some(ref mut rng) => f(rng.as_mut()),
+                None => f(&mut thread_rng()),
+            }
+        })
+    }
+}
+
+impl RngCore for CrateRng {
+    fn next_u32(&mut self) -> u32 {
+        Self::with(|rng| rng.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        Self::with(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        Self::with(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), RandError> {
+        Self::with(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+// All possible sources of randomness are cryptographically secure.
+impl CryptoRng for CrateRng {}
+
+/// Generates `len` random bytes with the crate RNG.
+pub(crate) fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0_u8; len];
+    CrateRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+#[test]
+fn seeded_rng_is_deterministic_and_scoped() {
+    let draw = || random_bytes(16);
+    let bytes = with_seeded_rng([1; 32], draw);
+    assert_eq!(bytes, with_seeded_rng([1; 32], draw));
+    assert_ne!(bytes, with_seeded_rng([2; 32], draw));
+
+    // Nested calls restore the outer RNG.
+    let (outer, inner) = with_seeded_rng([1; 32], || {
+        let inner = with_seeded_rng([2; 32], draw);
+        (draw(), inner)
+    });
+    assert_eq!(outer, bytes);
+    assert_eq!(inner, with_seeded_rng([2; 32], draw));
+    // Outside of the closure, the thread-local RNG is used.
+    assert_ne!(draw(), bytes);
+}
+
+#[test]
+fn rng_factory_is_used_on_all_threads() {
+    use rand::FromEntropy;
+
+    use std::{cell::Cell, thread};
+
+    thread_local! {
+        static CREATED_RNGS: Cell<usize> = Cell::new(0);
+    }
+
+    // The factory affects other tests in the process, so it must produce secure RNGs.
+    set_rng_factory(|| {
+        CREATED_RNGS.with(|count| count.set(count.get() + 1));
+        ChaChaRng::from_entropy()
+    })
+    .unwrap();
+    assert_eq!(
+        set_rng_factory(ChaChaRng::from_entropy).unwrap_err(),
+        RngFactoryError::AlreadySet
+    );
+
+    let (bytes, created_rngs) = thread::spawn(|| {
+        let bytes = random_bytes(16);
+        assert_ne!(random_bytes(16), bytes);
+        (bytes, CREATED_RNGS.with(Cell::get))
+    })
+    .join()
+    .unwrap();
+    // The RNG is created once per thread.
+    assert_eq!(created_rngs, 1);
+    assert_ne!(bytes, random_bytes(16));
+
+    // The seeded RNG takes precedence over the factory.
+    let draw = || random_bytes(16);
+    assert_eq!(
+        with_seeded_rng([3; 32], draw),
+        with_seeded_rng([3; 32], draw)
+    );
+}
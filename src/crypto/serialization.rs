@@ -27,9 +27,11 @@ use exonum::{
     storage::StorageValue,
 };
 
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+
 use std::{borrow::Cow, error::Error};
 
-use super::proofs::{Commitment, SimpleRangeProof};
+use super::proofs::{AmountProof, Commitment, Opening, SimpleRangeProof};
 
 impl<'a> Field<'a> for Commitment {
     fn field_size() -> u32 {
@@ -109,6 +111,63 @@ impl ExonumJson for Commitment {
     }
 }
 
+/// Openings are serialized as hex strings; they are never a part of transactions
+/// or storage values.
+impl Serialize for Opening {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&serialize::encode_hex(&self.to_bytes()))
+    }
+}
+
+impl<'de> Deserialize<'de> for Opening {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex_string = String::deserialize(deserializer)?;
+        let bytes = serialize::decode_hex(hex_string).map_err(D::Error::custom)?;
+        Opening::from_slice(&bytes).ok_or_else(|| D::Error::custom("invalid `Opening`"))
+    }
+}
+
+/// Standalone range proofs (e.g., in [`BalanceFloorProof`]s) are serialized as hex strings.
+///
+/// [`BalanceFloorProof`]: ::BalanceFloorProof
+impl<'a> Serialize for SimpleRangeProof<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&serialize::encode_hex(self.as_bytes()))
+    }
+}
+
+impl<'de> Deserialize<'de> for SimpleRangeProof<'static> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex_string = String::deserialize(deserializer)?;
+        let bytes = serialize::decode_hex(hex_string).map_err(D::Error::custom)?;
+        SimpleRangeProof::from_slice(&bytes)
+            .map(SimpleRangeProof::into_owned)
+            .ok_or_else(|| D::Error::custom("invalid `SimpleRangeProof`"))
+    }
+}
+
+#[test]
+fn opening_serde_roundtrip() {
+    use exonum::encoding::serialize::json::reexport as serde_json;
+
+    let (_, opening) = Commitment::new(42);
+    let opening_json = serde_json::to_string(&opening).expect("to_string");
+    let opening_copy: Opening = serde_json::from_str(&opening_json).expect("from_str");
+    assert_eq!(opening, opening_copy);
+}
+
+#[test]
+fn range_proof_serde_roundtrip() {
+    use exonum::encoding::serialize::json::reexport as serde_json;
+
+    let (commitment, opening) = Commitment::new(42);
+    let proof = SimpleRangeProof::prove(&opening).expect("prove");
+    let proof_json = serde_json::to_string(&proof).expect("to_string");
+    let proof_copy: SimpleRangeProof = serde_json::from_str(&proof_json).expect("from_str");
+    assert_eq!(proof.as_bytes(), proof_copy.as_bytes());
+    assert!(proof_copy.verify(&commitment));
+}
+
 #[test]
 fn commitment_roundtrip() {
     use exonum::{encoding::serialize::json::reexport as serde_json, storage::StorageValue};
@@ -130,7 +189,7 @@ fn commitment_roundtrip() {
     assert_eq!(value, value_copy);
 }
 
-impl<'a> SegmentField<'a> for SimpleRangeProof {
+impl<'a> SegmentField<'a> for SimpleRangeProof<'a> {
     fn item_size() -> u32 {
         32
     }
@@ -141,13 +200,13 @@ impl<'a> SegmentField<'a> for SimpleRangeProof {
 
     unsafe fn from_buffer(buffer: &'a [u8], from: u32, count: u32) -> Self {
         assert_eq!(count as usize, Self::ELEMENTS_SIZE);
+        // The proof borrows the buffer, so reading it does not allocate.
         let slice = &buffer[from as usize..(from + Self::item_size() * count) as usize];
-        SimpleRangeProof::from_slice(slice)
-            .expect("failed to read `SimpleRangeProof` from trusted source")
+        SimpleRangeProof::from_slice_unchecked(slice)
     }
 
     fn extend_buffer(&self, buffer: &mut Vec<u8>) {
-        buffer.extend_from_slice(&self.to_bytes());
+        buffer.extend_from_slice(self.as_bytes());
     }
 
     fn check_data(
@@ -166,14 +225,12 @@ impl<'a> SegmentField<'a> for SimpleRangeProof {
         if slice.len() != size.unchecked_offset() as usize {
             Err("undersized buffer for `SimpleRangeProof`")?;
         }
-
-        SimpleRangeProof::from_slice(slice)
-            .map(|_| latest_segment)
-            .ok_or_else(|| "incorrect `SimpleRangeProof`".into())
+        // The proof is decoded lazily, during verification.
+        Ok(latest_segment)
     }
 }
 
-impl ExonumJson for SimpleRangeProof {
+impl<'a> ExonumJson for SimpleRangeProof<'a> {
     fn deserialize_field<B: WriteBufferWrapper>(
         value: &Value,
         buffer: &mut B,
@@ -202,8 +259,8 @@ impl ExonumJson for SimpleRangeProof {
     }
 
     fn serialize_field(&self) -> Result<Value, Box<dyn Error + Send + Sync>> {
-        let bytes = self.to_bytes();
-        let element_strings: Vec<_> = bytes
+        let element_strings: Vec<_> = self
+            .as_bytes()
             .chunks(32)
             .map(serialize::encode_hex)
             .map(Value::String)
@@ -213,6 +270,93 @@ impl ExonumJson for SimpleRangeProof {
     }
 }
 
+impl<'a> SegmentField<'a> for AmountProof<'a> {
+    fn item_size() -> u32 {
+        32
+    }
+
+    fn count(&self) -> u32 {
+        (self.as_bytes().len() / 32) as u32
+    }
+
+    unsafe fn from_buffer(buffer: &'a [u8], from: u32, count: u32) -> Self {
+        assert!(Self::is_valid_size(count as usize));
+        let slice = &buffer[from as usize..(from + Self::item_size() * count) as usize];
+        AmountProof::from_slice_unchecked(slice)
+    }
+
+    fn extend_buffer(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(self.as_bytes());
+    }
+
+    fn check_data(
+        buffer: &'a [u8],
+        from: CheckedOffset,
+        count: CheckedOffset,
+        latest_segment: CheckedOffset,
+    ) -> CheckResult {
+        if !Self::is_valid_size(count.unchecked_offset() as usize) {
+            Err("incorrect buffer size for `AmountProof`")?;
+        }
+
+        let size: CheckedOffset = (count * Self::item_size())?;
+        let to: CheckedOffset = (from + size)?;
+        let slice = &buffer[from.unchecked_offset() as usize..to.unchecked_offset() as usize];
+        if slice.len() != size.unchecked_offset() as usize {
+            Err("undersized buffer for `AmountProof`")?;
+        }
+        // The proofs are decoded lazily, during verification.
+        Ok(latest_segment)
+    }
+}
+
+/// Amount proofs are serialized in the same way as `SimpleRangeProof`s, i.e., as arrays
+/// of hex-encoded group elements.
+impl<'a> ExonumJson for AmountProof<'a> {
+    fn deserialize_field<B: WriteBufferWrapper>(
+        value: &Value,
+        buffer: &mut B,
+        from: u32,
+        to: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let elements = value.as_array().ok_or("expected array")?;
+        if !Self::is_valid_size(elements.len()) {
+            Err("incorrect number of elements in proof")?;
+        }
+
+        let mut bytes = Vec::with_capacity(32 * elements.len());
+        for element in elements {
+            let s = element.as_str().ok_or("expected hex string for element")?;
+            let element_bytes = serialize::decode_hex(s)?;
+            if element_bytes.len() != 32 {
+                Err("invalid element byte size, 32 expected")?;
+            }
+            bytes.extend_from_slice(&element_bytes);
+        }
+
+        let proof = AmountProof::from_slice_unchecked(&bytes);
+        if SimpleRangeProof::from_slice(proof.lower_bound().as_bytes()).is_none()
+            || proof.upper_bound().map_or(false, |upper| {
+                SimpleRangeProof::from_slice(upper.as_bytes()).is_none()
+            })
+        {
+            Err("invalid `AmountProof`")?;
+        }
+        buffer.write(from, to, proof);
+        Ok(())
+    }
+
+    fn serialize_field(&self) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        let element_strings: Vec<_> = self
+            .as_bytes()
+            .chunks(32)
+            .map(serialize::encode_hex)
+            .map(Value::String)
+            .collect();
+        Ok(Value::Array(element_strings))
+    }
+}
+
 #[test]
 fn proof_roundtrip() {
     use super::proofs::Opening;
@@ -237,3 +381,33 @@ fn proof_roundtrip() {
     let value_copy = Value::from_bytes(value_bytes.into());
     assert_eq!(value, value_copy);
 }
+
+#[test]
+fn amount_proof_roundtrip() {
+    use exonum::{encoding::serialize::json::reexport as serde_json, storage::StorageValue};
+
+    encoding_struct! {
+        struct Value {
+            first: u32,
+            second: AmountProof,
+        }
+    }
+
+    let (_, opening) = Commitment::new(42);
+    let lower = SimpleRangeProof::prove(&opening).expect("prove");
+    let upper =
+        SimpleRangeProof::prove(&(&Opening::with_no_blinding(100) - &opening)).expect("prove");
+    for proof in vec![
+        AmountProof::new(lower.clone(), None),
+        AmountProof::new(lower, Some(upper)),
+    ] {
+        let value = Value::new(123, proof);
+        let value_json = serde_json::to_string(&value).expect("to_string");
+        let value_copy = serde_json::from_str(&value_json).expect("from_str");
+        assert_eq!(value, value_copy);
+
+        let value_bytes = value.clone().into_bytes();
+        let value_copy = Value::from_bytes(value_bytes.into());
+        assert_eq!(value, value_copy);
+    }
+}
@@ -15,19 +15,36 @@
 //! Debugger for the service.
 
 use exonum::{
-    blockchain::{Schema as CoreSchema, ServiceContext},
-    crypto::Hash,
+    api::{self, ServiceApiState},
+    blockchain::{Schema as CoreSchema, ServiceContext, TransactionError, TransactionErrorType},
+    crypto::{Hash, PublicKey},
     helpers::Height,
     storage::{Fork, KeySetIndex, Snapshot},
 };
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    mpsc,
+use serde::{Serialize, Serializer};
+use serde_json;
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use storage::{maybe_transfer, EventTag, Schema};
-use transactions::Transfer;
+use storage::{maybe_transfer, EventTag, Schema, Wallet};
+use transactions::{
+    Accept, AcceptWithNote, BackUpTransfer, CloseStream, CreateWallet, CryptoTransactions,
+    Error as TxError, Lock, LockForBridge, OpenDispute, ReleaseFromBridge, RequestTransfer,
+    Rerandomize, ResolveDispute, SetProfile, StreamTransfer, Transfer, Unlock, UpdateSettings,
+};
+use SERVICE_ID;
 
 /// Name of table containing transfers rolled back at the previous height.
 ///
@@ -35,9 +52,208 @@ use transactions::Transfer;
 /// to the debugger in `Service::after_commit`.
 const ROLLED_BACK_TRANSFERS: &str = "private_currency.debug.rolled_back";
 
+/// Number of recent events available via the HTTP API of the service.
+const RECENT_EVENTS_CAPACITY: usize = 256;
+
 /// Event sent to the debugger.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Events related to transactions in a block are sent in the order of transactions
+/// in the block, followed by rollbacks performed at the height of the block.
+///
+/// Events are serialized as JSON objects with the `type` field set to the snake-cased
+/// name of the variant.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum DebugEvent {
+    /// A wallet has been created.
+    WalletCreated {
+        /// Transaction that has created the wallet.
+        tx: CreateWallet,
+        /// Height of the block with the transaction.
+        height: Height,
+    },
+
+    /// A transfer has been committed. The transfer needs to be accepted by the receiver;
+    /// otherwise, it will be rolled back.
+    TransferCommitted {
+        /// Committed transfer.
+        transfer: Transfer,
+        /// Height of the block with the transfer.
+        height: Height,
+    },
+
+    /// A transfer has been accepted by the receiver.
+    TransferAccepted {
+        /// Accepted transfer.
+        transfer: Transfer,
+        /// `Accept` transaction.
+        accept: Accept,
+        /// Height of the block with the `Accept` transaction.
+        height: Height,
+    },
+
+    /// A transfer has been accepted by the receiver with an acknowledgment for the sender.
+    TransferAcknowledged {
+        /// Accepted transfer.
+        transfer: Transfer,
+        /// `AcceptWithNote` transaction.
+        accept: AcceptWithNote,
+        /// Height of the block with the `AcceptWithNote` transaction.
+        height: Height,
+    },
+
+    /// Funds have been locked for the bridge to another deployment of the service.
+    BridgeLocked {
+        /// Committed lock.
+        lock: LockForBridge,
+        /// Height of the block with the lock.
+        height: Height,
+    },
+
+    /// A relayer vote to release a lock from another deployment has been committed.
+    /// The vote credits the receiver if it completes the threshold of relayer votes.
+    BridgeReleaseVoted {
+        /// Committed vote.
+        release: ReleaseFromBridge,
+        /// Height of the block with the vote.
+        height: Height,
+    },
+
+    /// Settings of a wallet have been updated.
+    SettingsUpdated {
+        /// Transaction that has updated the settings.
+        tx: UpdateSettings,
+        /// Height of the block with the transaction.
+        height: Height,
+    },
+
+    /// The sender of an accepted transfer has opened a dispute, moving the transferred amount
+    /// into the disputed escrow.
+    DisputeOpened {
+        /// Committed dispute.
+        dispute: OpenDispute,
+        /// Height of the block with the dispute.
+        height: Height,
+    },
+
+    /// The arbiter has resolved a dispute, releasing the escrowed amount.
+    DisputeResolved {
+        /// Committed resolution.
+        resolution: ResolveDispute,
+        /// Height of the block with the resolution.
+        height: Height,
+    },
+
+    /// A payment stream has been opened. Payments of the stream made in the following blocks
+    /// are not reported.
+    StreamOpened {
+        /// Committed stream.
+        stream: StreamTransfer,
+        /// Height of the block with the stream.
+        height: Height,
+    },
+
+    /// A payment stream has been closed early.
+    StreamClosed {
+        /// Committed closure.
+        close: CloseStream,
+        /// Height of the block with the closure.
+        height: Height,
+    },
+
+    /// Funds have been moved to the locked balance of a wallet.
+    FundsLocked {
+        /// Committed lock.
+        lock: Lock,
+        /// Height of the block with the lock.
+        height: Height,
+    },
+
+    /// Locked funds have been returned to the balance of a wallet.
+    FundsUnlocked {
+        /// Committed unlock.
+        unlock: Unlock,
+        /// Height of the block with the unlock.
+        height: Height,
+    },
+
+    /// The profile of a wallet has been set.
+    ProfileSet {
+        /// Transaction that has set the profile.
+        tx: SetProfile,
+        /// Height of the block with the transaction.
+        height: Height,
+    },
+
+    /// A transfer has been requested from a wallet.
+    TransferRequested {
+        /// Committed request.
+        request: RequestTransfer,
+        /// Height of the block with the request.
+        height: Height,
+    },
+
+    /// The balance commitment of a wallet has been rerandomized.
+    BalanceRerandomized {
+        /// Committed transaction.
+        tx: Rerandomize,
+        /// Height of the block with the transaction.
+        height: Height,
+    },
+
+    /// A backup copy of the opening for a transfer has been published.
+    TransferBackedUp {
+        /// Committed backup.
+        backup: BackUpTransfer,
+        /// Height of the block with the backup.
+        height: Height,
+    },
+
+    /// Execution of a transaction of the service has failed. Transfers rejected because
+    /// of an invalid proof are reported as `ProofRejected` instead.
+    ExecutionFailed {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Type of the error.
+        #[serde(serialize_with = "serialize_error_type")]
+        error: TransactionErrorType,
+        /// Description of the error, if any.
+        description: Option<String>,
+        /// Height of the block with the transaction.
+        height: Height,
+    },
+
+    /// A transfer has been rejected during execution because one of its proofs
+    /// has not verified.
+    ///
+    /// A rejected proof of sufficient balance may be caused by an attack, or by a client bug,
+    /// such as a stale `history_len` of the transfer. To distinguish these cases, the event
+    /// contains the state of the sender's wallet after the block with the transfer.
+    ProofRejected {
+        /// Rejected transfer.
+        transfer: Transfer,
+        /// Proof that has failed verification.
+        proof: RejectedProof,
+        /// Index of the past balance of the sender, against which the proof
+        /// of sufficient balance has been verified. `None` for amount proofs.
+        past_balance_index: Option<u64>,
+        /// Length of the sender's history after the block.
+        sender_history_len: u64,
+        /// Index of the last outgoing transfer in the sender's history after the block.
+        sender_last_send_index: u64,
+        /// Height of the block with the transfer.
+        height: Height,
+    },
+
+    /// Service invariants have been violated after committing a block. The event is sent
+    /// regardless of the [wallet filter](struct.DebuggerOptions.html#structfield.wallets).
+    InvariantsViolated {
+        /// Violations of invariants.
+        violations: Vec<InvariantViolation>,
+        /// Height of the block.
+        height: Height,
+    },
+
     /// A transfer has been rolled back.
     RolledBack {
         /// Transfer that has been rolled back.
@@ -47,6 +263,153 @@ pub enum DebugEvent {
     },
 }
 
+impl DebugEvent {
+    /// Returns the height of the block the event relates to.
+    pub fn height(&self) -> Height {
+        match *self {
+            DebugEvent::WalletCreated { height, .. }
+            | DebugEvent::TransferCommitted { height, .. }
+            | DebugEvent::TransferAccepted { height, .. }
+            | DebugEvent::TransferAcknowledged { height, .. }
+            | DebugEvent::BridgeLocked { height, .. }
+            | DebugEvent::BridgeReleaseVoted { height, .. }
+            | DebugEvent::SettingsUpdated { height, .. }
+            | DebugEvent::DisputeOpened { height, .. }
+            | DebugEvent::DisputeResolved { height, .. }
+            | DebugEvent::StreamOpened { height, .. }
+            | DebugEvent::StreamClosed { height, .. }
+            | DebugEvent::FundsLocked { height, .. }
+            | DebugEvent::FundsUnlocked { height, .. }
+            | DebugEvent::ProfileSet { height, .. }
+            | DebugEvent::TransferRequested { height, .. }
+            | DebugEvent::BalanceRerandomized { height, .. }
+            | DebugEvent::TransferBackedUp { height, .. }
+            | DebugEvent::ExecutionFailed { height, .. }
+            | DebugEvent::ProofRejected { height, .. }
+            | DebugEvent::InvariantsViolated { height, .. }
+            | DebugEvent::RolledBack { height, .. } => height,
+        }
+    }
+}
+
+/// Snapshot of the service state at a certain height, produced by [`Schema::dump_state()`].
+///
+/// Dumps are intended for bug reports and for comparing state among nodes which disagree
+/// on the state hash; all collections in the dump are sorted, so dumps of the same state
+/// are identical.
+///
+/// [`Schema::dump_state()`]: storage/struct.Schema.html#method.dump_state
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateDump {
+    /// Height of the latest committed block.
+    pub height: Height,
+    /// State hash of the service.
+    pub state_hash: Vec<Hash>,
+    /// Wallets ordered by their public keys.
+    pub wallets: Vec<WalletDump>,
+    /// Unaccepted transfers ordered by their hashes.
+    pub pending_transfers: Vec<PendingTransferDump>,
+    /// Non-empty rollback buckets, mapping heights to hashes of transfers
+    /// rolled back at this height.
+    pub rollback_queue: BTreeMap<u64, Vec<Hash>>,
+}
+
+/// Wallet in a [`StateDump`](struct.StateDump.html).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WalletDump {
+    /// Wallet summary.
+    pub wallet: Wallet,
+    /// Hashes of unaccepted incoming transfers of the wallet, sorted.
+    pub unaccepted_transfers: Vec<Hash>,
+}
+
+/// Unaccepted transfer in a [`StateDump`](struct.StateDump.html).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PendingTransferDump {
+    /// Hash of the transfer.
+    pub hash: Hash,
+    /// Transfer transaction.
+    pub transfer: Transfer,
+    /// Height at which the transfer will be rolled back.
+    pub rollback_height: Option<Height>,
+}
+
+/// Violation of a service invariant.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvariantViolation {
+    /// Violated invariant.
+    #[serde(serialize_with = "serialize_check")]
+    pub check: Invariants,
+    /// Wallet violating the invariant, or `None` if the invariant is not related
+    /// to a specific wallet.
+    pub wallet: Option<PublicKey>,
+    /// Description of the checked value.
+    pub description: String,
+    /// Expected value, formatted with `Debug`.
+    pub expected: String,
+    /// Actual value, formatted with `Debug`.
+    pub actual: String,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.check.name())?;
+        if let Some(ref wallet) = self.wallet {
+            write!(formatter, " (wallet {:?})", wallet)?;
+        }
+        write!(
+            formatter,
+            ": {}: expected {}, got {}",
+            self.description, self.expected, self.actual
+        )
+    }
+}
+
+fn serialize_check<S>(check: &Invariants, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(check.name())
+}
+
+/// Proof in a `Transfer` transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectedProof {
+    /// Proof that the transferred amount is not less than the minimum transfer amount.
+    Amount,
+    /// Proof that the sender's balance is sufficient to cover the transfer.
+    SufficientBalance,
+}
+
+/// Serializes the error type in the same way as [`TransactionOutcome`](::TransactionOutcome).
+fn serialize_error_type<S>(error: &TransactionErrorType, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    #[derive(Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum ErrorType {
+        Code(u8),
+        Panic,
+    }
+
+    match *error {
+        TransactionErrorType::Code(code) => ErrorType::Code(code),
+        TransactionErrorType::Panic => ErrorType::Panic,
+    }
+    .serialize(serializer)
+}
+
+/// Record in the event log of the debugger.
+#[derive(Serialize)]
+struct LogRecord<'a> {
+    height: Height,
+    /// Time of receiving the event by the debugger, in milliseconds since the Unix epoch.
+    timestamp_ms: u64,
+    event: &'a DebugEvent,
+}
+
 /// Debugger provides ability to connect to the service and retrieve information
 /// useful for debugging.
 ///
@@ -69,45 +432,194 @@ pub enum DebugEvent {
 /// # drop(service);
 /// # handle.join().unwrap();
 /// ```
-#[derive(Debug)]
+///
+/// The service keeps sending events to the debugger until it is dropped. If events
+/// are not retrieved from the debugger, the service will block once the channel between them
+/// is full; thus, the debugger should be dropped if it is not used.
+///
+/// # HTTP API
+///
+/// A service with an attached debugger serves recent events (up to 256 events, including
+/// events emitted after the debugger has been dropped) via the `v1/debug/events` endpoint
+/// of the private HTTP API.
+///
+/// The dump of the service state (see [`StateDump`]) is available via the `v1/debug/state`
+/// endpoint of the private HTTP API.
+///
+/// [`StateDump`]: struct.StateDump.html
+///
+/// # Event log
+///
+/// Events can be written to a file or another [`Write`] sink with [`log_to()`]
+/// or [`log_to_file()`]. Events are written in the [JSON lines] format, one event per line,
+/// as they are retrieved via the `Iterator` implementation. Each record contains
+/// the height of the event (`height`), the time it has been received by the debugger
+/// in milliseconds since the Unix epoch (`timestamp_ms`), and the event itself (`event`).
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`log_to()`]: #method.log_to
+/// [`log_to_file()`]: #method.log_to_file
+/// [JSON lines]: http://jsonlines.org/
 pub struct Debugger {
     rx: mpsc::Receiver<DebugEvent>,
+    log: Option<Box<dyn Write + Send>>,
+    log_error: Option<io::Error>,
+}
+
+impl fmt::Debug for Debugger {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Debugger")
+            .field("rx", &self.rx)
+            .field("logs_events", &self.log.is_some())
+            .field("log_error", &self.log_error)
+            .finish()
+    }
+}
+
+impl Debugger {
+    /// Writes all events retrieved from the debugger to the specified sink.
+    pub fn log_to<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.log = Some(Box::new(writer));
+        self.log_error = None;
+        self
+    }
+
+    /// Appends all events retrieved from the debugger to the specified file. The file
+    /// is created if necessary.
+    pub fn log_to_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(self.log_to(BufWriter::new(file)))
+    }
+
+    /// Returns the error that has occurred when writing the event log, if any.
+    /// Events are no longer logged after an error.
+    pub fn log_error(&self) -> Option<&io::Error> {
+        self.log_error.as_ref()
+    }
+
+    fn log_event(&mut self, event: &DebugEvent) {
+        let result = match self.log {
+            Some(ref mut log) => write_record(log, event),
+            None => return,
+        };
+        if let Err(e) = result {
+            self.log = None;
+            self.log_error = Some(e);
+        }
+    }
+}
+
+fn write_record<W: Write + ?Sized>(writer: &mut W, event: &DebugEvent) -> io::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let record = LogRecord {
+        height: event.height(),
+        timestamp_ms: timestamp.as_secs() * 1_000 + u64::from(timestamp.subsec_millis()),
+        event,
+    };
+    serde_json::to_writer(&mut *writer, &record)?;
+    writer.write_all(b"\n")?;
+    // Flush after each record, so that the log is complete even if the process is aborted.
+    writer.flush()
+}
+
+bitflags! {
+    /// Invariants of the service storage, which can be checked after each committed block.
+    ///
+    /// Checks are expensive; each of them is *at least* linear w.r.t. the number of
+    /// wallets in the system.
+    #[derive(Default)]
+    pub struct Invariants: u32 {
+        /// History hash, history length and the hash of unaccepted transfers recorded
+        /// in each wallet correspond to the wallet indexes.
+        const HISTORY_HASH = 1;
+        /// Past balances of each wallet are cached starting from its last outgoing transfer.
+        const PAST_BALANCES = 2;
+        /// There are no outgoing transfers in the wallet history after `last_send_index`.
+        const LAST_SEND_INDEX = 4;
+        /// Each unaccepted transfer is scheduled for rollback exactly once, at its rollback
+        /// height, and only unaccepted transfers are scheduled for rollback.
+        const ROLLBACK_INDEX = 8;
+    }
+}
+
+impl Invariants {
+    /// Returns the name of a single invariant, or `"multiple"` if the flags contain
+    /// more than one invariant.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Invariants::HISTORY_HASH => "history_hash",
+            Invariants::PAST_BALANCES => "past_balances",
+            Invariants::LAST_SEND_INDEX => "last_send_index",
+            Invariants::ROLLBACK_INDEX => "rollback_index",
+            _ => "multiple",
+        }
+    }
 }
 
 /// Debugger options.
 #[derive(Debug, Clone, Default)]
 pub struct DebuggerOptions {
-    /// Check service invariants on `after_commit`.
+    /// Service invariants checked on `after_commit`, in addition to invariants selected
+    /// with [`ServiceBuilder::invariants()`].
     ///
-    /// This is an expensive operation; it is *at least* linear w.r.t. the number of
-    /// wallets in the system.
-    pub check_invariants: bool,
+    /// [`ServiceBuilder::invariants()`]: struct.ServiceBuilder.html#method.invariants
+    pub check_invariants: Invariants,
+
+    /// Wallets to watch. If set, the debugger only receives events involving at least
+    /// one of the specified wallets:
+    ///
+    /// - `WalletCreated` if the created wallet is watched
+    /// - `TransferCommitted`, `TransferAccepted` and `RolledBack` if the sender
+    ///   or the receiver of the transfer is watched
+    /// - `BridgeLocked` if the sender of the lock is watched, and `BridgeReleaseVoted`
+    ///   if the receiver of the released lock is watched
+    /// - `ExecutionFailed` and `ProofRejected` if the transaction is authored
+    ///   by a watched wallet or,
+    ///   for transfers and accepts, if it names a watched wallet as the counterparty.
+    ///   Failed transactions of unknown types are not reported
+    ///
+    /// If not set, the debugger receives all events.
+    pub wallets: Option<HashSet<PublicKey>>,
 }
 
 impl Iterator for Debugger {
     type Item = DebugEvent;
 
     fn next(&mut self) -> Option<DebugEvent> {
-        self.rx.recv().ok()
+        let event = self.rx.recv().ok()?;
+        self.log_event(&event);
+        Some(event)
     }
 }
 
 #[derive(Debug)]
 pub(crate) struct DebuggerProbe {
     tx: mpsc::SyncSender<DebugEvent>,
+    wallets: Option<HashSet<PublicKey>>,
+    recent_events: Arc<Mutex<VecDeque<DebugEvent>>>,
     shutdown: AtomicBool,
-    options: DebuggerOptions,
 }
 
 impl DebuggerProbe {
-    pub(crate) fn create_channel(size: usize, options: DebuggerOptions) -> (Self, Debugger) {
+    pub(crate) fn create_channel(
+        size: usize,
+        wallets: Option<HashSet<PublicKey>>,
+    ) -> (Self, Debugger) {
         let (tx, rx) = mpsc::sync_channel(size);
         let probe = DebuggerProbe {
             tx,
+            wallets,
+            recent_events: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_EVENTS_CAPACITY))),
             shutdown: AtomicBool::new(false),
-            options,
         };
-        let debugger = Debugger { rx };
+        let debugger = Debugger {
+            rx,
+            log: None,
+            log_error: None,
+        };
         (probe, debugger)
     }
 
@@ -119,83 +631,520 @@ impl DebuggerProbe {
         self.shutdown.store(true, Ordering::SeqCst);
     }
 
-    pub fn on_before_commit(&self, fork: &mut Fork) {
-        if self.is_shutdown() {
-            return;
+    /// Checks if an event involving the specified wallets should be sent to the debugger.
+    fn is_watched(&self, keys: &[&PublicKey]) -> bool {
+        match self.wallets {
+            Some(ref wallets) => keys.iter().any(|key| wallets.contains(key)),
+            None => true,
         }
+    }
 
+    pub fn on_before_commit(&self, fork: &mut Fork) {
         let mut schema = Schema::new(fork);
         schema.copy_rolled_back_transfers();
     }
 
-    pub fn on_after_commit(&self, context: &ServiceContext) {
-        if self.is_shutdown() {
-            return;
-        }
-        let snapshot = context.snapshot();
+    /// Sends events for the committed block to the debugger, followed by invariant
+    /// violations detected after the block, if any.
+    pub fn on_after_commit(&self, context: &ServiceContext, violations: Vec<InvariantViolation>) {
         let height = context.height();
-        let schema = Schema::new(&snapshot);
-
-        if self.options.check_invariants {
-            schema.check_invariants();
+        let mut events = block_events(context.snapshot(), height, |keys| self.is_watched(keys));
+        if !violations.is_empty() {
+            events.push(DebugEvent::InvariantsViolated { violations, height });
         }
+        self.record_recent_events(&events);
 
-        // Send rolled back transfers to the debugger.
-        let rolled_back_transfers = schema.rolled_back_transfers();
-        let result: Result<(), _> = rolled_back_transfers
-            .iter()
-            .map(|hash| maybe_transfer(&snapshot, &hash).expect("Transfer"))
-            .map(|transfer| DebugEvent::RolledBack { transfer, height })
+        if self.is_shutdown() {
+            return;
+        }
+        let result: Result<(), _> = events
+            .into_iter()
             .map(|message| self.tx.send(message).map_err(drop))
             .collect();
         if result.is_err() {
-            // The debugger is shut down, we can shut down operations as well.
+            // The debugger is shut down; events are only recorded for the HTTP API from now on.
             self.shutdown();
         }
     }
+
+    fn record_recent_events(&self, events: &[DebugEvent]) {
+        let mut recent_events = self
+            .recent_events
+            .lock()
+            .expect("recent events are poisoned");
+        for event in events {
+            if recent_events.len() == RECENT_EVENTS_CAPACITY {
+                recent_events.pop_front();
+            }
+            recent_events.push_back(event.clone());
+        }
+    }
+
+    /// HTTP API handler returning the dump of the service state.
+    pub(crate) fn state_endpoint(
+        &self,
+    ) -> impl Fn(&ServiceApiState, ()) -> api::Result<StateDump> + Clone + Send + Sync + 'static
+    {
+        |state: &ServiceApiState, _: ()| Ok(Schema::new(state.snapshot()).dump_state())
+    }
+
+    /// HTTP API handler returning recent events, oldest first.
+    pub(crate) fn endpoint(
+        &self,
+    ) -> impl Fn(&ServiceApiState, ()) -> api::Result<Vec<DebugEvent>> + Clone + Send + Sync + 'static
+    {
+        let recent_events = Arc::clone(&self.recent_events);
+        move |_: &ServiceApiState, _: ()| {
+            let recent_events = recent_events.lock().expect("recent events are poisoned");
+            Ok(recent_events.iter().cloned().collect())
+        }
+    }
+}
+
+/// Produces events for the block at the specified height. Only events involving wallets
+/// matching `filter` are produced.
+///
+/// Rollbacks are only reported if the service has a debugger probe attached to it,
+/// since they are recorded by the probe.
+pub(crate) fn block_events<F>(snapshot: &dyn Snapshot, height: Height, filter: F) -> Vec<DebugEvent>
+where
+    F: Fn(&[&PublicKey]) -> bool,
+{
+    let mut events = transaction_events(snapshot, height, &filter);
+    let schema = Schema::new(snapshot);
+    let rolled_back_transfers = schema.rolled_back_transfers();
+    events.extend(
+        rolled_back_transfers
+            .iter()
+            .map(|hash| maybe_transfer(snapshot, &hash).expect("Transfer"))
+            .filter(|transfer| filter(&[transfer.from(), transfer.to()]))
+            .map(|transfer| DebugEvent::RolledBack { transfer, height }),
+    );
+    events
+}
+
+/// Produces events for transactions of the service in the block at the specified height.
+/// Only transactions involving wallets matching `filter` are considered.
+fn transaction_events<F>(snapshot: &dyn Snapshot, height: Height, filter: F) -> Vec<DebugEvent>
+where
+    F: Fn(&[&PublicKey]) -> bool,
+{
+    use exonum::blockchain::TransactionSet;
+
+    let core_schema = CoreSchema::new(snapshot);
+    let results = core_schema.transaction_results();
+    let mut events = vec![];
+
+    for tx_hash in core_schema.block_transactions(height).iter() {
+        let raw = match core_schema.transactions().get(&tx_hash) {
+            Some(ref raw) if raw.service_id() == SERVICE_ID => raw.clone(),
+            _ => continue,
+        };
+        let result = match results.get(&tx_hash) {
+            Some(result) => result.0,
+            None => continue,
+        };
+        // Transactions with unknown message types are skipped; they always fail.
+        let tx = match CryptoTransactions::tx_from_raw(raw) {
+            Ok(tx) => tx,
+            Err(_) => continue,
+        };
+
+        let accepted_transfer = match tx {
+            CryptoTransactions::Accept(ref accept) => {
+                maybe_transfer(snapshot, accept.transfer_id())
+            }
+            CryptoTransactions::AcceptWithNote(ref accept) => {
+                maybe_transfer(snapshot, accept.transfer_id())
+            }
+            _ => None,
+        };
+        let is_watched = match tx {
+            CryptoTransactions::CreateWallet(ref tx) => filter(&[tx.key()]),
+            CryptoTransactions::Transfer(ref transfer) => filter(&[transfer.from(), transfer.to()]),
+            CryptoTransactions::Accept(ref accept) => match accepted_transfer {
+                Some(ref transfer) => filter(&[accept.receiver(), transfer.from()]),
+                None => filter(&[accept.receiver()]),
+            },
+            CryptoTransactions::LockForBridge(ref lock) => filter(&[lock.from()]),
+            CryptoTransactions::ReleaseFromBridge(ref release) => {
+                match release.lock_transaction() {
+                    Some(ref lock) => filter(&[lock.to()]),
+                    None => false,
+                }
+            }
+            CryptoTransactions::AcceptWithNote(ref accept) => match accepted_transfer {
+                Some(ref transfer) => filter(&[accept.receiver(), transfer.from()]),
+                None => filter(&[accept.receiver()]),
+            },
+            CryptoTransactions::UpdateSettings(ref tx) => filter(&[tx.key()]),
+            CryptoTransactions::OpenDispute(ref dispute) => match dispute.transfer_transaction() {
+                Some(ref transfer) => filter(&[transfer.from(), transfer.to()]),
+                None => filter(&[dispute.sender()]),
+            },
+            CryptoTransactions::ResolveDispute(ref resolution) => {
+                match resolution.transfer_transaction() {
+                    Some(ref transfer) => filter(&[transfer.from(), transfer.to()]),
+                    None => false,
+                }
+            }
+            CryptoTransactions::StreamTransfer(ref stream) => filter(&[stream.from(), stream.to()]),
+            CryptoTransactions::CloseStream(ref close) => match close.stream_transaction() {
+                Some(ref stream) => filter(&[stream.from(), stream.to()]),
+                None => filter(&[close.author()]),
+            },
+            CryptoTransactions::Lock(ref lock) => filter(&[lock.owner()]),
+            CryptoTransactions::Unlock(ref unlock) => filter(&[unlock.owner()]),
+            CryptoTransactions::SetProfile(ref tx) => filter(&[tx.key()]),
+            CryptoTransactions::RequestTransfer(ref request) => {
+                filter(&[request.requester(), request.payer()])
+            }
+            CryptoTransactions::Rerandomize(ref tx) => filter(&[tx.owner()]),
+            CryptoTransactions::BackUpTransfer(ref backup) => {
+                filter(&[backup.from(), backup.holder()])
+            }
+        };
+        if !is_watched {
+            continue;
+        }
+
+        if let Err(error) = result {
+            if let CryptoTransactions::Transfer(ref transfer) = tx {
+                if let Some(event) = rejected_proof_event(snapshot, transfer, &error, height) {
+                    events.push(event);
+                    continue;
+                }
+            }
+            events.push(DebugEvent::ExecutionFailed {
+                tx_hash,
+                error: error.error_type(),
+                description: error.description().map(str::to_owned),
+                height,
+            });
+            continue;
+        }
+
+        let event = match tx {
+            CryptoTransactions::CreateWallet(tx) => DebugEvent::WalletCreated { tx, height },
+            CryptoTransactions::Transfer(transfer) => {
+                DebugEvent::TransferCommitted { transfer, height }
+            }
+            CryptoTransactions::Accept(accept) => DebugEvent::TransferAccepted {
+                transfer: accepted_transfer.expect("Transfer"),
+                accept,
+                height,
+            },
+            CryptoTransactions::LockForBridge(lock) => DebugEvent::BridgeLocked { lock, height },
+            CryptoTransactions::ReleaseFromBridge(release) => {
+                DebugEvent::BridgeReleaseVoted { release, height }
+            }
+            CryptoTransactions::AcceptWithNote(accept) => DebugEvent::TransferAcknowledged {
+                transfer: accepted_transfer.expect("Transfer"),
+                accept,
+                height,
+            },
+            CryptoTransactions::UpdateSettings(tx) => DebugEvent::SettingsUpdated { tx, height },
+            CryptoTransactions::OpenDispute(dispute) => {
+                DebugEvent::DisputeOpened { dispute, height }
+            }
+            CryptoTransactions::ResolveDispute(resolution) => {
+                DebugEvent::DisputeResolved { resolution, height }
+            }
+            CryptoTransactions::StreamTransfer(stream) => {
+                DebugEvent::StreamOpened { stream, height }
+            }
+            CryptoTransactions::CloseStream(close) => DebugEvent::StreamClosed { close, height },
+            CryptoTransactions::Lock(lock) => DebugEvent::FundsLocked { lock, height },
+            CryptoTransactions::Unlock(unlock) => DebugEvent::FundsUnlocked { unlock, height },
+            CryptoTransactions::SetProfile(tx) => DebugEvent::ProfileSet { tx, height },
+            CryptoTransactions::RequestTransfer(request) => {
+                DebugEvent::TransferRequested { request, height }
+            }
+            CryptoTransactions::Rerandomize(tx) => DebugEvent::BalanceRerandomized { tx, height },
+            CryptoTransactions::BackUpTransfer(backup) => {
+                DebugEvent::TransferBackedUp { backup, height }
+            }
+        };
+        events.push(event);
+    }
+    events
+}
+
+/// Produces a `ProofRejected` event if the transfer has failed because of an invalid proof.
+fn rejected_proof_event(
+    snapshot: &dyn Snapshot,
+    transfer: &Transfer,
+    error: &TransactionError,
+    height: Height,
+) -> Option<DebugEvent> {
+    let proof = match error.error_type() {
+        TransactionErrorType::Code(code) if code == TxError::IncorrectAmountProof as u8 => {
+            RejectedProof::Amount
+        }
+        TransactionErrorType::Code(code) if code == TxError::IncorrectProof as u8 => {
+            RejectedProof::SufficientBalance
+        }
+        _ => return None,
+    };
+    // The sender's wallet may be missing if the amount proof has been rejected,
+    // since the amount proof is checked first.
+    let sender = Schema::new(snapshot).wallet(transfer.from());
+    let (sender_history_len, sender_last_send_index) = sender.map_or((0, 0), |wallet| {
+        (wallet.history_len(), wallet.last_send_index())
+    });
+    let past_balance_index = match proof {
+        RejectedProof::Amount => None,
+        RejectedProof::SufficientBalance => Some(transfer.history_len() - 1),
+    };
+
+    Some(DebugEvent::ProofRejected {
+        transfer: transfer.clone(),
+        proof,
+        past_balance_index,
+        sender_history_len,
+        sender_last_send_index,
+        height,
+    })
 }
 
 impl<T: AsRef<dyn Snapshot>> Schema<T> {
+    /// Dumps the entire state of the service. This is an expensive operation; it is linear
+    /// w.r.t. the number of wallets and unaccepted transfers in the system.
+    pub fn dump_state(&self) -> StateDump {
+        let height = CoreSchema::new(&self.inner).height();
+        let mut wallets = vec![];
+        let mut pending_transfers = vec![];
+        for wallet in self.wallets().values() {
+            let mut unaccepted_transfers: Vec<_> = self
+                .unaccepted_transfers(wallet.public_key())
+                .into_iter()
+                .collect();
+            unaccepted_transfers.sort();
+            for hash in &unaccepted_transfers {
+                pending_transfers.push(PendingTransferDump {
+                    hash: *hash,
+                    transfer: maybe_transfer(&self.inner, hash).expect("Transfer"),
+                    rollback_height: self.transfer_rollback_height(hash),
+                });
+            }
+            wallets.push(WalletDump {
+                wallet,
+                unaccepted_transfers,
+            });
+        }
+        wallets.sort_by(|a, b| a.wallet.public_key().cmp(b.wallet.public_key()));
+        pending_transfers.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+        let max_height = pending_transfers
+            .iter()
+            .filter_map(|pending| pending.rollback_height)
+            .max()
+            .unwrap_or(height);
+        let mut rollback_queue = BTreeMap::new();
+        for bucket_height in height.0..=max_height.0 {
+            let mut bucket = self.rollback_transfers(Height(bucket_height));
+            if !bucket.is_empty() {
+                bucket.sort();
+                rollback_queue.insert(bucket_height, bucket);
+            }
+        }
+
+        StateDump {
+            height,
+            state_hash: self.state_hash(),
+            wallets,
+            pending_transfers,
+            rollback_queue,
+        }
+    }
+
     fn rolled_back_transfers(&self) -> KeySetIndex<&T, Hash> {
         KeySetIndex::new(ROLLED_BACK_TRANSFERS, &self.inner)
     }
 
-    fn check_invariants(&self) {
+    /// Checks the selected invariants of the service storage, returning violations
+    /// of the invariants.
+    pub fn check_invariants(&self, invariants: Invariants) -> Vec<InvariantViolation> {
+        let mut violations = vec![];
         let wallets = self.wallets();
         for wallet in wallets.values() {
-            let pk = wallet.public_key();
-            let wallet_history = self.history_index(pk);
-
-            // Check that summary in `wallet` corresponds to data in other indexes.
-            assert_eq!(*wallet.history_hash(), wallet_history.merkle_root());
-            assert_eq!(wallet.history_len(), wallet_history.len());
-            assert_eq!(
-                *wallet.unaccepted_transfers_hash(),
-                self.unaccepted_transfers_index(pk).merkle_root()
+            if invariants.contains(Invariants::HISTORY_HASH) {
+                let mut check = Check::new(Invariants::HISTORY_HASH, &mut violations);
+                self.check_history_hash(&wallet, &mut check);
+            }
+            if invariants.contains(Invariants::PAST_BALANCES) {
+                let mut check = Check::new(Invariants::PAST_BALANCES, &mut violations);
+                self.check_past_balances(&wallet, &mut check);
+            }
+            if invariants.contains(Invariants::LAST_SEND_INDEX) {
+                let mut check = Check::new(Invariants::LAST_SEND_INDEX, &mut violations);
+                self.check_last_send_index(&wallet, &mut check);
+            }
+        }
+        if invariants.contains(Invariants::ROLLBACK_INDEX) {
+            let mut check = Check::new(Invariants::ROLLBACK_INDEX, &mut violations);
+            self.check_rollback_index(&mut check);
+        }
+        violations
+    }
+
+    /// Checks that summary in `wallet` corresponds to data in other indexes.
+    fn check_history_hash(&self, wallet: &Wallet, check: &mut Check) {
+        let pk = wallet.public_key();
+        let wallet_history = self.history_index(pk);
+        check.expect_eq(
+            Some(pk),
+            "history hash",
+            wallet_history.merkle_root(),
+            *wallet.history_hash(),
+        );
+        check.expect_eq(
+            Some(pk),
+            "history length",
+            wallet_history.len(),
+            wallet.history_len(),
+        );
+        check.expect_eq(
+            Some(pk),
+            "hash of unaccepted transfers",
+            self.unaccepted_transfers_index(pk).merkle_root(),
+            *wallet.unaccepted_transfers_hash(),
+        );
+    }
+
+    /// Checks that past balances of the wallet are cached as expected.
+    fn check_past_balances(&self, wallet: &Wallet, check: &mut Check) {
+        let pk = wallet.public_key();
+        for i in wallet.last_send_index()..wallet.history_len() {
+            check.expect_eq(
+                Some(pk),
+                &format!("past balance #{} is cached", i),
+                true,
+                self.past_balance(pk, i).is_some(),
             );
+        }
+        check.expect_eq(
+            Some(pk),
+            "latest past balance",
+            Some(wallet.balance()),
+            self.past_balance(pk, wallet.history_len() - 1),
+        );
+    }
 
-            // Check that past balances of the wallet are cached as expected.
-            for i in wallet.last_send_index()..wallet.history_len() {
-                assert!(self.past_balance(pk, i).is_some());
+    /// Checks the validity of `last_send_index` field.
+    fn check_last_send_index(&self, wallet: &Wallet, check: &mut Check) {
+        let pk = wallet.public_key();
+        let wallet_history = self.history_index(pk);
+        let first_index = wallet.last_send_index() + 1;
+        for (i, event) in (first_index..).zip(wallet_history.iter_from(first_index)) {
+            if event.tag() == EventTag::Transfer as u8 {
+                let transfer =
+                    maybe_transfer(&self.inner, event.transaction_hash()).expect("Transfer");
+                check.expect_eq(
+                    Some(pk),
+                    &format!(
+                        "receiver of transfer #{} after indicated `last_send_index`",
+                        i
+                    ),
+                    pk,
+                    transfer.to(),
+                );
             }
-            assert_eq!(
-                self.past_balance(pk, wallet.history_len() - 1),
-                Some(wallet.balance())
-            );
+        }
+    }
 
-            // Check the validity of `last_send_index` field.
-            for event in wallet_history.iter_from(wallet.last_send_index() + 1) {
-                if event.tag() == EventTag::Transfer as u8 {
-                    let transfer =
-                        maybe_transfer(&self.inner, event.transaction_hash()).expect("Transfer");
-                    assert_eq!(
-                        transfer.to(),
-                        pk,
-                        "outgoing transfer after indicated `last_send_index`"
-                    );
+    /// Checks that each unaccepted transfer appears in exactly one rollback bucket,
+    /// which corresponds to its rollback height, and that rollback buckets contain
+    /// only unaccepted transfers.
+    fn check_rollback_index(&self, check: &mut Check) {
+        let mut pending = HashMap::new();
+        for wallet in self.wallets().values() {
+            let pk = wallet.public_key();
+            for transfer_id in self.unaccepted_transfers(pk) {
+                let height = self.transfer_rollback_height(&transfer_id);
+                check.expect_eq(
+                    Some(pk),
+                    &format!("transfer {:?} is scheduled for rollback", transfer_id),
+                    true,
+                    height.is_some(),
+                );
+                if let Some(height) = height {
+                    pending.insert(transfer_id, height);
                 }
             }
         }
+        check.expect_eq(
+            None,
+            "number of unaccepted transfers",
+            pending.len() as u64,
+            self.unaccepted_transfers_count(),
+        );
+
+        // Buckets below the current height are cleared by rollbacks.
+        let height = CoreSchema::new(&self.inner).height();
+        if let Some(previous) = height.0.checked_sub(1) {
+            check.expect_eq(
+                None,
+                &format!("rollback bucket at height {}", previous),
+                vec![],
+                self.rollback_transfers(Height(previous)),
+            );
+        }
+
+        let max_height = pending.values().max().map_or(height.0, |h| h.0);
+        let mut seen = HashSet::new();
+        for bucket_height in height.0..=max_height {
+            for transfer_id in self.rollback_transfers(Height(bucket_height)) {
+                check.expect_eq(
+                    None,
+                    &format!("rollback height of transfer {:?}", transfer_id),
+                    pending.get(&transfer_id).cloned(),
+                    Some(Height(bucket_height)),
+                );
+                check.expect_eq(
+                    None,
+                    &format!("transfer {:?} is in a single rollback bucket", transfer_id),
+                    true,
+                    seen.insert(transfer_id),
+                );
+            }
+        }
+        check.expect_eq(
+            None,
+            "number of transfers in rollback buckets",
+            pending.len(),
+            seen.len(),
+        );
+    }
+}
+
+/// Collector of violations for a single invariant check.
+struct Check<'a> {
+    check: Invariants,
+    violations: &'a mut Vec<InvariantViolation>,
+}
+
+impl<'a> Check<'a> {
+    fn new(check: Invariants, violations: &'a mut Vec<InvariantViolation>) -> Self {
+        Check { check, violations }
+    }
+
+    fn expect_eq<V: fmt::Debug + PartialEq>(
+        &mut self,
+        wallet: Option<&PublicKey>,
+        description: &str,
+        expected: V,
+        actual: V,
+    ) {
+        if expected != actual {
+            self.violations.push(InvariantViolation {
+                check: self.check,
+                wallet: wallet.cloned(),
+                description: description.to_owned(),
+                expected: format!("{:?}", expected),
+                actual: format!("{:?}", actual),
+            });
+        }
     }
 }
 
@@ -217,3 +1166,26 @@ impl<'a> Schema<&'a mut Fork> {
         }
     }
 }
+
+#[test]
+fn event_log_format() {
+    let event = DebugEvent::ExecutionFailed {
+        tx_hash: Hash::zero(),
+        error: TransactionErrorType::Code(3),
+        description: Some("oops".to_owned()),
+        height: Height(5),
+    };
+    let mut log = vec![];
+    write_record(&mut log, &event).unwrap();
+    write_record(&mut log, &event).unwrap();
+
+    let log = String::from_utf8(log).unwrap();
+    let lines: Vec<_> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(record["height"], serde_json::to_value(Height(5)).unwrap());
+    assert!(record["timestamp_ms"].as_u64().unwrap() > 0);
+    assert_eq!(record["event"]["type"], "execution_failed");
+    assert_eq!(record["event"]["error"], json!({ "code": 3 }));
+    assert_eq!(record["event"]["description"], "oops");
+}
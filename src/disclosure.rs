@@ -0,0 +1,612 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Selective disclosure of transfer amounts and balance bounds to third parties.
+
+use byteorder::{ByteOrder, LittleEndian};
+use exonum::{
+    blockchain::Block,
+    crypto::{self as exonum_crypto, PublicKey, SecretKey, Signature},
+    helpers::Height,
+};
+
+use api::{
+    BalanceHistoryProof, BalanceHistoryQuery, FullEvent, TrustAnchor, VerifyError, WalletProof,
+    WalletQuery,
+};
+use crypto::{enc, Commitment, Opening, SimpleRangeProof};
+use secrets::EncryptedData;
+use transactions::Transfer;
+
+/// Package disclosing amounts of selected transfers in the wallet history to a third party
+/// (e.g., an accountant), produced by [`SecretState::disclose()`].
+///
+/// The package contains a [`WalletProof`] for the wallet history, which allows to verify
+/// the disclosed transfers against the blockchain, and openings for the amounts of selected
+/// transfers. The package is signed by the wallet key, so the third party can be sure
+/// that the disclosure is authorized by the wallet owner. The package does not reveal
+/// the balance of the wallet or amounts of other transfers.
+///
+/// [`SecretState::disclose()`]: ::SecretState::disclose()
+/// [`WalletProof`]: ::api::WalletProof
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Disclosure {
+    key: PublicKey,
+    start_history_at: u64,
+    wallet_proof: WalletProof,
+    openings: Vec<DisclosedOpening>,
+    signature: Signature,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DisclosedOpening {
+    index: u64,
+    opening: Opening,
+}
+
+/// Archive of openings for all transfer amounts in the wallet history, encrypted
+/// to a designated compliance key, produced by [`SecretState::export_compliance_archive()`].
+///
+/// Unlike a [`Disclosure`], the archive covers the entire wallet history, including
+/// bridge locks and releases, and the openings can only be decrypted by the holder
+/// of the compliance key (e.g., a regulated entity that must retain decryptable records
+/// of the wallet activity). The archive contains a [`WalletProof`] for the entire history
+/// and is signed by the wallet key. When the archive is [checked](#method.check),
+/// each decrypted opening is verified against the committed amount of the corresponding
+/// event, and the openings are required to cover all amount-bearing events in the history.
+///
+/// Since the archive covers the entire history, the compliance key holder can derive
+/// the wallet balance at the time the wallet proof was obtained.
+///
+/// [`SecretState::export_compliance_archive()`]: ::SecretState::export_compliance_archive()
+/// [`Disclosure`]: struct.Disclosure.html
+/// [`WalletProof`]: ::api::WalletProof
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ComplianceArchive {
+    key: PublicKey,
+    compliance_key: PublicKey,
+    wallet_proof: WalletProof,
+    openings: EncryptedData,
+    signature: Signature,
+}
+
+/// Package proving that the balance of a wallet has not dropped below a certain value
+/// (the floor) within a range of heights, produced by [`SecretState::prove_balance_floor()`].
+///
+/// The package contains a [`BalanceHistoryProof`], from which commitments to all balances
+/// of the wallet within the range are restored, and a range proof for each commitment `C`
+/// showing that `C - Comm(floor; 0)` opens to a non-negative value. Hence, the package
+/// does not reveal the balances or transfer amounts; it can be used, e.g., to prove
+/// to a counterparty that the wallet has maintained collateral required by an agreement.
+/// The package is signed by the wallet key.
+///
+/// [`SecretState::prove_balance_floor()`]: ::SecretState::prove_balance_floor()
+/// [`BalanceHistoryProof`]: ::api::BalanceHistoryProof
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BalanceFloorProof {
+    key: PublicKey,
+    floor: u64,
+    since: Height,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    until: Option<Height>,
+    history_proof: BalanceHistoryProof,
+    range_proofs: Vec<SimpleRangeProof<'static>>,
+    signature: Signature,
+}
+
+/// Information obtained after checking a `BalanceFloorProof`.
+#[derive(Debug)]
+pub struct CheckedBalanceFloor {
+    /// Block against which the package is verified.
+    pub block: Block,
+    /// Public key of the wallet.
+    pub key: PublicKey,
+    /// Proven lower bound on the wallet balance.
+    pub floor: u64,
+    /// First height of the range.
+    pub since: Height,
+    /// Last height of the range (inclusive).
+    pub until: Height,
+}
+
+/// Event with a disclosed amount, obtained after checking a `Disclosure`
+/// or a `ComplianceArchive`.
+#[derive(Debug)]
+pub struct DisclosedEvent {
+    /// Index of the event in the wallet history.
+    pub index: u64,
+    /// Event in the wallet history. The event is always either a `Transfer` or a `Rollback`
+    /// for disclosures; compliance archives can also contain bridge locks and releases.
+    pub event: FullEvent,
+    /// Disclosed amount of the transfer.
+    pub amount: u64,
+}
+
+/// Information obtained after checking a `Disclosure` or a `ComplianceArchive`.
+#[derive(Debug)]
+pub struct CheckedDisclosure {
+    /// Block against which the disclosure is verified.
+    pub block: Block,
+    /// Public key of the wallet.
+    pub key: PublicKey,
+    /// Disclosed events.
+    pub events: Vec<DisclosedEvent>,
+}
+
+/// Error creating or checking a `Disclosure`.
+#[derive(Debug, Fail)]
+pub enum DisclosureError {
+    /// An event to disclose is not covered by the wallet proof.
+    #[fail(display = "event #{} is not covered by the wallet proof", index)]
+    MissingEvent {
+        /// Index of the event in the wallet history.
+        index: u64,
+    },
+
+    /// An event to disclose does not have an associated amount (i.e., it is
+    /// a `CreateWallet` event).
+    #[fail(display = "event #{} does not transfer tokens", index)]
+    NotTransfer {
+        /// Index of the event in the wallet history.
+        index: u64,
+    },
+
+    /// The disclosed opening does not correspond to the committed amount of a transfer,
+    /// or the opening cannot be decrypted when creating a disclosure.
+    #[fail(display = "invalid opening for event #{}", index)]
+    InvalidOpening {
+        /// Index of the event in the wallet history.
+        index: u64,
+    },
+
+    /// The signature of the package is invalid.
+    #[fail(display = "invalid disclosure signature")]
+    InvalidSignature,
+
+    /// The openings in a compliance archive cannot be decrypted with the provided key,
+    /// or are malformed.
+    #[fail(display = "cannot decrypt compliance archive")]
+    UndecryptableArchive,
+
+    /// A compliance archive does not contain the opening for an amount-bearing event
+    /// in the wallet history.
+    #[fail(display = "compliance archive misses opening for event #{}", index)]
+    IncompleteArchive {
+        /// Index of the event in the wallet history.
+        index: u64,
+    },
+
+    /// The wallet balance has been below the floor after the specified event.
+    #[fail(display = "balance after event #{} is below the floor", index)]
+    BelowFloor {
+        /// Index of the event in the wallet history.
+        index: u64,
+    },
+
+    /// The range proof for the balance after the specified event is invalid or missing,
+    /// or cannot be created.
+    #[fail(display = "invalid range proof for balance after event #{}", index)]
+    InvalidRangeProof {
+        /// Index of the event in the wallet history.
+        index: u64,
+    },
+
+    /// The secret state is not synchronized with the wallet history in the proof.
+    #[fail(display = "secret state does not correspond to wallet proof")]
+    StateMismatch,
+
+    /// The wallet proof is invalid.
+    #[fail(display = "invalid wallet proof: {}", _0)]
+    Proof(#[cause] VerifyError),
+}
+
+impl From<VerifyError> for DisclosureError {
+    fn from(e: VerifyError) -> Self {
+        DisclosureError::Proof(e)
+    }
+}
+
+/// Returns the amount-bearing transfer for the specified event.
+fn event_transfer(event: &FullEvent, index: u64) -> Result<&Transfer, DisclosureError> {
+    match event {
+        FullEvent::Transfer(transfer) | FullEvent::Rollback(transfer) => Ok(transfer),
+        FullEvent::CreateWallet(..)
+        | FullEvent::BridgeLock(..)
+        | FullEvent::BridgeRelease(..)
+        | FullEvent::Acknowledgment(..)
+        | FullEvent::DisputeOpened(..)
+        | FullEvent::DisputeResolved(..)
+        | FullEvent::StreamOpened(..)
+        | FullEvent::StreamPayment(..)
+        | FullEvent::StreamClosed(..)
+        | FullEvent::Lock(..)
+        | FullEvent::Unlock(..)
+        | FullEvent::TransferRequest(..)
+        | FullEvent::Rerandomization(..) => Err(DisclosureError::NotTransfer { index }),
+    }
+}
+
+/// Returns the committed amount for the specified event, taking bridge, dispute, stream,
+/// lock and rerandomization events into account.
+fn event_amount(event: &FullEvent, index: u64) -> Result<Commitment, DisclosureError> {
+    match event {
+        FullEvent::BridgeLock(lock) => Ok(lock.amount()),
+        FullEvent::BridgeRelease(release) => release
+            .lock_transaction()
+            .map(|lock| lock.amount())
+            .ok_or(DisclosureError::NotTransfer { index }),
+        FullEvent::DisputeOpened(dispute) => dispute
+            .transfer_transaction()
+            .map(|transfer| transfer.amount())
+            .ok_or(DisclosureError::NotTransfer { index }),
+        FullEvent::DisputeResolved(resolution) => resolution
+            .transfer_transaction()
+            .map(|transfer| transfer.amount())
+            .ok_or(DisclosureError::NotTransfer { index }),
+        FullEvent::StreamOpened(stream) => Ok(stream.total_amount()),
+        FullEvent::StreamPayment(stream) => Ok(stream.rate()),
+        FullEvent::StreamClosed(close) => close
+            .refunded_amount()
+            .ok_or(DisclosureError::NotTransfer { index }),
+        FullEvent::Lock(lock) => Ok(lock.amount()),
+        FullEvent::Unlock(unlock) => unlock
+            .lock_transaction()
+            .map(|lock| lock.amount())
+            .ok_or(DisclosureError::NotTransfer { index }),
+        FullEvent::Rerandomization(rerandomize) => Ok(rerandomize.blinding()),
+        _ => event_transfer(event, index).map(Transfer::amount),
+    }
+}
+
+impl Disclosure {
+    const DOMAIN_SEPARATOR: &'static [u8] = b"exonum.private_currency.disclosure";
+
+    /// Creates a signed disclosure. The caller is responsible for computing openings.
+    pub(crate) fn new(
+        key: PublicKey,
+        start_history_at: u64,
+        wallet_proof: WalletProof,
+        openings: Vec<(u64, Opening)>,
+        signing_key: &SecretKey,
+    ) -> Self {
+        let openings: Vec<_> = openings
+            .into_iter()
+            .map(|(index, opening)| DisclosedOpening { index, opening })
+            .collect();
+        let message = Self::message_to_sign(&key, start_history_at, &openings);
+        Disclosure {
+            key,
+            start_history_at,
+            wallet_proof,
+            openings,
+            signature: exonum_crypto::sign(&message, signing_key),
+        }
+    }
+
+    /// Returns the event with the specified index from a wallet proof.
+    pub(crate) fn event_in_proof(
+        wallet_proof: &WalletProof,
+        start_history_at: u64,
+        index: u64,
+    ) -> Result<&Transfer, DisclosureError> {
+        let event = index
+            .checked_sub(start_history_at)
+            .and_then(|i| wallet_proof.unchecked_history().get(i as usize))
+            .ok_or(DisclosureError::MissingEvent { index })?;
+        event_transfer(event, index)
+    }
+
+    fn message_to_sign(
+        key: &PublicKey,
+        start_history_at: u64,
+        openings: &[DisclosedOpening],
+    ) -> Vec<u8> {
+        let mut message = Self::DOMAIN_SEPARATOR.to_vec();
+        message.extend_from_slice(key.as_ref());
+        let mut u64_bytes = [0_u8; 8];
+        LittleEndian::write_u64(&mut u64_bytes, start_history_at);
+        message.extend_from_slice(&u64_bytes);
+        for disclosed in openings {
+            LittleEndian::write_u64(&mut u64_bytes, disclosed.index);
+            message.extend_from_slice(&u64_bytes);
+            message.extend_from_slice(&disclosed.opening.to_bytes());
+        }
+        message
+    }
+
+    /// Returns the public key of the wallet, which history is disclosed.
+    pub fn key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    /// Checks the disclosure against the blockchain state.
+    pub fn check(&self, trust_anchor: &TrustAnchor) -> Result<CheckedDisclosure, DisclosureError> {
+        let message = Self::message_to_sign(&self.key, self.start_history_at, &self.openings);
+        if !exonum_crypto::verify(&self.signature, &message, &self.key) {
+            return Err(DisclosureError::InvalidSignature);
+        }
+
+        let query = WalletQuery::new(self.key, self.start_history_at);
+        let checked_proof = self.wallet_proof.check(trust_anchor, &query)?;
+
+        let mut events = Vec::with_capacity(self.openings.len());
+        for disclosed in &self.openings {
+            let index = disclosed.index;
+            let event = index
+                .checked_sub(self.start_history_at)
+                .and_then(|i| checked_proof.history.get(i as usize))
+                .ok_or(DisclosureError::MissingEvent { index })?;
+            if !event_transfer(event, index)?
+                .amount()
+                .verify(&disclosed.opening)
+            {
+                return Err(DisclosureError::InvalidOpening { index });
+            }
+
+            events.push(DisclosedEvent {
+                index,
+                event: event.clone(),
+                amount: disclosed.opening.value,
+            });
+        }
+
+        Ok(CheckedDisclosure {
+            block: checked_proof.block,
+            key: self.key,
+            events,
+        })
+    }
+}
+
+impl ComplianceArchive {
+    const DOMAIN_SEPARATOR: &'static [u8] = b"exonum.private_currency.compliance_archive";
+
+    /// Creates a signed archive. The caller is responsible for computing openings
+    /// for all amount-bearing events in `wallet_proof`, which must start
+    /// at the beginning of the wallet history.
+    pub(crate) fn new(
+        key: PublicKey,
+        compliance_key: PublicKey,
+        wallet_proof: WalletProof,
+        openings: &[(u64, Opening)],
+        encryption_sk: &enc::SecretKey,
+        signing_key: &SecretKey,
+    ) -> Self {
+        let mut payload = Vec::with_capacity(openings.len() * (8 + Opening::BYTE_SIZE));
+        let mut u64_bytes = [0_u8; 8];
+        for (index, opening) in openings {
+            LittleEndian::write_u64(&mut u64_bytes, *index);
+            payload.extend_from_slice(&u64_bytes);
+            payload.extend_from_slice(&opening.to_bytes());
+        }
+        let openings = EncryptedData::seal(
+            &payload,
+            &enc::pk_from_ed25519(compliance_key),
+            encryption_sk,
+        );
+
+        let message = Self::message_to_sign(&key, &compliance_key, &openings);
+        ComplianceArchive {
+            key,
+            compliance_key,
+            wallet_proof,
+            openings,
+            signature: exonum_crypto::sign(&message, signing_key),
+        }
+    }
+
+    fn message_to_sign(
+        key: &PublicKey,
+        compliance_key: &PublicKey,
+        openings: &EncryptedData,
+    ) -> Vec<u8> {
+        let mut message = Self::DOMAIN_SEPARATOR.to_vec();
+        message.extend_from_slice(key.as_ref());
+        message.extend_from_slice(compliance_key.as_ref());
+        message.extend_from_slice(openings.nonce());
+        message.extend_from_slice(openings.encrypted_data());
+        message
+    }
+
+    /// Returns the public key of the wallet, which history is archived.
+    pub fn key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    /// Returns the Ed25519 key of the compliance officer, to which the archive is encrypted.
+    pub fn compliance_key(&self) -> &PublicKey {
+        &self.compliance_key
+    }
+
+    /// Decrypts the archive with the secret counterpart of the [compliance key] and checks it
+    /// against the blockchain state.
+    ///
+    /// [compliance key]: #method.compliance_key
+    pub fn check(
+        &self,
+        trust_anchor: &TrustAnchor,
+        compliance_sk: &SecretKey,
+    ) -> Result<CheckedDisclosure, DisclosureError> {
+        let message = Self::message_to_sign(&self.key, &self.compliance_key, &self.openings);
+        if !exonum_crypto::verify(&self.signature, &message, &self.key) {
+            return Err(DisclosureError::InvalidSignature);
+        }
+
+        let openings = self.decrypt(compliance_sk)?;
+        let query = WalletQuery::new(self.key, 0);
+        let checked_proof = self.wallet_proof.check(trust_anchor, &query)?;
+
+        let mut events = Vec::with_capacity(openings.len());
+        let mut openings = openings.into_iter();
+        for (i, event) in checked_proof.history.into_iter().enumerate() {
+            let index = i as u64;
+            // These events do not change the balance, so the archive has no openings for them.
+            match event {
+                FullEvent::CreateWallet(..)
+                | FullEvent::Acknowledgment(..)
+                | FullEvent::TransferRequest(..) => continue,
+                _ => {}
+            }
+            let amount = event_amount(&event, index)?;
+            let opening = match openings.next() {
+                Some((opening_index, opening)) if opening_index == index => opening,
+                _ => return Err(DisclosureError::IncompleteArchive { index }),
+            };
+            if !amount.verify(&opening) {
+                return Err(DisclosureError::InvalidOpening { index });
+            }
+            events.push(DisclosedEvent {
+                index,
+                event,
+                amount: opening.value,
+            });
+        }
+        if let Some((index, _)) = openings.next() {
+            return Err(DisclosureError::MissingEvent { index });
+        }
+
+        Ok(CheckedDisclosure {
+            block: checked_proof.block,
+            key: self.key,
+            events,
+        })
+    }
+
+    fn decrypt(&self, compliance_sk: &SecretKey) -> Result<Vec<(u64, Opening)>, DisclosureError> {
+        const ENTRY_LEN: usize = 8 + Opening::BYTE_SIZE;
+
+        let (_, encryption_sk) =
+            enc::keypair_from_ed25519(self.compliance_key, compliance_sk.clone());
+        let payload = self
+            .openings
+            .open(&enc::pk_from_ed25519(self.key), &encryption_sk)
+            .ok_or(DisclosureError::UndecryptableArchive)?;
+        if payload.len() % ENTRY_LEN != 0 {
+            return Err(DisclosureError::UndecryptableArchive);
+        }
+        payload
+            .chunks(ENTRY_LEN)
+            .map(|entry| {
+                let (index, opening) = entry.split_at(8);
+                let opening =
+                    Opening::from_slice(opening).ok_or(DisclosureError::UndecryptableArchive)?;
+                Ok((LittleEndian::read_u64(index), opening))
+            })
+            .collect()
+    }
+}
+
+impl BalanceFloorProof {
+    const DOMAIN_SEPARATOR: &'static [u8] = b"exonum.private_currency.balance_floor";
+
+    /// Creates a signed package. The caller is responsible for creating range proofs
+    /// for all balances in `history_proof`.
+    pub(crate) fn new(
+        key: PublicKey,
+        floor: u64,
+        query: &BalanceHistoryQuery,
+        history_proof: BalanceHistoryProof,
+        range_proofs: Vec<SimpleRangeProof<'static>>,
+        signing_key: &SecretKey,
+    ) -> Self {
+        let message = Self::message_to_sign(&key, floor, query.since, query.until, &range_proofs);
+        BalanceFloorProof {
+            key,
+            floor,
+            since: query.since,
+            until: query.until,
+            history_proof,
+            range_proofs,
+            signature: exonum_crypto::sign(&message, signing_key),
+        }
+    }
+
+    fn message_to_sign(
+        key: &PublicKey,
+        floor: u64,
+        since: Height,
+        until: Option<Height>,
+        range_proofs: &[SimpleRangeProof],
+    ) -> Vec<u8> {
+        let mut message = Self::DOMAIN_SEPARATOR.to_vec();
+        message.extend_from_slice(key.as_ref());
+        let mut u64_bytes = [0_u8; 8];
+        for &value in &[
+            floor,
+            since.0,
+            until.map_or(u64::max_value(), |height| height.0),
+        ] {
+            LittleEndian::write_u64(&mut u64_bytes, value);
+            message.extend_from_slice(&u64_bytes);
+        }
+        for proof in range_proofs {
+            message.extend_from_slice(proof.as_bytes());
+        }
+        message
+    }
+
+    /// Returns the public key of the wallet, which balance is bounded.
+    pub fn key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    /// Returns the proven lower bound on the wallet balance.
+    pub fn floor(&self) -> u64 {
+        self.floor
+    }
+
+    /// Checks the package against the blockchain state.
+    pub fn check(
+        &self,
+        trust_anchor: &TrustAnchor,
+    ) -> Result<CheckedBalanceFloor, DisclosureError> {
+        let message = Self::message_to_sign(
+            &self.key,
+            self.floor,
+            self.since,
+            self.until,
+            &self.range_proofs,
+        );
+        if !exonum_crypto::verify(&self.signature, &message, &self.key) {
+            return Err(DisclosureError::InvalidSignature);
+        }
+
+        let query = BalanceHistoryQuery::new(self.key, self.since, self.until);
+        let checked = self.history_proof.check(trust_anchor, &query)?;
+        let floor = Commitment::with_no_blinding(self.floor);
+        for (i, balance) in checked.balances.iter().enumerate() {
+            let index = checked.start + i as u64;
+            let is_valid = self
+                .range_proofs
+                .get(i)
+                .map_or(false, |proof| proof.verify(&(balance - &floor)));
+            if !is_valid {
+                return Err(DisclosureError::InvalidRangeProof { index });
+            }
+        }
+        if self.range_proofs.len() > checked.balances.len() {
+            let index = checked.start + checked.balances.len() as u64;
+            return Err(DisclosureError::InvalidRangeProof { index });
+        }
+
+        let until = self.until.unwrap_or_else(|| checked.block.height());
+        Ok(CheckedBalanceFloor {
+            block: checked.block,
+            key: self.key,
+            floor: self.floor,
+            since: self.since,
+            until,
+        })
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Disputes (chargebacks) for accepted transfers.
+//!
+//! If the service configuration specifies a [`DisputeConfig`], the sender of an accepted
+//! transfer may contest it within the dispute [`window`] after the acceptance:
+//!
+//! 1. The sender commits an [`OpenDispute`] transaction (created with
+//!    [`SecretState::open_dispute()`]), which embeds the disputed transfer. The transferred
+//!    amount is subtracted from the receiver’s balance and is moved into the disputed escrow
+//!    (see [`Schema::escrowed_amount()`]).
+//! 2. The arbiter specified in the configuration commits a [`ResolveDispute`] transaction
+//!    (created with [`Arbiter::resolve()`]), which credits the escrowed amount either back
+//!    to the sender (a chargeback), or to the receiver (the dispute is rejected).
+//!
+//! Both steps are recorded in the history of the wallets whose balance changes; the amount
+//! is hidden in the same way as for the disputed transfer, and is decrypted by the parties
+//! of the transfer with the transfer opening. The arbiter does not learn the amount.
+//!
+//! # Limitations
+//!
+//! - The escrowed amount is subtracted from the receiver’s balance regardless of whether
+//!   the balance is sufficient, since the receiver does not participate in opening
+//!   the dispute. If the receiver has already spent the funds, the balance becomes negative,
+//!   and the receiver cannot make transfers until the balance is replenished. Client-side
+//!   states track the uncovered part of the amount as a [deficit], which is settled
+//!   from incoming funds; the wallet history keeps advancing in the meantime.
+//! - Opening a dispute is treated as an outgoing payment of the receiver, so transfers
+//!   by the receiver referencing the history before the dispute are rejected.
+//! - Only transfers accepted after the service has started recording acceptance heights
+//!   can be disputed.
+//!
+//! [`DisputeConfig`]: struct.DisputeConfig.html
+//! [`window`]: struct.DisputeConfig.html#structfield.window
+//! [`OpenDispute`]: ::transactions::OpenDispute
+//! [`ResolveDispute`]: ::transactions::ResolveDispute
+//! [`SecretState::open_dispute()`]: ::SecretState::open_dispute()
+//! [`Schema::escrowed_amount()`]: ::storage::Schema::escrowed_amount()
+//! [`Arbiter::resolve()`]: struct.Arbiter.html#method.resolve
+//! [deficit]: ::SecretState::deficit()
+
+use exonum::{
+    crypto::{PublicKey, SecretKey},
+    messages::{Message, MessageBuffer},
+};
+
+use transactions::{OpenDispute, ResolveDispute};
+
+/// Dispute configuration of the service, stored in the
+/// [service configuration](::Config::dispute).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DisputeConfig {
+    /// Ed25519 key of the arbiter authorized to resolve disputes.
+    pub arbiter: PublicKey,
+    /// Number of blocks after the acceptance of a transfer during which the transfer
+    /// can be disputed by its sender.
+    pub window: u32,
+}
+
+impl DisputeConfig {
+    /// Checks if the configuration is valid, that is, the dispute window is positive.
+    pub fn is_valid(&self) -> bool {
+        self.window > 0
+    }
+}
+
+/// Arbiter resolving disputes.
+#[derive(Debug, Clone)]
+pub struct Arbiter {
+    public_key: PublicKey,
+    secret_key: SecretKey,
+}
+
+impl Arbiter {
+    /// Creates an arbiter with the specified keypair. The public key must coincide
+    /// with the [arbiter key](struct.DisputeConfig.html#structfield.arbiter)
+    /// in the service configuration.
+    pub fn new(public_key: PublicKey, secret_key: SecretKey) -> Self {
+        Arbiter {
+            public_key,
+            secret_key,
+        }
+    }
+
+    /// Returns the public key of the arbiter.
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Produces a resolution of the dispute. If `refund` is set, the escrowed amount
+    /// is credited to the sender of the disputed transfer; otherwise, it is returned
+    /// to the receiver.
+    pub fn resolve(&self, dispute: &OpenDispute, refund: bool) -> ResolveDispute {
+        let buffer: &MessageBuffer = dispute.raw();
+        ResolveDispute::new(&self.public_key, buffer.as_ref(), refund, &self.secret_key)
+    }
+}
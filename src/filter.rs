@@ -0,0 +1,192 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bloom filters of unaccepted transfers.
+
+use exonum::{crypto::Hash, encoding::serialize, storage::StorageValue};
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
+
+use std::{borrow::Cow, fmt};
+
+/// Size of a filter in bytes.
+const FILTER_LEN: usize = 128;
+/// Number of bits set in a filter for each transfer.
+const BITS_PER_TRANSFER: usize = 4;
+
+/// Bloom filter of the hashes of unaccepted incoming transfers of a wallet.
+///
+/// The filter is maintained by the service for each wallet and can be retrieved
+/// with the [`unaccepted_filter`] endpoint. Unlike the [`WalletProof`], the filter
+/// has a fixed size and is not authenticated; it is intended as a hint for clients
+/// polling for incoming transfers. If all transfers in the filter are already known
+/// to the client (as checked with [`is_covered_by()`]), there is nothing new for the client
+/// with high probability, and it may skip downloading the wallet proof. Otherwise,
+/// the client should request the proof as usual.
+///
+/// With 1,024 bits and 4 bits per transfer, the false positive rate of
+/// the filter is about 10<sup>-4</sup> for 20 unaccepted transfers.
+///
+/// [`unaccepted_filter`]: ::api::Api::unaccepted_filter()
+/// [`WalletProof`]: ::api::WalletProof
+/// [`is_covered_by()`]: #method.is_covered_by
+#[derive(Clone, PartialEq, Eq)]
+pub struct TransferFilter {
+    bits: Vec<u8>,
+}
+
+impl TransferFilter {
+    /// Creates an empty filter.
+    pub fn new() -> Self {
+        TransferFilter {
+            bits: vec![0; FILTER_LEN],
+        }
+    }
+
+    /// Creates a filter containing the specified transfer hashes.
+    pub fn from_hashes<'a, I>(hashes: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Hash>,
+    {
+        let mut filter = Self::new();
+        for hash in hashes {
+            filter.insert(hash);
+        }
+        filter
+    }
+
+    /// Indices of the bits corresponding to a transfer. Transfer hashes are uniformly
+    /// distributed, so the indices are taken directly from the hash bytes.
+    fn bit_indices<'a>(hash: &'a Hash) -> impl Iterator<Item = usize> + 'a {
+        let bytes = hash.as_ref();
+        (0..BITS_PER_TRANSFER).map(move |i| {
+            let index = usize::from(bytes[2 * i]) | (usize::from(bytes[2 * i + 1]) << 8);
+            index % (FILTER_LEN * 8)
+        })
+    }
+
+    /// Adds a transfer hash to the filter.
+    pub fn insert(&mut self, hash: &Hash) {
+        for index in Self::bit_indices(hash) {
+            self.bits[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    /// Checks whether the filter may contain the transfer hash. Returns `false`
+    /// only if the hash is definitely absent from the filter.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        Self::bit_indices(hash).all(|index| self.bits[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Checks whether the filter is empty, i.e., the wallet has no unaccepted transfers.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&byte| byte == 0)
+    }
+
+    /// Checks whether all transfers in this filter are among the `known` transfers,
+    /// up to false positives. If the method returns `false`, the filter definitely contains
+    /// a transfer not in `known`.
+    pub fn is_covered_by<'a, I>(&self, known: I) -> bool
+    where
+        I: IntoIterator<Item = &'a Hash>,
+    {
+        let known = Self::from_hashes(known);
+        self.bits
+            .iter()
+            .zip(&known.bits)
+            .all(|(&byte, &known_byte)| byte & !known_byte == 0)
+    }
+}
+
+impl Default for TransferFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TransferFilter {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_tuple("TransferFilter")
+            .field(&serialize::encode_hex(&self.bits))
+            .finish()
+    }
+}
+
+impl StorageValue for TransferFilter {
+    fn into_bytes(self) -> Vec<u8> {
+        self.bits
+    }
+
+    fn from_bytes(value: Cow<[u8]>) -> Self {
+        assert_eq!(
+            value.len(),
+            FILTER_LEN,
+            "Cannot restore `TransferFilter` from trusted source"
+        );
+        TransferFilter {
+            bits: value.into_owned(),
+        }
+    }
+}
+
+/// Filters are serialized as hex strings.
+impl Serialize for TransferFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&serialize::encode_hex(&self.bits))
+    }
+}
+
+impl<'de> Deserialize<'de> for TransferFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex_string = String::deserialize(deserializer)?;
+        let bits = serialize::decode_hex(hex_string).map_err(D::Error::custom)?;
+        if bits.len() != FILTER_LEN {
+            return Err(D::Error::custom("invalid `TransferFilter` length"));
+        }
+        Ok(TransferFilter { bits })
+    }
+}
+
+#[test]
+fn filter_membership() {
+    use exonum::crypto::hash;
+
+    let hashes: Vec<_> = (0_u8..20).map(|i| hash(&[i])).collect();
+    let filter = TransferFilter::from_hashes(&hashes[..10]);
+    assert!(!filter.is_empty());
+    assert!(hashes[..10].iter().all(|hash| filter.contains(hash)));
+    assert!(filter.is_covered_by(&hashes[..10]));
+    assert!(filter.is_covered_by(&hashes));
+    assert!(!filter.is_covered_by(&hashes[..9]));
+    assert!(!filter.is_covered_by(&hashes[10..]));
+
+    assert!(TransferFilter::new().is_empty());
+    assert!(TransferFilter::new().is_covered_by(&[]));
+}
+
+#[test]
+fn filter_serde_roundtrip() {
+    use exonum::crypto::hash;
+    use serde_json;
+
+    let filter = TransferFilter::from_hashes(&[hash(b"transfer")]);
+    let json = serde_json::to_string(&filter).unwrap();
+    assert_eq!(json.len(), 2 * FILTER_LEN + 2);
+    let restored: TransferFilter = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, filter);
+    assert!(serde_json::from_str::<TransferFilter>("\"00ff\"").is_err());
+
+    let restored = TransferFilter::from_bytes(filter.clone().into_bytes().into());
+    assert_eq!(restored, filter);
+}
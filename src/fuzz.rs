@@ -0,0 +1,139 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fuzzing harness for deserialization of untrusted data.
+//!
+//! Each function in this module accepts arbitrary bytes, parses them with one
+//! of the deserializers exposed to attacker-controlled input (transaction messages
+//! submitted to the node, JSON submitted to the HTTP API, wallet proofs received
+//! by clients), and panics if a parsed value violates a property that should hold
+//! for any input. The functions are called by `cargo-fuzz` targets in the `fuzz` directory;
+//! run them with
+//!
+//! ```shell
+//! cargo +nightly fuzz run <TARGET>
+//! ```
+//!
+//! This module is available with the `fuzzing` crate feature.
+
+use exonum::crypto::{gen_keypair_from_seed, Seed, SEED_LENGTH};
+use serde_json;
+
+use std::str;
+
+use api::WalletProof;
+use crypto::{enc, Commitment, Opening, SimpleRangeProof};
+use secrets::EncryptedData;
+use transactions::CryptoTransactions;
+
+lazy_static! {
+    /// Encryption keypair used to open fuzzed encrypted data.
+    static ref ENCRYPTION_KEYS: (enc::PublicKey, enc::SecretKey) = {
+        let (pk, sk) = gen_keypair_from_seed(&Seed::new([1; SEED_LENGTH]));
+        enc::keypair_from_ed25519(pk, sk)
+    };
+}
+
+/// Parses a commitment; successfully parsed commitments must serialize back
+/// to the same bytes.
+pub fn commitment(data: &[u8]) {
+    if let Some(commitment) = Commitment::from_slice(data) {
+        assert_eq!(commitment.to_bytes(), data);
+    }
+}
+
+/// Parses an opening; successfully parsed openings must serialize back to the same bytes
+/// and open the commitment computed from them.
+pub fn opening(data: &[u8]) {
+    if let Some(opening) = Opening::from_slice(data) {
+        assert_eq!(opening.to_bytes(), data);
+        assert!(Commitment::from_opening(&opening).verify(&opening));
+    }
+}
+
+/// Parses a range proof and verifies it against a commitment. Random proofs
+/// must not verify.
+pub fn range_proof(data: &[u8]) {
+    if let Some(proof) = SimpleRangeProof::from_slice(data) {
+        assert_eq!(proof.as_bytes(), data);
+        assert!(!proof.verify(&Commitment::with_no_blinding(0)));
+    }
+}
+
+/// Parses encrypted data embedded into a binary transaction message, or constructed
+/// from the input directly, and attempts to decrypt it.
+///
+/// The first byte of the input selects the mode. If it is even, the rest of the input
+/// is parsed as a transaction message. Otherwise, the second byte specifies the length
+/// of the nonce, and the rest of the input is used as the ciphertext.
+pub fn encrypted_data(data: &[u8]) {
+    let (mode, data) = match data.split_first() {
+        Some((&mode, data)) => (mode, data),
+        None => return,
+    };
+
+    let encrypted_data = if mode % 2 == 0 {
+        match CryptoTransactions::from_bytes(data.to_vec()) {
+            Ok(CryptoTransactions::Transfer(transfer)) => transfer.encrypted_data(),
+            Ok(CryptoTransactions::LockForBridge(lock)) => lock.encrypted_data(),
+            Ok(CryptoTransactions::AcceptWithNote(accept)) => accept.note(),
+            Ok(CryptoTransactions::StreamTransfer(stream)) => stream.encrypted_data(),
+            Ok(CryptoTransactions::Lock(lock)) => lock.encrypted_data(),
+            Ok(CryptoTransactions::SetProfile(tx)) => tx.profile(),
+            Ok(CryptoTransactions::RequestTransfer(request)) => request.encrypted_data(),
+            Ok(CryptoTransactions::Rerandomize(tx)) => tx.encrypted_data(),
+            Ok(CryptoTransactions::BackUpTransfer(tx)) => tx.encrypted_data(),
+            _ => return,
+        }
+    } else {
+        let (nonce_len, data) = match data.split_first() {
+            Some((&len, data)) if (len as usize) <= data.len() => (len as usize, data),
+            _ => return,
+        };
+        let (nonce, ciphertext) = data.split_at(nonce_len);
+        EncryptedData::new(nonce, ciphertext)
+    };
+
+    let (ref pk, ref sk) = *ENCRYPTION_KEYS;
+    // Data not produced with the fuzzing keypair must not decrypt.
+    assert!(encrypted_data.open(pk, sk).is_none());
+}
+
+/// Parses a binary transaction message; successfully parsed transactions must serialize
+/// back to the same bytes and survive a JSON roundtrip.
+pub fn transaction(data: &[u8]) {
+    if let Ok(tx) = CryptoTransactions::from_bytes(data.to_vec()) {
+        let json = tx.to_json();
+        let tx_copy = CryptoTransactions::from_json(&json).expect("JSON roundtrip");
+        assert_eq!(tx_copy.hash(), tx.hash());
+    }
+}
+
+/// Parses the input as JSON with the `ExonumJson` and `serde` deserializers used
+/// by the HTTP API and clients.
+pub fn json(data: &[u8]) {
+    let json = match str::from_utf8(data) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+
+    if let Ok(tx) = CryptoTransactions::from_json(json) {
+        let tx_copy = CryptoTransactions::from_json(&tx.to_json()).expect("JSON roundtrip");
+        assert_eq!(tx_copy.hash(), tx.hash());
+    }
+    if let Ok(opening) = serde_json::from_str::<Opening>(json) {
+        assert!(Commitment::from_opening(&opening).verify(&opening));
+    }
+    let _ = serde_json::from_str::<WalletProof>(json);
+}
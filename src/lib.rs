@@ -13,7 +13,10 @@
 // limitations under the License.
 
 #![feature(external_doc)]
+#![cfg_attr(feature = "python", feature(specialization))]
 #![deny(missing_docs, missing_debug_implementations)]
+// The JSON schema of the wire format is a single large `json!` literal.
+#![recursion_limit = "256"]
 #![doc(html_favicon_url = "https://exonum.com/favicon.ico")]
 
 //! Privacy-focused Exonum service. The service hides the amounts being
@@ -23,59 +26,186 @@
 
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate bitflags;
 extern crate byteorder;
 #[macro_use]
 extern crate exonum;
+extern crate base64;
+extern crate bip39;
 extern crate bulletproofs;
 extern crate curve25519_dalek as curve25519;
 extern crate exonum_sodiumoxide as sodiumoxide;
+#[cfg(any(feature = "replay", feature = "test_utils"))]
+extern crate exonum_testkit;
+#[cfg(feature = "time-oracle")]
+extern crate exonum_time;
 extern crate failure;
+#[cfg(feature = "async-client")]
+extern crate futures;
+#[macro_use]
+extern crate log;
 extern crate merlin;
+#[cfg(feature = "proptest")]
+#[macro_use]
+extern crate proptest;
+#[cfg(feature = "python")]
+#[macro_use]
+extern crate pyo3;
 extern crate rand;
+#[cfg(any(feature = "client", feature = "webhooks"))]
+extern crate reqwest;
+#[cfg(feature = "async-client")]
+extern crate tokio_timer;
 #[macro_use]
 extern crate failure_derive;
 extern crate serde;
+#[cfg(feature = "cbor")]
+extern crate serde_cbor;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+#[cfg(test)]
+extern crate tempdir;
 
 use exonum::{
     api::ServiceApiBuilder,
     blockchain::{self as bc, ServiceContext, Transaction},
     crypto::Hash,
     encoding::Error as EncodingError,
+    helpers::Height,
     messages::RawMessage,
     storage::{Fork, Snapshot},
 };
 
+use serde_json::Value;
+
 use std::ops::Range;
 
 pub mod api;
+pub mod bridge;
+mod builder;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod crypto;
 mod debug;
+mod disclosure;
+pub mod dispute;
+mod filter;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+mod metrics;
+pub mod migrations;
+mod policy;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod prover;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "replay")]
+pub mod replay;
 mod secrets;
+#[cfg(feature = "test_utils")]
+pub mod simulation;
 pub mod storage;
+mod store;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+#[cfg(feature = "test_utils")]
+pub mod test_utils;
+#[cfg(feature = "time-oracle")]
+mod time;
 pub mod transactions;
 mod utils;
+mod verification;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+pub mod wire;
 
 pub use api::Api;
+pub use bridge::BridgeConfig;
+pub use builder::ServiceBuilder;
 use debug::DebuggerProbe;
-pub use debug::{DebugEvent, Debugger, DebuggerOptions};
-pub use secrets::{EncryptedData, SecretState, VerifiedTransfer};
+pub use debug::{
+    DebugEvent, Debugger, DebuggerOptions, InvariantViolation, Invariants, PendingTransferDump,
+    RejectedProof, StateDump, WalletDump,
+};
+pub use disclosure::{
+    BalanceFloorProof, CheckedBalanceFloor, CheckedDisclosure, ComplianceArchive, DisclosedEvent,
+    Disclosure, DisclosureError,
+};
+pub use dispute::DisputeConfig;
+pub use filter::TransferFilter;
+pub use metrics::{
+    EndpointCount, Histogram, Metrics, MetricsSnapshot, TransactionCount, TransactionKind,
+    TransactionOutcome,
+};
+pub use policy::{AcceptDecision, AcceptPolicy, ThresholdPolicy};
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusExporter;
+pub use prover::{Prover, TransferHandle};
+pub use secrets::{
+    EncryptedData, EventError, ImportError, LedgerEntry, MnemonicError, SecretState,
+    SharedSecretState, Speculation, SyncError, TransferError, VerifiedTransfer, ViewKey,
+    WatchWallet,
+};
 pub use storage::{Schema, Wallet};
+pub use store::{FileStore, StateStore, StoreError, StoredWallet, UnconfirmedTransaction};
 pub use transactions::CryptoTransactions as Transactions;
+use verification::Verifier;
 
 /// Human-readable service name.
 pub const SERVICE_NAME: &str = "private_currency";
 /// Service identifier.
+///
+/// # Limitations
+///
+/// The identifier cannot be changed at runtime. With Exonum 0.9, the service identifier
+/// is embedded into [transactions](::transactions) by the `transactions!` macro; it is
+/// a part of the signed message header, so a transaction cannot be routed to a service
+/// with another identifier without re-signing it. The identifier is also used
+/// to verify proofs for the wallets table on the client side. Names of the storage tables
+/// of the service are fixed as well, so two instances of the service cannot be deployed
+/// on the same blockchain. If the identifier collides with another service, it needs
+/// to be changed here, which affects both nodes and clients.
 pub const SERVICE_ID: u16 = 2_000;
-/// Service configuration.
+/// Default service configuration.
+///
+/// The actual configuration of the service is stored in the blockchain and can be retrieved
+/// with [`Schema::config()`]. Clients should use the actual configuration if the network
+/// may use non-default settings.
+///
+/// [`Schema::config()`]: storage::Schema::config()
 pub const CONFIG: Config = Config {
     initial_balance: 1_000_000,
     rollback_delay_bounds: 5..1_000,
     min_transfer_amount: 1,
+    max_transfer_amount: None,
+    rollback_delay_unit: DelayUnit::Blocks,
+    wire_version: 1,
+    bridge: None,
+    dispute: None,
+    rate_limit: None,
 };
 
 /// Service configuration.
+///
+/// The configuration is set for the network in the genesis block with
+/// [`Service::with_config()`], and is stored among service configurations
+/// in the blockchain configuration. The default configuration is [`CONFIG`].
+///
+/// The configuration can be changed by validators via the configuration service,
+/// by proposing a blockchain configuration with the new service configuration
+/// under the [`SERVICE_NAME`] key. Changes do not affect transfers committed
+/// before the change; e.g., their rollback delays remain the same. Invalid configurations
+/// (see [`is_valid()`](#method.is_valid)) are ignored by the service.
+///
+/// [`Service::with_config()`]: struct.Service.html#method.with_config
+/// [`CONFIG`]: constant.CONFIG.html
+/// [`SERVICE_NAME`]: constant.SERVICE_NAME.html
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Config {
     /// Initial amount of tokens for a new account.
@@ -84,14 +214,159 @@ pub struct Config {
     pub rollback_delay_bounds: Range<u32>,
     /// Minimum acceptable transfer amount.
     pub min_transfer_amount: u64,
+    /// Maximum acceptable amount of a single `Transfer`, or `None` if transfer amounts
+    /// are not capped. If set, transfers must include an additional range proof that
+    /// the amount does not exceed the maximum, so the amounts remain confidential.
+    ///
+    /// The maximum amount applies to `Transfer`s only; payment streams, locks of funds and
+    /// bridge locks are not capped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_transfer_amount: Option<u64>,
+    /// Unit in which `Transfer::rollback_delay()` and `rollback_delay_bounds`
+    /// are expressed.
+    #[serde(default)]
+    pub rollback_delay_unit: DelayUnit,
+    /// Wire version of transactions activated on the network. Transactions introduced
+    /// in later versions are rejected. See [`WIRE_VERSION`] for details.
+    ///
+    /// [`WIRE_VERSION`]: transactions/constant.WIRE_VERSION.html
+    #[serde(default = "initial_wire_version")]
+    pub wire_version: u16,
+    /// Configuration of the [bridge](bridge/index.html) to other deployments of the service,
+    /// or `None` if bridge transactions are disabled. The bridge requires wire version 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bridge: Option<BridgeConfig>,
+    /// Configuration of [disputes](dispute/index.html) for accepted transfers, or `None`
+    /// if disputes are disabled. Disputes require wire version 3.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispute: Option<DisputeConfig>,
+    /// Limit on outgoing transfers of a single wallet, or `None` if transfers
+    /// are not rate-limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+fn initial_wire_version() -> u16 {
+    1
+}
+
+/// Unit of rollback delays in transfers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DelayUnit {
+    /// Rollback delays are measured in blocks.
+    Blocks,
+
+    /// Rollback delays are measured in seconds of wall-clock time, as reported
+    /// by the [exonum-time] oracle. A delay is converted into blocks when the transfer
+    /// is executed, based on the average block interval observed by the service;
+    /// thus, the actual delay is approximate.
+    ///
+    /// This unit requires the `time-oracle` crate feature, which must be enabled
+    /// on all validators, and the time oracle service running on the blockchain.
+    /// Without the feature, configurations with this unit are invalid. Note that
+    /// client-side helpers (e.g., acceptance deadlines in the HTTP client) assume
+    /// that delays are measured in blocks.
+    ///
+    /// [exonum-time]: https://crates.io/crates/exonum-time
+    Seconds,
+}
+
+impl Default for DelayUnit {
+    fn default() -> Self {
+        DelayUnit::Blocks
+    }
+}
+
+/// Limit on outgoing transfers of a wallet, stored in the
+/// [service configuration](::Config::rate_limit).
+///
+/// The blockchain is split into consecutive windows of `window` blocks, starting
+/// from the genesis block. A wallet may send at most `max_transfers` transfers within
+/// a single window; excess transfers are rejected with
+/// [`Error::RateLimited`](::transactions::Error::RateLimited). The limit is meant
+/// as a spam control on public networks, since transfers are expensive to verify.
+///
+/// [Transfer requests](::transactions::RequestTransfer) are counted against the same limit
+/// as transfers of the requester, since each request is recorded in the history
+/// of the payer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Maximum number of outgoing transfers of a wallet within a window.
+    pub max_transfers: u32,
+    /// Length of a window in blocks.
+    pub window: u32,
+}
+
+impl RateLimitConfig {
+    /// Checks if the configuration is valid, that is, both the limit and the window
+    /// are positive.
+    pub fn is_valid(&self) -> bool {
+        self.max_transfers > 0 && self.window > 0
+    }
+
+    /// Returns the first height of the window containing the specified height.
+    pub fn window_start(&self, height: Height) -> Height {
+        let window = u64::from(self.window);
+        Height(height.0 / window * window)
+    }
+}
+
+impl Config {
+    /// Checks if the configuration is valid, that is, the range of acceptable rollback delays
+    /// is non-empty and does not include zero, the maximum transfer amount (if any)
+    /// is not less than the minimum one, the rollback delay unit is supported,
+    /// the bridge and dispute configurations (if any) are valid and activated
+    /// by the wire version, and the rate limit (if any) is valid.
+    pub fn is_valid(&self) -> bool {
+        let bounds = &self.rollback_delay_bounds;
+        let unit_supported =
+            self.rollback_delay_unit == DelayUnit::Blocks || cfg!(feature = "time-oracle");
+        let bridge_valid = self
+            .bridge
+            .as_ref()
+            .map_or(true, |bridge| bridge.is_valid() && self.wire_version >= 2);
+        let dispute_valid = self
+            .dispute
+            .as_ref()
+            .map_or(true, |dispute| dispute.is_valid() && self.wire_version >= 3);
+        let rate_limit_valid = self.rate_limit.map_or(true, |limit| limit.is_valid());
+        let max_amount_valid = self
+            .max_transfer_amount
+            .map_or(true, |max_amount| max_amount >= self.min_transfer_amount);
+        bounds.start > 0
+            && bounds.start < bounds.end
+            && max_amount_valid
+            && unit_supported
+            && self.wire_version > 0
+            && bridge_valid
+            && dispute_valid
+            && rate_limit_valid
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        CONFIG
+    }
 }
 
 /// Privacy-preserving cryptocurrency service.
 ///
-/// See crate documentation for more details.
+/// See crate documentation for more details. Optional features of the service
+/// can be toggled with a [`ServiceBuilder`].
+///
+/// [`ServiceBuilder`]: struct.ServiceBuilder.html
 #[derive(Debug, Default)]
 pub struct Service {
     debugger_probe: Option<DebuggerProbe>,
+    signed_queries: bool,
+    invariants: Invariants,
+    metrics: Option<Metrics>,
+    #[cfg(feature = "webhooks")]
+    webhooks: Option<webhooks::Webhooks>,
+    verifier: Verifier,
+    config: Config,
 }
 
 impl Service {
@@ -100,11 +375,48 @@ impl Service {
     /// The service created in this way has high associated performance penalty. Use for
     /// debugging only; otherwise, use `Service::default()`.
     pub fn debug(options: DebuggerOptions) -> (Self, Debugger) {
-        let (probe, debugger) = DebuggerProbe::create_channel(16, options);
-        let service = Service {
-            debugger_probe: Some(probe),
-        };
-        (service, debugger)
+        let (service, debugger) = ServiceBuilder::new().debugger(options).build();
+        (service, debugger.expect("debugger"))
+    }
+
+    /// Requires queries to the `v1/wallet` endpoint to be signed by the wallet owner.
+    ///
+    /// With this option, the node refuses to disclose the history and unaccepted transfers
+    /// of a wallet to anyone except its owner. See [`WalletQuery`] for details on query
+    /// authentication.
+    ///
+    /// [`WalletQuery`]: ::api::WalletQuery
+    pub fn require_signed_queries(mut self) -> Self {
+        self.signed_queries = true;
+        self
+    }
+
+    /// Sets the configuration of the service.
+    ///
+    /// The configuration is only used when creating the genesis block; afterwards,
+    /// the configuration is read from the blockchain. Thus, all nodes in the network need
+    /// to use the same configuration in order to agree on the genesis block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is [invalid](struct.Config.html#method.is_valid).
+    pub fn with_config(mut self, config: Config) -> Self {
+        assert!(config.is_valid(), "invalid service configuration");
+        self.config = config;
+        self
+    }
+
+    /// Returns the metrics collected by the service, or `None` if the service is built
+    /// without metrics.
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// Returns the webhook registry of the service, or `None` if the service is built
+    /// without webhooks.
+    #[cfg(feature = "webhooks")]
+    pub fn webhooks(&self) -> Option<&webhooks::Webhooks> {
+        self.webhooks.as_ref()
     }
 }
 
@@ -121,28 +433,172 @@ impl bc::Service for Service {
         Schema::new(snapshot).state_hash()
     }
 
+    fn initialize(&self, fork: &mut Fork) -> Value {
+        Schema::new(&mut *fork).set_config(self.config.clone());
+        migrations::set_latest_version(fork);
+        serde_json::to_value(&self.config).expect("cannot serialize service config")
+    }
+
     fn tx_from_raw(&self, raw: RawMessage) -> Result<Box<Transaction>, EncodingError> {
         use bc::TransactionSet;
-        Transactions::tx_from_raw(raw).map(|tx| tx.into())
+        use exonum::messages::Message;
+        use transactions::{message_wire_version, UnknownTransaction};
+        use verification::CachedTransfer;
+
+        if message_wire_version(raw.message_type()).is_none() {
+            return UnknownTransaction::from_raw(raw).map(|tx| Box::new(tx) as Box<Transaction>);
+        }
+        Ok(match Transactions::tx_from_raw(raw)? {
+            // Transfers are linked to the verification cache of this service instance.
+            Transactions::Transfer(transfer) => {
+                Box::new(CachedTransfer::new(transfer, self.verifier.clone())) as Box<Transaction>
+            }
+            tx => tx.into(),
+        })
     }
 
     fn before_commit(&self, fork: &mut Fork) {
         if let Some(ref probe) = self.debugger_probe {
             probe.on_before_commit(fork);
         }
-        Schema::new(fork).do_rollback();
+        #[cfg(feature = "time-oracle")]
+        time::record_anchor(fork);
+        let mut schema = Schema::new(fork);
+        schema.do_rollback();
+        schema.do_stream_payments();
+        schema.update_config();
     }
 
     fn after_commit(&self, context: &ServiceContext) {
+        let config = Schema::new(context.snapshot()).config();
+        self.verifier.set_amount_bounds(&config);
+        let violations = if self.invariants.is_empty() {
+            vec![]
+        } else {
+            Schema::new(context.snapshot()).check_invariants(self.invariants)
+        };
+        for violation in &violations {
+            error!(
+                "service invariant violated at height {}: {}",
+                context.height(),
+                violation
+            );
+        }
+        if let Some(ref metrics) = self.metrics {
+            metrics.on_commit(context.snapshot(), context.height());
+            metrics.on_invariant_violations(violations.len() as u64);
+        }
+        #[cfg(feature = "webhooks")]
+        {
+            if let Some(ref webhooks) = self.webhooks {
+                webhooks.on_commit(
+                    context.snapshot(),
+                    context.height(),
+                    context.public_key(),
+                    context.secret_key(),
+                );
+            }
+        }
         if let Some(ref probe) = self.debugger_probe {
-            probe.on_after_commit(context);
+            probe.on_after_commit(context, violations);
         }
     }
 
     fn wire_api(&self, builder: &mut ServiceApiBuilder) {
-        builder
-            .public_scope()
-            .endpoint("v1/wallet", Api::wallet)
-            .endpoint_mut("v1/transaction", Api::transaction);
+        use metrics::instrument;
+
+        let metrics = &self.metrics;
+        {
+            let scope = builder.public_scope();
+            if self.signed_queries {
+                let handler = instrument(metrics, "v1/wallet", Api::authenticated_wallet);
+                scope.endpoint("v1/wallet", handler);
+            } else {
+                scope.endpoint("v1/wallet", instrument(metrics, "v1/wallet", Api::wallet));
+            }
+            if self.signed_queries {
+                let handler = instrument(metrics, "v1/inactivity", Api::authenticated_inactivity);
+                scope.endpoint("v1/inactivity", handler);
+            } else {
+                let handler = instrument(metrics, "v1/inactivity", Api::inactivity);
+                scope.endpoint("v1/inactivity", handler);
+            }
+            if self.signed_queries {
+                let handler = instrument(metrics, "v1/receipt", Api::authenticated_receipt);
+                scope.endpoint("v1/receipt", handler);
+            } else {
+                scope.endpoint(
+                    "v1/receipt",
+                    instrument(metrics, "v1/receipt", Api::receipt),
+                );
+            }
+            if self.signed_queries {
+                let handler = instrument(metrics, "v1/checkpoints", Api::authenticated_checkpoints);
+                scope.endpoint("v1/checkpoints", handler);
+            } else {
+                let handler = instrument(metrics, "v1/checkpoints", Api::checkpoints);
+                scope.endpoint("v1/checkpoints", handler);
+            }
+            if self.signed_queries {
+                let handler = instrument(
+                    metrics,
+                    "v1/balance_history",
+                    Api::authenticated_balance_history,
+                );
+                scope.endpoint("v1/balance_history", handler);
+            } else {
+                let handler = instrument(metrics, "v1/balance_history", Api::balance_history);
+                scope.endpoint("v1/balance_history", handler);
+            }
+            scope.endpoint("v1/supply", instrument(metrics, "v1/supply", Api::supply));
+            scope.endpoint("v1/config", instrument(metrics, "v1/config", Api::config));
+            scope.endpoint(
+                "v1/settings",
+                instrument(metrics, "v1/settings", Api::settings),
+            );
+            scope.endpoint(
+                "v1/rollback_delay",
+                instrument(metrics, "v1/rollback_delay", Api::rollback_delay),
+            );
+            scope.endpoint(
+                "v1/unaccepted_filter",
+                instrument(metrics, "v1/unaccepted_filter", Api::unaccepted_filter),
+            );
+            scope.endpoint(
+                "v1/transfer_request",
+                instrument(metrics, "v1/transfer_request", Api::transfer_request),
+            );
+            scope.endpoint(
+                "v1/transfer_backup",
+                instrument(metrics, "v1/transfer_backup", Api::transfer_backup),
+            );
+            scope.endpoint("v1/schema", instrument(metrics, "v1/schema", Api::schema));
+            scope.endpoint_mut(
+                "v1/transaction",
+                instrument(metrics, "v1/transaction", Api::transaction),
+            );
+        }
+
+        if let Some(ref metrics) = self.metrics {
+            builder
+                .private_scope()
+                .endpoint("v1/metrics", metrics.endpoint());
+        }
+        if let Some(ref probe) = self.debugger_probe {
+            let handler = instrument(metrics, "v1/debug/events", probe.endpoint());
+            builder.private_scope().endpoint("v1/debug/events", handler);
+            let handler = instrument(metrics, "v1/debug/state", probe.state_endpoint());
+            builder.private_scope().endpoint("v1/debug/state", handler);
+        }
+        #[cfg(feature = "webhooks")]
+        {
+            if let Some(ref webhooks) = self.webhooks {
+                builder
+                    .private_scope()
+                    .endpoint("v1/webhooks", webhooks.list_endpoint())
+                    .endpoint_mut("v1/webhooks/register", webhooks.register_endpoint())
+                    .endpoint_mut("v1/webhooks/unregister", webhooks.unregister_endpoint());
+            }
+        }
     }
 }
@@ -0,0 +1,387 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics collected by the service.
+
+use exonum::{
+    api::{self, ServiceApiState},
+    blockchain::{Schema as CoreSchema, TransactionErrorType},
+    helpers::Height,
+    storage::Snapshot,
+};
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use storage::Schema;
+use SERVICE_ID;
+
+/// Upper bounds of histogram buckets for proof verification latency, in microseconds.
+const LATENCY_BUCKETS: &[u64] = &[250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000];
+
+lazy_static! {
+    /// Latency of range proof verification. Proofs are verified within transaction execution,
+    /// which has no access to the service instance, so the histogram is shared
+    /// by all service instances in the process.
+    static ref PROOF_VERIFICATION: Mutex<Histogram> = Mutex::new(Histogram::new(LATENCY_BUCKETS));
+}
+
+/// Records the latency of a range proof verification.
+pub(crate) fn observe_proof_verification(latency: Duration) {
+    PROOF_VERIFICATION
+        .lock()
+        .expect("proof verification histogram is poisoned")
+        .observe(latency);
+}
+
+/// Histogram with fixed buckets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Upper bounds of the buckets, in microseconds. The last bucket, which is not listed
+    /// here, has no upper bound.
+    pub bounds: Vec<u64>,
+    /// Number of observations in each bucket (not cumulative). The length of this vector
+    /// is greater than the length of `bounds` by one.
+    pub counts: Vec<u64>,
+    /// Total number of observations.
+    pub count: u64,
+    /// Sum of all observations, in microseconds.
+    pub sum: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &[u64]) -> Self {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0,
+        }
+    }
+
+    fn observe(&mut self, value: Duration) {
+        let micros = value.as_secs() * 1_000_000 + u64::from(value.subsec_micros());
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|&bound| micros <= bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += micros;
+    }
+
+    /// Returns the mean of observations, or `None` if there are no observations.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_micros(self.sum / self.count))
+        }
+    }
+}
+
+/// Type of a transaction of the service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    /// `CreateWallet` transaction.
+    CreateWallet,
+    /// `Transfer` transaction.
+    Transfer,
+    /// `Accept` transaction.
+    Accept,
+    /// `LockForBridge` transaction.
+    LockForBridge,
+    /// `ReleaseFromBridge` transaction.
+    ReleaseFromBridge,
+    /// `AcceptWithNote` transaction.
+    AcceptWithNote,
+    /// `UpdateSettings` transaction.
+    UpdateSettings,
+    /// `OpenDispute` transaction.
+    OpenDispute,
+    /// `ResolveDispute` transaction.
+    ResolveDispute,
+    /// `StreamTransfer` transaction.
+    StreamTransfer,
+    /// `CloseStream` transaction.
+    CloseStream,
+    /// `Lock` transaction.
+    Lock,
+    /// `Unlock` transaction.
+    Unlock,
+    /// `SetProfile` transaction.
+    SetProfile,
+    /// `RequestTransfer` transaction.
+    RequestTransfer,
+    /// `Rerandomize` transaction.
+    Rerandomize,
+    /// `BackUpTransfer` transaction.
+    BackUpTransfer,
+    /// Transaction with an unknown message type.
+    Unknown,
+}
+
+impl TransactionKind {
+    fn from_message_type(message_type: u16) -> Self {
+        // Message types are assigned by the `transactions!` macro in the order
+        // of transaction declaration.
+        match message_type {
+            0 => TransactionKind::CreateWallet,
+            1 => TransactionKind::Transfer,
+            2 => TransactionKind::Accept,
+            3 => TransactionKind::LockForBridge,
+            4 => TransactionKind::ReleaseFromBridge,
+            5 => TransactionKind::AcceptWithNote,
+            6 => TransactionKind::UpdateSettings,
+            7 => TransactionKind::OpenDispute,
+            8 => TransactionKind::ResolveDispute,
+            9 => TransactionKind::StreamTransfer,
+            10 => TransactionKind::CloseStream,
+            11 => TransactionKind::Lock,
+            12 => TransactionKind::Unlock,
+            13 => TransactionKind::SetProfile,
+            14 => TransactionKind::RequestTransfer,
+            15 => TransactionKind::Rerandomize,
+            16 => TransactionKind::BackUpTransfer,
+            _ => TransactionKind::Unknown,
+        }
+    }
+}
+
+/// Outcome of a committed transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionOutcome {
+    /// The transaction was successfully executed.
+    Success,
+    /// The transaction execution has failed with an [error code](::transactions::Error).
+    Error(u8),
+    /// The transaction execution has panicked.
+    Panic,
+}
+
+/// Number of committed transactions with the specified kind and outcome.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionCount {
+    /// Kind of transactions.
+    pub kind: TransactionKind,
+    /// Outcome of transactions.
+    pub outcome: TransactionOutcome,
+    /// Number of transactions.
+    pub count: u64,
+}
+
+/// Number of requests to an HTTP API endpoint of the service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointCount {
+    /// Total number of requests.
+    pub requests: u64,
+    /// Number of requests that have resulted in an error.
+    pub errors: u64,
+}
+
+/// Snapshot of the service metrics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Height of the latest block processed by the metrics, or `None` if no blocks
+    /// have been processed yet.
+    pub height: Option<Height>,
+    /// Numbers of committed transactions by kind and outcome.
+    pub transactions: Vec<TransactionCount>,
+    /// Number of rolled back transfers.
+    pub rollbacks: u64,
+    /// Number of transfers awaiting acceptance or rollback.
+    pub rollback_queue_depth: u64,
+    /// Number of violations of service invariants detected after committed blocks.
+    /// Invariants are checked only if enabled with [`ServiceBuilder::check_invariants()`]
+    /// or [`ServiceBuilder::invariants()`].
+    ///
+    /// [`ServiceBuilder::check_invariants()`]: ::ServiceBuilder::check_invariants()
+    /// [`ServiceBuilder::invariants()`]: ::ServiceBuilder::invariants()
+    pub invariant_violations: u64,
+    /// Latency of range proof verification during transaction execution. Unlike other
+    /// metrics, the latency is aggregated among all services in the process, and it includes
+    /// transactions executed in blocks that have not been committed.
+    pub proof_verification: Histogram,
+    /// Numbers of requests to HTTP API endpoints of the service.
+    pub api_requests: BTreeMap<String, EndpointCount>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsData {
+    height: Option<Height>,
+    transactions: BTreeMap<(TransactionKind, TransactionOutcome), u64>,
+    rollbacks: u64,
+    rollback_queue_depth: u64,
+    invariant_violations: u64,
+    api_requests: BTreeMap<&'static str, EndpointCount>,
+}
+
+/// Handle to metrics collected by the service.
+///
+/// Metrics are collected if the service is built with [`ServiceBuilder::metrics()`].
+/// Transaction counts are collected from committed blocks, so they are consistent
+/// among nodes which have started collecting metrics at the same height.
+/// The handle is cheaply clonable; all clones refer to the same metrics.
+///
+/// If the service is built with metrics, snapshots of metrics are also available
+/// via the `v1/metrics` endpoint of the private HTTP API.
+///
+/// [`ServiceBuilder::metrics()`]: struct.ServiceBuilder.html#method.metrics
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<MetricsData>>,
+}
+
+impl Metrics {
+    fn lock(&self) -> MutexGuard<MetricsData> {
+        self.inner.lock().expect("metrics are poisoned")
+    }
+
+    /// Returns a snapshot of the metrics.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let data = self.lock();
+        MetricsSnapshot {
+            height: data.height,
+            transactions: data
+                .transactions
+                .iter()
+                .map(|(&(kind, outcome), &count)| TransactionCount {
+                    kind,
+                    outcome,
+                    count,
+                })
+                .collect(),
+            rollbacks: data.rollbacks,
+            rollback_queue_depth: data.rollback_queue_depth,
+            invariant_violations: data.invariant_violations,
+            proof_verification: PROOF_VERIFICATION
+                .lock()
+                .expect("proof verification histogram is poisoned")
+                .clone(),
+            api_requests: data
+                .api_requests
+                .iter()
+                .map(|(&endpoint, &count)| (endpoint.to_owned(), count))
+                .collect(),
+        }
+    }
+
+    /// Updates metrics based on the block committed at the specified height.
+    pub(crate) fn on_commit(&self, snapshot: &dyn Snapshot, height: Height) {
+        let core_schema = CoreSchema::new(snapshot);
+        let results = core_schema.transaction_results();
+        let mut data = self.lock();
+
+        let (mut transfers, mut accepts) = (0, 0);
+        for hash in core_schema.block_transactions(height).iter() {
+            let raw = match core_schema.transactions().get(&hash) {
+                Some(raw) => raw,
+                None => continue,
+            };
+            if raw.service_id() != SERVICE_ID {
+                continue;
+            }
+
+            let kind = TransactionKind::from_message_type(raw.message_type());
+            let outcome = match results.get(&hash).map(|result| result.0) {
+                Some(Ok(())) => TransactionOutcome::Success,
+                Some(Err(e)) => match e.error_type() {
+                    TransactionErrorType::Code(code) => TransactionOutcome::Error(code),
+                    TransactionErrorType::Panic => TransactionOutcome::Panic,
+                },
+                None => continue,
+            };
+            match (kind, outcome) {
+                (TransactionKind::Transfer, TransactionOutcome::Success) => transfers += 1,
+                (TransactionKind::Accept, TransactionOutcome::Success)
+                | (TransactionKind::AcceptWithNote, TransactionOutcome::Success) => accepts += 1,
+                _ => {}
+            }
+            *data.transactions.entry((kind, outcome)).or_insert(0) += 1;
+        }
+
+        // Rollbacks are not recorded in the block, so we infer their number from the change
+        // of the rollback queue.
+        let queue_depth = Schema::new(snapshot).unaccepted_transfers_count();
+        if data.height.is_some() {
+            data.rollbacks += (data.rollback_queue_depth + transfers)
+                .saturating_sub(accepts)
+                .saturating_sub(queue_depth);
+        }
+        data.rollback_queue_depth = queue_depth;
+        data.height = Some(height);
+    }
+
+    /// Records violations of service invariants detected after a committed block.
+    pub(crate) fn on_invariant_violations(&self, count: u64) {
+        self.lock().invariant_violations += count;
+    }
+
+    fn on_api_request(&self, endpoint: &'static str, is_error: bool) {
+        let mut data = self.lock();
+        let count = data.api_requests.entry(endpoint).or_default();
+        count.requests += 1;
+        if is_error {
+            count.errors += 1;
+        }
+    }
+
+    /// HTTP API handler returning a snapshot of the metrics.
+    pub(crate) fn endpoint(
+        &self,
+    ) -> impl Fn(&ServiceApiState, ()) -> api::Result<MetricsSnapshot> + Clone + Send + Sync + 'static
+    {
+        let metrics = self.clone();
+        move |_: &ServiceApiState, _: ()| Ok(metrics.snapshot())
+    }
+}
+
+/// Wraps an HTTP API handler so that requests to it are counted if metrics are enabled.
+pub(crate) fn instrument<Q, I, F>(
+    metrics: &Option<Metrics>,
+    endpoint: &'static str,
+    handler: F,
+) -> impl Fn(&ServiceApiState, Q) -> api::Result<I> + Clone + Send + Sync + 'static
+where
+    F: Fn(&ServiceApiState, Q) -> api::Result<I> + Clone + Send + Sync + 'static,
+{
+    let metrics = metrics.clone();
+    move |state: &ServiceApiState, query: Q| {
+        let result = handler(state, query);
+        if let Some(ref metrics) = metrics {
+            metrics.on_api_request(endpoint, result.is_err());
+        }
+        result
+    }
+}
+
+#[test]
+fn histogram_buckets() {
+    let mut histogram = Histogram::new(&[10, 100]);
+    assert_eq!(histogram.mean(), None);
+    histogram.observe(Duration::from_micros(5));
+    histogram.observe(Duration::from_micros(10));
+    histogram.observe(Duration::from_micros(50));
+    histogram.observe(Duration::from_millis(1));
+    assert_eq!(histogram.counts, vec![2, 1, 1]);
+    assert_eq!(histogram.count, 4);
+    assert_eq!(histogram.sum, 1_065);
+    assert_eq!(histogram.mean(), Some(Duration::from_micros(266)));
+}
@@ -0,0 +1,212 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Migrations of the service storage between versions of the crate.
+//!
+//! The storage of the service is versioned. A node database created with an older version
+//! of the crate needs to be migrated with [`migrate()`] (or with the `migrate` binary
+//! shipped with the crate) before the node is started with a newer version.
+//!
+//! Most migrations only touch auxiliary (non-Merkelized) tables of the service. Such
+//! migrations must not change the [state hash](::Schema::state_hash()) of the service;
+//! this is checked after they are applied. The only exception is the migration
+//! of the wallet layout described below, which changes the state hash explicitly.
+//!
+//! # Versions
+//!
+//! - **Version 1** is the initial storage layout.
+//! - **Version 2** adds the service configuration, the number of unaccepted transfers
+//!   and rollback heights of unaccepted transfers.
+//! - **Version 3** adds [Bloom filters](::TransferFilter) of unaccepted transfers.
+//! - **Version 4** stores wallets in the layout with the [`locked_balance`] field.
+//!
+//! # Wallet layout
+//!
+//! The `locked_balance` field changes the layout of the Merkelized wallets table.
+//! The migration to version 4 re-encodes wallets stored in the layout preceding the field,
+//! setting their locked balance to the commitment to zero with no blinding. Wallets
+//! already stored in the current layout are left intact.
+//!
+//! Since the wallets table is Merkelized, re-encoding changes the state hash of the service;
+//! the [report](struct.MigrationReport.html) of the migration contains the state hash
+//! both before and after the migration. Nodes with re-encoded wallets compute a different
+//! state hash than nodes running older versions of the crate, so all validators need
+//! to apply the migration at the same height, i.e., the migration is a coordinated upgrade
+//! of the network. Funds can be locked only after the [wire version](::Config::wire_version)
+//! is raised to 3; a node refuses to activate this wire version while its wallets
+//! are stored in the legacy layout.
+//!
+//! [`locked_balance`]: ::storage::Wallet::locked_balance()
+//! [`migrate()`]: fn.migrate.html
+
+use exonum::{
+    crypto::Hash,
+    storage::{Database, Entry, Error as StorageError, Fork, Snapshot},
+};
+
+use storage::Schema;
+
+/// Latest version of the service storage.
+pub const STORAGE_VERSION: u16 = 4;
+
+/// Name of the entry with the storage version.
+const VERSION: &str = "private_currency.storage_version";
+
+/// Error that can occur during a migration.
+#[derive(Debug, Fail)]
+pub enum MigrationError {
+    /// The storage has been created with a newer version of the crate.
+    #[fail(
+        display = "storage version {} is not supported (latest supported version is {})",
+        _0, STORAGE_VERSION
+    )]
+    UnsupportedVersion(u16),
+
+    /// The state hash of the service has changed during a migration step which must
+    /// preserve it.
+    #[fail(display = "state hash of the service has changed during migration")]
+    StateHashMismatch {
+        /// State hash before the migration.
+        before: Vec<Hash>,
+        /// State hash after the migration.
+        after: Vec<Hash>,
+    },
+
+    /// Error merging the migrated data into the database.
+    #[fail(display = "cannot merge migrated data: {}", _0)]
+    Storage(#[cause] StorageError),
+}
+
+/// Outcome of a successful migration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationReport {
+    /// Storage version before the migration.
+    pub from: u16,
+    /// Storage version after the migration.
+    pub to: u16,
+    /// State hash of the service before the migration.
+    pub prev_state_hash: Vec<Hash>,
+    /// State hash of the service after the migration. Differs from `prev_state_hash`
+    /// only if the migration has re-encoded wallets.
+    pub state_hash: Vec<Hash>,
+    /// Number of wallets re-encoded in the layout with the locked balance.
+    pub reencoded_wallets: u64,
+}
+
+impl MigrationReport {
+    /// Checks if the migration has changed the storage.
+    pub fn is_noop(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// Checks if the migration has changed the state hash of the service.
+    pub fn changes_state_hash(&self) -> bool {
+        self.prev_state_hash != self.state_hash
+    }
+}
+
+/// Returns the version of the service storage. Storage without a recorded version
+/// is considered to have version 1.
+pub fn storage_version<T: AsRef<dyn Snapshot>>(view: T) -> u16 {
+    Entry::new(VERSION, &view).get().unwrap_or(1)
+}
+
+/// Records the latest storage version. Called when the service is initialized
+/// in the genesis block.
+pub(crate) fn set_latest_version(fork: &mut Fork) {
+    Entry::new(VERSION, fork).set(STORAGE_VERSION);
+}
+
+fn migrate_to_v2(fork: &mut Fork) {
+    let mut schema = Schema::new(fork);
+    let config = schema.config();
+    schema.set_config(config);
+
+    let receivers: Vec<_> = schema.wallets().keys().collect();
+    let mut count = 0;
+    for key in &receivers {
+        for transfer_id in schema.unaccepted_transfers(key) {
+            let height = schema
+                .transfer_rollback_height(&transfer_id)
+                .expect("unaccepted transfer is not scheduled for rollback");
+            schema.rollback_heights_mut().put(&transfer_id, height.0);
+            count += 1;
+        }
+    }
+    schema.unaccepted_count_entry_mut().set(count);
+}
+
+fn migrate_to_v3(fork: &mut Fork) {
+    let mut schema = Schema::new(fork);
+    let receivers: Vec<_> = schema.wallets().keys().collect();
+    for key in &receivers {
+        schema.rebuild_transfer_filter(key);
+    }
+}
+
+fn migrate_to_v4(fork: &mut Fork) -> u64 {
+    Schema::new(fork).reencode_legacy_wallets()
+}
+
+/// Migrates the storage within the fork to the latest version.
+///
+/// The fork is left intact if the migration fails.
+pub fn migrate_fork(fork: &mut Fork) -> Result<MigrationReport, MigrationError> {
+    let from = storage_version(&*fork);
+    if from > STORAGE_VERSION {
+        return Err(MigrationError::UnsupportedVersion(from));
+    }
+    let prev_state_hash = Schema::new(&*fork).state_hash();
+
+    fork.checkpoint();
+    // Wallets need to be re-encoded before any of them is decoded. This is the only step
+    // allowed to change the state hash.
+    let reencoded_wallets = if from < 4 { migrate_to_v4(fork) } else { 0 };
+    let before = Schema::new(&*fork).state_hash();
+    if from < 2 {
+        migrate_to_v2(fork);
+    }
+    if from < 3 {
+        migrate_to_v3(fork);
+    }
+    Entry::new(VERSION, &mut *fork).set(STORAGE_VERSION);
+
+    let after = Schema::new(&*fork).state_hash();
+    if before != after {
+        fork.rollback();
+        return Err(MigrationError::StateHashMismatch { before, after });
+    }
+    fork.commit();
+
+    Ok(MigrationReport {
+        from,
+        to: STORAGE_VERSION,
+        prev_state_hash,
+        state_hash: after,
+        reencoded_wallets,
+    })
+}
+
+/// Migrates the node database to the latest storage version. If `dry_run` is set,
+/// the migration is performed and checked, but its results are not written
+/// to the database.
+pub fn migrate(db: &dyn Database, dry_run: bool) -> Result<MigrationReport, MigrationError> {
+    let mut fork = db.fork();
+    let report = migrate_fork(&mut fork)?;
+    if !dry_run && !report.is_noop() {
+        db.merge(fork.into_patch())
+            .map_err(MigrationError::Storage)?;
+    }
+    Ok(report)
+}
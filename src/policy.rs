@@ -0,0 +1,108 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Policies for accepting incoming transfers.
+
+use exonum::crypto::PublicKey;
+
+use std::collections::HashSet;
+
+use transactions::Transfer;
+
+/// Decision about an incoming transfer made by an [`AcceptPolicy`].
+///
+/// [`AcceptPolicy`]: trait.AcceptPolicy.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AcceptDecision {
+    /// The transfer should be accepted automatically.
+    Accept,
+    /// The transfer should be accepted only after a manual approval by the wallet owner.
+    ManualApproval,
+    /// The transfer should not be accepted; it will be rolled back after the rollback delay.
+    Reject,
+}
+
+/// Policy for accepting incoming transfers.
+///
+/// The policy is consulted by [`SecretState::evaluate_transfer()`] after the transfer
+/// is successfully verified, so the plaintext transfer amount is available.
+///
+/// The trait is implemented for closures with the matching signature.
+///
+/// [`SecretState::evaluate_transfer()`]: ::SecretState::evaluate_transfer()
+pub trait AcceptPolicy {
+    /// Decides whether to accept a transfer with the specified plaintext amount.
+    fn decide(&self, transfer: &Transfer, amount: u64) -> AcceptDecision;
+}
+
+impl<F> AcceptPolicy for F
+where
+    F: Fn(&Transfer, u64) -> AcceptDecision,
+{
+    fn decide(&self, transfer: &Transfer, amount: u64) -> AcceptDecision {
+        self(transfer, amount)
+    }
+}
+
+/// Policy accepting transfers automatically if their amount does not exceed a threshold,
+/// and requiring manual approval for larger transfers. Transfers from blacklisted senders
+/// are rejected regardless of the amount.
+#[derive(Debug, Clone, Default)]
+pub struct ThresholdPolicy {
+    auto_accept_limit: u64,
+    blacklist: HashSet<PublicKey>,
+}
+
+impl ThresholdPolicy {
+    /// Creates a policy automatically accepting transfers with the amount not exceeding
+    /// `auto_accept_limit`.
+    pub fn new(auto_accept_limit: u64) -> Self {
+        ThresholdPolicy {
+            auto_accept_limit,
+            blacklist: HashSet::new(),
+        }
+    }
+
+    /// Creates a policy automatically accepting all transfers from non-blacklisted senders.
+    pub fn accept_all() -> Self {
+        Self::new(u64::max_value())
+    }
+
+    /// Adds a sender to the blacklist.
+    pub fn block_sender(mut self, sender: PublicKey) -> Self {
+        self.blacklist.insert(sender);
+        self
+    }
+
+    /// Adds senders to the blacklist.
+    pub fn block_senders<I>(mut self, senders: I) -> Self
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        self.blacklist.extend(senders);
+        self
+    }
+}
+
+impl AcceptPolicy for ThresholdPolicy {
+    fn decide(&self, transfer: &Transfer, amount: u64) -> AcceptDecision {
+        if self.blacklist.contains(transfer.from()) {
+            AcceptDecision::Reject
+        } else if amount <= self.auto_accept_limit {
+            AcceptDecision::Accept
+        } else {
+            AcceptDecision::ManualApproval
+        }
+    }
+}
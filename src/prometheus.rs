@@ -0,0 +1,345 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of the service metrics in the Prometheus text format.
+
+use std::{
+    fmt::Write as FmtWrite,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    thread,
+};
+
+use metrics::{Metrics, MetricsSnapshot, TransactionKind, TransactionOutcome};
+
+/// Prefix of names of all exported metrics.
+const PREFIX: &str = "private_currency";
+
+impl MetricsSnapshot {
+    /// Renders the metrics in the [Prometheus text format].
+    ///
+    /// The following metrics are exported:
+    ///
+    /// - `private_currency_height` (gauge): height of the latest processed block
+    /// - `private_currency_transactions_total` (counter): committed transactions labeled
+    ///   with `kind` and `outcome`; failed transactions are additionally labeled
+    ///   with the error `code`
+    /// - `private_currency_rollbacks_total` (counter): rolled back transfers
+    /// - `private_currency_unaccepted_transfers` (gauge): transfers awaiting acceptance
+    ///   or rollback
+    /// - `private_currency_invariant_violations_total` (counter): detected violations
+    ///   of service invariants
+    /// - `private_currency_proof_verification_seconds` (histogram): latency of range proof
+    ///   verification
+    /// - `private_currency_api_requests_total` and `private_currency_api_errors_total`
+    ///   (counters): requests to HTTP API endpoints labeled with `endpoint`
+    ///
+    /// [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        self.write_prometheus(&mut out)
+            .expect("writing to a string cannot fail");
+        out
+    }
+
+    fn write_prometheus(&self, out: &mut String) -> ::std::fmt::Result {
+        if let Some(height) = self.height {
+            header(
+                out,
+                "height",
+                "gauge",
+                "Height of the latest processed block.",
+            )?;
+            writeln!(out, "{}_height {}", PREFIX, height.0)?;
+        }
+
+        header(
+            out,
+            "transactions_total",
+            "counter",
+            "Committed transactions of the service.",
+        )?;
+        for count in &self.transactions {
+            let outcome = match count.outcome {
+                TransactionOutcome::Success => "outcome=\"success\"".to_owned(),
+                TransactionOutcome::Error(code) => format!("outcome=\"error\",code=\"{}\"", code),
+                TransactionOutcome::Panic => "outcome=\"panic\"".to_owned(),
+            };
+            writeln!(
+                out,
+                "{}_transactions_total{{kind=\"{}\",{}}} {}",
+                PREFIX,
+                kind_label(count.kind),
+                outcome,
+                count.count
+            )?;
+        }
+
+        header(out, "rollbacks_total", "counter", "Rolled back transfers.")?;
+        writeln!(out, "{}_rollbacks_total {}", PREFIX, self.rollbacks)?;
+        header(
+            out,
+            "unaccepted_transfers",
+            "gauge",
+            "Transfers awaiting acceptance or rollback.",
+        )?;
+        writeln!(
+            out,
+            "{}_unaccepted_transfers {}",
+            PREFIX, self.rollback_queue_depth
+        )?;
+        header(
+            out,
+            "invariant_violations_total",
+            "counter",
+            "Detected violations of service invariants.",
+        )?;
+        writeln!(
+            out,
+            "{}_invariant_violations_total {}",
+            PREFIX, self.invariant_violations
+        )?;
+
+        let histogram = &self.proof_verification;
+        header(
+            out,
+            "proof_verification_seconds",
+            "histogram",
+            "Latency of range proof verification.",
+        )?;
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+            cumulative += count;
+            writeln!(
+                out,
+                "{}_proof_verification_seconds_bucket{{le=\"{}\"}} {}",
+                PREFIX,
+                micros_to_seconds(*bound),
+                cumulative
+            )?;
+        }
+        writeln!(
+            out,
+            "{}_proof_verification_seconds_bucket{{le=\"+Inf\"}} {}",
+            PREFIX, histogram.count
+        )?;
+        writeln!(
+            out,
+            "{}_proof_verification_seconds_sum {}",
+            PREFIX,
+            micros_to_seconds(histogram.sum)
+        )?;
+        writeln!(
+            out,
+            "{}_proof_verification_seconds_count {}",
+            PREFIX, histogram.count
+        )?;
+
+        header(
+            out,
+            "api_requests_total",
+            "counter",
+            "Requests to HTTP API endpoints of the service.",
+        )?;
+        for (endpoint, count) in &self.api_requests {
+            writeln!(
+                out,
+                "{}_api_requests_total{{endpoint=\"{}\"}} {}",
+                PREFIX, endpoint, count.requests
+            )?;
+        }
+        header(
+            out,
+            "api_errors_total",
+            "counter",
+            "Requests to HTTP API endpoints of the service resulted in an error.",
+        )?;
+        for (endpoint, count) in &self.api_requests {
+            writeln!(
+                out,
+                "{}_api_errors_total{{endpoint=\"{}\"}} {}",
+                PREFIX, endpoint, count.errors
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> ::std::fmt::Result {
+    writeln!(out, "# HELP {}_{} {}", PREFIX, name, help)?;
+    writeln!(out, "# TYPE {}_{} {}", PREFIX, name, kind)
+}
+
+fn kind_label(kind: TransactionKind) -> &'static str {
+    match kind {
+        TransactionKind::CreateWallet => "create_wallet",
+        TransactionKind::Transfer => "transfer",
+        TransactionKind::Accept => "accept",
+        TransactionKind::LockForBridge => "lock_for_bridge",
+        TransactionKind::ReleaseFromBridge => "release_from_bridge",
+        TransactionKind::AcceptWithNote => "accept_with_note",
+        TransactionKind::UpdateSettings => "update_settings",
+        TransactionKind::OpenDispute => "open_dispute",
+        TransactionKind::ResolveDispute => "resolve_dispute",
+        TransactionKind::StreamTransfer => "stream_transfer",
+        TransactionKind::CloseStream => "close_stream",
+        TransactionKind::Lock => "lock",
+        TransactionKind::Unlock => "unlock",
+        TransactionKind::SetProfile => "set_profile",
+        TransactionKind::RequestTransfer => "request_transfer",
+        TransactionKind::Rerandomize => "rerandomize",
+        TransactionKind::BackUpTransfer => "back_up_transfer",
+        TransactionKind::Unknown => "unknown",
+    }
+}
+
+fn micros_to_seconds(micros: u64) -> f64 {
+    micros as f64 / 1_000_000.0
+}
+
+/// Sidecar HTTP listener serving the service metrics in the Prometheus text format
+/// at the `/metrics` path.
+///
+/// The listener runs on a dedicated thread until the process exits. Unlike the `v1/metrics`
+/// endpoint of the service, the listener does not depend on the HTTP API of the node,
+/// so it can be exposed to a Prometheus server without exposing the private API.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate private_currency;
+/// use private_currency::{PrometheusExporter, ServiceBuilder};
+///
+/// let (service, _) = ServiceBuilder::new().metrics(true).build();
+/// let metrics = service.metrics().unwrap().clone();
+/// let exporter = PrometheusExporter::bind(metrics, "127.0.0.1:0").unwrap();
+/// println!("serving metrics at http://{}/metrics", exporter.local_addr());
+/// ```
+#[derive(Debug)]
+pub struct PrometheusExporter {
+    local_addr: SocketAddr,
+}
+
+impl PrometheusExporter {
+    /// Binds the listener to the specified address and starts serving metrics.
+    pub fn bind<A: ToSocketAddrs>(metrics: Metrics, addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        thread::Builder::new()
+            .name("private-currency-prometheus".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if let Ok(stream) = stream {
+                        // Errors are caused by misbehaving clients; ignore them.
+                        handle_request(&metrics, stream).ok();
+                    }
+                }
+            })?;
+        Ok(PrometheusExporter { local_addr })
+    }
+
+    /// Returns the address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+fn handle_request(metrics: &Metrics, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip request headers.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut stream = stream;
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = metrics.snapshot().to_prometheus();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+    }
+}
+
+#[test]
+fn prometheus_format() {
+    use exonum::helpers::Height;
+    use metrics::{EndpointCount, Histogram, TransactionCount};
+
+    let snapshot = MetricsSnapshot {
+        height: Some(Height(5)),
+        transactions: vec![
+            TransactionCount {
+                kind: TransactionKind::Transfer,
+                outcome: TransactionOutcome::Success,
+                count: 3,
+            },
+            TransactionCount {
+                kind: TransactionKind::Transfer,
+                outcome: TransactionOutcome::Error(3),
+                count: 1,
+            },
+        ],
+        rollbacks: 2,
+        rollback_queue_depth: 1,
+        invariant_violations: 0,
+        proof_verification: Histogram {
+            bounds: vec![500, 1_000],
+            counts: vec![1, 2, 1],
+            count: 4,
+            sum: 5_000,
+        },
+        api_requests: vec![(
+            "v1/wallet".to_owned(),
+            EndpointCount {
+                requests: 10,
+                errors: 1,
+            },
+        )]
+        .into_iter()
+        .collect(),
+    };
+
+    let text = snapshot.to_prometheus();
+    let lines: Vec<_> = text.lines().filter(|line| !line.starts_with('#')).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "private_currency_height 5",
+            "private_currency_transactions_total{kind=\"transfer\",outcome=\"success\"} 3",
+            "private_currency_transactions_total{kind=\"transfer\",outcome=\"error\",code=\"3\"} 1",
+            "private_currency_rollbacks_total 2",
+            "private_currency_unaccepted_transfers 1",
+            "private_currency_invariant_violations_total 0",
+            "private_currency_proof_verification_seconds_bucket{le=\"0.0005\"} 1",
+            "private_currency_proof_verification_seconds_bucket{le=\"0.001\"} 3",
+            "private_currency_proof_verification_seconds_bucket{le=\"+Inf\"} 4",
+            "private_currency_proof_verification_seconds_sum 0.005",
+            "private_currency_proof_verification_seconds_count 4",
+            "private_currency_api_requests_total{endpoint=\"v1/wallet\"} 10",
+            "private_currency_api_errors_total{endpoint=\"v1/wallet\"} 1",
+        ]
+    );
+}
@@ -0,0 +1,232 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Background creation of transfers.
+
+use exonum::crypto::PublicKey;
+
+use std::{
+    fmt,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use secrets::{SharedSecretState, TransferDraft, TransferError};
+use transactions::Transfer;
+
+struct Job {
+    state: SharedSecretState,
+    draft: TransferDraft,
+    reply: Sender<Result<Transfer, TransferError>>,
+}
+
+impl Job {
+    fn run(self) {
+        let Job {
+            state,
+            draft,
+            reply,
+        } = self;
+        let result = draft
+            .prove()
+            .and_then(|(transfer, opening)| state.add_proven_transfer(transfer, opening));
+        // The receiver may be dropped if the caller is not interested in the result.
+        reply.send(result).ok();
+    }
+}
+
+/// Pool of worker threads creating range proofs for transfers.
+///
+/// Creating a transfer requires two range proofs, which takes tens of milliseconds.
+/// The prover allows to move this work off the calling thread (e.g., a thread handling
+/// HTTP requests) via [`SharedSecretState::create_transfer_async()`].
+///
+/// Dropping the prover waits until all submitted transfers are created.
+///
+/// [`SharedSecretState::create_transfer_async()`]: ::SharedSecretState::create_transfer_async()
+pub struct Prover {
+    jobs: Option<Mutex<Sender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl fmt::Debug for Prover {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Prover")
+            .field("workers", &self.workers.len())
+            .finish()
+    }
+}
+
+impl Prover {
+    /// Creates a prover with the specified number of worker threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is zero.
+    pub fn new(workers: usize) -> Self {
+        assert!(workers > 0, "prover needs at least one worker");
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..workers)
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("prover-{}", i))
+                    .spawn(move || loop {
+                        let job = receiver.lock().expect("prover queue is poisoned").recv();
+                        match job {
+                            Ok(job) => job.run(),
+                            // The prover is dropped.
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("cannot spawn prover thread")
+            })
+            .collect();
+
+        Prover {
+            jobs: Some(Mutex::new(sender)),
+            workers,
+        }
+    }
+
+    fn submit(&self, job: Job) {
+        self.jobs
+            .as_ref()
+            .expect("prover is shut down")
+            .lock()
+            .expect("prover queue is poisoned")
+            .send(job)
+            .expect("prover workers have terminated");
+    }
+}
+
+impl Drop for Prover {
+    fn drop(&mut self) {
+        // Closing the channel makes workers terminate after processing remaining jobs.
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+/// Handle to a transfer being created by a [`Prover`].
+///
+/// [`Prover`]: struct.Prover.html
+#[derive(Debug)]
+pub struct TransferHandle {
+    receiver: Receiver<Result<Transfer, TransferError>>,
+}
+
+impl TransferHandle {
+    /// Blocks until the transfer is created.
+    pub fn wait(self) -> Result<Transfer, TransferError> {
+        self.receiver.recv().expect("prover worker has panicked")
+    }
+
+    /// Blocks until the transfer is created or the timeout expires. Returns `None`
+    /// on timeout.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<Result<Transfer, TransferError>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(result) => Some(result),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => panic!("prover worker has panicked"),
+        }
+    }
+
+    /// Returns the transfer if it has been created, or `None` otherwise.
+    pub fn try_get(&self) -> Option<Result<Transfer, TransferError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("prover worker has panicked"),
+        }
+    }
+}
+
+impl SharedSecretState {
+    /// Creates a transfer in the background using the specified prover.
+    ///
+    /// Constraints on the transfer are checked immediately, as in
+    /// [`SecretState::try_create_transfer()`]. The created transfer is added to the pending
+    /// transfers of the state once it is ready, even if the returned handle is dropped.
+    /// Creating the transfer fails with `TransferError::StateChanged` if an event is applied
+    /// to the state in the meantime.
+    ///
+    /// [`SecretState::try_create_transfer()`]: ::SecretState::try_create_transfer()
+    pub fn create_transfer_async(
+        &self,
+        prover: &Prover,
+        amount: u64,
+        receiver: &PublicKey,
+        rollback_delay: u32,
+    ) -> Result<TransferHandle, TransferError> {
+        let draft = self.draft_transfer(amount, receiver, rollback_delay)?;
+        let (reply, receiver) = mpsc::channel();
+        prover.submit(Job {
+            state: self.clone(),
+            draft,
+            reply,
+        });
+        Ok(TransferHandle { receiver })
+    }
+}
+
+#[test]
+fn transfers_are_created_in_background() {
+    use exonum::crypto::{gen_keypair, CryptoHash};
+    use SecretState;
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let state = SharedSecretState::new(state);
+    let (receiver, _) = gen_keypair();
+    let prover = Prover::new(2);
+
+    let handles: Vec<_> = (1..=3)
+        .map(|i| {
+            state
+                .create_transfer_async(&prover, i * 1_000, &receiver, 10)
+                .unwrap()
+        })
+        .collect();
+    let transfers: Vec<_> = handles
+        .into_iter()
+        .map(|handle| handle.wait().unwrap())
+        .collect();
+
+    let mut pending: Vec<_> = state
+        .pending_transfers()
+        .iter()
+        .map(|tx| tx.hash())
+        .collect();
+    let mut expected: Vec<_> = transfers.iter().map(|tx| tx.hash()).collect();
+    pending.sort();
+    expected.sort();
+    assert_eq!(pending, expected);
+
+    assert_eq!(
+        state
+            .create_transfer_async(&prover, 0, &receiver, 10)
+            .unwrap_err(),
+        TransferError::AmountTooSmall(0)
+    );
+}
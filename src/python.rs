@@ -0,0 +1,226 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Python bindings for the client-side cryptography.
+//!
+//! The bindings are built as a Python extension module named `private_currency`:
+//!
+//! ```text
+//! cargo rustc --release --lib --features python-extension -- --crate-type cdylib
+//! ```
+//!
+//! The `python-extension` feature enables `pyo3/extension-module` in addition to `python`.
+//! It is kept separate because extension modules are not linked against `libpython`,
+//! which breaks linking of tests and binaries; the `python` feature alone is enough
+//! to check and test the bindings.
+//!
+//! The resulting shared library should be renamed to `private_currency.so`
+//! (`private_currency.pyd` on Windows). Transactions and proofs are passed to and from Python
+//! in their JSON form (the same as used by the HTTP API); keys, hashes, commitments and
+//! openings are passed as hex strings.
+
+use exonum::{
+    crypto::{Hash, PublicKey},
+    encoding::serialize::{decode_hex, encode_hex, FromHex},
+};
+use pyo3::{exceptions::ValueError, prelude::*};
+use serde_json;
+
+use std::fmt::Display;
+
+use api::{WalletProof, WalletQuery};
+use crypto::{Commitment, Opening};
+use transactions::{CryptoTransactions, Transfer};
+use utils::TrustAnchor;
+
+fn value_error<E: Display>(e: E) -> PyErr {
+    ValueError::py_err(e.to_string())
+}
+
+fn parse_hex<T: FromHex>(hex: &str) -> PyResult<T>
+where
+    T::Error: Display,
+{
+    T::from_hex(hex).map_err(value_error)
+}
+
+fn parse_opening(hex: &str) -> PyResult<Opening> {
+    let bytes = decode_hex(hex).map_err(value_error)?;
+    Opening::from_slice(&bytes).ok_or_else(|| value_error("invalid opening"))
+}
+
+fn parse_transfer(json: &str) -> PyResult<Transfer> {
+    match CryptoTransactions::from_json(json).map_err(value_error)? {
+        CryptoTransactions::Transfer(transfer) => Ok(transfer),
+        _ => Err(value_error("expected `Transfer` transaction")),
+    }
+}
+
+fn to_json<T: ::serde::Serialize>(value: &T) -> PyResult<String> {
+    serde_json::to_string(value).map_err(value_error)
+}
+
+/// Secret state of a wallet (`SecretState` in the Rust API).
+#[pyclass]
+struct SecretState {
+    inner: ::SecretState,
+    token: PyToken,
+}
+
+#[pymethods]
+impl SecretState {
+    /// Creates a state with a random keypair.
+    #[new]
+    fn __new__(obj: &PyRawObject) -> PyResult<()> {
+        obj.init(|token| SecretState {
+            inner: ::SecretState::with_random_keypair(),
+            token,
+        })
+    }
+
+    /// Hex-encoded public key of the wallet.
+    #[getter]
+    fn public_key(&self) -> PyResult<String> {
+        Ok(encode_hex(self.inner.public_key()))
+    }
+
+    /// Current balance of the wallet.
+    #[getter]
+    fn balance(&self) -> PyResult<u64> {
+        Ok(self.inner.balance())
+    }
+
+    /// Length of the wallet history known to the state.
+    #[getter]
+    fn history_len(&self) -> PyResult<u64> {
+        Ok(self.inner.history_len())
+    }
+
+    /// Initializes the state after the `CreateWallet` transaction is committed.
+    fn initialize(&mut self) -> PyResult<()> {
+        self.inner.initialize();
+        Ok(())
+    }
+
+    /// Returns the JSON of the `CreateWallet` transaction for the wallet.
+    fn create_wallet(&self) -> PyResult<String> {
+        to_json(&self.inner.create_wallet())
+    }
+
+    /// Returns the JSON of a new `Transfer` transaction.
+    fn create_transfer(
+        &mut self,
+        amount: u64,
+        receiver: &str,
+        rollback_delay: u32,
+    ) -> PyResult<String> {
+        let receiver: PublicKey = parse_hex(receiver)?;
+        let transfer = self
+            .inner
+            .try_create_transfer(amount, &receiver, rollback_delay)
+            .map_err(value_error)?;
+        to_json(&transfer)
+    }
+
+    /// Verifies an incoming transfer. Returns the transferred amount together with
+    /// the JSON of the `Accept` transaction, or `None` if the transfer cannot be verified.
+    fn verify_transfer(&self, transfer: &str) -> PyResult<Option<(u64, String)>> {
+        let transfer = parse_transfer(transfer)?;
+        match self.inner.verify_transfer(&transfer) {
+            Some(verified) => Ok(Some((verified.value(), to_json(&verified.accept)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Updates the state according to a committed `Transfer` transaction.
+    fn transfer(&mut self, transfer: &str) -> PyResult<()> {
+        let transfer = parse_transfer(transfer)?;
+        self.inner.transfer(&transfer);
+        Ok(())
+    }
+
+    /// Updates the state according to a rolled-back `Transfer` transaction.
+    fn rollback(&mut self, transfer: &str) -> PyResult<()> {
+        let transfer = parse_transfer(transfer)?;
+        self.inner.rollback(&transfer);
+        Ok(())
+    }
+
+    /// Aborts a pending outgoing transfer with the specified hex-encoded hash.
+    fn abort(&mut self, transfer_hash: &str) -> PyResult<bool> {
+        let transfer_hash: Hash = parse_hex(transfer_hash)?;
+        Ok(self.inner.abort(&transfer_hash))
+    }
+}
+
+/// Creates a commitment to the value with a random blinding. Returns hex-encoded
+/// commitment and opening.
+#[pyfunction]
+fn commit(value: u64) -> PyResult<(String, String)> {
+    let (commitment, opening) = Commitment::new(value);
+    Ok((
+        encode_hex(commitment.to_bytes()),
+        encode_hex(opening.to_bytes()),
+    ))
+}
+
+/// Checks if the hex-encoded commitment corresponds to the hex-encoded opening.
+#[pyfunction]
+fn verify_commitment(commitment: &str, opening: &str) -> PyResult<bool> {
+    let commitment: Commitment = parse_hex(commitment)?;
+    Ok(commitment.verify(&parse_opening(opening)?))
+}
+
+/// Returns the value committed to by the hex-encoded opening.
+#[pyfunction]
+fn opening_value(opening: &str) -> PyResult<u64> {
+    Ok(parse_opening(opening)?.value)
+}
+
+/// Checks the JSON of a `WalletProof` returned by the `v1/wallet` endpoint for the wallet
+/// with the specified key. Returns the JSON of the checked wallet information.
+#[pyfunction]
+fn check_wallet_proof(
+    proof: &str,
+    validator_keys: Vec<String>,
+    key: &str,
+    start_history_at: u64,
+) -> PyResult<String> {
+    let proof: WalletProof = serde_json::from_str(proof).map_err(value_error)?;
+    let validator_keys = validator_keys
+        .iter()
+        .map(|key| parse_hex(key))
+        .collect::<PyResult<Vec<PublicKey>>>()?;
+    let query = WalletQuery::new(parse_hex(key)?, start_history_at);
+
+    let checked = proof
+        .check(&TrustAnchor::new(validator_keys), &query)
+        .map_err(value_error)?;
+    to_json(&json!({
+        "block": checked.block,
+        "wallet": checked.wallet,
+        "history": checked.history,
+        "unaccepted_transfers": checked.unaccepted_transfers,
+    }))
+}
+
+#[pymodinit]
+fn private_currency(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<SecretState>()?;
+    module.add_function(wrap_function!(commit))?;
+    module.add_function(wrap_function!(verify_commitment))?;
+    module.add_function(wrap_function!(opening_value))?;
+    module.add_function(wrap_function!(check_wallet_proof))?;
+    Ok(())
+}
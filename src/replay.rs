@@ -0,0 +1,255 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic replay of recorded transactions.
+//!
+//! A state divergence observed on a node can be reproduced locally by [recording] blocks
+//! of the node blockchain and [replaying] them against a fresh in-memory blockchain
+//! with the service. Replay emits [`DebugEvent`]s for each block and checks all service
+//! [invariants](::Invariants), so the first block causing the divergence can be found
+//! by bisecting the recording.
+//!
+//! This module is available with the `replay` crate feature.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # extern crate exonum;
+//! # extern crate private_currency;
+//! # use exonum::storage::{Database, MemoryDB};
+//! use private_currency::{replay::{record_blocks, Replayer}, CONFIG};
+//!
+//! # let db = MemoryDB::new();
+//! # let snapshot = db.snapshot();
+//! // `snapshot` is a snapshot of the node database.
+//! let blocks = record_blocks(&snapshot);
+//! let mut replayer = Replayer::new(CONFIG);
+//! for block in &blocks {
+//!     let replayed = replayer.replay_block(block).unwrap();
+//!     println!("{:?}: {:?}", replayed.state_hash, replayed.events);
+//! }
+//! ```
+//!
+//! [recording]: fn.record_blocks.html
+//! [replaying]: struct.Replayer.html
+//! [`DebugEvent`]: ::DebugEvent
+
+use exonum::{
+    blockchain::{Schema as CoreSchema, Service as ServiceTrait, Transaction},
+    crypto::{self, Hash},
+    encoding::{
+        serialize::{encode_hex, FromHex, FromHexError},
+        Error as EncodingError,
+    },
+    helpers::Height,
+    messages::{MessageBuffer, RawMessage},
+    storage::Snapshot,
+};
+use exonum_testkit::{TestKit, TestKitBuilder};
+
+use debug::block_events;
+use storage::Schema;
+use {
+    Config, DebugEvent, DebuggerOptions, Invariants, Service, ServiceBuilder, StateDump, SERVICE_ID,
+};
+
+/// Transactions of the service in a block of the recorded blockchain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedBlock {
+    /// Height of the block.
+    pub height: Height,
+    /// Hex-encoded binary messages of the service transactions in the block,
+    /// in the order of their execution.
+    pub transactions: Vec<String>,
+}
+
+/// Records blocks with transactions of the service from the node blockchain.
+/// Blocks without service transactions are omitted.
+pub fn record_blocks<T: AsRef<dyn Snapshot>>(snapshot: T) -> Vec<RecordedBlock> {
+    let core_schema = CoreSchema::new(snapshot);
+    let transactions = core_schema.transactions();
+    let mut blocks = vec![];
+
+    for height in 1..=core_schema.height().0 {
+        let height = Height(height);
+        let block_transactions: Vec<_> = core_schema
+            .block_transactions(height)
+            .iter()
+            .filter_map(|hash| transactions.get(&hash))
+            .filter(|raw| raw.service_id() == SERVICE_ID)
+            .map(|raw| {
+                let buffer: &MessageBuffer = raw.as_ref();
+                encode_hex(buffer.as_ref())
+            })
+            .collect();
+        if !block_transactions.is_empty() {
+            blocks.push(RecordedBlock {
+                height,
+                transactions: block_transactions,
+            });
+        }
+    }
+    blocks
+}
+
+/// Error during replay.
+#[derive(Debug, Fail)]
+pub enum ReplayError {
+    /// Blocks must be replayed in the order of increasing heights.
+    #[fail(
+        display = "block at height {} cannot be replayed after height {}",
+        height, current_height
+    )]
+    OutOfOrder {
+        /// Height of the replayed block.
+        height: Height,
+        /// Height of the latest block in the replay blockchain.
+        current_height: Height,
+    },
+
+    /// A transaction is not a valid hex string.
+    #[fail(display = "transaction is not a valid hex string: {}", _0)]
+    Hex(#[cause] FromHexError),
+
+    /// A transaction cannot be parsed.
+    #[fail(display = "cannot parse transaction: {}", _0)]
+    Encoding(#[cause] EncodingError),
+}
+
+/// Result of replaying a block.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplayedBlock {
+    /// Height of the block.
+    pub height: Height,
+    /// Hashes of the transactions in the block.
+    pub transactions: Vec<Hash>,
+    /// Events produced by the block, in the same form as they are sent to the debugger.
+    pub events: Vec<DebugEvent>,
+    /// State hash of the service after the block.
+    pub state_hash: Vec<Hash>,
+}
+
+/// Replays recorded blocks against a fresh in-memory blockchain with a single validator.
+///
+/// All service invariants are checked after each block; violations are reported
+/// as a [`DebugEvent::InvariantsViolated`] event of the block. Empty blocks are created between recorded blocks, so that the heights
+/// of the replayed blocks, and thus the rollback heights of transfers, match the recording.
+///
+/// [`DebugEvent::InvariantsViolated`]: ::DebugEvent::InvariantsViolated
+pub struct Replayer {
+    testkit: TestKit,
+}
+
+impl ::std::fmt::Debug for Replayer {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        formatter
+            .debug_struct("Replayer")
+            .field("height", &self.height())
+            .finish()
+    }
+}
+
+impl Replayer {
+    /// Creates a replayer with the service initialized with the specified configuration.
+    /// The configuration should be the same as the configuration of the recorded blockchain.
+    pub fn new(config: Config) -> Self {
+        // The debugger probe records rollbacks, but events are retrieved directly
+        // from the blockchain, so the debugger itself is not needed.
+        let (service, debugger) = ServiceBuilder::new()
+            .config(config)
+            .debugger(DebuggerOptions::default())
+            .build();
+        drop(debugger);
+        let testkit = TestKitBuilder::validator().with_service(service).create();
+        Replayer { testkit }
+    }
+
+    /// Returns the height of the latest block in the replay blockchain.
+    pub fn height(&self) -> Height {
+        self.testkit.height()
+    }
+
+    /// Replays a single block. Blocks must be replayed in the order of increasing heights.
+    pub fn replay_block(&mut self, block: &RecordedBlock) -> Result<ReplayedBlock, ReplayError> {
+        if block.height <= self.height() {
+            return Err(ReplayError::OutOfOrder {
+                height: block.height,
+                current_height: self.height(),
+            });
+        }
+        let (hashes, transactions): (Vec<_>, Vec<_>) = block
+            .transactions
+            .iter()
+            .map(|hex| parse_transaction(hex))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+
+        self.testkit.create_blocks_until(Height(block.height.0 - 1));
+        self.testkit.create_block_with_transactions(transactions);
+
+        let snapshot = self.testkit.snapshot();
+        let schema = Schema::new(&snapshot);
+        let mut events = block_events(snapshot.as_ref(), block.height, |_| true);
+        let violations = schema.check_invariants(Invariants::all());
+        if !violations.is_empty() {
+            events.push(DebugEvent::InvariantsViolated {
+                violations,
+                height: block.height,
+            });
+        }
+        Ok(ReplayedBlock {
+            height: block.height,
+            transactions: hashes,
+            events,
+            state_hash: schema.state_hash(),
+        })
+    }
+
+    /// Replays blocks until the first block with events matching the predicate,
+    /// and returns this block. Returns `Ok(None)` if there is no such block.
+    pub fn replay_until<'a, I, F>(
+        &mut self,
+        blocks: I,
+        mut predicate: F,
+    ) -> Result<Option<ReplayedBlock>, ReplayError>
+    where
+        I: IntoIterator<Item = &'a RecordedBlock>,
+        F: FnMut(&ReplayedBlock) -> bool,
+    {
+        for block in blocks {
+            let replayed = self.replay_block(block)?;
+            if predicate(&replayed) {
+                return Ok(Some(replayed));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Dumps the state of the service in the replay blockchain.
+    pub fn dump_state(&self) -> StateDump {
+        Schema::new(self.testkit.snapshot()).dump_state()
+    }
+}
+
+/// Parses a hex-encoded transaction, returning it together with its hash.
+fn parse_transaction(hex: &str) -> Result<(Hash, Box<dyn Transaction>), ReplayError> {
+    let bytes = Vec::<u8>::from_hex(hex).map_err(ReplayError::Hex)?;
+    let hash = crypto::hash(&bytes);
+    let raw = RawMessage::new(MessageBuffer::from_vec(bytes));
+    let tx = Service::default()
+        .tx_from_raw(raw)
+        .map_err(ReplayError::Encoding)?;
+    Ok((hash, tx))
+}
@@ -0,0 +1,640 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Simulation of many clients interacting with the service.
+//!
+//! A [`Simulation`] drives virtual clients with scripted [`Behavior`]s directly against
+//! a [`TestKit`], block by block, without the HTTP API or wall-clock timers. Clients may
+//! go offline for periods of time, withhold or delay acceptance of incoming transfers,
+//! submit invalid `Accept`s, or flood the blockchain with transfers. The simulation
+//! records a [trace](enum.TraceEvent.html) of submitted transactions, their outcomes,
+//! accepted and rolled-back transfers.
+//!
+//! All decisions of clients and all randomness used by the crate are derived from the
+//! simulation seed (see [`with_seeded_rng()`]), so a simulation with the same seed, clients
+//! and number of blocks produces the same trace. This makes traces suitable as regression
+//! fixtures for the rollback and acceptance logic.
+//!
+//! After each block, the secret states of online clients are synchronized with and checked
+//! against the blockchain, and service invariants are checked by the testkit.
+//!
+//! This module is available with the `test_utils` crate feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate private_currency;
+//! use private_currency::simulation::{AcceptPolicy, Behavior, Simulation};
+//!
+//! # fn main() {
+//! let run = || {
+//!     let mut simulation = Simulation::new([1; 32]);
+//!     simulation.add_client(Behavior::honest(2));
+//!     simulation.add_client(Behavior::storm().accepting(AcceptPolicy::Withhold));
+//!     simulation.add_client(Behavior::passive().offline_during(3..10));
+//!     simulation.run(20);
+//!     simulation.trace().to_vec()
+//! };
+//! assert_eq!(run(), run());
+//! # }
+//! ```
+//!
+//! [`Simulation`]: struct.Simulation.html
+//! [`Behavior`]: struct.Behavior.html
+//! [`TestKit`]: https://docs.rs/exonum-testkit/0.9.2/exonum_testkit/struct.TestKit.html
+//! [`with_seeded_rng()`]: ::crypto::with_seeded_rng
+
+use exonum::{
+    blockchain::{Transaction, TransactionErrorType},
+    crypto::{CryptoHash, Hash, SEED_LENGTH},
+};
+use exonum_testkit::TestKit;
+use rand::{prng::ChaChaRng, Rng, SeedableRng};
+
+use std::ops::Range;
+
+use crypto::with_seeded_rng;
+use storage::{maybe_transfer, Schema};
+use test_utils::{self, assert_wallet_matches};
+use transactions::Accept;
+use {Config, SecretState, CONFIG};
+
+/// Policy of a client with respect to incoming transfers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptPolicy {
+    /// Accepts incoming transfers in the block following the one that has committed them
+    /// (or, if the client was offline, the block following its return).
+    Immediate,
+    /// Accepts incoming transfers the specified number of blocks later than `Immediate`.
+    /// Acceptance fails if the transfer is rolled back in the meantime.
+    Delayed(u64),
+    /// Never accepts incoming transfers, so all of them are rolled back.
+    Withhold,
+    /// Accepts incoming transfers as `Immediate`, and submits `Accept`s signed by itself
+    /// for transfers between other clients. The latter should fail.
+    Foreign,
+}
+
+/// Scripted behavior of a simulated client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Behavior {
+    /// Interval in blocks between outgoing transfers, or `None` if the client
+    /// does not send transfers. A client with interval `n` sends a transfer in each block
+    /// with the height divisible by `n`, provided it is online and has spendable funds.
+    pub send_interval: Option<u64>,
+    /// Ranges of block heights during which the client is offline: it does not synchronize
+    /// its state, send transfers or accept incoming ones.
+    pub offline: Vec<Range<u64>>,
+    /// Policy with respect to incoming transfers.
+    pub accepts: AcceptPolicy,
+}
+
+impl Behavior {
+    /// Client that sends a transfer every `send_interval` blocks and accepts incoming
+    /// transfers immediately.
+    pub fn honest(send_interval: u64) -> Self {
+        assert!(send_interval > 0, "send interval must be positive");
+        Behavior {
+            send_interval: Some(send_interval),
+            offline: vec![],
+            accepts: AcceptPolicy::Immediate,
+        }
+    }
+
+    /// Client that does not send transfers and accepts incoming transfers immediately.
+    pub fn passive() -> Self {
+        Behavior {
+            send_interval: None,
+            offline: vec![],
+            accepts: AcceptPolicy::Immediate,
+        }
+    }
+
+    /// Client that sends a transfer in every block.
+    pub fn storm() -> Self {
+        Self::honest(1)
+    }
+
+    /// Adds a period of block heights during which the client is offline.
+    pub fn offline_during(mut self, heights: Range<u64>) -> Self {
+        self.offline.push(heights);
+        self
+    }
+
+    /// Sets the policy with respect to incoming transfers.
+    pub fn accepting(mut self, policy: AcceptPolicy) -> Self {
+        self.accepts = policy;
+        self
+    }
+
+    fn is_online(&self, height: u64) -> bool {
+        !self
+            .offline
+            .iter()
+            .any(|range| range.start <= height && height < range.end)
+    }
+}
+
+/// Outcome of a transaction executed in the simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The transaction has been executed successfully.
+    Success,
+    /// The transaction has failed with the specified [error code](::transactions::Error).
+    Failure(u8),
+    /// The transaction has panicked.
+    Panic,
+}
+
+/// Event in a simulation trace. Clients are identified by their indexes, as returned
+/// by [`Simulation::add_client()`]; heights are heights of the blocks in which
+/// the events occur.
+///
+/// [`Simulation::add_client()`]: struct.Simulation.html#method.add_client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceEvent {
+    /// `CreateWallet` transaction has been submitted for the client.
+    WalletSubmitted {
+        /// Block height.
+        height: u64,
+        /// Client index.
+        client: usize,
+        /// Transaction hash.
+        tx_hash: Hash,
+    },
+    /// Client has submitted a transfer.
+    TransferSubmitted {
+        /// Block height.
+        height: u64,
+        /// Sender index.
+        from: usize,
+        /// Receiver index.
+        to: usize,
+        /// Transferred amount.
+        amount: u64,
+        /// Transaction hash.
+        tx_hash: Hash,
+    },
+    /// Client has submitted an `Accept` transaction.
+    AcceptSubmitted {
+        /// Block height.
+        height: u64,
+        /// Client index.
+        client: usize,
+        /// Hash of the accepted transfer.
+        transfer: Hash,
+        /// Transaction hash.
+        tx_hash: Hash,
+    },
+    /// Transaction has been executed.
+    Executed {
+        /// Block height.
+        height: u64,
+        /// Transaction hash.
+        tx_hash: Hash,
+        /// Execution outcome.
+        outcome: Outcome,
+    },
+    /// Transfer has been accepted by its receiver.
+    Accepted {
+        /// Block height.
+        height: u64,
+        /// Transfer hash.
+        transfer: Hash,
+    },
+    /// Transfer has been rolled back.
+    RolledBack {
+        /// Block height.
+        height: u64,
+        /// Transfer hash.
+        transfer: Hash,
+    },
+    /// Client has gone offline.
+    WentOffline {
+        /// Block height.
+        height: u64,
+        /// Client index.
+        client: usize,
+    },
+    /// Client has returned online and synchronized its state.
+    CameOnline {
+        /// Block height.
+        height: u64,
+        /// Client index.
+        client: usize,
+    },
+}
+
+/// Simulated client.
+#[derive(Debug)]
+struct Client {
+    state: SecretState,
+    behavior: Behavior,
+    registered: bool,
+    online: bool,
+    // Incoming transfers noticed by the client.
+    seen: Vec<Hash>,
+    // `Accept`s scheduled for submission, with the height of the block to submit them in.
+    scheduled_accepts: Vec<(u64, Accept)>,
+}
+
+/// Transaction submitted by the simulation.
+#[derive(Debug, Clone, Copy)]
+enum Submission {
+    CreateWallet { client: usize },
+    Transfer { from: usize, to: usize },
+    Accept { transfer: Hash },
+}
+
+/// Committed transfer, which is neither accepted nor rolled back yet.
+#[derive(Debug)]
+struct InFlight {
+    hash: Hash,
+    from: usize,
+    to: usize,
+}
+
+/// Simulation of clients interacting with the service. See the [module docs](index.html)
+/// for details.
+pub struct Simulation {
+    testkit: TestKit,
+    rng: ChaChaRng,
+    rollback_delay: u32,
+    clients: Vec<Client>,
+    // Transactions for the next block.
+    block: Vec<(Hash, Submission, Box<dyn Transaction>)>,
+    in_flight: Vec<InFlight>,
+    trace: Vec<TraceEvent>,
+}
+
+impl ::std::fmt::Debug for Simulation {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        formatter
+            .debug_struct("Simulation")
+            .field("height", &self.testkit.height())
+            .field("clients", &self.clients)
+            .field("trace_len", &self.trace.len())
+            .finish()
+    }
+}
+
+impl Simulation {
+    /// Creates a simulation with the default service configuration.
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self::with_config(seed, CONFIG)
+    }
+
+    /// Creates a simulation with the specified service configuration. Transfers use
+    /// the minimum acceptable rollback delay, unless [overridden](#method.rollback_delay).
+    pub fn with_config(seed: [u8; 32], config: Config) -> Self {
+        let rollback_delay = config.rollback_delay_bounds.start;
+        Simulation {
+            testkit: test_utils::create_testkit_with_config(config),
+            rng: ChaChaRng::from_seed(seed),
+            rollback_delay,
+            clients: vec![],
+            block: vec![],
+            in_flight: vec![],
+            trace: vec![],
+        }
+    }
+
+    /// Sets the rollback delay for transfers sent by clients.
+    pub fn rollback_delay(&mut self, delay: u32) -> &mut Self {
+        self.rollback_delay = delay;
+        self
+    }
+
+    /// Adds a client with the specified behavior and returns its index. The wallet
+    /// of the client is created in the next block.
+    pub fn add_client(&mut self, behavior: Behavior) -> usize {
+        let config = Schema::new(self.testkit.snapshot()).config();
+        let seed: [u8; SEED_LENGTH] = self.rng.gen();
+        let state = SecretState::from_seed(&seed).with_config(config);
+
+        let index = self.clients.len();
+        let tx = state.create_wallet();
+        let tx_hash = tx.hash();
+        self.trace.push(TraceEvent::WalletSubmitted {
+            height: self.next_height(),
+            client: index,
+            tx_hash,
+        });
+        self.block.push((
+            tx_hash,
+            Submission::CreateWallet { client: index },
+            Box::new(tx),
+        ));
+        self.clients.push(Client {
+            state,
+            behavior,
+            registered: false,
+            online: true,
+            seen: vec![],
+            scheduled_accepts: vec![],
+        });
+        index
+    }
+
+    /// Returns the testkit the simulation runs on.
+    pub fn testkit(&self) -> &TestKit {
+        &self.testkit
+    }
+
+    /// Returns the secret state of a client. The state of an offline client may lag
+    /// behind the blockchain.
+    pub fn client(&self, index: usize) -> &SecretState {
+        &self.clients[index].state
+    }
+
+    /// Returns the number of clients.
+    pub fn clients_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Returns the trace recorded so far.
+    pub fn trace(&self) -> &[TraceEvent] {
+        &self.trace
+    }
+
+    /// Runs the simulation for the specified number of blocks.
+    pub fn run(&mut self, blocks: u64) {
+        for _ in 0..blocks {
+            self.step();
+        }
+    }
+
+    /// Lets clients act and commits a single block.
+    ///
+    /// Transfers are placed into the block before `Accept`s: an `Accept` changes the history
+    /// of the receiver, so a transfer from the receiver placed after it would fail.
+    pub fn step(&mut self) {
+        let height = self.next_height();
+        for index in 0..self.clients.len() {
+            if !self.clients[index].registered {
+                continue;
+            }
+            let online = self.clients[index].behavior.is_online(height);
+            if online != self.clients[index].online {
+                self.clients[index].online = online;
+                self.trace.push(if online {
+                    TraceEvent::CameOnline {
+                        height,
+                        client: index,
+                    }
+                } else {
+                    TraceEvent::WentOffline {
+                        height,
+                        client: index,
+                    }
+                });
+            }
+            if online {
+                self.sync(index);
+                self.handle_incoming(index, height);
+                self.send_transfer(index, height);
+            }
+        }
+        for index in 0..self.clients.len() {
+            if self.clients[index].registered && self.clients[index].online {
+                self.submit_accepts(index, height);
+            }
+        }
+        self.commit_block(height);
+    }
+
+    fn next_height(&self) -> u64 {
+        self.testkit.height().next().0
+    }
+
+    fn sync(&mut self, index: usize) {
+        let client = &mut self.clients[index];
+        test_utils::sync(&self.testkit, &mut client.state);
+        assert_wallet_matches(&self.testkit, &client.state);
+    }
+
+    /// Schedules `Accept`s for incoming transfers noticed by the client.
+    fn handle_incoming(&mut self, index: usize, height: u64) {
+        let snapshot = self.testkit.snapshot();
+        let unaccepted =
+            Schema::new(&snapshot).unaccepted_transfers(self.clients[index].state.public_key());
+        let policy = self.clients[index].behavior.accepts;
+
+        for in_flight in &self.in_flight {
+            if in_flight.to != index && (policy != AcceptPolicy::Foreign || in_flight.from == index)
+            {
+                continue;
+            }
+            let client = &mut self.clients[index];
+            if client.seen.contains(&in_flight.hash) {
+                continue;
+            }
+            client.seen.push(in_flight.hash);
+
+            if in_flight.to != index {
+                let accept = client.state.sign_accept(&in_flight.hash);
+                client.scheduled_accepts.push((height, accept));
+                continue;
+            }
+            if !unaccepted.contains(&in_flight.hash) {
+                continue;
+            }
+
+            let transfer = maybe_transfer(&snapshot, &in_flight.hash).expect("transfer");
+            let accept = client
+                .state
+                .verify_transfer(&transfer)
+                .expect("cannot verify incoming transfer")
+                .accept;
+            match policy {
+                AcceptPolicy::Immediate | AcceptPolicy::Foreign => {
+                    client.scheduled_accepts.push((height, accept));
+                }
+                AcceptPolicy::Delayed(delay) => {
+                    client.scheduled_accepts.push((height + delay, accept));
+                }
+                AcceptPolicy::Withhold => {}
+            }
+        }
+    }
+
+    fn submit_accepts(&mut self, index: usize, height: u64) {
+        let (due, scheduled): (Vec<_>, Vec<_>) = self.clients[index]
+            .scheduled_accepts
+            .drain(..)
+            .partition(|&(at, _)| at <= height);
+        self.clients[index].scheduled_accepts = scheduled;
+
+        for (_, accept) in due {
+            let transfer = *accept.transfer_id();
+            let tx_hash = accept.hash();
+            self.trace.push(TraceEvent::AcceptSubmitted {
+                height,
+                client: index,
+                transfer,
+                tx_hash,
+            });
+            self.block
+                .push((tx_hash, Submission::Accept { transfer }, Box::new(accept)));
+        }
+    }
+
+    fn send_transfer(&mut self, index: usize, height: u64) {
+        match self.clients[index].behavior.send_interval {
+            Some(interval) if height % interval == 0 => {}
+            _ => return,
+        }
+
+        let receivers: Vec<_> = (0..self.clients.len())
+            .filter(|&i| i != index && self.clients[i].registered)
+            .collect();
+        if receivers.is_empty() {
+            return;
+        }
+        let to = receivers[self.rng.gen_range(0, receivers.len())];
+
+        let config = self.clients[index].state.config().clone();
+        let spendable = self.clients[index].state.spendable_balance();
+        if spendable < config.min_transfer_amount {
+            return;
+        }
+        let max_amount = (spendable / 4).max(config.min_transfer_amount);
+        let amount = self
+            .rng
+            .gen_range(config.min_transfer_amount, max_amount + 1);
+
+        let seed: [u8; 32] = self.rng.gen();
+        let receiver = *self.clients[to].state.public_key();
+        let rollback_delay = self.rollback_delay;
+        let transfer = {
+            let state = &mut self.clients[index].state;
+            with_seeded_rng(seed, || {
+                state.try_create_transfer(amount, &receiver, rollback_delay)
+            })
+        };
+        let transfer = transfer.expect("cannot create transfer");
+
+        let tx_hash = transfer.hash();
+        self.trace.push(TraceEvent::TransferSubmitted {
+            height,
+            from: index,
+            to,
+            amount,
+            tx_hash,
+        });
+        self.block.push((
+            tx_hash,
+            Submission::Transfer { from: index, to },
+            Box::new(transfer),
+        ));
+    }
+
+    fn commit_block(&mut self, height: u64) {
+        let mut submissions = Vec::with_capacity(self.block.len());
+        let mut transactions = Vec::with_capacity(self.block.len());
+        for (tx_hash, submission, tx) in self.block.drain(..) {
+            submissions.push((tx_hash, submission));
+            transactions.push(tx);
+        }
+        let block = self.testkit.create_block_with_transactions(transactions);
+
+        let mut accepted = vec![];
+        for (tx, (tx_hash, submission)) in block.iter().zip(submissions) {
+            let outcome = match tx.status() {
+                Ok(()) => Outcome::Success,
+                Err(e) => match e.error_type() {
+                    TransactionErrorType::Code(code) => Outcome::Failure(code),
+                    TransactionErrorType::Panic => Outcome::Panic,
+                },
+            };
+            self.trace.push(TraceEvent::Executed {
+                height,
+                tx_hash,
+                outcome,
+            });
+            self.on_executed(submission, &tx_hash, outcome, &mut accepted);
+        }
+
+        // Resolve transfers which are no longer unaccepted.
+        let schema = Schema::new(self.testkit.snapshot());
+        let mut still_in_flight = vec![];
+        for in_flight in self.in_flight.drain(..) {
+            let receiver = self.clients[in_flight.to].state.public_key();
+            if schema
+                .unaccepted_transfers(receiver)
+                .contains(&in_flight.hash)
+            {
+                still_in_flight.push(in_flight);
+            } else if accepted.contains(&in_flight.hash) {
+                self.trace.push(TraceEvent::Accepted {
+                    height,
+                    transfer: in_flight.hash,
+                });
+            } else {
+                self.trace.push(TraceEvent::RolledBack {
+                    height,
+                    transfer: in_flight.hash,
+                });
+            }
+        }
+        self.in_flight = still_in_flight;
+    }
+
+    /// Updates the simulation state after a transaction is executed.
+    fn on_executed(
+        &mut self,
+        submission: Submission,
+        tx_hash: &Hash,
+        outcome: Outcome,
+        accepted: &mut Vec<Hash>,
+    ) {
+        match (submission, outcome) {
+            (Submission::CreateWallet { client }, Outcome::Success) => {
+                let client = &mut self.clients[client];
+                client.state.initialize();
+                client.registered = true;
+            }
+            (Submission::Transfer { from, to }, Outcome::Success) => {
+                self.in_flight.push(InFlight {
+                    hash: *tx_hash,
+                    from,
+                    to,
+                });
+            }
+            (Submission::Transfer { from, .. }, _) => {
+                self.clients[from].state.abort(tx_hash);
+            }
+            (Submission::Accept { transfer }, Outcome::Success) => accepted.push(transfer),
+            _ => {}
+        }
+    }
+}
+
+impl TraceEvent {
+    /// Returns the height of the block in which the event has occurred.
+    pub fn height(&self) -> u64 {
+        match *self {
+            TraceEvent::WalletSubmitted { height, .. }
+            | TraceEvent::TransferSubmitted { height, .. }
+            | TraceEvent::AcceptSubmitted { height, .. }
+            | TraceEvent::Executed { height, .. }
+            | TraceEvent::Accepted { height, .. }
+            | TraceEvent::RolledBack { height, .. }
+            | TraceEvent::WentOffline { height, .. }
+            | TraceEvent::CameOnline { height, .. } => height,
+        }
+    }
+}
@@ -16,30 +16,84 @@
 
 use exonum::{
     blockchain::Schema as CoreSchema,
-    crypto::{CryptoHash, Hash, PublicKey},
+    crypto::{self as exonum_crypto, CryptoHash, Hash, PublicKey},
     helpers::Height,
     messages::Message,
-    storage::{Fork, KeySetIndex, ProofListIndex, ProofMapIndex, Snapshot, SparseListIndex},
+    storage::{
+        Entry, Fork, KeySetIndex, ListIndex, MapIndex, ProofListIndex, ProofMapIndex, Snapshot,
+        SparseListIndex, StorageValue,
+    },
 };
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use serde_json;
 
-use super::CONFIG;
 use crypto::{enc, Commitment};
-use transactions::{CreateWallet, Error, Transfer};
+use filter::TransferFilter;
+use secrets::EncryptedData;
+#[cfg(feature = "time-oracle")]
+use time;
+use transactions::{
+    AcceptWithNote, BackUpTransfer, CloseStream, CreateWallet, Error, Lock, LockForBridge,
+    OpenDispute, ReleaseFromBridge, RequestTransfer, Rerandomize, ResolveDispute, StreamTransfer,
+    Transfer, Unlock, WIRE_VERSION,
+};
+use {Config, DelayUnit, SERVICE_NAME};
 
 const WALLETS: &str = "private_currency.wallets";
 const HISTORY: &str = "private_currency.history";
 const UNACCEPTED_PAYMENTS: &str = "private_currency.unaccepted_payments";
 const ROLLBACK_BY_HEIGHT: &str = "private_currency.rollback_by_height";
+const ROLLBACK_HEIGHTS: &str = "private_currency.rollback_heights";
 const PAST_BALANCES: &str = "private_currency.past_balances";
+const ACTUAL_CONFIG: &str = "private_currency.config";
+const UNACCEPTED_COUNT: &str = "private_currency.unaccepted_count";
+const BRIDGE_VOTES: &str = "private_currency.bridge_votes";
+const BRIDGE_RELEASES: &str = "private_currency.bridge_releases";
+const MIN_ROLLBACK_DELAYS: &str = "private_currency.min_rollback_delays";
+const ACCEPTANCE_HEIGHTS: &str = "private_currency.acceptance_heights";
+const ACCEPTANCE_LATENCIES: &str = "private_currency.acceptance_latencies";
+const DISPUTES: &str = "private_currency.disputes";
+const DISPUTED_ESCROW: &str = "private_currency.disputed_escrow";
+const STREAMS: &str = "private_currency.streams";
+const FUND_LOCKS: &str = "private_currency.fund_locks";
+const PROFILES: &str = "private_currency.profiles";
+const PENDING_TRANSFER_REQUESTS: &str = "private_currency.pending_transfer_requests";
+const TRANSFER_REQUEST_PAYMENTS: &str = "private_currency.transfer_request_payments";
+const PAID_TRANSFER_REQUESTS: &str = "private_currency.paid_transfer_requests";
+const TRANSFER_COUNTERS: &str = "private_currency.transfer_counters";
+const RERANDOMIZATIONS: &str = "private_currency.rerandomizations";
+const TRANSFER_BACKUPS: &str = "private_currency.transfer_backups";
+const RECEIPTS: &str = "private_currency.receipts";
+const RECEIPT_ROOTS: &str = "private_currency.receipt_roots";
+const HISTORY_CHECKPOINTS: &str = "private_currency.history_checkpoints";
+const LATEST_CHECKPOINTS: &str = "private_currency.latest_checkpoints";
+const TRANSFER_FILTERS: &str = "private_currency.transfer_filters";
 
-lazy_static! {
-    /// Commitment to the initial balance of a wallet.
-    ///
-    /// We don’t use a blinding factor for the commitment since we assume
-    /// that the initial balance of a wallet is a public constant.
-    static ref INITIAL_BALANCE: Commitment = Commitment::with_no_blinding(CONFIG.initial_balance);
+/// Number of events in a wallet history between consecutive [`HistoryCheckpoint`]s.
+///
+/// [`HistoryCheckpoint`]: self::HistoryCheckpoint
+pub const CHECKPOINT_INTERVAL: u64 = 16;
+
+/// The configuration is stored in the same JSON form as in the blockchain configuration.
+impl StorageValue for Config {
+    fn into_bytes(self) -> Vec<u8> {
+        serde_json::to_vec(&self).expect("cannot serialize service config")
+    }
+
+    fn from_bytes(value: Cow<[u8]>) -> Self {
+        serde_json::from_slice(value.as_ref()).expect("Cannot restore `Config` from trusted source")
+    }
+}
+
+impl CryptoHash for Config {
+    fn hash(&self) -> Hash {
+        exonum_crypto::hash(&serde_json::to_vec(self).expect("cannot serialize service config"))
+    }
 }
 
 encoding_struct! {
@@ -58,6 +112,11 @@ encoding_struct! {
         history_hash: &Hash,
         /// Merkle root of the unaccepted incoming transfers.
         unaccepted_transfers_hash: &Hash,
+        /// Commitment to the funds locked by [`Lock`] transactions. Locked funds are
+        /// not included into `balance` and cannot be spent until they are unlocked.
+        ///
+        /// [`Lock`]: ::transactions::Lock
+        locked_balance: Commitment,
     }
 }
 
@@ -76,6 +135,69 @@ encoding_struct! {
     }
 }
 
+encoding_struct! {
+    /// Status of an active payment stream.
+    struct StreamStatus {
+        /// Number of payments already made.
+        paid: u32,
+        /// Total number of payments in the stream. Differs from the duration specified
+        /// in the `StreamTransfer` transaction if the stream has been closed early.
+        duration: u32,
+    }
+}
+
+encoding_struct! {
+    /// Number of outgoing transfers of a wallet within a rate limit window.
+    /// See [`RateLimitConfig`](::RateLimitConfig) for details.
+    struct TransferCounter {
+        /// First height of the window.
+        window_start: u64,
+        /// Number of transfers sent within the window.
+        count: u32,
+    }
+}
+
+encoding_struct! {
+    /// Settlement receipt of an accepted transfer, which is recorded for both the sender
+    /// and the receiver of the transfer.
+    ///
+    /// # See also
+    ///
+    /// - [Receipt proofs](::api::ReceiptProof)
+    struct Receipt {
+        /// Hash of the `Transfer` transaction signed by the sender.
+        transfer_id: &Hash,
+        /// Hash of the `Accept` or `AcceptWithNote` transaction signed by the receiver.
+        accept_id: &Hash,
+        /// Height of the block in which the transfer has been accepted.
+        height: u64,
+    }
+}
+
+encoding_struct! {
+    /// Checkpoint of a wallet history recorded each time the history length reaches
+    /// a multiple of [`CHECKPOINT_INTERVAL`]. Checkpoints of a wallet form a hash chain.
+    ///
+    /// Checkpoints are recorded only after the [wire version](::Config::wire_version) 5
+    /// is activated. Thus, the first checkpoint of a wallet created earlier may
+    /// be recorded at a longer history.
+    ///
+    /// # See also
+    ///
+    /// - [Checkpoint proofs](::api::CheckpointProof)
+    ///
+    /// [`CHECKPOINT_INTERVAL`]: self::CHECKPOINT_INTERVAL
+    struct HistoryCheckpoint {
+        /// Length of the wallet history at the checkpoint.
+        history_len: u64,
+        /// Merkle root of the wallet history at the checkpoint.
+        history_hash: &Hash,
+        /// Hash of the previous checkpoint of the wallet, or `Hash::zero()` for the first
+        /// checkpoint.
+        prev_checkpoint: &Hash,
+    }
+}
+
 impl Event {
     /// Creates a new transfer event.
     pub fn transfer(id: &Hash) -> Self {
@@ -91,18 +213,133 @@ impl Event {
     pub fn rollback(id: &Hash) -> Self {
         Event::new(EventTag::Rollback as u8, id)
     }
+
+    /// Creates a new event for funds locked by a `LockForBridge` transaction.
+    pub fn bridge_lock(id: &Hash) -> Self {
+        Event::new(EventTag::BridgeLock as u8, id)
+    }
+
+    /// Creates a new event for funds released by a `ReleaseFromBridge` transaction.
+    pub fn bridge_release(id: &Hash) -> Self {
+        Event::new(EventTag::BridgeRelease as u8, id)
+    }
+
+    /// Creates a new event for an acknowledgment of an outgoing transfer recorded
+    /// by an `AcceptWithNote` transaction.
+    pub fn acknowledgment(id: &Hash) -> Self {
+        Event::new(EventTag::Acknowledgment as u8, id)
+    }
+
+    /// Creates a new event for funds moved into the disputed escrow by an `OpenDispute`
+    /// transaction.
+    pub fn dispute_opened(id: &Hash) -> Self {
+        Event::new(EventTag::DisputeOpened as u8, id)
+    }
+
+    /// Creates a new event for funds released from the disputed escrow by a `ResolveDispute`
+    /// transaction.
+    pub fn dispute_resolved(id: &Hash) -> Self {
+        Event::new(EventTag::DisputeResolved as u8, id)
+    }
+
+    /// Creates a new event for funds locked by a `StreamTransfer` transaction.
+    pub fn stream_opened(id: &Hash) -> Self {
+        Event::new(EventTag::StreamOpened as u8, id)
+    }
+
+    /// Creates a new event for a payment of a stream. `id` is the hash of the `StreamTransfer`
+    /// transaction.
+    pub fn stream_payment(id: &Hash) -> Self {
+        Event::new(EventTag::StreamPayment as u8, id)
+    }
+
+    /// Creates a new event for funds refunded by a `CloseStream` transaction.
+    pub fn stream_closed(id: &Hash) -> Self {
+        Event::new(EventTag::StreamClosed as u8, id)
+    }
+
+    /// Creates a new event for funds locked by a `Lock` transaction.
+    pub fn lock(id: &Hash) -> Self {
+        Event::new(EventTag::Lock as u8, id)
+    }
+
+    /// Creates a new event for funds unlocked by an `Unlock` transaction.
+    pub fn unlock(id: &Hash) -> Self {
+        Event::new(EventTag::Unlock as u8, id)
+    }
+
+    /// Creates a new event for a transfer requested by a `RequestTransfer` transaction.
+    pub fn transfer_request(id: &Hash) -> Self {
+        Event::new(EventTag::TransferRequest as u8, id)
+    }
+
+    /// Creates a new event for a balance commitment rerandomized by a `Rerandomize`
+    /// transaction.
+    pub fn rerandomization(id: &Hash) -> Self {
+        Event::new(EventTag::Rerandomization as u8, id)
+    }
 }
 
 /// Tag used in `Event`s.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
-pub(crate) enum EventTag {
+pub enum EventTag {
     /// Wallet initialization.
     CreateWallet = 0,
     /// Transfer to or from the wallet.
     Transfer = 1,
     /// Transfer rollback.
     Rollback = 2,
+    /// Funds locked for the bridge.
+    BridgeLock = 3,
+    /// Funds released from the bridge.
+    BridgeRelease = 4,
+    /// Acknowledgment of an outgoing transfer by its receiver.
+    Acknowledgment = 5,
+    /// Funds of an accepted transfer moved into the disputed escrow.
+    DisputeOpened = 6,
+    /// Funds released from the disputed escrow.
+    DisputeResolved = 7,
+    /// Funds locked for a payment stream.
+    StreamOpened = 8,
+    /// Payment of a stream.
+    StreamPayment = 9,
+    /// Cancelled payments of a stream refunded to the sender.
+    StreamClosed = 10,
+    /// Funds moved into the locked balance.
+    Lock = 11,
+    /// Funds moved from the locked balance back to the liquid balance.
+    Unlock = 12,
+    /// Transfer requested from the wallet.
+    TransferRequest = 13,
+    /// Balance commitment replaced with a fresh commitment to the same value.
+    Rerandomization = 14,
+}
+
+impl EventTag {
+    /// All tags, in the ascending order of their numeric values.
+    const ALL: [EventTag; 15] = [
+        EventTag::CreateWallet,
+        EventTag::Transfer,
+        EventTag::Rollback,
+        EventTag::BridgeLock,
+        EventTag::BridgeRelease,
+        EventTag::Acknowledgment,
+        EventTag::DisputeOpened,
+        EventTag::DisputeResolved,
+        EventTag::StreamOpened,
+        EventTag::StreamPayment,
+        EventTag::StreamClosed,
+        EventTag::Lock,
+        EventTag::Unlock,
+        EventTag::TransferRequest,
+        EventTag::Rerandomization,
+    ];
+
+    /// Converts a numeric tag into an `EventTag`. Returns `None` if the tag is unknown.
+    pub fn from_u8(tag: u8) -> Option<Self> {
+        Self::ALL.get(tag as usize).cloned()
+    }
 }
 
 /// Gist of information about the wallet, stripped of auxiliary data.
@@ -123,14 +360,17 @@ impl WalletInfo {
 }
 
 impl Wallet {
-    fn initialize(key: &PublicKey, history_hash: &Hash) -> Self {
+    fn initialize(key: &PublicKey, initial_balance: u64, history_hash: &Hash) -> Self {
+        // We don’t use a blinding factor for the commitment since the initial balance
+        // of a wallet is public.
         Wallet::new(
             key,
-            INITIAL_BALANCE.clone(),
+            Commitment::with_no_blinding(initial_balance),
             1,
             0,
             history_hash,
             &Hash::zero(),
+            Commitment::with_no_blinding(0),
         )
     }
 
@@ -155,6 +395,7 @@ impl Wallet {
             self.history_len(), // `last_send_index` field is updated
             history_hash,
             self.unaccepted_transfers_hash(),
+            self.locked_balance(),
         )
     }
 
@@ -166,6 +407,55 @@ impl Wallet {
             self.last_send_index(), // unchanged: this is an incoming transfer or a refund
             history_hash,
             self.unaccepted_transfers_hash(),
+            self.locked_balance(),
+        )
+    }
+
+    fn lock_balance(&self, amount: &Commitment, history_hash: &Hash) -> Self {
+        Wallet::new(
+            self.public_key(),
+            self.balance() - amount.clone(),
+            self.history_len() + 1,
+            self.history_len(), // `last_send_index` field is updated
+            history_hash,
+            self.unaccepted_transfers_hash(),
+            self.locked_balance() + amount.clone(),
+        )
+    }
+
+    fn unlock_balance(&self, amount: &Commitment, history_hash: &Hash) -> Self {
+        Wallet::new(
+            self.public_key(),
+            self.balance() + amount.clone(),
+            self.history_len() + 1,
+            self.last_send_index(), // unchanged: the liquid balance can only increase
+            history_hash,
+            self.unaccepted_transfers_hash(),
+            self.locked_balance() - amount.clone(),
+        )
+    }
+
+    fn append_history(&self, history_hash: &Hash) -> Self {
+        Wallet::new(
+            self.public_key(),
+            self.balance(),
+            self.history_len() + 1,
+            self.last_send_index(), // unchanged: the event does not affect the balance
+            history_hash,
+            self.unaccepted_transfers_hash(),
+            self.locked_balance(),
+        )
+    }
+
+    fn set_history_hash(&self, hash: &Hash) -> Self {
+        Wallet::new(
+            self.public_key(),
+            self.balance(),
+            self.history_len(),
+            self.last_send_index(),
+            hash,
+            self.unaccepted_transfers_hash(),
+            self.locked_balance(),
         )
     }
 
@@ -177,10 +467,26 @@ impl Wallet {
             self.last_send_index(),
             self.history_hash(),
             hash,
+            self.locked_balance(),
         )
     }
 }
 
+/// Loads a committed transaction of the specified type with the specified hash
+/// from a storage snapshot.
+fn maybe_transaction<T, M>(view: T, id: &Hash) -> Option<M>
+where
+    T: AsRef<dyn Snapshot>,
+    M: Message,
+{
+    let core_schema = CoreSchema::new(view);
+    if !core_schema.transactions_locations().contains(id) {
+        return None;
+    }
+    let transaction = core_schema.transactions().get(id)?;
+    M::from_raw(transaction).ok()
+}
+
 /// Loads a `CreateWallet` transaction with the specified hash from a storage snapshot.
 ///
 /// # Return value
@@ -191,12 +497,7 @@ pub(crate) fn maybe_create_wallet<T>(view: T, id: &Hash) -> Option<CreateWallet>
 where
     T: AsRef<dyn Snapshot>,
 {
-    let core_schema = CoreSchema::new(view);
-    if !core_schema.transactions_locations().contains(id) {
-        return None;
-    }
-    let transaction = core_schema.transactions().get(id)?;
-    CreateWallet::from_raw(transaction).ok()
+    maybe_transaction(view, id)
 }
 
 /// Loads a `Transfer` transaction with the specified hash from a storage snapshot.
@@ -209,12 +510,112 @@ pub(crate) fn maybe_transfer<T>(view: T, id: &Hash) -> Option<Transfer>
 where
     T: AsRef<dyn Snapshot>,
 {
-    let core_schema = CoreSchema::new(view);
-    if !core_schema.transactions_locations().contains(id) {
-        return None;
-    }
-    let transaction = core_schema.transactions().get(id)?;
-    Transfer::from_raw(transaction).ok()
+    maybe_transaction(view, id)
+}
+
+/// Loads a `LockForBridge` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_bridge_lock<T>(view: T, id: &Hash) -> Option<LockForBridge>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `ReleaseFromBridge` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_bridge_release<T>(view: T, id: &Hash) -> Option<ReleaseFromBridge>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads an `AcceptWithNote` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_accept_with_note<T>(view: T, id: &Hash) -> Option<AcceptWithNote>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads an `OpenDispute` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_open_dispute<T>(view: T, id: &Hash) -> Option<OpenDispute>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `ResolveDispute` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_resolve_dispute<T>(view: T, id: &Hash) -> Option<ResolveDispute>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `StreamTransfer` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_stream_transfer<T>(view: T, id: &Hash) -> Option<StreamTransfer>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `CloseStream` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_close_stream<T>(view: T, id: &Hash) -> Option<CloseStream>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `Lock` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_lock<T>(view: T, id: &Hash) -> Option<Lock>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads an `Unlock` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_unlock<T>(view: T, id: &Hash) -> Option<Unlock>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `RequestTransfer` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_request_transfer<T>(view: T, id: &Hash) -> Option<RequestTransfer>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `Rerandomize` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_rerandomize<T>(view: T, id: &Hash) -> Option<Rerandomize>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Loads a `BackUpTransfer` transaction with the specified hash from a storage snapshot.
+pub(crate) fn maybe_back_up_transfer<T>(view: T, id: &Hash) -> Option<BackUpTransfer>
+where
+    T: AsRef<dyn Snapshot>,
+{
+    maybe_transaction(view, id)
+}
+
+/// Computes the key matching a transfer request with the transfer answering it.
+fn transfer_request_key(payer: &PublicKey, requester: &PublicKey, amount: &Commitment) -> Hash {
+    let mut bytes = Vec::with_capacity(96);
+    bytes.extend_from_slice(payer.as_ref());
+    bytes.extend_from_slice(requester.as_ref());
+    bytes.extend_from_slice(&amount.to_bytes());
+    exonum_crypto::hash(&bytes)
 }
 
 /// Schema for the private currency service.
@@ -229,15 +630,62 @@ impl<T: AsRef<dyn Snapshot>> Schema<T> {
         Schema { inner: view }
     }
 
+    fn config_entry(&self) -> Entry<&T, Config> {
+        Entry::new(ACTUAL_CONFIG, &self.inner)
+    }
+
+    /// Returns the actual configuration of the service, which is used by transactions
+    /// in the next block.
+    ///
+    /// The configuration is set in the genesis block and can be changed afterwards
+    /// by changing the blockchain configuration, e.g., with the configuration service.
+    /// A new configuration takes effect starting from the block following the one
+    /// in which the blockchain configuration has become actual.
+    pub fn config(&self) -> Config {
+        self.config_entry()
+            .get()
+            .or_else(|| self.blockchain_config())
+            .unwrap_or_default()
+    }
+
+    /// Returns the service configuration recorded in the actual blockchain configuration.
+    /// Returns `None` if the configuration is absent, cannot be parsed or is
+    /// [invalid](::Config::is_valid()).
+    fn blockchain_config(&self) -> Option<Config> {
+        CoreSchema::new(&self.inner)
+            .actual_configuration()
+            .services
+            .get(SERVICE_NAME)
+            .and_then(|value| serde_json::from_value::<Config>(value.clone()).ok())
+            .filter(Config::is_valid)
+    }
+
     /// Returns the state hash of the service.
     ///
-    /// The state hash directly commits to a single table of the service, wallets.
-    /// Other Merkelized tables (wallet histories and unaccepted transfers) are connected
-    /// to the state via fields in [`Wallet`] records.
+    /// The state hash directly commits to four tables of the service, wallets,
+    /// [profiles](#method.profiles), [receipt roots](#method.receipt_roots) and
+    /// [latest history checkpoints](#method.latest_checkpoints). Wallet histories
+    /// and unaccepted transfers are connected to the state via fields in [`Wallet`] records;
+    /// receipts of each wallet are connected via receipt roots.
+    ///
+    /// Tables other than wallets are committed to only after the [wire version] introducing
+    /// them is activated (3 for profiles, and 5 for receipt roots and checkpoints), so that
+    /// the state hash matches the one computed by older versions of the crate until then.
+    /// The position of each table in the state hash never changes.
     ///
     /// [`Wallet`]: self::Wallet
+    /// [wire version]: ::Config::wire_version
     pub fn state_hash(&self) -> Vec<Hash> {
-        vec![self.wallets().merkle_root()]
+        let wire_version = self.config().wire_version;
+        let mut state_hash = vec![self.wallets().merkle_root()];
+        if wire_version >= 3 {
+            state_hash.push(self.profiles().merkle_root());
+        }
+        if wire_version >= 5 {
+            state_hash.push(self.receipt_roots().merkle_root());
+            state_hash.push(self.latest_checkpoints().merkle_root());
+        }
+        state_hash
     }
 
     /// Returns the mapping of public keys to wallets.
@@ -250,6 +698,19 @@ impl<T: AsRef<dyn Snapshot>> Schema<T> {
         self.wallets().get(public_key)
     }
 
+    /// Checks that all wallets are stored in the current layout, which includes
+    /// the [`locked_balance`](self::Wallet::locked_balance()) field. Wallets stored
+    /// by versions of the crate preceding the field cannot be decoded until they are
+    /// [migrated](::migrations).
+    pub(crate) fn has_current_wallet_layout(&self) -> bool {
+        let wallet_len = Wallet::initialize(&PublicKey::zero(), 0, &Hash::zero())
+            .into_bytes()
+            .len();
+        let raw_wallets: ProofMapIndex<&T, PublicKey, Vec<u8>> =
+            ProofMapIndex::new(WALLETS, &self.inner);
+        raw_wallets.values().all(|bytes| bytes.len() == wallet_len)
+    }
+
     pub(crate) fn unaccepted_transfers_index(
         &self,
         key: &PublicKey,
@@ -266,6 +727,16 @@ impl<T: AsRef<dyn Snapshot>> Schema<T> {
         hashes
     }
 
+    fn transfer_filters(&self) -> MapIndex<&T, PublicKey, TransferFilter> {
+        MapIndex::new(TRANSFER_FILTERS, &self.inner)
+    }
+
+    /// Returns the Bloom filter of unaccepted incoming transfers for the account associated
+    /// with the given public `key`. The filter is not a part of the service state hash.
+    pub fn transfer_filter(&self, key: &PublicKey) -> TransferFilter {
+        self.transfer_filters().get(key).unwrap_or_default()
+    }
+
     pub(crate) fn history_index(&self, key: &PublicKey) -> ProofListIndex<&T, Event> {
         ProofListIndex::new_in_family(HISTORY, key, &self.inner)
     }
@@ -287,11 +758,287 @@ impl<T: AsRef<dyn Snapshot>> Schema<T> {
         self.past_balances(key).get(index)
     }
 
+    fn unaccepted_count_entry(&self) -> Entry<&T, u64> {
+        Entry::new(UNACCEPTED_COUNT, &self.inner)
+    }
+
+    /// Returns the total number of unaccepted transfers, i.e., transfers awaiting
+    /// either acceptance or rollback.
+    pub fn unaccepted_transfers_count(&self) -> u64 {
+        self.unaccepted_count_entry().get().unwrap_or(0)
+    }
+
     fn rollback_index(&self, height: Height) -> KeySetIndex<&T, Hash> {
         let height = height.0;
         KeySetIndex::new_in_family(ROLLBACK_BY_HEIGHT, &height, &self.inner)
     }
 
+    fn rollback_heights(&self) -> MapIndex<&T, Hash, u64> {
+        MapIndex::new(ROLLBACK_HEIGHTS, &self.inner)
+    }
+
+    /// Returns the height at which an unaccepted transfer with the specified hash
+    /// will be rolled back, or `None` if there is no such transfer.
+    pub fn transfer_rollback_height(&self, transfer_id: &Hash) -> Option<Height> {
+        if let Some(height) = self.rollback_heights().get(transfer_id) {
+            return Some(Height(height));
+        }
+
+        // Transfers committed before rollback heights were recorded.
+        let core_schema = CoreSchema::new(&self.inner);
+        let height = core_schema
+            .transactions_locations()
+            .get(transfer_id)?
+            .block_height();
+        let transfer = maybe_transfer(&self.inner, transfer_id)?;
+        let rollback_height = Height(height.0 + u64::from(transfer.rollback_delay()));
+        if self.rollback_index(rollback_height).contains(transfer_id) {
+            Some(rollback_height)
+        } else {
+            None
+        }
+    }
+
+    fn bridge_votes(&self, lock_id: &Hash) -> KeySetIndex<&T, PublicKey> {
+        KeySetIndex::new_in_family(BRIDGE_VOTES, lock_id, &self.inner)
+    }
+
+    /// Returns the relayers that have voted to release the bridge lock with the specified
+    /// hash. The votes are retained after the lock is released.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::let_and_return))]
+    pub fn bridge_release_votes(&self, lock_id: &Hash) -> Vec<PublicKey> {
+        let index = self.bridge_votes(lock_id);
+        let relayers = index.iter().collect();
+        relayers
+    }
+
+    fn bridge_releases(&self) -> MapIndex<&T, Hash, Hash> {
+        MapIndex::new(BRIDGE_RELEASES, &self.inner)
+    }
+
+    /// Returns the hash of the `ReleaseFromBridge` transaction that has released the bridge
+    /// lock with the specified hash, or `None` if the lock has not been released.
+    pub fn bridge_release(&self, lock_id: &Hash) -> Option<Hash> {
+        self.bridge_releases().get(lock_id)
+    }
+
+    fn min_rollback_delays(&self) -> MapIndex<&T, PublicKey, u32> {
+        MapIndex::new(MIN_ROLLBACK_DELAYS, &self.inner)
+    }
+
+    /// Returns the minimum rollback delay of incoming transfers preferred by the owner
+    /// of the wallet with the specified key, or zero if there is no preference.
+    ///
+    /// Wallet settings are not a part of the service state hash, so they cannot be proven
+    /// to light clients.
+    pub fn min_rollback_delay(&self, key: &PublicKey) -> u32 {
+        self.min_rollback_delays().get(key).unwrap_or(0)
+    }
+
+    fn acceptance_heights(&self) -> MapIndex<&T, Hash, u64> {
+        MapIndex::new(ACCEPTANCE_HEIGHTS, &self.inner)
+    }
+
+    /// Returns the height of the block in which the transfer with the specified hash
+    /// has been accepted, or `None` if the transfer has not been accepted, or has been
+    /// accepted before acceptance heights were recorded.
+    pub fn transfer_acceptance_height(&self, transfer_id: &Hash) -> Option<Height> {
+        self.acceptance_heights().get(transfer_id).map(Height)
+    }
+
+    /// Returns acceptance latencies of incoming transfers of the wallet with the specified key,
+    /// oldest first. A latency is the number of blocks between the block with the transfer
+    /// and the block with its acceptance.
+    ///
+    /// Latencies are recorded for transfers accepted after the table was introduced.
+    /// They are not a part of the service state hash.
+    pub fn acceptance_latencies(&self, key: &PublicKey) -> ListIndex<&T, u64> {
+        ListIndex::new_in_family(ACCEPTANCE_LATENCIES, key, &self.inner)
+    }
+
+    fn disputes(&self) -> MapIndex<&T, Hash, Hash> {
+        MapIndex::new(DISPUTES, &self.inner)
+    }
+
+    /// Returns the hash of the `OpenDispute` transaction disputing the transfer
+    /// with the specified hash, or `None` if the transfer has not been disputed.
+    /// Disputes are retained after they are resolved.
+    pub fn transfer_dispute(&self, transfer_id: &Hash) -> Option<Hash> {
+        self.disputes().get(transfer_id)
+    }
+
+    fn disputed_escrow(&self) -> MapIndex<&T, Hash, Commitment> {
+        MapIndex::new(DISPUTED_ESCROW, &self.inner)
+    }
+
+    /// Returns the commitment to the amount held in the disputed escrow by the open dispute
+    /// with the specified hash, or `None` if there is no such dispute (e.g., the dispute
+    /// has been resolved).
+    ///
+    /// The disputed escrow is not a part of the service state hash, so escrowed amounts
+    /// cannot be proven to light clients.
+    pub fn escrowed_amount(&self, dispute_id: &Hash) -> Option<Commitment> {
+        self.disputed_escrow().get(dispute_id)
+    }
+
+    /// Checks if any funds are held in the disputed escrow.
+    pub(crate) fn has_disputed_escrow(&self) -> bool {
+        self.disputed_escrow().keys().next().is_some()
+    }
+
+    fn streams(&self) -> MapIndex<&T, Hash, StreamStatus> {
+        MapIndex::new(STREAMS, &self.inner)
+    }
+
+    /// Returns the status of the active payment stream with the specified hash, or `None`
+    /// if there is no such stream (e.g., all payments of the stream have been made).
+    ///
+    /// Streams are not a part of the service state hash, so their status cannot be proven
+    /// to light clients.
+    pub fn stream_status(&self, stream_id: &Hash) -> Option<StreamStatus> {
+        self.streams().get(stream_id)
+    }
+
+    /// Checks if there are active payment streams.
+    pub(crate) fn has_active_streams(&self) -> bool {
+        self.streams().keys().next().is_some()
+    }
+
+    fn fund_locks(&self) -> KeySetIndex<&T, Hash> {
+        KeySetIndex::new(FUND_LOCKS, &self.inner)
+    }
+
+    /// Checks if the funds locked by the `Lock` transaction with the specified hash
+    /// are still locked, i.e., the lock is committed and has not been unlocked.
+    pub fn is_locked(&self, lock_id: &Hash) -> bool {
+        self.fund_locks().contains(lock_id)
+    }
+
+    /// Returns the mapping of public keys to encrypted wallet profiles set with
+    /// [`SetProfile`](::transactions::SetProfile) transactions.
+    pub fn profiles(&self) -> ProofMapIndex<&T, PublicKey, EncryptedData> {
+        ProofMapIndex::new(PROFILES, &self.inner)
+    }
+
+    /// Returns the encrypted profile of the wallet with the specified public key.
+    pub fn profile(&self, key: &PublicKey) -> Option<EncryptedData> {
+        self.profiles().get(key)
+    }
+
+    /// Returns hashes of all committed [`Rerandomize`](::transactions::Rerandomize)
+    /// transactions, in the order of their execution.
+    pub fn rerandomizations(&self) -> ListIndex<&T, Hash> {
+        ListIndex::new(RERANDOMIZATIONS, &self.inner)
+    }
+
+    /// Returns the mapping of public keys to Merkle roots of wallet receipts.
+    /// Wallets without receipts are absent from the mapping. Receipts are recorded only
+    /// after the [wire version](::Config::wire_version) 5 is activated.
+    pub fn receipt_roots(&self) -> ProofMapIndex<&T, PublicKey, Hash> {
+        ProofMapIndex::new(RECEIPT_ROOTS, &self.inner)
+    }
+
+    /// Returns settlement receipts of the wallet with the specified key, indexed
+    /// by the transfer hash.
+    pub fn receipts(&self, key: &PublicKey) -> ProofMapIndex<&T, Hash, Receipt> {
+        ProofMapIndex::new_in_family(RECEIPTS, key, &self.inner)
+    }
+
+    /// Returns the settlement receipt of the transfer with the specified hash recorded
+    /// for the wallet, or `None` if the wallet has not sent or received such a transfer,
+    /// or the transfer has not been accepted.
+    pub fn receipt(&self, key: &PublicKey, transfer_id: &Hash) -> Option<Receipt> {
+        self.receipts(key).get(transfer_id)
+    }
+
+    /// Returns the mapping of public keys to the latest history checkpoints of wallets.
+    /// Wallets without checkpoints are absent from the mapping.
+    pub fn latest_checkpoints(&self) -> ProofMapIndex<&T, PublicKey, HistoryCheckpoint> {
+        ProofMapIndex::new(LATEST_CHECKPOINTS, &self.inner)
+    }
+
+    /// Returns all history checkpoints of the wallet with the specified key, oldest first.
+    ///
+    /// Only the latest checkpoint is a part of the service state hash; earlier checkpoints
+    /// are connected to it via `prev_checkpoint` hashes.
+    pub fn history_checkpoints(&self, key: &PublicKey) -> ListIndex<&T, HistoryCheckpoint> {
+        ListIndex::new_in_family(HISTORY_CHECKPOINTS, key, &self.inner)
+    }
+
+    fn pending_transfer_requests(&self) -> MapIndex<&T, Hash, Hash> {
+        MapIndex::new(PENDING_TRANSFER_REQUESTS, &self.inner)
+    }
+
+    fn transfer_request_payments(&self) -> MapIndex<&T, Hash, Hash> {
+        MapIndex::new(TRANSFER_REQUEST_PAYMENTS, &self.inner)
+    }
+
+    fn paid_transfer_requests(&self) -> MapIndex<&T, Hash, Hash> {
+        MapIndex::new(PAID_TRANSFER_REQUESTS, &self.inner)
+    }
+
+    /// Checks if the `RequestTransfer` transaction with the specified hash is committed
+    /// and has not been paid yet.
+    pub fn is_transfer_request_pending(&self, request_id: &Hash) -> bool {
+        let request = match maybe_request_transfer(&self.inner, request_id) {
+            Some(request) => request,
+            None => return false,
+        };
+        let key = transfer_request_key(request.payer(), request.requester(), &request.amount());
+        self.pending_transfer_requests().get(&key) == Some(*request_id)
+    }
+
+    /// Returns the hash of the transfer paying the `RequestTransfer` transaction with
+    /// the specified hash, or `None` if the request has not been paid. The transfer
+    /// may be not accepted yet.
+    ///
+    /// Transfer requests are not a part of the service state hash, so their status
+    /// cannot be proven to light clients.
+    pub fn transfer_request_payment(&self, request_id: &Hash) -> Option<Hash> {
+        self.transfer_request_payments().get(request_id)
+    }
+
+    /// Returns the hash of the `RequestTransfer` transaction paid by the transfer
+    /// with the specified hash, or `None` if the transfer does not answer a request.
+    pub fn paid_transfer_request(&self, transfer_id: &Hash) -> Option<Hash> {
+        self.paid_transfer_requests().get(transfer_id)
+    }
+
+    fn transfer_backups(&self) -> MapIndex<&T, Hash, Hash> {
+        MapIndex::new(TRANSFER_BACKUPS, &self.inner)
+    }
+
+    /// Returns the hash of the [`BackUpTransfer`](::transactions::BackUpTransfer) transaction
+    /// publishing the backup copy of the opening for the transfer with the specified hash,
+    /// or `None` if no backup has been published.
+    ///
+    /// Backups are not a part of the service state hash, so their presence cannot be proven
+    /// to light clients.
+    pub fn transfer_backup(&self, transfer_id: &Hash) -> Option<Hash> {
+        self.transfer_backups().get(transfer_id)
+    }
+
+    fn transfer_counters(&self) -> MapIndex<&T, PublicKey, TransferCounter> {
+        MapIndex::new(TRANSFER_COUNTERS, &self.inner)
+    }
+
+    /// Returns the number of outgoing transfers and transfer requests sent from the wallet
+    /// within the rate limit window containing the next block. Returns 0 if transfers
+    /// are not rate-limited.
+    ///
+    /// Transfer counters are not a part of the service state hash.
+    pub fn outgoing_transfers_in_window(&self, key: &PublicKey) -> u32 {
+        let limit = match self.config().rate_limit {
+            Some(limit) => limit,
+            None => return 0,
+        };
+        let window_start = limit.window_start(CoreSchema::new(&self.inner).height().next());
+        self.transfer_counters()
+            .get(key)
+            .filter(|counter| counter.window_start() == window_start.0)
+            .map_or(0, |counter| counter.count())
+    }
+
     /// Returns hashes for all unaccepted transfers that should rolled back at
     /// the specified blockchain height.
     #[doc(hidden)]
@@ -316,15 +1063,236 @@ impl<'a> Schema<&'a mut Fork> {
         ProofMapIndex::new_in_family(UNACCEPTED_PAYMENTS, key, self.inner)
     }
 
+    fn transfer_filters_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, TransferFilter> {
+        MapIndex::new(TRANSFER_FILTERS, self.inner)
+    }
+
+    /// Rebuilds the Bloom filter of unaccepted transfers of a wallet. Bloom filters do not
+    /// support removal, so the filter is rebuilt each time a transfer leaves the unaccepted set.
+    pub(crate) fn rebuild_transfer_filter(&mut self, key: &PublicKey) {
+        let filter = TransferFilter::from_hashes(&self.unaccepted_transfers(key));
+        if filter.is_empty() {
+            self.transfer_filters_mut().remove(key);
+        } else {
+            self.transfer_filters_mut().put(key, filter);
+        }
+    }
+
     fn rollback_index_mut(&mut self, height: Height) -> KeySetIndex<&mut Fork, Hash> {
         let height = height.0;
         KeySetIndex::new_in_family(ROLLBACK_BY_HEIGHT, &height, self.inner)
     }
 
+    pub(crate) fn rollback_heights_mut(&mut self) -> MapIndex<&mut Fork, Hash, u64> {
+        MapIndex::new(ROLLBACK_HEIGHTS, self.inner)
+    }
+
     fn past_balances_mut(&mut self, key: &PublicKey) -> SparseListIndex<&mut Fork, Commitment> {
         SparseListIndex::new_in_family(PAST_BALANCES, key, self.inner)
     }
 
+    fn bridge_votes_mut(&mut self, lock_id: &Hash) -> KeySetIndex<&mut Fork, PublicKey> {
+        KeySetIndex::new_in_family(BRIDGE_VOTES, lock_id, self.inner)
+    }
+
+    fn bridge_releases_mut(&mut self) -> MapIndex<&mut Fork, Hash, Hash> {
+        MapIndex::new(BRIDGE_RELEASES, self.inner)
+    }
+
+    fn min_rollback_delays_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, u32> {
+        MapIndex::new(MIN_ROLLBACK_DELAYS, self.inner)
+    }
+
+    fn acceptance_heights_mut(&mut self) -> MapIndex<&mut Fork, Hash, u64> {
+        MapIndex::new(ACCEPTANCE_HEIGHTS, self.inner)
+    }
+
+    fn acceptance_latencies_mut(&mut self, key: &PublicKey) -> ListIndex<&mut Fork, u64> {
+        ListIndex::new_in_family(ACCEPTANCE_LATENCIES, key, self.inner)
+    }
+
+    fn disputes_mut(&mut self) -> MapIndex<&mut Fork, Hash, Hash> {
+        MapIndex::new(DISPUTES, self.inner)
+    }
+
+    fn disputed_escrow_mut(&mut self) -> MapIndex<&mut Fork, Hash, Commitment> {
+        MapIndex::new(DISPUTED_ESCROW, self.inner)
+    }
+
+    fn streams_mut(&mut self) -> MapIndex<&mut Fork, Hash, StreamStatus> {
+        MapIndex::new(STREAMS, self.inner)
+    }
+
+    fn fund_locks_mut(&mut self) -> KeySetIndex<&mut Fork, Hash> {
+        KeySetIndex::new(FUND_LOCKS, self.inner)
+    }
+
+    fn profiles_mut(&mut self) -> ProofMapIndex<&mut Fork, PublicKey, EncryptedData> {
+        ProofMapIndex::new(PROFILES, self.inner)
+    }
+
+    pub(crate) fn set_profile(&mut self, key: &PublicKey, profile: EncryptedData) {
+        self.profiles_mut().put(key, profile);
+    }
+
+    fn history_checkpoints_mut(
+        &mut self,
+        key: &PublicKey,
+    ) -> ListIndex<&mut Fork, HistoryCheckpoint> {
+        ListIndex::new_in_family(HISTORY_CHECKPOINTS, key, self.inner)
+    }
+
+    fn latest_checkpoints_mut(&mut self) -> ProofMapIndex<&mut Fork, PublicKey, HistoryCheckpoint> {
+        ProofMapIndex::new(LATEST_CHECKPOINTS, self.inner)
+    }
+
+    /// Appends an event to the history of a wallet. If the history length becomes
+    /// a multiple of `CHECKPOINT_INTERVAL`, records a history checkpoint. Checkpoints
+    /// are committed to by the state hash, so they are recorded only after the wire version
+    /// introducing them is activated.
+    fn push_history_event(&mut self, key: &PublicKey, event: Event) {
+        let history_len = {
+            let mut history = self.history_index_mut(key);
+            history.push(event);
+            history.len()
+        };
+        if history_len % CHECKPOINT_INTERVAL == 0 && self.config().wire_version >= 5 {
+            let history_hash = self.history_index(key).merkle_root();
+            let prev_checkpoint = self
+                .latest_checkpoints()
+                .get(key)
+                .map_or_else(Hash::zero, |checkpoint| checkpoint.hash());
+            let checkpoint = HistoryCheckpoint::new(history_len, &history_hash, &prev_checkpoint);
+            self.history_checkpoints_mut(key).push(checkpoint.clone());
+            self.latest_checkpoints_mut().put(key, checkpoint);
+        }
+    }
+
+    /// Re-encodes wallets stored in the layout preceding the `locked_balance` field, setting
+    /// the locked balance to the commitment to zero with no blinding. Returns the number
+    /// of re-encoded wallets.
+    pub(crate) fn reencode_legacy_wallets(&mut self) -> u64 {
+        let wallet_bytes = Wallet::initialize(&PublicKey::zero(), 0, &Hash::zero()).into_bytes();
+        // `locked_balance` is the last field of the wallet.
+        let legacy_len = wallet_bytes.len() - Commitment::BYTE_LEN;
+        let zero_locked_balance = &wallet_bytes[legacy_len..];
+
+        let mut raw_wallets: ProofMapIndex<&mut Fork, PublicKey, Vec<u8>> =
+            ProofMapIndex::new(WALLETS, self.inner);
+        let legacy_wallets: Vec<_> = raw_wallets
+            .iter()
+            .filter(|&(_, ref bytes)| bytes.len() == legacy_len)
+            .collect();
+        let count = legacy_wallets.len() as u64;
+        for (key, mut bytes) in legacy_wallets {
+            bytes.extend_from_slice(zero_locked_balance);
+            raw_wallets.put(&key, bytes);
+        }
+        count
+    }
+
+    fn receipt_roots_mut(&mut self) -> ProofMapIndex<&mut Fork, PublicKey, Hash> {
+        ProofMapIndex::new(RECEIPT_ROOTS, self.inner)
+    }
+
+    fn receipts_mut(&mut self, key: &PublicKey) -> ProofMapIndex<&mut Fork, Hash, Receipt> {
+        ProofMapIndex::new_in_family(RECEIPTS, key, self.inner)
+    }
+
+    /// Records a settlement receipt for a wallet and updates the receipt root of the wallet.
+    fn record_receipt(&mut self, key: &PublicKey, receipt: Receipt) {
+        let receipts_hash = {
+            let mut receipts = self.receipts_mut(key);
+            receipts.put(receipt.transfer_id(), receipt.clone());
+            receipts.merkle_root()
+        };
+        self.receipt_roots_mut().put(key, receipts_hash);
+    }
+
+    fn rerandomizations_mut(&mut self) -> ListIndex<&mut Fork, Hash> {
+        ListIndex::new(RERANDOMIZATIONS, self.inner)
+    }
+
+    fn pending_transfer_requests_mut(&mut self) -> MapIndex<&mut Fork, Hash, Hash> {
+        MapIndex::new(PENDING_TRANSFER_REQUESTS, self.inner)
+    }
+
+    fn transfer_request_payments_mut(&mut self) -> MapIndex<&mut Fork, Hash, Hash> {
+        MapIndex::new(TRANSFER_REQUEST_PAYMENTS, self.inner)
+    }
+
+    fn paid_transfer_requests_mut(&mut self) -> MapIndex<&mut Fork, Hash, Hash> {
+        MapIndex::new(PAID_TRANSFER_REQUESTS, self.inner)
+    }
+
+    fn transfer_backups_mut(&mut self) -> MapIndex<&mut Fork, Hash, Hash> {
+        MapIndex::new(TRANSFER_BACKUPS, self.inner)
+    }
+
+    fn transfer_counters_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, TransferCounter> {
+        MapIndex::new(TRANSFER_COUNTERS, self.inner)
+    }
+
+    pub(crate) fn set_min_rollback_delay(&mut self, key: &PublicKey, delay: u32) {
+        if delay == 0 {
+            self.min_rollback_delays_mut().remove(key);
+        } else {
+            self.min_rollback_delays_mut().put(key, delay);
+        }
+    }
+
+    pub(crate) fn unaccepted_count_entry_mut(&mut self) -> Entry<&mut Fork, u64> {
+        Entry::new(UNACCEPTED_COUNT, self.inner)
+    }
+
+    fn update_unaccepted_count(&mut self, added: u64, removed: u64) {
+        let count = self.unaccepted_transfers_count() + added - removed;
+        self.unaccepted_count_entry_mut().set(count);
+    }
+
+    fn config_entry_mut(&mut self) -> Entry<&mut Fork, Config> {
+        Entry::new(ACTUAL_CONFIG, self.inner)
+    }
+
+    pub(crate) fn set_config(&mut self, config: Config) {
+        self.config_entry_mut().set(config);
+    }
+
+    /// Synchronizes the stored service configuration with the actual blockchain
+    /// configuration. Invalid configurations are ignored, i.e., the service continues
+    /// to use the previous configuration.
+    ///
+    /// # Return value
+    ///
+    /// Returns the new configuration if it has changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new configuration activates a wire version of transactions
+    /// unsupported by the node. Continuing in this case could lead to the node diverging
+    /// from the rest of the network; the node needs to be upgraded instead.
+    pub(crate) fn update_config(&mut self) -> Option<Config> {
+        let config = self.blockchain_config()?;
+        assert!(
+            config.wire_version <= WIRE_VERSION,
+            "service configuration requires wire version {}, while the node supports \
+             version {}; upgrade the node",
+            config.wire_version,
+            WIRE_VERSION
+        );
+        assert!(
+            config.wire_version < 3 || self.has_current_wallet_layout(),
+            "service configuration requires wire version {}, while wallets are stored \
+             in the legacy layout; migrate the storage of the node",
+            config.wire_version
+        );
+        if self.config_entry().get().as_ref() == Some(&config) {
+            return None;
+        }
+        self.set_config(config.clone());
+        Some(config)
+    }
+
     pub(crate) fn create_wallet(
         &mut self,
         key: &PublicKey,
@@ -334,35 +1302,38 @@ impl<'a> Schema<&'a mut Fork> {
             return Err(Error::WalletExists);
         }
 
-        self.history_index_mut(key)
-            .push(Event::create_wallet(&tx.hash()));
+        self.push_history_event(key, Event::create_wallet(&tx.hash()));
         let history_hash = self.history_index(key).merkle_root();
-        let wallet = Wallet::initialize(key, &history_hash);
+        let initial_balance = self.config().initial_balance;
+        let wallet = Wallet::initialize(key, initial_balance, &history_hash);
         self.past_balances_mut(key).set(0, wallet.balance());
         self.wallets_mut().put(key, wallet);
         Ok(())
     }
 
-    pub(crate) fn update_sender(&mut self, sender: &Wallet, amount: &Commitment, tx: &Transfer) {
+    /// Subtracts the amount of an outgoing payment from the sender’s balance, recording
+    /// `event` in the sender’s history. Funds moved into the disputed escrow are treated
+    /// as an outgoing payment of the receiver of the disputed transfer.
+    pub(crate) fn update_sender(&mut self, sender: &Wallet, amount: &Commitment, event: Event) {
         let key = sender.public_key();
-        let event = Event::transfer(&tx.hash());
-        self.history_index_mut(key).push(event);
+        self.push_history_event(key, event);
         let history_hash = self.history_index(key).merkle_root();
         let updated_sender = sender.subtract_balance(amount, &history_hash);
+        self.reset_past_balances(&updated_sender);
+        self.wallets_mut().put(sender.public_key(), updated_sender);
+    }
 
-        {
-            // Remove all previously cached past balances and record the newest one.
-            // FIXME: update once https://github.com/exonum/exonum/pull/1042 lands.
-            // self.past_balances_mut(key).clear();
-            let mut past_balances = self.past_balances_mut(key);
-            let indices: Vec<_> = past_balances.indices().collect();
-            for i in indices {
-                past_balances.remove(i);
-            }
-            past_balances.set(updated_sender.history_len() - 1, updated_sender.balance());
+    /// Removes all previously cached past balances of a wallet after an outgoing payment
+    /// and records the newest one.
+    fn reset_past_balances(&mut self, wallet: &Wallet) {
+        // FIXME: update once https://github.com/exonum/exonum/pull/1042 lands.
+        // self.past_balances_mut(key).clear();
+        let mut past_balances = self.past_balances_mut(wallet.public_key());
+        let indices: Vec<_> = past_balances.indices().collect();
+        for i in indices {
+            past_balances.remove(i);
         }
-
-        self.wallets_mut().put(sender.public_key(), updated_sender);
+        past_balances.set(wallet.history_len() - 1, wallet.balance());
     }
 
     pub(crate) fn add_unaccepted_payment(&mut self, receiver: &Wallet, transfer: &Transfer) {
@@ -371,44 +1342,47 @@ impl<'a> Schema<&'a mut Fork> {
             unaccepted_transfers.put(&transfer.hash(), ());
             unaccepted_transfers.merkle_root()
         };
+        let mut filter = self.transfer_filter(receiver.public_key());
+        filter.insert(&transfer.hash());
+        self.transfer_filters_mut()
+            .put(receiver.public_key(), filter);
 
-        let rollback_height =
-            CoreSchema::new(&self.inner).height().next().0 + u64::from(transfer.rollback_delay());
-        let rollback_height = Height(rollback_height);
+        let rollback_height = self.new_rollback_height(transfer);
         self.rollback_index_mut(rollback_height)
             .insert(transfer.hash());
+        self.rollback_heights_mut()
+            .put(&transfer.hash(), rollback_height.0);
 
         let receiver = receiver.set_unaccepted_transfers_hash(&unaccepted_transfers_hash);
         let receiver_pk = *receiver.public_key();
         self.wallets_mut().put(&receiver_pk, receiver);
+        self.update_unaccepted_count(1, 0);
     }
 
-    fn rollback_height(&self, transfer_id: &Hash) -> Height {
-        let core_schema = CoreSchema::new(&self.inner);
-        let tx_location = core_schema
-            .transactions_locations()
-            .get(transfer_id)
-            .expect("transfer");
-        let height = tx_location.block_height();
-        let transfer = core_schema
-            .transactions()
-            .get(transfer_id)
-            .expect("transfer");
-        let transfer = Transfer::from_raw(transfer).expect("parse transfer");
-        let rollback_height = Height(height.0 + u64::from(transfer.rollback_delay()));
-        debug_assert!(rollback_height >= core_schema.height());
-        rollback_height
+    /// Computes the rollback height for a transfer being executed, converting
+    /// its rollback delay into blocks if necessary.
+    fn new_rollback_height(&self, transfer: &Transfer) -> Height {
+        let delay = match self.config().rollback_delay_unit {
+            DelayUnit::Blocks => u64::from(transfer.rollback_delay()),
+            #[cfg(feature = "time-oracle")]
+            DelayUnit::Seconds => time::delay_in_blocks(&self.inner, transfer.rollback_delay()),
+            // Configurations with this unit are invalid without the feature.
+            #[cfg(not(feature = "time-oracle"))]
+            DelayUnit::Seconds => unreachable!("wall-clock delays are not supported"),
+        };
+        Height(CoreSchema::new(&self.inner).height().next().0 + delay)
     }
 
     pub(crate) fn accept_payment(
         &mut self,
         transfer: &Transfer,
         transfer_id: &Hash,
+        accept_id: &Hash,
     ) -> Result<(), Error> {
         let receiver = transfer.to();
 
         let event = Event::transfer(transfer_id);
-        self.history_index_mut(receiver).push(event);
+        self.push_history_event(receiver, event);
         let history_hash = self.history_index(receiver).merkle_root();
 
         // Remove the transfer from the unaccepted list.
@@ -420,8 +1394,11 @@ impl<'a> Schema<&'a mut Fork> {
             payments.remove(transfer_id);
             payments.merkle_root()
         };
+        self.rebuild_transfer_filter(receiver);
 
-        // Update the receiver’s wallet.
+        // Update the receiver’s wallet. Unlike rollbacks, writes cannot be grouped among
+        // several acceptances in a block: each `Accept` is a separate transaction, which
+        // must observe the wallet state left by the previous transactions.
         let transfer_amount = transfer.amount();
         let receiver_wallet = self.wallet(receiver).ok_or(Error::UnregisteredReceiver)?;
         let receiver_wallet = receiver_wallet
@@ -433,55 +1410,400 @@ impl<'a> Schema<&'a mut Fork> {
         self.wallets_mut().put(receiver, receiver_wallet);
 
         // Remove the transfer from the rollback index.
-        let rollback_height = self.rollback_height(transfer_id);
-        let mut rollback_set = self.rollback_index_mut(rollback_height);
-        debug_assert!(rollback_set.contains(transfer_id));
-        rollback_set.remove(transfer_id);
+        let rollback_height = self
+            .transfer_rollback_height(transfer_id)
+            .expect("rollback height");
+        debug_assert!(rollback_height >= CoreSchema::new(&self.inner).height());
+        self.rollback_index_mut(rollback_height).remove(transfer_id);
+        self.rollback_heights_mut().remove(transfer_id);
+        self.update_unaccepted_count(0, 1);
+
+        let (height, committed_at) = {
+            let core_schema = CoreSchema::new(&self.inner);
+            let location = core_schema
+                .transactions_locations()
+                .get(transfer_id)
+                .expect("location of a committed transfer");
+            (core_schema.height().next(), location.block_height())
+        };
+        self.acceptance_heights_mut().put(transfer_id, height.0);
+        self.acceptance_latencies_mut(receiver)
+            .push(height.0 - committed_at.0);
+
+        // Receipts are committed to by the state hash, so they are recorded only after
+        // the wire version introducing them is activated.
+        if self.config().wire_version >= 5 {
+            let receipt = Receipt::new(transfer_id, accept_id, height.0);
+            self.record_receipt(transfer.from(), receipt.clone());
+            self.record_receipt(receiver, receipt);
+        }
+
+        Ok(())
+    }
+
+    /// Records an acknowledgment of an accepted transfer in the history of the transfer
+    /// sender. The balance of the sender is not changed.
+    pub(crate) fn record_acknowledgment(&mut self, sender: &PublicKey, id: &Hash) {
+        let wallet = self.wallet(sender).expect("sender of an accepted transfer");
+        self.append_event(&wallet, Event::acknowledgment(id));
+    }
+
+    /// Appends an event not changing the balance to the history of a wallet.
+    fn append_event(&mut self, wallet: &Wallet, event: Event) {
+        let key = wallet.public_key();
+        self.push_history_event(key, event);
+        let history_hash = self.history_index(key).merkle_root();
+        let wallet = wallet.append_history(&history_hash);
+        // Past balances are indexed by the history length, so that later transfers
+        // may reference the history including the event.
+        self.past_balances_mut(key).push(wallet.balance());
+        self.wallets_mut().put(key, wallet);
+    }
+
+    /// Records a transfer request in the history of the payer and marks it as pending.
+    pub(crate) fn add_transfer_request(
+        &mut self,
+        payer: &Wallet,
+        request: &RequestTransfer,
+    ) -> Result<(), Error> {
+        let key = transfer_request_key(request.payer(), request.requester(), &request.amount());
+        if self.pending_transfer_requests().contains(&key) {
+            return Err(Error::DuplicateTransferRequest);
+        }
+
+        let request_id = request.hash();
+        self.append_event(payer, Event::transfer_request(&request_id));
+        self.pending_transfer_requests_mut().put(&key, request_id);
+        Ok(())
+    }
+
+    /// Counts an outgoing transfer of the wallet against the rate limit set
+    /// in the service configuration. Returns an error if the wallet has exhausted
+    /// its limit within the current window.
+    pub(crate) fn count_outgoing_transfer(&mut self, key: &PublicKey) -> Result<(), Error> {
+        let limit = match self.config().rate_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let count = self.outgoing_transfers_in_window(key);
+        if count >= limit.max_transfers {
+            return Err(Error::RateLimited);
+        }
+
+        let window_start = limit.window_start(CoreSchema::new(&self.inner).height().next());
+        let counter = TransferCounter::new(window_start.0, count + 1);
+        self.transfer_counters_mut().put(key, counter);
+        Ok(())
+    }
+
+    /// Links a committed transfer with the pending request it answers, if any.
+    pub(crate) fn link_transfer_request(&mut self, transfer: &Transfer) {
+        let key = transfer_request_key(transfer.from(), transfer.to(), &transfer.amount());
+        let request_id = match self.pending_transfer_requests().get(&key) {
+            Some(request_id) => request_id,
+            None => return,
+        };
+        let transfer_id = transfer.hash();
+        self.pending_transfer_requests_mut().remove(&key);
+        self.transfer_request_payments_mut()
+            .put(&request_id, transfer_id);
+        self.paid_transfer_requests_mut()
+            .put(&transfer_id, request_id);
+    }
+
+    /// Makes the request answered by a rolled-back transfer pending again.
+    fn unlink_transfer_request(&mut self, transfer: &Transfer, transfer_id: &Hash) {
+        let request_id = match self.paid_transfer_request(transfer_id) {
+            Some(request_id) => request_id,
+            None => return,
+        };
+        let key = transfer_request_key(transfer.from(), transfer.to(), &transfer.amount());
+        self.paid_transfer_requests_mut().remove(transfer_id);
+        self.transfer_request_payments_mut().remove(&request_id);
+        self.pending_transfer_requests_mut().put(&key, request_id);
+    }
+
+    /// Records a relayer vote to release a bridge lock. Once the number of votes reaches
+    /// `threshold`, credits the locked amount to the receiver of the lock.
+    pub(crate) fn vote_for_release(
+        &mut self,
+        release: &ReleaseFromBridge,
+        lock: &LockForBridge,
+        threshold: u32,
+    ) -> Result<(), Error> {
+        let lock_id = lock.hash();
+        if self.bridge_releases().contains(&lock_id) {
+            return Err(Error::AlreadyReleased);
+        }
+        let receiver = self.wallet(lock.to()).ok_or(Error::UnregisteredReceiver)?;
+        if self.bridge_votes(&lock_id).contains(release.relayer()) {
+            return Err(Error::DuplicateBridgeVote);
+        }
 
+        self.bridge_votes_mut(&lock_id).insert(*release.relayer());
+        let votes_count = self.bridge_votes(&lock_id).iter().count() as u64;
+        if votes_count < u64::from(threshold) {
+            return Ok(());
+        }
+
+        let key = *lock.to();
+        let release_id = release.hash();
+        self.push_history_event(&key, Event::bridge_release(&release_id));
+        let history_hash = self.history_index(&key).merkle_root();
+        let receiver = receiver.add_balance(&lock.amount(), &history_hash);
+        self.past_balances_mut(&key).push(receiver.balance());
+        self.wallets_mut().put(&key, receiver);
+        self.bridge_releases_mut().put(&lock_id, release_id);
+        Ok(())
+    }
+
+    /// Moves the amount of an accepted transfer from the receiver’s balance into
+    /// the disputed escrow. The transfer must have been accepted at most `window` blocks ago.
+    pub(crate) fn open_dispute(
+        &mut self,
+        dispute: &OpenDispute,
+        transfer: &Transfer,
+        window: u32,
+    ) -> Result<(), Error> {
+        let transfer_id = transfer.hash();
+        let accepted_at = self
+            .transfer_acceptance_height(&transfer_id)
+            .ok_or(Error::InvalidDispute)?;
+        let height = CoreSchema::new(&self.inner).height().next();
+        if height.0 > accepted_at.0 + u64::from(window) {
+            return Err(Error::DisputeWindowExpired);
+        }
+        if self.disputes().contains(&transfer_id) {
+            return Err(Error::AlreadyDisputed);
+        }
+
+        let receiver = self
+            .wallet(transfer.to())
+            .expect("receiver of an accepted transfer");
+        let dispute_id = dispute.hash();
+        let amount = transfer.amount();
+        self.update_sender(&receiver, &amount, Event::dispute_opened(&dispute_id));
+        self.disputes_mut().put(&transfer_id, dispute_id);
+        self.disputed_escrow_mut().put(&dispute_id, amount);
         Ok(())
     }
 
-    fn rollback_single(&mut self, transfer: &Transfer, transfer_hash: &Hash) {
-        // Update sender history.
-        let event = Event::rollback(transfer_hash);
-        self.history_index_mut(transfer.from()).push(event);
-        let history_hash = self.history_index(transfer.from()).merkle_root();
-
-        let sender_wallet = {
-            // Refund sender.
-            let mut wallets = self.wallets_mut();
-            let sender_wallet = wallets.get(transfer.from()).expect("sender");
-            let amount = transfer.amount();
-            let sender_wallet = sender_wallet.add_balance(&amount, &history_hash);
-            wallets.put(transfer.from(), sender_wallet.clone());
-            sender_wallet
+    /// Credits the amount held in the disputed escrow to the party of the disputed transfer
+    /// chosen by the arbiter, and closes the dispute.
+    pub(crate) fn resolve_dispute(
+        &mut self,
+        resolution: &ResolveDispute,
+        dispute: &OpenDispute,
+        transfer: &Transfer,
+    ) -> Result<(), Error> {
+        let dispute_id = dispute.hash();
+        let amount = self
+            .escrowed_amount(&dispute_id)
+            .ok_or(Error::UnknownDispute)?;
+        let key = if resolution.refund() {
+            *transfer.from()
+        } else {
+            *transfer.to()
         };
-        // Remember the balance.
-        self.past_balances_mut(transfer.from())
-            .push(sender_wallet.balance());
+
+        let wallet = self.wallet(&key).expect("party of a disputed transfer");
+        self.push_history_event(&key, Event::dispute_resolved(&resolution.hash()));
+        let history_hash = self.history_index(&key).merkle_root();
+        let wallet = wallet.add_balance(&amount, &history_hash);
+        self.past_balances_mut(&key).push(wallet.balance());
+        self.wallets_mut().put(&key, wallet);
+        self.disputed_escrow_mut().remove(&dispute_id);
+        Ok(())
+    }
+
+    /// Subtracts the total amount of a payment stream from the sender’s balance
+    /// and activates the stream.
+    pub(crate) fn open_stream(&mut self, sender: &Wallet, stream: &StreamTransfer) {
+        let stream_id = stream.hash();
+        let total_amount = stream.total_amount();
+        self.update_sender(sender, &total_amount, Event::stream_opened(&stream_id));
+        self.streams_mut()
+            .put(&stream_id, StreamStatus::new(0, stream.duration()));
+    }
+
+    /// Shortens an active payment stream and refunds the amount of cancelled payments
+    /// to the sender of the stream.
+    pub(crate) fn close_stream(
+        &mut self,
+        close: &CloseStream,
+        stream: &StreamTransfer,
+    ) -> Result<(), Error> {
+        let stream_id = stream.hash();
+        let status = self.stream_status(&stream_id).ok_or(Error::UnknownStream)?;
+        if status.duration() != stream.duration()
+            || close.duration() >= status.duration()
+            || close.duration() < status.paid()
+        {
+            return Err(Error::InvalidStreamClose);
+        }
+
+        let key = *stream.from();
+        let refund = close.refunded_amount().expect("refund of a valid close");
+        let wallet = self.wallet(&key).expect("sender of a stream");
+        self.push_history_event(&key, Event::stream_closed(&close.hash()));
+        let history_hash = self.history_index(&key).merkle_root();
+        let wallet = wallet.add_balance(&refund, &history_hash);
+        self.past_balances_mut(&key).push(wallet.balance());
+        self.wallets_mut().put(&key, wallet);
+
+        if close.duration() == status.paid() {
+            self.streams_mut().remove(&stream_id);
+        } else {
+            self.streams_mut().put(
+                &stream_id,
+                StreamStatus::new(status.paid(), close.duration()),
+            );
+        }
+        Ok(())
+    }
+
+    /// Makes a payment for each active stream, and deactivates streams for which
+    /// all payments have been made.
+    pub(crate) fn do_stream_payments(&mut self) {
+        let streams: Vec<_> = self.streams().iter().collect();
+
+        for (stream_id, status) in streams {
+            let stream = maybe_stream_transfer(&self.inner, &stream_id).expect("StreamTransfer");
+            let key = *stream.to();
+            let wallet = self.wallet(&key).expect("receiver of a stream");
+            self.push_history_event(&key, Event::stream_payment(&stream_id));
+            let history_hash = self.history_index(&key).merkle_root();
+            let wallet = wallet.add_balance(&stream.rate(), &history_hash);
+            self.past_balances_mut(&key).push(wallet.balance());
+            self.wallets_mut().put(&key, wallet);
+
+            let paid = status.paid() + 1;
+            if paid == status.duration() {
+                self.streams_mut().remove(&stream_id);
+            } else {
+                self.streams_mut()
+                    .put(&stream_id, StreamStatus::new(paid, status.duration()));
+            }
+        }
+    }
+
+    /// Moves the amount of a `Lock` transaction from the owner’s liquid balance
+    /// to the locked balance. Like an outgoing payment, the lock updates `last_send_index`
+    /// of the owner.
+    pub(crate) fn lock_funds(&mut self, owner: &Wallet, lock: &Lock) {
+        let key = owner.public_key();
+        let lock_id = lock.hash();
+        self.push_history_event(key, Event::lock(&lock_id));
+        let history_hash = self.history_index(key).merkle_root();
+        let updated_owner = owner.lock_balance(&lock.amount(), &history_hash);
+        self.reset_past_balances(&updated_owner);
+        self.wallets_mut().put(key, updated_owner);
+        self.fund_locks_mut().insert(lock_id);
+    }
+
+    /// Moves the amount of an expired `Lock` transaction from the owner’s locked balance
+    /// back to the liquid balance.
+    pub(crate) fn unlock_funds(&mut self, unlock: &Unlock, lock: &Lock) -> Result<(), Error> {
+        let lock_id = lock.hash();
+        if !self.is_locked(&lock_id) {
+            return Err(Error::UnknownLock);
+        }
+
+        let key = *lock.owner();
+        let wallet = self.wallet(&key).expect("owner of a lock");
+        self.push_history_event(&key, Event::unlock(&unlock.hash()));
+        let history_hash = self.history_index(&key).merkle_root();
+        let wallet = wallet.unlock_balance(&lock.amount(), &history_hash);
+        self.past_balances_mut(&key).push(wallet.balance());
+        self.wallets_mut().put(&key, wallet);
+        self.fund_locks_mut().remove(&lock_id);
+        Ok(())
+    }
+
+    /// Adds the commitment to zero from a `Rerandomize` transaction to the owner’s balance.
+    pub(crate) fn rerandomize_balance(&mut self, owner: &Wallet, rerandomize: &Rerandomize) {
+        let key = owner.public_key();
+        self.push_history_event(key, Event::rerandomization(&rerandomize.hash()));
+        let history_hash = self.history_index(key).merkle_root();
+        let wallet = owner.add_balance(&rerandomize.blinding(), &history_hash);
+        self.past_balances_mut(key).push(wallet.balance());
+        self.wallets_mut().put(key, wallet);
+        self.rerandomizations_mut().push(rerandomize.hash());
+    }
+
+    /// Records the `BackUpTransfer` transaction for the transfer with the specified hash.
+    pub(crate) fn add_transfer_backup(
+        &mut self,
+        transfer_id: &Hash,
+        backup_id: &Hash,
+    ) -> Result<(), Error> {
+        if self.transfer_backups().contains(transfer_id) {
+            return Err(Error::DuplicateBackup);
+        }
+        self.transfer_backups_mut().put(transfer_id, *backup_id);
+        Ok(())
+    }
+
+    /// Returns the wallet from the write cache, or loads it from the storage.
+    fn cached_wallet(&self, cache: &HashMap<PublicKey, Wallet>, key: &PublicKey) -> Wallet {
+        cache
+            .get(key)
+            .cloned()
+            .unwrap_or_else(|| self.wallet(key).expect("wallet"))
     }
 
     /// Rolls back unaccepted transfers that expire at the current height.
+    ///
+    /// Wallets affected by rollbacks are updated in memory and written once per wallet,
+    /// after all rollbacks are processed; likewise, history and unaccepted transfers hashes
+    /// are computed once per wallet.
     pub(crate) fn do_rollback(&mut self) {
         let height = CoreSchema::new(&self.inner).height();
         let transfer_ids = self.rollback_transfers(height);
 
-        let mut updated_unaccepted_transfers = HashMap::new();
+        let mut wallets = HashMap::new();
+        let mut senders = HashSet::new();
+        let mut receivers = HashSet::new();
         for hash in &transfer_ids {
             let transfer = maybe_transfer(&self.inner, hash).expect("Transfer");
-            self.rollback_single(&transfer, hash);
-            self.rollback_index_mut(height).remove(hash);
+            let sender = *transfer.from();
+
+            // Refund the sender. The history hash is updated once all rollbacks are processed.
+            self.push_history_event(&sender, Event::rollback(hash));
+            let sender_wallet = self.cached_wallet(&wallets, &sender);
+            let sender_wallet =
+                sender_wallet.add_balance(&transfer.amount(), sender_wallet.history_hash());
+            // Remember the balance.
+            self.past_balances_mut(&sender)
+                .push(sender_wallet.balance());
+            wallets.insert(sender, sender_wallet);
+            senders.insert(sender);
 
-            let mut unaccepted_transfers = self.unaccepted_transfers_mut(transfer.to());
-            unaccepted_transfers.remove(hash);
-            updated_unaccepted_transfers.insert(*transfer.to(), unaccepted_transfers.merkle_root());
+            self.rollback_index_mut(height).remove(hash);
+            self.rollback_heights_mut().remove(hash);
+            self.unaccepted_transfers_mut(transfer.to()).remove(hash);
+            receivers.insert(*transfer.to());
+            self.unlink_transfer_request(&transfer, hash);
         }
+        self.update_unaccepted_count(0, transfer_ids.len() as u64);
 
-        let mut wallets = self.wallets_mut();
-        for (key, hash) in updated_unaccepted_transfers {
-            let wallet = wallets.get(&key).expect("receiver's wallet");
-            let wallet = wallet.set_unaccepted_transfers_hash(&hash);
-            wallets.put(&key, wallet);
+        for key in &senders {
+            let history_hash = self.history_index(key).merkle_root();
+            let wallet = self.cached_wallet(&wallets, key);
+            wallets.insert(*key, wallet.set_history_hash(&history_hash));
+        }
+        for key in &receivers {
+            self.rebuild_transfer_filter(key);
+            let unaccepted_transfers_hash = self.unaccepted_transfers_index(key).merkle_root();
+            let wallet = self.cached_wallet(&wallets, key);
+            wallets.insert(
+                *key,
+                wallet.set_unaccepted_transfers_hash(&unaccepted_transfers_hash),
+            );
+        }
+        let mut wallets_index = self.wallets_mut();
+        for (key, wallet) in wallets {
+            wallets_index.put(&key, wallet);
         }
 
         // FIXME: uncomment once https://github.com/exonum/exonum/pull/1042 lands.
@@ -0,0 +1,381 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistence of client wallets.
+
+use exonum::crypto::{CryptoHash, Hash};
+use serde_json;
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use api::FullEvent;
+use secrets::SecretState;
+use transactions::{CryptoTransactions, WireError};
+
+/// Transaction submitted by the wallet owner, which is not known to be committed yet.
+///
+/// Unconfirmed transactions are persisted together with the wallet, so that they can be
+/// [re-broadcast](::client::CurrencyClient::rebroadcast()) if they are lost by the node
+/// (e.g., after a node restart or eviction from the memory pool).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnconfirmedTransaction {
+    /// Hash of the transaction.
+    pub hash: Hash,
+    /// Hex-encoded binary message of the transaction.
+    message: String,
+}
+
+impl UnconfirmedTransaction {
+    /// Creates an unconfirmed transaction record.
+    pub fn new(transaction: &CryptoTransactions) -> Self {
+        UnconfirmedTransaction {
+            hash: transaction.hash(),
+            message: transaction.to_hex(),
+        }
+    }
+
+    /// Restores the transaction from the record.
+    pub fn transaction(&self) -> Result<CryptoTransactions, WireError> {
+        CryptoTransactions::from_hex(&self.message)
+    }
+}
+
+/// Client-side wallet: the secret state together with the applied events
+/// from the wallet history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredWallet {
+    /// Secret state of the wallet, including pending outgoing transfers.
+    pub state: SecretState,
+    /// Events from the wallet history applied to the state.
+    pub events: Vec<FullEvent>,
+    /// Transactions submitted by the wallet owner, which are not known to be committed yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unconfirmed: Vec<UnconfirmedTransaction>,
+}
+
+impl StoredWallet {
+    /// Creates a wallet with the specified state and no events.
+    pub fn new(state: SecretState) -> Self {
+        StoredWallet {
+            state,
+            events: vec![],
+            unconfirmed: vec![],
+        }
+    }
+
+    /// Records a transaction as unconfirmed. Returns `false` if the transaction
+    /// is already recorded.
+    pub fn track(&mut self, transaction: &CryptoTransactions) -> bool {
+        let hash = transaction.hash();
+        if self.unconfirmed.iter().any(|tx| tx.hash == hash) {
+            return false;
+        }
+        self.unconfirmed
+            .push(UnconfirmedTransaction::new(transaction));
+        true
+    }
+
+    /// Checks that the number of stored events matches the history length
+    /// known to the secret state.
+    fn check(&self) -> Result<(), StoreError> {
+        if self.events.len() as u64 == self.state.history_len() {
+            Ok(())
+        } else {
+            Err(StoreError::Inconsistent)
+        }
+    }
+}
+
+/// Error occurring in a [`StateStore`].
+///
+/// [`StateStore`]: trait.StateStore.html
+#[derive(Debug, Fail)]
+pub enum StoreError {
+    /// I/O error.
+    #[fail(display = "I/O error: {}", _0)]
+    Io(#[cause] io::Error),
+
+    /// The stored data cannot be serialized or deserialized.
+    #[fail(display = "invalid stored data: {}", _0)]
+    Format(#[cause] serde_json::Error),
+
+    /// The stored events do not correspond to the stored secret state.
+    #[fail(display = "stored events do not match the secret state")]
+    Inconsistent,
+
+    /// The store is locked by another process.
+    #[fail(display = "wallet is locked by another process")]
+    Locked {
+        /// Identifier of the process holding the lock, if known.
+        pid: Option<u32>,
+    },
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Format(e)
+    }
+}
+
+/// Persistent storage for a client wallet.
+///
+/// The wallet should be saved after each change of its state (e.g., after creating
+/// a transfer or applying new events from the wallet history), so that the client
+/// can resume querying the wallet history from `state.history_len()` after a restart.
+pub trait StateStore {
+    /// Saves the wallet, overwriting the previously saved one.
+    fn save(&self, wallet: &StoredWallet) -> Result<(), StoreError>;
+
+    /// Loads the previously saved wallet. Returns `Ok(None)` if no wallet has been saved.
+    fn load(&self) -> Result<Option<StoredWallet>, StoreError>;
+}
+
+/// File-backed `StateStore`.
+///
+/// The wallet is stored as a JSON file. The file contains the secret key of the wallet
+/// in plaintext; on Unix systems, it is created with permissions restricting access
+/// to the owner. Writes are atomic: the wallet is written to a temporary file, which
+/// then replaces the original file.
+///
+/// # Locking
+///
+/// Two processes working with the same wallet would create transfers referencing the same
+/// history length, at most one of which can be committed. To prevent this, a store can be
+/// opened with an advisory lock using [`locked()`]. The lock is a file next to the wallet
+/// file with the `lock` extension, which contains the identifier of the owning process.
+/// The lock is shared among clones of the store and is released when the last clone
+/// is dropped. Stores opened with [`new()`] do not check the lock.
+///
+/// If the owning process is killed, the lock file remains in place and needs to be
+/// removed manually.
+///
+/// [`locked()`]: #method.locked
+/// [`new()`]: #method.new
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    path: PathBuf,
+    lock: Option<Arc<FileLock>>,
+}
+
+/// Lock file of a `FileStore`, which is removed on drop.
+#[derive(Debug)]
+struct FileLock {
+    path: PathBuf,
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // The lock may have been removed manually; there is nothing to do in this case.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl FileStore {
+    /// Creates a store with the specified file path.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FileStore {
+            path: path.as_ref().to_owned(),
+            lock: None,
+        }
+    }
+
+    /// Creates a store with the specified file path and acquires an advisory lock on it.
+    /// Fails with [`StoreError::Locked`] if the store is locked by another process
+    /// (or another store within the same process).
+    ///
+    /// [`StoreError::Locked`]: enum.StoreError.html#variant.Locked
+    pub fn locked<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_owned();
+        let lock_path = path.with_extension("lock");
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let pid = fs::read_to_string(&lock_path)
+                    .ok()
+                    .and_then(|contents| contents.trim().parse().ok());
+                return Err(StoreError::Locked { pid });
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let lock = FileLock { path: lock_path };
+        writeln!(file, "{}", process::id())?;
+        Ok(FileStore {
+            path,
+            lock: Some(Arc::new(lock)),
+        })
+    }
+
+    /// Returns the path to the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks whether the store holds an advisory lock.
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_some()
+    }
+
+    #[cfg(unix)]
+    fn create_file(path: &Path) -> io::Result<File> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+    }
+
+    #[cfg(not(unix))]
+    fn create_file(path: &Path) -> io::Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    }
+}
+
+impl StateStore for FileStore {
+    fn save(&self, wallet: &StoredWallet) -> Result<(), StoreError> {
+        wallet.check()?;
+
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(Self::create_file(&tmp_path)?);
+            serde_json::to_writer(&mut writer, wallet)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Option<StoredWallet>, StoreError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let wallet: StoredWallet = serde_json::from_reader(BufReader::new(file))?;
+        wallet.check()?;
+        Ok(Some(wallet))
+    }
+}
+
+#[test]
+fn file_store_roundtrip() {
+    use exonum::crypto::gen_keypair;
+    use tempdir::TempDir;
+
+    let dir = TempDir::new("private_currency").unwrap();
+    let store = FileStore::new(dir.path().join("wallet.json"));
+    assert!(store.load().unwrap().is_none());
+
+    let mut state = SecretState::with_random_keypair();
+    let create_wallet = state.create_wallet();
+    state.initialize();
+    let (receiver, _) = gen_keypair();
+    state.create_transfer(1_000, &receiver, 10);
+    let mut wallet = StoredWallet::new(state);
+
+    match store.save(&wallet) {
+        Err(StoreError::Inconsistent) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    wallet.events.push(FullEvent::CreateWallet(create_wallet));
+    store.save(&wallet).unwrap();
+
+    let loaded = store.load().unwrap().unwrap();
+    assert_eq!(loaded.events, wallet.events);
+    assert_eq!(loaded.state.public_key(), wallet.state.public_key());
+    assert_eq!(loaded.state.history_len(), 1);
+    assert!(loaded.state.corresponds_to(&wallet.state.to_public()));
+    assert_eq!(loaded.state.ledger(), wallet.state.ledger());
+    assert_eq!(
+        loaded.state.pending_transfers().collect::<Vec<_>>(),
+        wallet.state.pending_transfers().collect::<Vec<_>>()
+    );
+    assert!(loaded.unconfirmed.is_empty());
+}
+
+#[test]
+fn file_store_unconfirmed_transactions() {
+    use exonum::crypto::gen_keypair;
+    use tempdir::TempDir;
+
+    let dir = TempDir::new("private_currency").unwrap();
+    let store = FileStore::new(dir.path().join("wallet.json"));
+
+    let mut state = SecretState::with_random_keypair();
+    let create_wallet = state.create_wallet();
+    state.initialize();
+    let (receiver, _) = gen_keypair();
+    let transfer = state.create_transfer(1_000, &receiver, 10);
+    let mut wallet = StoredWallet::new(state);
+    wallet.events.push(FullEvent::CreateWallet(create_wallet));
+
+    let tx = CryptoTransactions::Transfer(transfer.clone());
+    assert!(wallet.track(&tx));
+    assert!(!wallet.track(&tx));
+    store.save(&wallet).unwrap();
+
+    let loaded = store.load().unwrap().unwrap();
+    assert_eq!(loaded.unconfirmed, wallet.unconfirmed);
+    assert_eq!(loaded.unconfirmed[0].hash, transfer.hash());
+    let restored = loaded.unconfirmed[0].transaction().unwrap();
+    assert_eq!(restored.hash(), transfer.hash());
+}
+
+#[test]
+fn file_store_locking() {
+    use tempdir::TempDir;
+
+    let dir = TempDir::new("private_currency").unwrap();
+    let path = dir.path().join("wallet.json");
+    let store = FileStore::locked(&path).unwrap();
+    assert!(store.is_locked());
+    assert!(!FileStore::new(&path).is_locked());
+
+    match FileStore::locked(&path) {
+        Err(StoreError::Locked { pid }) => assert_eq!(pid, Some(process::id())),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // The lock is held until the last clone of the store is dropped.
+    let clone = store.clone();
+    drop(store);
+    assert!(FileStore::locked(&path).is_err());
+    drop(clone);
+    assert!(!path.with_extension("lock").exists());
+    assert!(FileStore::locked(&path).unwrap().is_locked());
+}
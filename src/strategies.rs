@@ -0,0 +1,189 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! [`proptest`] strategies generating transfers and interleavings of service transactions.
+//!
+//! Strategies in this module produce two kinds of values:
+//!
+//! - Self-contained values, such as [wallets](fn.wallet.html) and [transfers](fn.transfer.html)
+//!   signed by them, which are useful to test client-side logic.
+//! - [Scenarios](fn.scenario.html), i.e., sequences of [`Action`]s on a set of wallets.
+//!   A scenario is executed by a driver, e.g., on top of a testkit; the driver creates
+//!   transfers and `Accept` transactions according to the specs in the scenario. See
+//!   `tests/properties.rs` in the crate repository for a driver checking service invariants.
+//!
+//! Transfer amounts and rollback delays are bounded in order to keep scenarios short;
+//! rollback delays are taken from the start of the acceptable range, so that rollbacks
+//! happen within a few blocks.
+//!
+//! This module is available with the `proptest` crate feature.
+//!
+//! [`proptest`]: https://docs.rs/proptest/0.8/proptest/
+//! [`Action`]: enum.Action.html
+
+use exonum::{
+    crypto::SEED_LENGTH,
+    messages::{Message, MessageBuffer},
+};
+use proptest::{collection, option, prelude::*};
+
+use transactions::Transfer;
+use {Config, SecretState};
+
+/// Maximum number of rollback delays added to the minimum acceptable delay.
+const MAX_EXTRA_DELAY: u32 = 8;
+
+/// Kind of a transfer in a scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransferKind {
+    /// Transfer which should be committed successfully.
+    Valid,
+    /// Transfer to a wallet which is not registered in the blockchain.
+    /// The transfer should fail with `UnregisteredReceiver`.
+    UnregisteredReceiver,
+    /// Pair of transfers created by the sender from the same state, i.e., before the first
+    /// of them is committed. Both transfers should be included into the same block;
+    /// the first transfer should be committed successfully, and the second one should fail
+    /// with `OutdatedHistory`.
+    Stale,
+}
+
+/// Specification of a transfer in a scenario.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransferSpec {
+    /// Index of the sender among the scenario wallets.
+    pub from: usize,
+    /// Index of the receiver among the scenario wallets; always differs from `from`.
+    pub to: usize,
+    /// Transferred amount.
+    pub amount: u64,
+    /// Rollback delay of the transfer.
+    pub rollback_delay: u32,
+    /// Kind of the transfer.
+    pub kind: TransferKind,
+    /// Number of blocks after committing the transfer, after which the receiver submits
+    /// the `Accept` transaction, or `None` if the transfer is never accepted. Acceptance
+    /// after the rollback delay should fail, since the transfer is already rolled back.
+    pub accept_after: Option<u32>,
+}
+
+/// Action in a scenario.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Create a transfer and add it to the block being built.
+    Transfer(TransferSpec),
+    /// Commit the block being built (possibly, an empty one).
+    CommitBlock,
+}
+
+/// Generates an initialized wallet with the specified configuration. The wallet keys
+/// are derived from a generated seed, so the wallet is reproducible.
+pub fn wallet(config: Config) -> impl Strategy<Value = SecretState> {
+    any::<[u8; SEED_LENGTH]>().prop_map(move |seed| {
+        let mut state = SecretState::from_seed(&seed).with_config(config.clone());
+        state.initialize();
+        state
+    })
+}
+
+/// Generates a valid transfer between two initialized wallets with the specified configuration.
+/// The values are the sender state (with the transfer recorded as pending), the receiver state
+/// and the transfer.
+pub fn transfer(config: Config) -> impl Strategy<Value = (SecretState, SecretState, Transfer)> {
+    let amounts = config.min_transfer_amount..=config.initial_balance;
+    let delays = config.rollback_delay_bounds.clone();
+    (wallet(config.clone()), wallet(config), amounts, delays)
+        .prop_filter(
+            "sender and receiver must differ",
+            |(sender, receiver, ..)| sender.public_key() != receiver.public_key(),
+        )
+        .prop_map(|(mut sender, receiver, amount, delay)| {
+            let transfer = sender.create_transfer(amount, receiver.public_key(), delay);
+            (sender, receiver, transfer)
+        })
+}
+
+/// Generates binary messages of transfers from [`transfer()`] with a single corrupted byte.
+/// Corrupted messages should either fail to parse, or produce transfers that do not verify.
+///
+/// [`transfer()`]: fn.transfer.html
+pub fn corrupted_transfer(config: Config) -> impl Strategy<Value = Vec<u8>> {
+    (transfer(config), any::<usize>(), 1..=u8::max_value()).prop_map(
+        |((.., transfer), index, mask)| {
+            let buffer: &MessageBuffer = transfer.raw();
+            let mut bytes = buffer.as_ref().to_vec();
+            let index = index % bytes.len();
+            bytes[index] ^= mask;
+            bytes
+        },
+    )
+}
+
+/// Generates transfer kinds; most of generated transfers are valid.
+pub fn transfer_kind() -> impl Strategy<Value = TransferKind> {
+    prop_oneof![
+        6 => Just(TransferKind::Valid),
+        1 => Just(TransferKind::UnregisteredReceiver),
+        1 => Just(TransferKind::Stale),
+    ]
+}
+
+/// Generates rollback delays close to the minimum acceptable delay.
+pub fn rollback_delay(config: &Config) -> impl Strategy<Value = u32> {
+    let bounds = &config.rollback_delay_bounds;
+    bounds.start..bounds.end.min(bounds.start + MAX_EXTRA_DELAY)
+}
+
+/// Generates transfer specs for a scenario with `wallets` wallets. Amounts do not exceed
+/// 1/8 of the initial balance, so wallets rarely run out of funds.
+///
+/// # Panics
+///
+/// Panics if there are less than 2 wallets.
+pub fn transfer_spec(wallets: usize, config: &Config) -> impl Strategy<Value = TransferSpec> {
+    assert!(wallets >= 2, "transfers require at least 2 wallets");
+    let max_amount = (config.initial_balance / 8).max(config.min_transfer_amount);
+    let max_delay = config.rollback_delay_bounds.start + MAX_EXTRA_DELAY;
+    (
+        0..wallets,
+        1..wallets,
+        config.min_transfer_amount..=max_amount,
+        rollback_delay(config),
+        transfer_kind(),
+        option::of(0..max_delay + 2),
+    )
+        .prop_map(
+            move |(from, offset, amount, rollback_delay, kind, accept_after)| TransferSpec {
+                from,
+                to: (from + offset) % wallets,
+                amount,
+                rollback_delay,
+                kind,
+                accept_after,
+            },
+        )
+}
+
+/// Generates scenarios with `wallets` wallets and at most `max_len` actions.
+pub fn scenario(
+    wallets: usize,
+    max_len: usize,
+    config: &Config,
+) -> impl Strategy<Value = Vec<Action>> {
+    let action = prop_oneof![
+        3 => transfer_spec(wallets, config).prop_map(Action::Transfer).boxed(),
+        1 => Just(Action::CommitBlock).boxed(),
+    ];
+    collection::vec(action, 0..=max_len)
+}
@@ -0,0 +1,147 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for testing services integrating with this one.
+//!
+//! The helpers wrap the boilerplate used in the crate’s own tests: building a [`TestKit`]
+//! with the service, creating funded wallets together with the matching [`SecretState`]s,
+//! keeping secret states in sync with the blockchain, and checking that they correspond
+//! to the wallets stored by the service.
+//!
+//! This module is available with the `test_utils` crate feature.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate private_currency;
+//! use private_currency::test_utils::{assert_wallet_matches, create_testkit, fund_wallets, sync};
+//!
+//! # fn main() {
+//! let mut testkit = create_testkit();
+//! let mut wallets = fund_wallets(&mut testkit, 2);
+//! let transfer = {
+//!     let bob_key = *wallets[1].public_key();
+//!     wallets[0].create_transfer(1_000, &bob_key, 10)
+//! };
+//! testkit.create_block_with_transaction(transfer);
+//! for wallet in &mut wallets {
+//!     sync(&testkit, wallet);
+//!     assert_wallet_matches(&testkit, wallet);
+//! }
+//! # }
+//! ```
+//!
+//! [`TestKit`]: https://docs.rs/exonum-testkit/0.9.2/exonum_testkit/struct.TestKit.html
+//! [`SecretState`]: ::SecretState
+
+use exonum::blockchain::Transaction;
+use exonum_testkit::{TestKit, TestKitBuilder};
+
+use api::FullEvent;
+use storage::Schema;
+use {Config, SecretState, ServiceBuilder, CONFIG};
+
+/// Creates a testkit with a single validator and the service with the default configuration.
+/// Service invariants are checked after each block.
+pub fn create_testkit() -> TestKit {
+    create_testkit_with_config(CONFIG)
+}
+
+/// Creates a testkit with a single validator and the service with the specified configuration.
+/// Service invariants are checked after each block.
+pub fn create_testkit_with_config(config: Config) -> TestKit {
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create()
+}
+
+/// Creates `count` wallets with random keys in a single block and returns their
+/// secret states, initialized with the initial balance.
+///
+/// The secret states use the service configuration stored in the testkit, so wallets
+/// created in a testkit built with [`create_testkit_with_config()`] have matching
+/// configurations.
+///
+/// [`create_testkit_with_config()`]: fn.create_testkit_with_config.html
+pub fn fund_wallets(testkit: &mut TestKit, count: usize) -> Vec<SecretState> {
+    let config = Schema::new(testkit.snapshot()).config();
+    let mut states: Vec<_> = (0..count)
+        .map(|_| SecretState::with_random_keypair().with_config(config.clone()))
+        .collect();
+
+    let transactions = states
+        .iter()
+        .map(|state| Box::new(state.create_wallet()) as Box<dyn Transaction>);
+    let block = testkit.create_block_with_transactions(transactions);
+    for tx in block.iter() {
+        assert!(
+            tx.status().is_ok(),
+            "cannot create wallet: {:?}",
+            tx.status()
+        );
+    }
+
+    for state in &mut states {
+        state.initialize();
+    }
+    states
+}
+
+/// Applies new events from the wallet history stored in the testkit to the secret state.
+/// Returns the number of applied events.
+///
+/// # Panics
+///
+/// Panics if the wallet does not exist, or an event cannot be applied to the state.
+pub fn sync(testkit: &TestKit, state: &mut SecretState) -> u64 {
+    let snapshot = testkit.snapshot();
+    let history_len = Schema::new(&snapshot)
+        .wallet(state.public_key())
+        .expect("wallet does not exist")
+        .history_len();
+
+    let start = state.history_len();
+    for index in start..history_len {
+        let event = FullEvent::at(&snapshot, state.public_key(), index);
+        state
+            .apply_event(index, &event)
+            .unwrap_or_else(|e| panic!("cannot apply event #{}: {}", index, e));
+    }
+    history_len - start
+}
+
+/// Asserts that the secret state corresponds to the wallet stored in the testkit,
+/// i.e., the wallet exists and its balance commitment opens to the balance of the state.
+///
+/// # Panics
+///
+/// Panics if the wallet does not exist or does not correspond to the state.
+pub fn assert_wallet_matches(testkit: &TestKit, state: &SecretState) {
+    let wallet = Schema::new(testkit.snapshot())
+        .wallet(state.public_key())
+        .expect("wallet does not exist");
+    assert_eq!(
+        wallet.history_len(),
+        state.history_len(),
+        "history length mismatch for wallet {:?}",
+        state.public_key()
+    );
+    assert!(
+        state.corresponds_to(&wallet.info()),
+        "balance mismatch for wallet {:?}: expected {}",
+        state.public_key(),
+        state.balance()
+    );
+}
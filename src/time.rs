@@ -0,0 +1,129 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion of wall-clock rollback delays into blocks based on the exonum-time oracle.
+
+use exonum::{
+    blockchain::Schema as CoreSchema,
+    storage::{Entry, Fork, Snapshot},
+};
+use exonum_time::schema::TimeSchema;
+
+/// Name of the entry with the time anchor.
+const TIME_ANCHOR: &str = "private_currency.time_anchor";
+
+/// Block interval assumed until it can be estimated, in milliseconds.
+const DEFAULT_BLOCK_INTERVAL_MS: u64 = 1_000;
+
+encoding_struct! {
+    /// Blockchain height together with the oracle time observed at this height.
+    /// The anchor is used to estimate the average block interval.
+    struct TimeAnchor {
+        height: u64,
+        time_ms: u64,
+    }
+}
+
+fn oracle_time_ms<T: AsRef<dyn Snapshot>>(view: T) -> Option<u64> {
+    let time = TimeSchema::new(view).time().get()?;
+    Some(time.timestamp_millis().max(0) as u64)
+}
+
+/// Records the time anchor once the oracle time becomes available.
+pub(crate) fn record_anchor(fork: &mut Fork) {
+    let height = CoreSchema::new(&*fork).height();
+    if Entry::<_, TimeAnchor>::new(TIME_ANCHOR, &*fork).exists() {
+        return;
+    }
+    if let Some(time_ms) = oracle_time_ms(&*fork) {
+        Entry::new(TIME_ANCHOR, fork).set(TimeAnchor::new(height.0, time_ms));
+    }
+}
+
+/// Estimates the average block interval since the time anchor.
+fn block_interval_ms<T: AsRef<dyn Snapshot>>(view: T) -> u64 {
+    let anchor: Option<TimeAnchor> = Entry::new(TIME_ANCHOR, &view).get();
+    let height = CoreSchema::new(&view).height().0;
+    estimate_interval_ms(anchor.as_ref(), height, oracle_time_ms(&view))
+}
+
+/// Estimates the average block interval from the time anchor, the current height and
+/// the current oracle time. The default interval is used if the anchor or the oracle time
+/// is unavailable, or if the height or the time has not advanced since the anchor.
+fn estimate_interval_ms(anchor: Option<&TimeAnchor>, height: u64, now_ms: Option<u64>) -> u64 {
+    match (anchor, now_ms) {
+        (Some(anchor), Some(now)) if height > anchor.height() && now > anchor.time_ms() => {
+            (now - anchor.time_ms()) / (height - anchor.height())
+        }
+        _ => DEFAULT_BLOCK_INTERVAL_MS,
+    }
+}
+
+/// Converts a delay in seconds into the number of blocks, rounding up.
+pub(crate) fn delay_in_blocks<T: AsRef<dyn Snapshot>>(view: T, seconds: u32) -> u64 {
+    blocks_for_delay(block_interval_ms(&view), seconds)
+}
+
+/// Converts a delay in seconds into the number of blocks with the specified interval,
+/// rounding up. The result is at least one block.
+fn blocks_for_delay(interval_ms: u64, seconds: u32) -> u64 {
+    let interval = interval_ms.max(1);
+    let delay_ms = u64::from(seconds) * 1_000;
+    ((delay_ms + interval - 1) / interval).max(1)
+}
+
+#[test]
+fn block_interval_estimation() {
+    let anchor = TimeAnchor::new(10, 50_000);
+    assert_eq!(estimate_interval_ms(Some(&anchor), 20, Some(75_000)), 2_500);
+    // Partial milliseconds are truncated.
+    assert_eq!(estimate_interval_ms(Some(&anchor), 13, Some(51_000)), 333);
+
+    // The default interval is used without an anchor or oracle time...
+    assert_eq!(
+        estimate_interval_ms(None, 20, Some(75_000)),
+        DEFAULT_BLOCK_INTERVAL_MS
+    );
+    assert_eq!(
+        estimate_interval_ms(Some(&anchor), 20, None),
+        DEFAULT_BLOCK_INTERVAL_MS
+    );
+    // ...and if the oracle time or the height has not moved since the anchor.
+    assert_eq!(
+        estimate_interval_ms(Some(&anchor), 20, Some(50_000)),
+        DEFAULT_BLOCK_INTERVAL_MS
+    );
+    assert_eq!(
+        estimate_interval_ms(Some(&anchor), 20, Some(40_000)),
+        DEFAULT_BLOCK_INTERVAL_MS
+    );
+    assert_eq!(
+        estimate_interval_ms(Some(&anchor), 10, Some(75_000)),
+        DEFAULT_BLOCK_INTERVAL_MS
+    );
+}
+
+#[test]
+fn delay_conversion() {
+    assert_eq!(blocks_for_delay(1_000, 60), 60);
+    // The number of blocks is rounded up.
+    assert_eq!(blocks_for_delay(1_500, 60), 40);
+    assert_eq!(blocks_for_delay(1_501, 60), 40);
+    assert_eq!(blocks_for_delay(7_000, 60), 9);
+    // The delay is at least one block.
+    assert_eq!(blocks_for_delay(1_000, 0), 1);
+    assert_eq!(blocks_for_delay(120_000, 1), 1);
+    // A zero interval is treated as 1 ms.
+    assert_eq!(blocks_for_delay(0, 2), 2_000);
+}
@@ -15,20 +15,67 @@
 //! Transaction logic of the service.
 
 use exonum::{
-    blockchain::{ExecutionError, Transaction},
-    crypto::{Hash, PublicKey},
-    messages::Message,
-    storage::Fork,
+    blockchain::{ExecutionError, Schema as CoreSchema, Transaction, TransactionSet},
+    crypto::{
+        self as exonum_crypto, CryptoHash, Hash, PublicKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH,
+    },
+    encoding::{
+        serialize::{encode_hex, FromHex, FromHexError},
+        Error as EncodingError,
+    },
+    messages::{Message, MessageBuffer, RawMessage, HEADER_LENGTH},
+    storage::{Fork, Snapshot},
 };
+use serde::{Serialize, Serializer};
+use serde_json;
 
-use super::{CONFIG, SERVICE_ID};
-use crypto::{Commitment, SimpleRangeProof};
+use std::time::Instant;
+
+use super::{Config, SERVICE_ID};
+use crypto::{AmountProof, Commitment, EqualityProof, SimpleRangeProof};
+use metrics;
 use secrets::EncryptedData;
-use storage::{maybe_transfer, Schema};
+use storage::{maybe_transfer, Event, Schema, Wallet};
+use verification::{self, AmountBounds, Verifier};
+
+/// Latest version of the wire format of transactions supported by this crate.
+///
+/// The wire version determines which transaction types are accepted by the service.
+/// New transaction types are introduced with a new wire version; they are rejected
+/// until the [`wire_version`] in the service configuration is raised. Thus, validators
+/// can be upgraded one by one while the network continues to work, and new transaction
+/// types are activated once all validators are upgraded.
+///
+/// A wire version can also activate new tables committed to by the service
+/// [state hash](::Schema::state_hash()), which would otherwise diverge from the state hash
+/// computed by validators running older versions of the crate. Wire version 5 does not
+/// introduce new transaction types; it activates settlement [receipts](::Schema::receipts())
+/// and history checkpoints.
+///
+/// Transactions of all types carry the public key of their author as the first field,
+/// and are signed with the corresponding secret key. New transaction types must follow
+/// this convention, since nodes running older versions of the crate rely on it to check
+/// signatures of [unknown transactions](self::UnknownTransaction).
+///
+/// [`wire_version`]: ::Config::wire_version
+pub const WIRE_VERSION: u16 = 5;
 
-lazy_static! {
-    static ref MIN_TRANSFER_COMMITMENT: Commitment =
-        Commitment::with_no_blinding(CONFIG.min_transfer_amount);
+/// Returns the wire version in which a transaction with the specified message type
+/// has been introduced, or `None` if the message type is unknown.
+pub fn message_wire_version(message_type: u16) -> Option<u16> {
+    match message_type {
+        // `CreateWallet`, `Transfer` and `Accept`.
+        0..=2 => Some(1),
+        // `LockForBridge` and `ReleaseFromBridge`.
+        3..=4 => Some(2),
+        // `AcceptWithNote`, `UpdateSettings`, `OpenDispute`, `ResolveDispute`,
+        // `StreamTransfer`, `CloseStream`, `Lock`, `Unlock`, `SetProfile`,
+        // `RequestTransfer` and `Rerandomize`.
+        5..=15 => Some(3),
+        // `BackUpTransfer`.
+        16 => Some(4),
+        _ => None,
+    }
 }
 
 transactions! {
@@ -83,8 +130,11 @@ transactions! {
             /// Commitment to the transferred amount.
             amount: Commitment,
 
-            /// Proof that `amount` is positive.
-            amount_proof: SimpleRangeProof,
+            /// Proof that `amount` is not less than the minimum transfer amount and,
+            /// if the [maximum transfer amount] is set, does not exceed it.
+            ///
+            /// [maximum transfer amount]: ::Config::max_transfer_amount
+            amount_proof: AmountProof,
 
             /// Proof that the sender’s balance is sufficient relative to `amount`.
             sufficient_balance_proof: SimpleRangeProof,
@@ -100,7 +150,441 @@ transactions! {
             /// Hash of the transfer transaction.
             transfer_id: &Hash,
         }
+
+        /// Locks funds in the sender’s wallet, so that they can be released in another
+        /// deployment of the service. See the [`bridge`](::bridge) module for details.
+        ///
+        /// The fields have the same meaning as in [`Transfer`]; the funds are subtracted from
+        /// the sender’s balance immediately and are never rolled back.
+        ///
+        /// Introduced in wire version 2.
+        ///
+        /// [`Transfer`]: struct.Transfer.html
+        struct LockForBridge {
+            /// Ed25519 public key of the sender. The transaction must be signed with the
+            /// corresponding secret key.
+            from: &PublicKey,
+
+            /// Ed25519 public key of the receiver in the destination deployment.
+            to: &PublicKey,
+
+            /// [Network identifier](::BridgeConfig::network_id) of the destination deployment.
+            destination: &Hash,
+
+            /// Length of the wallet history as perceived by the wallet sender.
+            history_len: u64,
+
+            /// Commitment to the locked amount.
+            amount: Commitment,
+
+            /// Proof that `amount` is not less than the minimum transfer amount.
+            amount_proof: SimpleRangeProof,
+
+            /// Proof that the sender’s balance is sufficient relative to `amount`.
+            sufficient_balance_proof: SimpleRangeProof,
+
+            /// Encryption of the opening for `amount`.
+            encrypted_data: EncryptedData,
+        }
+
+        /// Vote of a bridge relayer to release funds locked in another deployment
+        /// of the service. Once the number of votes for a lock reaches the [threshold],
+        /// the locked amount is credited to the receiver of the lock.
+        ///
+        /// Introduced in wire version 2.
+        ///
+        /// [threshold]: ::BridgeConfig::threshold
+        struct ReleaseFromBridge {
+            /// Ed25519 public key of the relayer. The transaction must be signed with the
+            /// corresponding secret key.
+            relayer: &PublicKey,
+
+            /// Binary message of the [`LockForBridge`] transaction committed in the source
+            /// deployment.
+            ///
+            /// [`LockForBridge`]: struct.LockForBridge.html
+            lock: &[u8],
+        }
+
+        /// Transaction to accept an incoming transfer, carrying an acknowledgment
+        /// for the sender of the transfer. The acknowledgment (e.g., a receipt note
+        /// or a reference to an invoice) is encrypted to the sender and is recorded
+        /// in the sender’s history; it does not affect the sender’s balance.
+        ///
+        /// Apart from the acknowledgment, the transaction is equivalent to [`Accept`].
+        ///
+        /// Introduced in wire version 3.
+        ///
+        /// [`Accept`]: struct.Accept.html
+        struct AcceptWithNote {
+            /// Public key of the receiver of the transfer.
+            receiver: &PublicKey,
+            /// Hash of the transfer transaction.
+            transfer_id: &Hash,
+            /// Acknowledgment encrypted to the sender of the transfer.
+            note: EncryptedData,
+        }
+
+        /// Transaction updating settings of a wallet.
+        ///
+        /// Introduced in wire version 3.
+        struct UpdateSettings {
+            /// Ed25519 public key of the wallet. The transaction must be signed with the
+            /// corresponding secret key.
+            key: &PublicKey,
+
+            /// Minimum rollback delay of incoming transfers preferred by the wallet owner.
+            /// Transfers to the wallet with a lesser [`rollback_delay`] are rejected,
+            /// so that the owner has enough time to accept transfers even if it connects
+            /// to the network infrequently. Zero means no preference.
+            ///
+            /// The delay is measured in the same units as [`rollback_delay`], and must be less
+            /// than the upper bound of [`rollback_delay_bounds`] in the service configuration.
+            ///
+            /// [`rollback_delay`]: struct.Transfer.html#method.rollback_delay
+            /// [`rollback_delay_bounds`]: ::Config::rollback_delay_bounds
+            min_rollback_delay: u32,
+
+            /// Arbitrary number making the transaction unique, so that settings can be
+            /// changed back to a previously used value.
+            seed: u64,
+        }
+
+        /// Dispute of an accepted transfer by its sender. The transferred amount is moved
+        /// from the receiver’s balance into the disputed escrow until the dispute is resolved
+        /// by the arbiter. See the [`dispute`](::dispute) module for details.
+        ///
+        /// Introduced in wire version 3.
+        struct OpenDispute {
+            /// Ed25519 public key of the sender of the disputed transfer. The transaction
+            /// must be signed with the corresponding secret key.
+            sender: &PublicKey,
+
+            /// Binary message of the disputed [`Transfer`].
+            ///
+            /// [`Transfer`]: struct.Transfer.html
+            transfer: &[u8],
+
+            /// Arbitrary number making the transaction unique, so that a dispute rejected
+            /// because of a transient error (e.g., the transfer is not accepted yet)
+            /// can be resubmitted.
+            seed: u64,
+        }
+
+        /// Resolution of a dispute by the [arbiter]. The escrowed amount is credited
+        /// to the sender of the disputed transfer if `refund` is set, or to the receiver
+        /// otherwise.
+        ///
+        /// Introduced in wire version 3.
+        ///
+        /// [arbiter]: ::DisputeConfig::arbiter
+        struct ResolveDispute {
+            /// Ed25519 public key of the arbiter. The transaction must be signed with the
+            /// corresponding secret key.
+            arbiter: &PublicKey,
+
+            /// Binary message of the resolved [`OpenDispute`] transaction.
+            ///
+            /// [`OpenDispute`]: struct.OpenDispute.html
+            dispute: &[u8],
+
+            /// Whether the escrowed amount is refunded to the sender of the disputed transfer.
+            refund: bool,
+        }
+
+        /// Stream of payments from one wallet to another wallet, paid out once per block.
+        ///
+        /// The total amount of the stream, `rate * duration`, is subtracted from the sender’s
+        /// balance immediately. Afterwards, `rate` is credited to the receiver’s balance
+        /// in each block, starting from the block containing the stream, until `duration`
+        /// payments are made. Payments are made in the same `before_commit` hook as rollbacks
+        /// of transfers and do not require actions from the receiver. Either party may stop
+        /// the stream early with a [`CloseStream`] transaction.
+        ///
+        /// The other fields have the same meaning as in [`Transfer`].
+        ///
+        /// Introduced in wire version 3.
+        ///
+        /// [`CloseStream`]: struct.CloseStream.html
+        /// [`Transfer`]: struct.Transfer.html
+        struct StreamTransfer {
+            /// Ed25519 public key of the sender. The transaction must be signed with the
+            /// corresponding secret key.
+            from: &PublicKey,
+
+            /// Ed25519 public key of the receiver.
+            to: &PublicKey,
+
+            /// Number of payments, i.e., blocks during which the stream is paid out.
+            duration: u32,
+
+            /// Length of the wallet history as perceived by the wallet sender.
+            history_len: u64,
+
+            /// Commitment to the amount paid in each block.
+            rate: Commitment,
+
+            /// Proof that `rate` is not less than the minimum transfer amount.
+            rate_proof: SimpleRangeProof,
+
+            /// Proof that the sender’s balance is sufficient relative to the total amount
+            /// of the stream, `rate * duration`.
+            sufficient_balance_proof: SimpleRangeProof,
+
+            /// Encryption of the opening for `rate`.
+            encrypted_data: EncryptedData,
+        }
+
+        /// Early closure of a payment stream by its sender or receiver. The stream is
+        /// shortened to `duration` payments, and the amount of the cancelled payments
+        /// is refunded to the sender immediately. A stream can be closed only once.
+        ///
+        /// Introduced in wire version 3.
+        struct CloseStream {
+            /// Ed25519 public key of the sender or the receiver of the stream. The transaction
+            /// must be signed with the corresponding secret key.
+            author: &PublicKey,
+
+            /// Binary message of the closed [`StreamTransfer`].
+            ///
+            /// [`StreamTransfer`]: struct.StreamTransfer.html
+            stream: &[u8],
+
+            /// New number of payments in the stream. Must be less than the current number,
+            /// and not less than the number of payments already made; the latter can be
+            /// obtained with [`Schema::stream_status()`].
+            ///
+            /// [`Schema::stream_status()`]: ::storage::Schema::stream_status()
+            duration: u32,
+        }
+
+        /// Locks funds in the owner’s wallet until the specified height.
+        ///
+        /// The locked amount is moved from the wallet balance to the separate
+        /// [locked balance](::storage::Wallet::locked_balance()), so that it cannot be spent.
+        /// Once the unlock height is reached, the owner may return the funds to the balance
+        /// with an [`Unlock`] transaction.
+        ///
+        /// The other fields have the same meaning as in [`Transfer`]. The opening
+        /// for the amount is encrypted to the owner.
+        ///
+        /// Introduced in wire version 3.
+        ///
+        /// [`Unlock`]: struct.Unlock.html
+        /// [`Transfer`]: struct.Transfer.html
+        struct Lock {
+            /// Ed25519 public key of the wallet owner. The transaction must be signed with
+            /// the corresponding secret key.
+            owner: &PublicKey,
+
+            /// Blockchain height starting from which the funds can be unlocked.
+            unlock_height: u64,
+
+            /// Length of the wallet history as perceived by the wallet owner.
+            history_len: u64,
+
+            /// Commitment to the locked amount.
+            amount: Commitment,
+
+            /// Proof that `amount` is not less than the minimum transfer amount.
+            amount_proof: SimpleRangeProof,
+
+            /// Proof that the owner’s liquid balance remains non-negative after the lock.
+            sufficient_balance_proof: SimpleRangeProof,
+
+            /// Encryption of the opening for `amount`.
+            encrypted_data: EncryptedData,
+        }
+
+        /// Returns funds locked by a [`Lock`] transaction to the owner’s balance.
+        ///
+        /// Introduced in wire version 3.
+        ///
+        /// [`Lock`]: struct.Lock.html
+        struct Unlock {
+            /// Ed25519 public key of the wallet owner. The transaction must be signed with
+            /// the corresponding secret key.
+            owner: &PublicKey,
+
+            /// Binary message of the [`Lock`] transaction.
+            ///
+            /// [`Lock`]: struct.Lock.html
+            lock: &[u8],
+
+            /// Arbitrary number making the transaction unique, so that an unlock rejected
+            /// because the unlock height has not been reached can be resubmitted.
+            seed: u64,
+        }
+
+        /// Transaction setting the profile of a wallet, i.e., small metadata such as
+        /// the display name or contact information of the owner. The profile replaces
+        /// the previously set one; it is encrypted by the owner to itself, so that
+        /// it can be read by the owner and holders of the wallet [`ViewKey`].
+        ///
+        /// The encrypted profile must not exceed [`MAX_PROFILE_SIZE`] bytes.
+        ///
+        /// Introduced in wire version 3.
+        ///
+        /// [`ViewKey`]: ::ViewKey
+        /// [`MAX_PROFILE_SIZE`]: constant.MAX_PROFILE_SIZE.html
+        struct SetProfile {
+            /// Ed25519 public key of the wallet. The transaction must be signed with the
+            /// corresponding secret key.
+            key: &PublicKey,
+
+            /// Profile encrypted by the wallet owner to itself.
+            profile: EncryptedData,
+
+            /// Arbitrary number making the transaction unique, so that the profile can be
+            /// changed back to a previously used value.
+            seed: u64,
+        }
+
+        /// Request of a transfer from the `payer` to the author of the request.
+        /// The request is recorded in the payer’s history; it does not change balances.
+        ///
+        /// The payer answers the request with a regular [`Transfer`] to the requester,
+        /// which uses `amount` as its amount commitment (i.e., the payer reuses the opening
+        /// encrypted in the request). Such a transfer is linked to the request, and
+        /// the request is no longer pending; if the transfer is rolled back, the request
+        /// becomes pending again. The status of a request can be obtained with
+        /// [`Schema::transfer_request_payment()`] and [`Schema::is_transfer_request_pending()`].
+        ///
+        /// Introduced in wire version 3.
+        ///
+        /// [`Transfer`]: struct.Transfer.html
+        /// [`Schema::transfer_request_payment()`]: ::storage::Schema::transfer_request_payment()
+        /// [`Schema::is_transfer_request_pending()`]: ::storage::Schema::is_transfer_request_pending()
+        struct RequestTransfer {
+            /// Ed25519 public key of the requester, i.e., the receiver of the requested
+            /// transfer. The transaction must be signed with the corresponding secret key.
+            requester: &PublicKey,
+
+            /// Ed25519 public key of the wallet asked to pay.
+            payer: &PublicKey,
+
+            /// Commitment to the requested amount.
+            amount: Commitment,
+
+            /// Encryption of the opening for `amount` to the payer.
+            encrypted_data: EncryptedData,
+
+            /// Arbitrary number making the transaction unique, so that the same amount
+            /// can be requested again.
+            seed: u64,
+        }
+
+        /// Replaces the balance commitment of the wallet with a fresh commitment
+        /// to the same value.
+        ///
+        /// A balance commitment changes only by adding and subtracting amount commitments
+        /// of public operations, so a long-lived commitment accumulates linkable structure.
+        /// Rerandomization adds a commitment to zero (`blinding`) to the balance, so that
+        /// the balance commitment is no longer a combination of amount commitments alone.
+        /// The equality proof shows that `blinding` commits to zero; it does not depend
+        /// on the current balance, so the transaction stays valid if the balance changes
+        /// before the transaction is committed.
+        ///
+        /// Rerandomization is recorded in the wallet history, and the blinding is encrypted
+        /// by the owner to itself, so that the balance opening can be restored from
+        /// the history.
+        ///
+        /// Introduced in wire version 3.
+        struct Rerandomize {
+            /// Ed25519 public key of the wallet owner. The transaction must be signed with
+            /// the corresponding secret key.
+            owner: &PublicKey,
+
+            /// Commitment to zero added to the balance commitment.
+            blinding: Commitment,
+
+            /// Serialized [`EqualityProof`] that the balance commitment before
+            /// and after adding `blinding` commit to the same value.
+            ///
+            /// [`EqualityProof`]: ::crypto::EqualityProof
+            equality_proof: &[u8],
+
+            /// Encryption of the opening for `blinding` by the owner to itself.
+            encrypted_data: EncryptedData,
+
+            /// Arbitrary number making the transaction unique.
+            seed: u64,
+        }
+
+        /// Publishes a backup copy of the opening for the amount of an outgoing transfer.
+        ///
+        /// The opening is encrypted for the holder of the [backup key] of the sender,
+        /// who can restore the amount from the blockchain even if the sender’s state
+        /// is lost. The backup is a separate transaction, so the encrypted data
+        /// of the transfer itself keeps its format. At most one backup can be published
+        /// for each transfer. Nodes do not interpret the encrypted data, so they cannot
+        /// check that the backup corresponds to the amount of the transfer; the holder
+        /// checks this on decryption.
+        ///
+        /// Introduced in wire version 4.
+        ///
+        /// [backup key]: ::SecretState::set_backup_key()
+        struct BackUpTransfer {
+            /// Ed25519 public key of the sender of the transfer. The transaction must be
+            /// signed with the corresponding secret key.
+            from: &PublicKey,
+
+            /// Ed25519 public key of the holder of the backup key.
+            holder: &PublicKey,
+
+            /// Hash of the backed up transfer.
+            transfer_id: &Hash,
+
+            /// Encryption of the opening for the transfer amount by the sender
+            /// to the holder.
+            encrypted_data: EncryptedData,
+
+            /// Arbitrary number making the transaction unique.
+            seed: u64,
+        }
+    }
+}
+
+/// Maximum size of the encrypted data in a [`SetProfile`] transaction, in bytes.
+/// Takes into account the authentication tag added by encryption, so the plaintext
+/// profile can be at most 16 bytes shorter.
+///
+/// [`SetProfile`]: struct.SetProfile.html
+pub const MAX_PROFILE_SIZE: usize = 512;
+
+/// Checks that transactions introduced in the specified wire version are activated
+/// by the service configuration.
+fn check_wire_version(config: &Config, wire_version: u16) -> Result<(), Error> {
+    if config.wire_version >= wire_version {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedTransaction)
+    }
+}
+
+/// Checks that an outgoing payment (a transfer or a bridge lock) by `sender` does not
+/// spend funds already spent by another payment. Returns the sender’s balance referenced
+/// by the payment.
+fn referenced_balance(
+    snapshot: &dyn Snapshot,
+    sender: &Wallet,
+    history_len: u64,
+) -> Result<Commitment, Error> {
+    if sender.last_send_index() + 1 > history_len {
+        return Err(Error::OutdatedHistory);
+    }
+    Schema::new(snapshot)
+        .past_balance(sender.public_key(), history_len - 1)
+        .ok_or(Error::InvalidHistoryRef)
+}
+
+/// Decodes a transaction of the service embedded into another transaction.
+fn embedded_transaction<M: Message>(bytes: &[u8]) -> Option<M> {
+    let raw = RawMessage::new(MessageBuffer::from_vec(bytes.to_vec()));
+    if raw.service_id() != SERVICE_ID {
+        return None;
     }
+    M::from_raw(raw).ok()
 }
 
 impl Transaction for CreateWallet {
@@ -116,109 +600,616 @@ impl Transaction for CreateWallet {
 }
 
 impl Transfer {
-    /// Performs stateless verification of the transfer operation.
-    pub(crate) fn verify_stateless(&self) -> bool {
-        self.amount_proof()
-            .verify(&(&self.amount() - &MIN_TRANSFER_COMMITMENT))
+    /// Verifies the transfer against the service configuration: checks the rollback delay
+    /// and the proof that the transferred amount is within the bounds set by the configuration.
+    pub(crate) fn verify_with_config(&self, config: &Config) -> Result<(), Error> {
+        self.verify_with_config_cached(config, None)
+    }
+
+    /// Same as `verify_with_config()`, but consults memoized results of the `verifier`
+    /// (if any) before verifying the amount proof.
+    fn verify_with_config_cached(
+        &self,
+        config: &Config,
+        verifier: Option<&Verifier>,
+    ) -> Result<(), Error> {
+        if config.rollback_delay_bounds.start > self.rollback_delay()
+            || config.rollback_delay_bounds.end <= self.rollback_delay()
+        {
+            return Err(Error::InvalidRollbackDelay);
+        }
+
+        let bounds = AmountBounds::new(config);
+        // The proof may have been verified when the transaction has entered the node.
+        let is_valid = match verifier {
+            Some(verifier) => verifier.verify_amount(self, bounds),
+            None => verification::verify_amount(self, bounds),
+        };
+        if is_valid {
+            Ok(())
+        } else {
+            Err(Error::IncorrectAmountProof)
+        }
+    }
+
+    /// Returns the commitment to the sender’s balance remaining after the transfer, given
+    /// the commitment to the sender’s balance referenced by [`history_len`]. If the sender
+    /// is aware of its entire history, this is the balance of the sender’s wallet
+    /// after the transfer is executed.
+    ///
+    /// The commitment is deliberately not included into the transfer as a separate field,
+    /// since it is fully determined by the referenced balance and the amount; see
+    /// the implementation notes for details.
+    ///
+    /// [`history_len`]: #method.history_len
+    pub fn remaining_balance(&self, referenced_balance: &Commitment) -> Commitment {
+        referenced_balance - &self.amount()
     }
 
     pub(crate) fn verify_stateful(&self, balance: &Commitment) -> bool {
-        let remaining_balance = balance - &self.amount();
-        self.sufficient_balance_proof().verify(&remaining_balance)
+        self.sufficient_balance_proof()
+            .verify(&self.remaining_balance(balance))
     }
 }
 
-impl Transaction for Transfer {
-    fn verify(&self) -> bool {
-        if CONFIG.rollback_delay_bounds.start > self.rollback_delay()
-            || CONFIG.rollback_delay_bounds.end <= self.rollback_delay()
-        {
-            return false;
-        }
-        self.history_len() > 0
-            && self.from() != self.to()
-            && self.verify_signature(self.from())
-            && self.verify_stateless()
-    }
+/// Verifies a proof in a transaction, recording the verification latency.
+fn time_proof<T, F: FnOnce() -> T>(verify: F) -> T {
+    let start = Instant::now();
+    let result = verify();
+    metrics::observe_proof_verification(start.elapsed());
+    result
+}
 
-    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
-        let (sender, receiver) = {
+impl Transfer {
+    /// Executes the transfer, consulting memoized results of the `verifier` (if any)
+    /// when verifying the amount proof.
+    pub(crate) fn execute_with(
+        &self,
+        fork: &mut Fork,
+        verifier: Option<&Verifier>,
+    ) -> Result<(), ExecutionError> {
+        let (sender, receiver, config) = {
             let schema = Schema::new(fork.as_ref());
-            (schema.wallet(self.from()), schema.wallet(self.to()))
+            (
+                schema.wallet(self.from()),
+                schema.wallet(self.to()),
+                schema.config(),
+            )
         };
+        time_proof(|| self.verify_with_config_cached(&config, verifier))?;
         let sender = sender.ok_or(Error::UnregisteredSender)?;
         let receiver = receiver.ok_or(Error::UnregisteredReceiver)?;
-
-        if sender.last_send_index() + 1 > self.history_len() {
-            Err(Error::OutdatedHistory)?;
+        let min_rollback_delay = Schema::new(fork.as_ref()).min_rollback_delay(self.to());
+        if self.rollback_delay() < min_rollback_delay {
+            Err(Error::RollbackDelayTooShort)?;
         }
-        let past_balance = {
-            let schema = Schema::new(fork.as_ref());
-            schema
-                .past_balance(sender.public_key(), self.history_len() - 1)
-                .ok_or_else(|| {
-                    println!(
-                        "!!! missing ref: {} / {} / len={}",
-                        self.history_len() - 1,
-                        sender.last_send_index(),
-                        sender.history_len()
-                    );
-                    Error::InvalidHistoryRef
-                })?
-        };
-        if !self.verify_stateful(&past_balance) {
+
+        let past_balance = referenced_balance(fork.as_ref(), &sender, self.history_len())?;
+        if !time_proof(|| self.verify_stateful(&past_balance)) {
             Err(Error::IncorrectProof)?;
         }
 
         let mut schema = Schema::new(fork);
-        schema.update_sender(&sender, &self.amount(), self);
+        schema.count_outgoing_transfer(self.from())?;
+        schema.update_sender(&sender, &self.amount(), Event::transfer(&self.hash()));
         schema.add_unaccepted_payment(&receiver, self);
+        schema.link_transfer_request(self);
 
         Ok(())
     }
 }
 
+impl Transaction for Transfer {
+    fn verify(&self) -> bool {
+        // Checks depending on the service configuration are performed during execution,
+        // since the configuration is stored in the blockchain.
+        self.history_len() > 0 && self.from() != self.to() && self.verify_signature(self.from())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        self.execute_with(fork, None)
+    }
+}
+
+/// Accepts the transfer with the specified hash on behalf of `receiver`, recording
+/// the hash of the accepting transaction in settlement receipts. Returns the accepted transfer.
+fn accept_transfer(
+    fork: &mut Fork,
+    receiver: &PublicKey,
+    transfer_id: &Hash,
+    accept_id: &Hash,
+) -> Result<Transfer, Error> {
+    let transfer = maybe_transfer(&fork, transfer_id).ok_or(Error::UnknownTransfer)?;
+    if transfer.to() != receiver {
+        return Err(Error::UnauthorizedAccept);
+    }
+
+    let mut schema = Schema::new(fork);
+    schema.accept_payment(&transfer, transfer_id, accept_id)?;
+    Ok(transfer)
+}
+
 impl Transaction for Accept {
     fn verify(&self) -> bool {
         self.verify_signature(self.receiver())
     }
 
     fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
-        let transfer = maybe_transfer(&fork, self.transfer_id()).ok_or(Error::UnknownTransfer)?;
-        if transfer.to() != self.receiver() {
-            Err(Error::UnauthorizedAccept)?;
-        }
+        accept_transfer(fork, self.receiver(), self.transfer_id(), &self.hash())?;
+        Ok(())
+    }
+}
+
+impl Transaction for AcceptWithNote {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.receiver())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let config = Schema::new(fork.as_ref()).config();
+        check_wire_version(&config, 3)?;
+        let transfer = accept_transfer(fork, self.receiver(), self.transfer_id(), &self.hash())?;
 
         let mut schema = Schema::new(fork);
-        schema.accept_payment(&transfer, self.transfer_id())?;
+        schema.record_acknowledgment(transfer.from(), &self.hash());
         Ok(())
     }
 }
 
-/// Errors that can occur during transaction processing.
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Fail)]
-#[repr(u8)]
-pub enum Error {
-    /// Wallet already exists.
-    ///
-    /// Can occur in [`CreateWallet`](self::CreateWallet).
-    #[fail(display = "wallet already exists")]
-    WalletExists = 0,
+impl Transaction for LockForBridge {
+    fn verify(&self) -> bool {
+        self.history_len() > 0 && self.verify_signature(self.from())
+    }
 
-    /// The sender of a transfer is not registered.
-    ///
-    /// Can occur in [`Transfer`](self::Transfer).
-    #[fail(display = "the sender of a transfer is not registered")]
-    UnregisteredSender = 1,
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let (sender, config) = {
+            let schema = Schema::new(fork.as_ref());
+            (schema.wallet(self.from()), schema.config())
+        };
+        check_wire_version(&config, 2)?;
+        let bridge = config.bridge.as_ref().ok_or(Error::BridgeDisabled)?;
+        if *self.destination() == bridge.network_id {
+            Err(Error::InvalidBridgeLock)?;
+        }
 
-    /// The receiver of a transfer is not registered.
-    ///
-    /// Can occur in [`Transfer`](self::Transfer).
-    #[fail(display = "the receiver of a transfer is not registered")]
-    UnregisteredReceiver = 2,
+        let min_amount = Commitment::with_no_blinding(config.min_transfer_amount);
+        let amount = self.amount();
+        if !time_proof(|| self.amount_proof().verify(&(&amount - &min_amount))) {
+            Err(Error::IncorrectAmountProof)?;
+        }
+        let sender = sender.ok_or(Error::UnregisteredSender)?;
+        let past_balance = referenced_balance(fork.as_ref(), &sender, self.history_len())?;
+        let remaining_balance = &past_balance - &amount;
+        if !time_proof(|| self.sufficient_balance_proof().verify(&remaining_balance)) {
+            Err(Error::IncorrectProof)?;
+        }
 
-    /// The range proof for the sender’s sufficient account balance is incorrect.
-    ///
+        let mut schema = Schema::new(fork);
+        schema.update_sender(&sender, &amount, Event::bridge_lock(&self.hash()));
+        Ok(())
+    }
+}
+
+impl ReleaseFromBridge {
+    /// Decodes the lock released by this transaction. Returns `None` if the lock
+    /// is not a valid `LockForBridge` transaction.
+    pub fn lock_transaction(&self) -> Option<LockForBridge> {
+        embedded_transaction(self.lock())
+    }
+}
+
+impl Transaction for ReleaseFromBridge {
+    fn verify(&self) -> bool {
+        // The signature of the lock is verified as well, so that relayers cannot forge locks.
+        self.verify_signature(self.relayer())
+            && self
+                .lock_transaction()
+                .map_or(false, |lock| lock.verify_signature(lock.from()))
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let config = Schema::new(fork.as_ref()).config();
+        check_wire_version(&config, 2)?;
+        let bridge = config.bridge.as_ref().ok_or(Error::BridgeDisabled)?;
+        if !bridge.relayers.contains(self.relayer()) {
+            Err(Error::UnauthorizedRelayer)?;
+        }
+        let lock = self.lock_transaction().ok_or(Error::InvalidBridgeLock)?;
+        if *lock.destination() != bridge.network_id {
+            Err(Error::InvalidBridgeLock)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.vote_for_release(self, &lock, bridge.threshold)?;
+        Ok(())
+    }
+}
+
+impl Transaction for UpdateSettings {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.key())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let (wallet, config) = {
+            let schema = Schema::new(fork.as_ref());
+            (schema.wallet(self.key()), schema.config())
+        };
+        check_wire_version(&config, 3)?;
+        wallet.ok_or(Error::UnregisteredWallet)?;
+        if self.min_rollback_delay() >= config.rollback_delay_bounds.end {
+            Err(Error::InvalidRollbackDelay)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.set_min_rollback_delay(self.key(), self.min_rollback_delay());
+        Ok(())
+    }
+}
+
+impl OpenDispute {
+    /// Decodes the disputed transfer. Returns `None` if the embedded message
+    /// is not a valid `Transfer` transaction.
+    pub fn transfer_transaction(&self) -> Option<Transfer> {
+        embedded_transaction(self.transfer())
+    }
+}
+
+impl Transaction for OpenDispute {
+    fn verify(&self) -> bool {
+        // The disputed transfer is checked against the committed transfers during execution,
+        // so its signature does not need to be verified.
+        self.transfer_transaction().is_some() && self.verify_signature(self.sender())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let config = Schema::new(fork.as_ref()).config();
+        check_wire_version(&config, 3)?;
+        let dispute = config.dispute.as_ref().ok_or(Error::DisputesDisabled)?;
+        let transfer = self.transfer_transaction().ok_or(Error::InvalidDispute)?;
+        if transfer.from() != self.sender() {
+            Err(Error::UnauthorizedDispute)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.open_dispute(self, &transfer, dispute.window)?;
+        Ok(())
+    }
+}
+
+impl ResolveDispute {
+    /// Decodes the resolved dispute. Returns `None` if the embedded message
+    /// is not a valid `OpenDispute` transaction.
+    pub fn dispute_transaction(&self) -> Option<OpenDispute> {
+        embedded_transaction(self.dispute())
+    }
+
+    /// Decodes the disputed transfer. Returns `None` if the embedded messages
+    /// are invalid.
+    pub fn transfer_transaction(&self) -> Option<Transfer> {
+        self.dispute_transaction()?.transfer_transaction()
+    }
+
+    /// Returns the public key of the wallet credited with the escrowed amount,
+    /// or `None` if the embedded messages are invalid.
+    pub fn credited_wallet(&self) -> Option<PublicKey> {
+        let transfer = self.transfer_transaction()?;
+        Some(if self.refund() {
+            *transfer.from()
+        } else {
+            *transfer.to()
+        })
+    }
+}
+
+impl Transaction for ResolveDispute {
+    fn verify(&self) -> bool {
+        self.transfer_transaction().is_some() && self.verify_signature(self.arbiter())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let config = Schema::new(fork.as_ref()).config();
+        check_wire_version(&config, 3)?;
+        let dispute_config = config.dispute.as_ref().ok_or(Error::DisputesDisabled)?;
+        if *self.arbiter() != dispute_config.arbiter {
+            Err(Error::UnauthorizedArbiter)?;
+        }
+        let dispute = self.dispute_transaction().ok_or(Error::InvalidDispute)?;
+        let transfer = dispute
+            .transfer_transaction()
+            .ok_or(Error::InvalidDispute)?;
+
+        let mut schema = Schema::new(fork);
+        schema.resolve_dispute(self, &dispute, &transfer)?;
+        Ok(())
+    }
+}
+
+impl StreamTransfer {
+    /// Returns the commitment to the total amount of the stream.
+    pub fn total_amount(&self) -> Commitment {
+        &self.rate() * u64::from(self.duration())
+    }
+}
+
+impl Transaction for StreamTransfer {
+    fn verify(&self) -> bool {
+        self.history_len() > 0
+            && self.duration() > 0
+            && self.from() != self.to()
+            && self.verify_signature(self.from())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let (sender, receiver, config) = {
+            let schema = Schema::new(fork.as_ref());
+            (
+                schema.wallet(self.from()),
+                schema.wallet(self.to()),
+                schema.config(),
+            )
+        };
+        check_wire_version(&config, 3)?;
+
+        let min_amount = Commitment::with_no_blinding(config.min_transfer_amount);
+        let rate = self.rate();
+        if !time_proof(|| self.rate_proof().verify(&(&rate - &min_amount))) {
+            Err(Error::IncorrectAmountProof)?;
+        }
+        let sender = sender.ok_or(Error::UnregisteredSender)?;
+        receiver.ok_or(Error::UnregisteredReceiver)?;
+        // The rate is proven to be less than `2^64`, so the total amount is less than `2^96`
+        // and cannot wrap around the group order.
+        let total_amount = self.total_amount();
+        let past_balance = referenced_balance(fork.as_ref(), &sender, self.history_len())?;
+        let remaining_balance = &past_balance - &total_amount;
+        if !time_proof(|| self.sufficient_balance_proof().verify(&remaining_balance)) {
+            Err(Error::IncorrectProof)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.open_stream(&sender, self);
+        Ok(())
+    }
+}
+
+impl CloseStream {
+    /// Decodes the closed stream. Returns `None` if the embedded message
+    /// is not a valid `StreamTransfer` transaction.
+    pub fn stream_transaction(&self) -> Option<StreamTransfer> {
+        embedded_transaction(self.stream())
+    }
+
+    /// Returns the number of cancelled payments of the stream, or `None` if the embedded
+    /// message is invalid or the closure does not shorten the stream.
+    pub fn cancelled_payments(&self) -> Option<u32> {
+        let stream = self.stream_transaction()?;
+        match stream.duration().checked_sub(self.duration())? {
+            0 => None,
+            cancelled => Some(cancelled),
+        }
+    }
+
+    /// Returns the commitment to the amount refunded to the sender of the stream,
+    /// or `None` if the embedded message is invalid or the closure does not shorten
+    /// the stream.
+    pub fn refunded_amount(&self) -> Option<Commitment> {
+        let rate = self.stream_transaction()?.rate();
+        Some(&rate * u64::from(self.cancelled_payments()?))
+    }
+}
+
+impl Transaction for CloseStream {
+    fn verify(&self) -> bool {
+        // The stream is checked against the active streams during execution,
+        // so its signature does not need to be verified.
+        self.stream_transaction().is_some() && self.verify_signature(self.author())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let config = Schema::new(fork.as_ref()).config();
+        check_wire_version(&config, 3)?;
+        let stream = self.stream_transaction().ok_or(Error::UnknownStream)?;
+        if self.author() != stream.from() && self.author() != stream.to() {
+            Err(Error::UnauthorizedStreamClose)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.close_stream(self, &stream)?;
+        Ok(())
+    }
+}
+
+impl Transaction for Lock {
+    fn verify(&self) -> bool {
+        self.history_len() > 0 && self.verify_signature(self.owner())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let (owner, config) = {
+            let schema = Schema::new(fork.as_ref());
+            (schema.wallet(self.owner()), schema.config())
+        };
+        check_wire_version(&config, 3)?;
+
+        let min_amount = Commitment::with_no_blinding(config.min_transfer_amount);
+        let amount = self.amount();
+        if !time_proof(|| self.amount_proof().verify(&(&amount - &min_amount))) {
+            Err(Error::IncorrectAmountProof)?;
+        }
+        let owner = owner.ok_or(Error::UnregisteredWallet)?;
+        let past_balance = referenced_balance(fork.as_ref(), &owner, self.history_len())?;
+        let remaining_balance = &past_balance - &amount;
+        if !time_proof(|| self.sufficient_balance_proof().verify(&remaining_balance)) {
+            Err(Error::IncorrectProof)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.lock_funds(&owner, self);
+        Ok(())
+    }
+}
+
+impl Unlock {
+    /// Decodes the lock released by this transaction. Returns `None` if the embedded message
+    /// is not a valid `Lock` transaction.
+    pub fn lock_transaction(&self) -> Option<Lock> {
+        embedded_transaction(self.lock())
+    }
+}
+
+impl Transaction for Unlock {
+    fn verify(&self) -> bool {
+        // The lock is checked against the active locks during execution,
+        // so its signature does not need to be verified.
+        self.lock_transaction().is_some() && self.verify_signature(self.owner())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let config = Schema::new(fork.as_ref()).config();
+        check_wire_version(&config, 3)?;
+        let lock = self.lock_transaction().ok_or(Error::UnknownLock)?;
+        if lock.owner() != self.owner() {
+            Err(Error::UnauthorizedUnlock)?;
+        }
+        let height = CoreSchema::new(fork.as_ref()).height().next();
+        if height.0 < lock.unlock_height() {
+            Err(Error::LockNotExpired)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.unlock_funds(self, &lock)?;
+        Ok(())
+    }
+}
+
+impl Transaction for SetProfile {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.key())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let (wallet, config) = {
+            let schema = Schema::new(fork.as_ref());
+            (schema.wallet(self.key()), schema.config())
+        };
+        check_wire_version(&config, 3)?;
+        wallet.ok_or(Error::UnregisteredWallet)?;
+        if self.profile().encrypted_data().len() > MAX_PROFILE_SIZE {
+            Err(Error::ProfileTooLarge)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.set_profile(self.key(), self.profile());
+        Ok(())
+    }
+}
+
+impl Transaction for RequestTransfer {
+    fn verify(&self) -> bool {
+        self.requester() != self.payer() && self.verify_signature(self.requester())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let (requester, payer, config) = {
+            let schema = Schema::new(fork.as_ref());
+            (
+                schema.wallet(self.requester()),
+                schema.wallet(self.payer()),
+                schema.config(),
+            )
+        };
+        check_wire_version(&config, 3)?;
+        requester.ok_or(Error::UnregisteredWallet)?;
+        let payer = payer.ok_or(Error::UnregisteredSender)?;
+
+        // Requests are appended to the history of the payer, so they are counted
+        // against the rate limit of the requester in order to bound history spam.
+        let mut schema = Schema::new(fork);
+        schema.count_outgoing_transfer(self.requester())?;
+        schema.add_transfer_request(&payer, self)?;
+        Ok(())
+    }
+}
+
+impl Rerandomize {
+    /// Verifies the equality proof in the transaction, i.e., checks that `blinding`
+    /// is a commitment to zero. The proof depends only on the difference between
+    /// the balance commitments, so it is verified against the zero balance.
+    pub(crate) fn verify_proof(&self) -> bool {
+        let zero = Commitment::with_no_blinding(0);
+        EqualityProof::from_slice(self.equality_proof())
+            .map_or(false, |proof| proof.verify(&zero, &self.blinding()))
+    }
+}
+
+impl Transaction for Rerandomize {
+    fn verify(&self) -> bool {
+        self.equality_proof().len() == EqualityProof::BYTE_LEN
+            && self.verify_signature(self.owner())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let (owner, config) = {
+            let schema = Schema::new(fork.as_ref());
+            (schema.wallet(self.owner()), schema.config())
+        };
+        check_wire_version(&config, 3)?;
+        let owner = owner.ok_or(Error::UnregisteredWallet)?;
+        if !time_proof(|| self.verify_proof()) {
+            Err(Error::IncorrectEqualityProof)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.rerandomize_balance(&owner, self);
+        Ok(())
+    }
+}
+
+impl Transaction for BackUpTransfer {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from())
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        let config = Schema::new(fork.as_ref()).config();
+        check_wire_version(&config, 4)?;
+        let transfer = maybe_transfer(&fork, self.transfer_id()).ok_or(Error::UnknownTransfer)?;
+        if transfer.from() != self.from() {
+            Err(Error::UnauthorizedBackup)?;
+        }
+
+        let mut schema = Schema::new(fork);
+        schema.add_transfer_backup(self.transfer_id(), &self.hash())?;
+        Ok(())
+    }
+}
+
+/// Errors that can occur during transaction processing.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Fail)]
+#[repr(u8)]
+pub enum Error {
+    /// Wallet already exists.
+    ///
+    /// Can occur in [`CreateWallet`](self::CreateWallet).
+    #[fail(display = "wallet already exists")]
+    WalletExists = 0,
+
+    /// The sender of a transfer is not registered.
+    ///
+    /// Can occur in [`Transfer`](self::Transfer) and
+    /// [`RequestTransfer`](self::RequestTransfer) (if the payer is not registered).
+    #[fail(display = "the sender of a transfer is not registered")]
+    UnregisteredSender = 1,
+
+    /// The receiver of a transfer is not registered.
+    ///
+    /// Can occur in [`Transfer`](self::Transfer).
+    #[fail(display = "the receiver of a transfer is not registered")]
+    UnregisteredReceiver = 2,
+
+    /// The range proof for the sender’s sufficient account balance is incorrect.
+    ///
     /// Can occur in [`Transfer`](self::Transfer).
     #[fail(display = "the range proof for the sender’s sufficient account balance is incorrect")]
     IncorrectProof = 3,
@@ -237,21 +1228,226 @@ pub enum Error {
     #[fail(display = "transfer refers to wallet history length exceeding real one")]
     InvalidHistoryRef = 5,
 
-    /// An `Accept` transaction references an unknown transfer.
+    /// A transaction references an unknown transfer.
     ///
-    /// Can occur in [`Accept`](self::Accept).
-    #[fail(display = "an `Accept` transaction references an unknown transfer")]
+    /// Can occur in [`Accept`](self::Accept), [`AcceptWithNote`](self::AcceptWithNote)
+    /// and [`BackUpTransfer`](self::BackUpTransfer).
+    #[fail(display = "a transaction references an unknown transfer")]
     UnknownTransfer = 6,
 
     /// The author of an `Accept` transaction differs from the receiver of the referenced
     /// transfer.
     ///
-    /// Can occur in [`Accept`](self::Accept).
+    /// Can occur in [`Accept`](self::Accept) and [`AcceptWithNote`](self::AcceptWithNote).
     #[fail(
         display = "the author of an `Accept` transaction differs from the receiver \
                    of the referenced transfer"
     )]
     UnauthorizedAccept = 7,
+
+    /// The rollback delay of a transfer is outside of the bounds specified
+    /// by the service configuration.
+    ///
+    /// Can occur in [`Transfer`](self::Transfer) and [`UpdateSettings`](self::UpdateSettings).
+    #[fail(display = "the rollback delay of a transfer is out of bounds")]
+    InvalidRollbackDelay = 8,
+
+    /// The range proof for the transferred amount is incorrect (e.g., the amount is less
+    /// than the minimum or greater than the maximum specified by the service configuration).
+    ///
+    /// Can occur in [`Transfer`](self::Transfer).
+    #[fail(display = "the range proof for the transferred amount is incorrect")]
+    IncorrectAmountProof = 9,
+
+    /// The transaction type is not supported by the node, or is not activated
+    /// by the [wire version](self::WIRE_VERSION) in the service configuration.
+    ///
+    /// Can occur in transactions with unknown message types.
+    #[fail(display = "unsupported transaction type")]
+    UnsupportedTransaction = 10,
+
+    /// The service configuration does not specify a [bridge](::BridgeConfig).
+    ///
+    /// Can occur in [`LockForBridge`](self::LockForBridge) and
+    /// [`ReleaseFromBridge`](self::ReleaseFromBridge).
+    #[fail(display = "bridge to other deployments of the service is disabled")]
+    BridgeDisabled = 11,
+
+    /// The lock is addressed to the deployment it is submitted to, or a released lock
+    /// is not addressed to this deployment.
+    ///
+    /// Can occur in [`LockForBridge`](self::LockForBridge) and
+    /// [`ReleaseFromBridge`](self::ReleaseFromBridge).
+    #[fail(display = "the bridge lock is addressed to a wrong deployment")]
+    InvalidBridgeLock = 12,
+
+    /// The author of a `ReleaseFromBridge` transaction is not a bridge relayer.
+    ///
+    /// Can occur in [`ReleaseFromBridge`](self::ReleaseFromBridge).
+    #[fail(display = "the author of a `ReleaseFromBridge` transaction is not a bridge relayer")]
+    UnauthorizedRelayer = 13,
+
+    /// The relayer has already voted to release the lock.
+    ///
+    /// Can occur in [`ReleaseFromBridge`](self::ReleaseFromBridge).
+    #[fail(display = "the relayer has already voted to release the lock")]
+    DuplicateBridgeVote = 14,
+
+    /// The lock has already been released.
+    ///
+    /// Can occur in [`ReleaseFromBridge`](self::ReleaseFromBridge).
+    #[fail(display = "the bridge lock has already been released")]
+    AlreadyReleased = 15,
+
+    /// The rollback delay of a transfer is less than the minimum delay preferred
+    /// by the receiver.
+    ///
+    /// Can occur in [`Transfer`](self::Transfer).
+    #[fail(display = "the rollback delay of a transfer is less than preferred by the receiver")]
+    RollbackDelayTooShort = 16,
+
+    /// The author of a transaction does not have a wallet.
+    ///
+    /// Can occur in [`UpdateSettings`](self::UpdateSettings), [`Lock`](self::Lock),
+    /// [`SetProfile`](self::SetProfile), [`RequestTransfer`](self::RequestTransfer)
+    /// and [`Rerandomize`](self::Rerandomize).
+    #[fail(display = "the author of a transaction does not have a wallet")]
+    UnregisteredWallet = 17,
+
+    /// The service configuration does not specify a [dispute arbiter](::DisputeConfig).
+    ///
+    /// Can occur in [`OpenDispute`](self::OpenDispute) and
+    /// [`ResolveDispute`](self::ResolveDispute).
+    #[fail(display = "disputes are disabled")]
+    DisputesDisabled = 18,
+
+    /// The author of an `OpenDispute` transaction differs from the sender of the disputed
+    /// transfer.
+    ///
+    /// Can occur in [`OpenDispute`](self::OpenDispute).
+    #[fail(
+        display = "the author of an `OpenDispute` transaction differs from the sender \
+                   of the disputed transfer"
+    )]
+    UnauthorizedDispute = 19,
+
+    /// The disputed transfer has not been accepted, or an embedded transaction is malformed.
+    ///
+    /// Can occur in [`OpenDispute`](self::OpenDispute) and
+    /// [`ResolveDispute`](self::ResolveDispute).
+    #[fail(display = "the disputed transfer is not an accepted transfer")]
+    InvalidDispute = 20,
+
+    /// The dispute window for the transfer has expired.
+    ///
+    /// Can occur in [`OpenDispute`](self::OpenDispute).
+    #[fail(display = "the dispute window for the transfer has expired")]
+    DisputeWindowExpired = 21,
+
+    /// The transfer has already been disputed.
+    ///
+    /// Can occur in [`OpenDispute`](self::OpenDispute).
+    #[fail(display = "the transfer has already been disputed")]
+    AlreadyDisputed = 22,
+
+    /// The author of a `ResolveDispute` transaction is not the dispute arbiter.
+    ///
+    /// Can occur in [`ResolveDispute`](self::ResolveDispute).
+    #[fail(display = "the author of a `ResolveDispute` transaction is not the dispute arbiter")]
+    UnauthorizedArbiter = 23,
+
+    /// The resolved dispute is not open, i.e., it is not committed or has already
+    /// been resolved.
+    ///
+    /// Can occur in [`ResolveDispute`](self::ResolveDispute).
+    #[fail(display = "the resolved dispute is not open")]
+    UnknownDispute = 24,
+
+    /// The closed stream is not active, i.e., it is not committed or all its payments
+    /// have been made.
+    ///
+    /// Can occur in [`CloseStream`](self::CloseStream).
+    #[fail(display = "the closed stream is not active")]
+    UnknownStream = 25,
+
+    /// The author of a `CloseStream` transaction is neither the sender nor the receiver
+    /// of the stream.
+    ///
+    /// Can occur in [`CloseStream`](self::CloseStream).
+    #[fail(display = "the author of a `CloseStream` transaction is not a party of the stream")]
+    UnauthorizedStreamClose = 26,
+
+    /// The new duration of a closed stream is not less than its current duration,
+    /// or is less than the number of payments already made; or the stream has already
+    /// been closed.
+    ///
+    /// Can occur in [`CloseStream`](self::CloseStream).
+    #[fail(display = "invalid duration of the closed stream")]
+    InvalidStreamClose = 27,
+
+    /// The unlocked funds are not locked, i.e., the `Lock` transaction is not committed
+    /// or has already been unlocked.
+    ///
+    /// Can occur in [`Unlock`](self::Unlock).
+    #[fail(display = "the unlocked funds are not locked")]
+    UnknownLock = 28,
+
+    /// The author of an `Unlock` transaction differs from the owner of the lock.
+    ///
+    /// Can occur in [`Unlock`](self::Unlock).
+    #[fail(display = "the author of an `Unlock` transaction differs from the owner of the lock")]
+    UnauthorizedUnlock = 29,
+
+    /// The unlock height of the lock has not been reached yet.
+    ///
+    /// Can occur in [`Unlock`](self::Unlock).
+    #[fail(display = "the unlock height of the lock has not been reached yet")]
+    LockNotExpired = 30,
+
+    /// The encrypted profile is larger than [`MAX_PROFILE_SIZE`](self::MAX_PROFILE_SIZE).
+    ///
+    /// Can occur in [`SetProfile`](self::SetProfile).
+    #[fail(display = "the encrypted profile is too large")]
+    ProfileTooLarge = 31,
+
+    /// A pending transfer request with the same payer, requester and amount commitment
+    /// already exists.
+    ///
+    /// Can occur in [`RequestTransfer`](self::RequestTransfer).
+    #[fail(display = "the same transfer request is already pending")]
+    DuplicateTransferRequest = 32,
+
+    /// The sender has exhausted the limit on outgoing transfers within the current
+    /// window, as specified by the [rate limit](::Config::rate_limit) in the service
+    /// configuration. The transfer can be resubmitted in a later window.
+    ///
+    /// Can occur in [`Transfer`](self::Transfer) and
+    /// [`RequestTransfer`](self::RequestTransfer).
+    #[fail(display = "the sender has exceeded the rate limit on transfers")]
+    RateLimited = 33,
+
+    /// The proof that the rerandomized balance commitment commits to the same value
+    /// is incorrect.
+    ///
+    /// Can occur in [`Rerandomize`](self::Rerandomize).
+    #[fail(display = "incorrect proof of balance equality")]
+    IncorrectEqualityProof = 34,
+
+    /// The author of a `BackUpTransfer` transaction differs from the sender
+    /// of the referenced transfer.
+    ///
+    /// Can occur in [`BackUpTransfer`](self::BackUpTransfer).
+    #[fail(
+        display = "the author of a `BackUpTransfer` transaction differs from the sender \
+                   of the referenced transfer"
+    )]
+    UnauthorizedBackup = 35,
+
+    /// A backup for the referenced transfer has already been published.
+    ///
+    /// Can occur in [`BackUpTransfer`](self::BackUpTransfer).
+    #[fail(display = "a backup for the transfer has already been published")]
+    DuplicateBackup = 36,
 }
 
 impl From<Error> for ExecutionError {
@@ -259,3 +1455,350 @@ impl From<Error> for ExecutionError {
         ExecutionError::new(e as u8)
     }
 }
+
+/// Transaction of the service with a message type unknown to this version of the crate.
+///
+/// Such transactions may be created by nodes running a newer version of the service.
+/// Rather than rejecting them on decoding (which would prevent the node from processing
+/// blocks containing such transactions), the service decodes them into this type.
+/// Execution of an unknown transaction always fails with
+/// [`Error::UnsupportedTransaction`]; newer nodes behave in the same way until
+/// the transaction type is activated via the [wire version](self::WIRE_VERSION).
+///
+/// Since transactions of all types carry the public key of the author as the first field
+/// (see [`WIRE_VERSION`]), an unknown transaction is verified against the key
+/// at this position. Thus, unsigned messages of unknown types are rejected before they
+/// reach the transaction pool.
+///
+/// [`Error::UnsupportedTransaction`]: self::Error::UnsupportedTransaction
+/// [`WIRE_VERSION`]: self::WIRE_VERSION
+#[derive(Debug, Clone)]
+pub struct UnknownTransaction {
+    raw: RawMessage,
+}
+
+impl UnknownTransaction {
+    /// Returns the message type of the transaction.
+    pub fn message_type(&self) -> u16 {
+        self.raw.message_type()
+    }
+
+    /// Returns the public key of the transaction author, which is the first field
+    /// of the message body. Returns `None` if the message is too short to contain the key.
+    pub fn author(&self) -> Option<PublicKey> {
+        let buffer: &MessageBuffer = &self.raw;
+        let bytes: &[u8] = buffer.as_ref();
+        if bytes.len() < HEADER_LENGTH + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH {
+            return None;
+        }
+        PublicKey::from_slice(&bytes[HEADER_LENGTH..HEADER_LENGTH + PUBLIC_KEY_LENGTH])
+    }
+}
+
+impl CryptoHash for UnknownTransaction {
+    fn hash(&self) -> Hash {
+        exonum_crypto::hash(self.raw.as_ref())
+    }
+}
+
+impl Message for UnknownTransaction {
+    fn from_raw(raw: RawMessage) -> Result<Self, EncodingError> {
+        if message_wire_version(raw.message_type()).is_some() {
+            return Err(EncodingError::IncorrectMessageType {
+                message_type: raw.message_type(),
+            });
+        }
+        Ok(UnknownTransaction { raw })
+    }
+
+    fn raw(&self) -> &RawMessage {
+        &self.raw
+    }
+}
+
+/// Unknown transactions are serialized as their message type and the hex-encoded
+/// binary message.
+impl Serialize for UnknownTransaction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Presentation {
+            message_type: u16,
+            message: String,
+        }
+
+        Presentation {
+            message_type: self.message_type(),
+            message: encode_hex(self.raw.as_ref()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Transaction for UnknownTransaction {
+    fn verify(&self) -> bool {
+        self.author()
+            .map_or(false, |author| self.verify_signature(&author))
+    }
+
+    fn execute(&self, _fork: &mut Fork) -> Result<(), ExecutionError> {
+        Err(Error::UnsupportedTransaction)?
+    }
+}
+
+/// Error decoding a transaction from its wire form.
+#[derive(Debug, Fail)]
+pub enum WireError {
+    /// The input is not a valid hex string.
+    #[fail(display = "invalid hex: {}", _0)]
+    Hex(#[cause] FromHexError),
+
+    /// The input is too short to contain a transaction message.
+    #[fail(display = "transaction message is truncated")]
+    Truncated,
+
+    /// The transaction has a message type unknown to this version of the crate; it may
+    /// have been created with a newer version of the service.
+    #[fail(display = "unknown transaction type: {}", _0)]
+    UnknownMessageType(u16),
+
+    /// The input is not a valid transaction of the service.
+    #[fail(display = "invalid transaction: {}", _0)]
+    Encoding(#[cause] EncodingError),
+
+    /// The input is not a valid JSON presentation of a transaction.
+    #[fail(display = "invalid JSON: {}", _0)]
+    Json(#[cause] serde_json::Error),
+}
+
+/// Conversions to and from wire forms of transactions.
+///
+/// Transactions can be created and signed offline with [`SecretState`], serialized
+/// on an air-gapped machine, and later decoded and submitted to the node
+/// by an online machine. Two wire forms are supported:
+///
+/// - JSON, as accepted by the `v1/transaction` endpoint of the service
+///   (see [`Api::transaction`])
+/// - Hex encoding of the binary transaction message, as stored in the blockchain
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate exonum;
+/// # extern crate private_currency;
+/// use exonum::crypto::CryptoHash;
+/// use private_currency::{SecretState, Transactions};
+///
+/// # fn main() {
+/// // On the offline machine:
+/// let state = SecretState::with_random_keypair();
+/// let tx = Transactions::CreateWallet(state.create_wallet());
+/// let hex = tx.to_hex();
+///
+/// // On the online machine:
+/// let tx = Transactions::from_hex(&hex).unwrap();
+/// assert_eq!(tx.hash(), state.create_wallet().hash());
+/// let json = tx.to_json();
+/// // `json` can be sent to the `v1/transaction` endpoint.
+/// # drop(json);
+/// # }
+/// ```
+///
+/// [`SecretState`]: ::SecretState
+/// [`Api::transaction`]: ::api::Api::transaction()
+impl CryptoTransactions {
+    fn raw(&self) -> &RawMessage {
+        match self {
+            CryptoTransactions::CreateWallet(tx) => tx.raw(),
+            CryptoTransactions::Transfer(tx) => tx.raw(),
+            CryptoTransactions::Accept(tx) => tx.raw(),
+            CryptoTransactions::LockForBridge(tx) => tx.raw(),
+            CryptoTransactions::ReleaseFromBridge(tx) => tx.raw(),
+            CryptoTransactions::AcceptWithNote(tx) => tx.raw(),
+            CryptoTransactions::UpdateSettings(tx) => tx.raw(),
+            CryptoTransactions::OpenDispute(tx) => tx.raw(),
+            CryptoTransactions::ResolveDispute(tx) => tx.raw(),
+            CryptoTransactions::StreamTransfer(tx) => tx.raw(),
+            CryptoTransactions::CloseStream(tx) => tx.raw(),
+            CryptoTransactions::Lock(tx) => tx.raw(),
+            CryptoTransactions::Unlock(tx) => tx.raw(),
+            CryptoTransactions::SetProfile(tx) => tx.raw(),
+            CryptoTransactions::RequestTransfer(tx) => tx.raw(),
+            CryptoTransactions::Rerandomize(tx) => tx.raw(),
+            CryptoTransactions::BackUpTransfer(tx) => tx.raw(),
+        }
+    }
+
+    /// Returns the hash of the transaction.
+    pub fn hash(&self) -> Hash {
+        match self {
+            CryptoTransactions::CreateWallet(tx) => tx.hash(),
+            CryptoTransactions::Transfer(tx) => tx.hash(),
+            CryptoTransactions::Accept(tx) => tx.hash(),
+            CryptoTransactions::LockForBridge(tx) => tx.hash(),
+            CryptoTransactions::ReleaseFromBridge(tx) => tx.hash(),
+            CryptoTransactions::AcceptWithNote(tx) => tx.hash(),
+            CryptoTransactions::UpdateSettings(tx) => tx.hash(),
+            CryptoTransactions::OpenDispute(tx) => tx.hash(),
+            CryptoTransactions::ResolveDispute(tx) => tx.hash(),
+            CryptoTransactions::StreamTransfer(tx) => tx.hash(),
+            CryptoTransactions::CloseStream(tx) => tx.hash(),
+            CryptoTransactions::Lock(tx) => tx.hash(),
+            CryptoTransactions::Unlock(tx) => tx.hash(),
+            CryptoTransactions::SetProfile(tx) => tx.hash(),
+            CryptoTransactions::RequestTransfer(tx) => tx.hash(),
+            CryptoTransactions::Rerandomize(tx) => tx.hash(),
+            CryptoTransactions::BackUpTransfer(tx) => tx.hash(),
+        }
+    }
+
+    /// Serializes the transaction into the JSON form accepted by the HTTP API.
+    pub fn to_json(&self) -> String {
+        let json = match self {
+            CryptoTransactions::CreateWallet(tx) => serde_json::to_string(tx),
+            CryptoTransactions::Transfer(tx) => serde_json::to_string(tx),
+            CryptoTransactions::Accept(tx) => serde_json::to_string(tx),
+            CryptoTransactions::LockForBridge(tx) => serde_json::to_string(tx),
+            CryptoTransactions::ReleaseFromBridge(tx) => serde_json::to_string(tx),
+            CryptoTransactions::AcceptWithNote(tx) => serde_json::to_string(tx),
+            CryptoTransactions::UpdateSettings(tx) => serde_json::to_string(tx),
+            CryptoTransactions::OpenDispute(tx) => serde_json::to_string(tx),
+            CryptoTransactions::ResolveDispute(tx) => serde_json::to_string(tx),
+            CryptoTransactions::StreamTransfer(tx) => serde_json::to_string(tx),
+            CryptoTransactions::CloseStream(tx) => serde_json::to_string(tx),
+            CryptoTransactions::Lock(tx) => serde_json::to_string(tx),
+            CryptoTransactions::Unlock(tx) => serde_json::to_string(tx),
+            CryptoTransactions::SetProfile(tx) => serde_json::to_string(tx),
+            CryptoTransactions::RequestTransfer(tx) => serde_json::to_string(tx),
+            CryptoTransactions::Rerandomize(tx) => serde_json::to_string(tx),
+            CryptoTransactions::BackUpTransfer(tx) => serde_json::to_string(tx),
+        };
+        json.expect("cannot serialize transaction")
+    }
+
+    /// Parses the transaction from the JSON form accepted by the HTTP API.
+    pub fn from_json(json: &str) -> Result<Self, WireError> {
+        serde_json::from_str(json).map_err(WireError::Json)
+    }
+
+    /// Serializes the binary transaction message as a hex string.
+    pub fn to_hex(&self) -> String {
+        let buffer: &MessageBuffer = self.raw();
+        encode_hex(buffer.as_ref())
+    }
+
+    /// Parses the transaction from a hex-encoded binary message. The signature
+    /// of the transaction is not verified.
+    pub fn from_hex(hex: &str) -> Result<Self, WireError> {
+        let bytes = Vec::<u8>::from_hex(hex).map_err(WireError::Hex)?;
+        Self::from_bytes(bytes)
+    }
+
+    /// Parses the transaction from a binary message. The signature of the transaction
+    /// is not verified.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, WireError> {
+        if bytes.len() < HEADER_LENGTH + SIGNATURE_LENGTH {
+            return Err(WireError::Truncated);
+        }
+        let raw = RawMessage::new(MessageBuffer::from_vec(bytes));
+        if message_wire_version(raw.message_type()).is_none() {
+            return Err(WireError::UnknownMessageType(raw.message_type()));
+        }
+        CryptoTransactions::tx_from_raw(raw).map_err(WireError::Encoding)
+    }
+}
+
+#[test]
+fn wire_forms_roundtrip() {
+    use exonum::crypto::gen_keypair;
+    use SecretState;
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let (receiver, _) = gen_keypair();
+    let transfer = state.create_transfer(1_000, &receiver, 10);
+    let tx = CryptoTransactions::Transfer(transfer.clone());
+
+    let restored = CryptoTransactions::from_hex(&tx.to_hex()).unwrap();
+    assert_eq!(restored.hash(), transfer.hash());
+    let restored = CryptoTransactions::from_json(&tx.to_json()).unwrap();
+    assert_eq!(restored.hash(), transfer.hash());
+
+    match CryptoTransactions::from_hex(&tx.to_hex()[..100]) {
+        Err(WireError::Truncated) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match CryptoTransactions::from_hex("zz") {
+        Err(WireError::Hex(..)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Change the message type in the header of the message.
+    let mut bytes = tx.raw().as_ref().to_vec();
+    bytes[2] = 100;
+    match CryptoTransactions::from_hex(&encode_hex(&bytes)) {
+        Err(WireError::UnknownMessageType(100)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let unknown =
+        UnknownTransaction::from_raw(RawMessage::new(MessageBuffer::from_vec(bytes))).unwrap();
+    assert_eq!(unknown.message_type(), 100);
+    assert!(UnknownTransaction::from_raw(tx.raw().clone()).is_err());
+}
+
+#[test]
+fn unknown_transactions_require_author_signature() {
+    use exonum::crypto::{gen_keypair, sign, Signature};
+
+    let (public_key, secret_key) = gen_keypair();
+    let create_wallet = CreateWallet::new(&public_key, &secret_key);
+    let buffer: &MessageBuffer = create_wallet.raw();
+    let mut bytes = buffer.as_ref().to_vec();
+    bytes[2] = 100;
+    let unsigned_len = bytes.len() - SIGNATURE_LENGTH;
+    bytes.truncate(unsigned_len);
+
+    let with_signature = |signature: &Signature| {
+        let mut bytes = bytes.clone();
+        bytes.extend_from_slice(signature.as_ref());
+        UnknownTransaction::from_raw(RawMessage::new(MessageBuffer::from_vec(bytes))).unwrap()
+    };
+    let signed = with_signature(&sign(&bytes, &secret_key));
+    assert_eq!(signed.author(), Some(public_key));
+    assert!(signed.verify());
+
+    let unsigned = with_signature(&Signature::zero());
+    assert!(!unsigned.verify());
+    let (_, other_key) = gen_keypair();
+    let signed_by_other = with_signature(&sign(&bytes, &other_key));
+    assert!(!signed_by_other.verify());
+
+    // The message is too short to contain the author key.
+    let mut bytes = bytes[..HEADER_LENGTH].to_vec();
+    bytes.extend_from_slice(&[0; SIGNATURE_LENGTH]);
+    let truncated =
+        UnknownTransaction::from_raw(RawMessage::new(MessageBuffer::from_vec(bytes))).unwrap();
+    assert_eq!(truncated.author(), None);
+    assert!(!truncated.verify());
+}
+
+#[test]
+fn proofs_are_borrowed_from_message() {
+    use exonum::crypto::gen_keypair;
+    use SecretState;
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let (receiver, _) = gen_keypair();
+    let transfer = state.create_transfer(1_000, &receiver, 10);
+
+    let buffer: &MessageBuffer = transfer.raw();
+    let message: &[u8] = buffer.as_ref();
+    let start = message.as_ptr() as usize;
+    let end = start + message.len();
+    let amount_proof = transfer.amount_proof();
+    let sufficient_balance_proof = transfer.sufficient_balance_proof();
+    for bytes in &[amount_proof.as_bytes(), sufficient_balance_proof.as_bytes()] {
+        let proof_start = bytes.as_ptr() as usize;
+        assert!(start <= proof_start && proof_start + bytes.len() <= end);
+    }
+}
@@ -14,11 +14,26 @@
 
 //! Miscellaneous utils.
 
-use exonum::{blockchain::BlockProof, crypto::PublicKey, helpers::ValidatorId, messages::Message};
+use exonum::{
+    blockchain::{Block, BlockProof},
+    crypto::{CryptoHash, Hash, PublicKey},
+    helpers::{Height, ValidatorId},
+    messages::Message,
+};
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 /// Trust anchor for block verification.
+///
+/// # Anchoring
+///
+/// Besides validator keys, the trust anchor may contain hashes of blocks anchored
+/// to the Bitcoin blockchain (e.g., by the [Exonum anchoring service]). The caller is responsible
+/// for extracting these hashes from anchoring transactions confirmed in the Bitcoin network.
+/// Anchors allow to verify blocks independently of the validator signatures, which is useful
+/// if validator keys may become compromised after the block was created.
+///
+/// [Exonum anchoring service]: https://github.com/exonum/exonum-btc-anchoring
 // This implementation is simplified; it assumes *a priori* knowledge of the current list
 // of validators. For maximum security, the trust anchor should be the hash of the genesis block;
 // the current list of validators could be derived from it using information about configuration
@@ -26,6 +41,7 @@ use std::collections::HashSet;
 #[derive(Debug, Clone)]
 pub struct TrustAnchor {
     validators: Vec<PublicKey>,
+    anchors: BTreeMap<Height, Hash>,
 }
 
 /// Error occuring during block header verification.
@@ -46,6 +62,19 @@ pub enum BlockVerifyError {
     /// Invalid validator signature.
     #[fail(display = "invalid validator signature")]
     InvalidSignature,
+
+    /// Block hash differs from the hash anchored at the same height.
+    #[fail(display = "block hash differs from the anchored one")]
+    AnchorMismatch,
+
+    /// Sequence of block headers is broken (a header does not reference the hash
+    /// of the previous header).
+    #[fail(display = "broken sequence of block headers")]
+    BrokenHeaderChain,
+
+    /// Block headers do not lead to any anchored block.
+    #[fail(display = "block is not anchored")]
+    NotAnchored,
 }
 
 impl TrustAnchor {
@@ -57,11 +86,85 @@ impl TrustAnchor {
     {
         TrustAnchor {
             validators: consensus_keys.into_iter().collect(),
+            anchors: BTreeMap::new(),
         }
     }
 
+    /// Adds hashes of anchored blocks to this trust anchor.
+    pub fn with_anchors<I>(mut self, anchors: I) -> Self
+    where
+        I: IntoIterator<Item = (Height, Hash)>,
+    {
+        self.anchors.extend(anchors);
+        self
+    }
+
+    /// Adds the hash of an anchored block to this trust anchor.
+    pub fn add_anchor(&mut self, height: Height, block_hash: Hash) {
+        self.anchors.insert(height, block_hash);
+    }
+
+    /// Returns the latest anchored block known to this trust anchor.
+    pub fn latest_anchor(&self) -> Option<(Height, Hash)> {
+        self.anchors
+            .iter()
+            .next_back()
+            .map(|(&height, &hash)| (height, hash))
+    }
+
+    /// Checks that the block does not contradict the anchor at the same height, if any.
+    ///
+    /// # Return value
+    ///
+    /// Returns `Ok(true)` if the block is anchored, and `Ok(false)` if there is no anchor
+    /// at the block height.
+    fn check_anchor(&self, block: &Block) -> Result<bool, BlockVerifyError> {
+        match self.anchors.get(&block.height()) {
+            Some(anchored_hash) if *anchored_hash == block.hash() => Ok(true),
+            Some(_) => Err(BlockVerifyError::AnchorMismatch),
+            None => Ok(false),
+        }
+    }
+
+    /// Verifies a block w.r.t. anchors in this trust anchor. Unlike [`verify_block_proof`],
+    /// this method does not use validator signatures.
+    ///
+    /// `subsequent_blocks` are headers of the blocks following `block` in the blockchain,
+    /// in the order of increasing height. The headers are used to connect `block`
+    /// to the nearest anchored block; they can be omitted if `block` is anchored itself.
+    ///
+    /// [`verify_block_proof`]: #method.verify_block_proof
+    pub fn verify_anchored_block(
+        &self,
+        block: &Block,
+        subsequent_blocks: &[Block],
+    ) -> Result<(), BlockVerifyError> {
+        if self.check_anchor(block)? {
+            return Ok(());
+        }
+
+        let mut prev_block = block;
+        for header in subsequent_blocks {
+            if header.height() != prev_block.height().next()
+                || *header.prev_hash() != prev_block.hash()
+            {
+                return Err(BlockVerifyError::BrokenHeaderChain);
+            }
+            if self.check_anchor(header)? {
+                return Ok(());
+            }
+            prev_block = header;
+        }
+        Err(BlockVerifyError::NotAnchored)
+    }
+
     /// Verifies a `BlockProof` w.r.t. this trust anchor.
+    ///
+    /// If the trust anchor contains an anchor at the height of the block, the block hash
+    /// is checked against it as well.
     pub fn verify_block_proof(&self, block_proof: &BlockProof) -> Result<(), BlockVerifyError> {
+        self.check_anchor(&block_proof.block)?;
+
         let validators: Result<Vec<_>, _> = block_proof
             .precommits
             .iter()
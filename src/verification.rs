@@ -0,0 +1,395 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pre-verification of `Transfer` proofs when transactions enter the node.
+//!
+//! The proof that the transferred amount is within bounds does not depend on the blockchain
+//! state except for the minimum and maximum amounts in the service configuration.
+//! Thus, it can be verified once, when the transaction is received by the node, rather than
+//! each time the transaction is executed. The result is memoized by the transaction hash
+//! together with the bounds the proof was verified against, so a change
+//! of the configuration invalidates memoized results.
+//!
+//! Only successful verifications are memoized; a transfer with an invalid proof is
+//! verified again during execution and fails there, exactly as without the cache.
+//! Since proof verification is deterministic, the cache does not influence
+//! transaction outcomes.
+//!
+//! Each service instance owns a separate cache. Transactions are executed without access
+//! to the service instance, so the service decodes transfers into [`CachedTransfer`]s,
+//! which carry a handle to the cache of the instance.
+//!
+//! Optionally, proofs of transfers entering the node are verified by a pool of background
+//! threads (see [`ServiceBuilder::verification_threads()`]), so that the thread receiving
+//! transactions is not blocked by verification.
+//!
+//! [`ServiceBuilder::verification_threads()`]: ::ServiceBuilder::verification_threads()
+//! [`CachedTransfer`]: self::CachedTransfer
+
+use exonum::{
+    blockchain::{ExecutionError, Transaction},
+    crypto::{CryptoHash, Hash},
+    encoding::Error as EncodingError,
+    messages::{Message, RawMessage},
+    storage::Fork,
+};
+use serde::{Serialize, Serializer};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+};
+
+use transactions::Transfer;
+use Config;
+
+/// Maximum number of memoized verification results.
+const CAPACITY: usize = 8_192;
+
+/// Bounds on transfer amounts set by the service configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AmountBounds {
+    min: u64,
+    max: Option<u64>,
+}
+
+impl AmountBounds {
+    pub fn new(config: &Config) -> Self {
+        AmountBounds {
+            min: config.min_transfer_amount,
+            max: config.max_transfer_amount,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct VerificationCache {
+    /// Amount bounds in the latest known service configuration.
+    bounds: Option<AmountBounds>,
+    /// Transfers with verified amount proofs, mapped to the bounds the proof
+    /// was verified against.
+    verified: HashMap<Hash, AmountBounds>,
+    /// Memoized hashes in the order of insertion, used to evict old entries.
+    order: VecDeque<Hash>,
+    capacity: usize,
+}
+
+impl VerificationCache {
+    fn new(capacity: usize) -> Self {
+        VerificationCache {
+            bounds: None,
+            verified: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn insert(&mut self, hash: Hash, bounds: AmountBounds) {
+        if self.verified.insert(hash, bounds).is_some() {
+            return;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            let evicted = self.order.pop_front().expect("order");
+            self.verified.remove(&evicted);
+        }
+    }
+
+    fn is_verified(&self, hash: &Hash, bounds: AmountBounds) -> bool {
+        self.verified.get(hash) == Some(&bounds)
+    }
+}
+
+fn lock(cache: &Mutex<VerificationCache>) -> MutexGuard<VerificationCache> {
+    cache.lock().expect("verification cache is poisoned")
+}
+
+/// Verifies the amount proof of a transfer entering the node and memoizes the result.
+/// Does nothing if the service configuration is not known yet.
+fn precheck(cache: &Mutex<VerificationCache>, transfer: &Transfer) {
+    let bounds = match lock(cache).bounds {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    // The lock is not held during verification, which is relatively slow.
+    if verify_amount(transfer, bounds) {
+        lock(cache).insert(transfer.hash(), bounds);
+    }
+}
+
+/// Pool of worker threads prechecking transfers entering the node.
+struct VerificationPool {
+    jobs: Option<Mutex<SyncSender<Transfer>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl fmt::Debug for VerificationPool {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("VerificationPool")
+            .field("workers", &self.workers.len())
+            .finish()
+    }
+}
+
+impl VerificationPool {
+    fn new(threads: usize, cache: &Arc<Mutex<VerificationCache>>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Transfer>(CAPACITY);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads)
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                let cache = Arc::clone(cache);
+                thread::Builder::new()
+                    .name(format!("verifier-{}", i))
+                    .spawn(move || loop {
+                        let job = receiver
+                            .lock()
+                            .expect("verification queue is poisoned")
+                            .recv();
+                        match job {
+                            Ok(transfer) => precheck(&cache, &transfer),
+                            // The pool is dropped.
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("cannot spawn verifier thread")
+            })
+            .collect();
+
+        VerificationPool {
+            jobs: Some(Mutex::new(sender)),
+            workers,
+        }
+    }
+
+    fn submit(&self, transfer: Transfer) {
+        // If the queue is full, the transfer is not prechecked; its proof is verified
+        // during execution instead.
+        self.jobs
+            .as_ref()
+            .expect("verification pool is shut down")
+            .lock()
+            .expect("verification queue is poisoned")
+            .try_send(transfer)
+            .ok();
+    }
+}
+
+impl Drop for VerificationPool {
+    fn drop(&mut self) {
+        // Closing the channel makes workers terminate after processing remaining jobs.
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+/// Handle to memoized verifications of a service instance. The handle is cheaply clonable;
+/// all clones refer to the same cache.
+#[derive(Debug, Clone)]
+pub(crate) struct Verifier {
+    cache: Arc<Mutex<VerificationCache>>,
+    pool: Option<Arc<VerificationPool>>,
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Verifier::with_threads(0)
+    }
+}
+
+impl Verifier {
+    /// Creates a verifier prechecking transfers on the specified number of background
+    /// threads. If `threads` is zero, transfers are prechecked on the calling thread.
+    pub fn with_threads(threads: usize) -> Self {
+        let cache = Arc::new(Mutex::new(VerificationCache::new(CAPACITY)));
+        let pool = if threads > 0 {
+            Some(Arc::new(VerificationPool::new(threads, &cache)))
+        } else {
+            None
+        };
+        Verifier { cache, pool }
+    }
+
+    fn cache(&self) -> MutexGuard<VerificationCache> {
+        lock(&self.cache)
+    }
+
+    /// Records the amount bounds from the latest committed service configuration.
+    pub fn set_amount_bounds(&self, config: &Config) {
+        self.cache().bounds = Some(AmountBounds::new(config));
+    }
+
+    /// Verifies the amount proof of a transfer entering the node and memoizes the result,
+    /// either on the calling thread or in the background.
+    pub fn precheck(&self, transfer: &Transfer) {
+        match self.pool {
+            Some(ref pool) => pool.submit(transfer.clone()),
+            None => precheck(&self.cache, transfer),
+        }
+    }
+
+    /// Checks the amount proof of a transfer, consulting the memoized results first.
+    pub fn verify_amount(&self, transfer: &Transfer, bounds: AmountBounds) -> bool {
+        if self.cache().is_verified(&transfer.hash(), bounds) {
+            return true;
+        }
+        verify_amount(transfer, bounds)
+    }
+}
+
+/// Checks the amount proof of a transfer without consulting memoized results.
+pub(crate) fn verify_amount(transfer: &Transfer, bounds: AmountBounds) -> bool {
+    transfer
+        .amount_proof()
+        .verify(&transfer.amount(), bounds.min, bounds.max)
+}
+
+/// `Transfer` decoded by a service instance. The amount proof of the transfer is verified
+/// when the transfer enters the node, and the result is memoized in the cache
+/// of the instance.
+///
+/// Transfers decoded in other ways (e.g., with [`Message::from_raw()`]) use a separate
+/// empty cache, i.e., they are verified in full during execution.
+///
+/// [`Message::from_raw()`]: https://docs.rs/exonum/0.9.5/exonum/messages/trait.Message.html#tymethod.from_raw
+#[derive(Debug, Clone)]
+pub(crate) struct CachedTransfer {
+    transfer: Transfer,
+    verifier: Verifier,
+}
+
+impl CachedTransfer {
+    pub fn new(transfer: Transfer, verifier: Verifier) -> Self {
+        CachedTransfer { transfer, verifier }
+    }
+}
+
+impl CryptoHash for CachedTransfer {
+    fn hash(&self) -> Hash {
+        self.transfer.hash()
+    }
+}
+
+impl Message for CachedTransfer {
+    fn from_raw(raw: RawMessage) -> Result<Self, EncodingError> {
+        Transfer::from_raw(raw).map(|transfer| CachedTransfer::new(transfer, Verifier::default()))
+    }
+
+    fn raw(&self) -> &RawMessage {
+        self.transfer.raw()
+    }
+}
+
+/// Cached transfers are serialized in the same way as the wrapped transfer.
+impl Serialize for CachedTransfer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.transfer.serialize(serializer)
+    }
+}
+
+impl Transaction for CachedTransfer {
+    fn verify(&self) -> bool {
+        // Checks depending on the service configuration are performed during execution,
+        // since the configuration is stored in the blockchain. The amount proof is verified
+        // against the latest known configuration in advance, so that the verification
+        // can be skipped during execution.
+        let is_valid = self.transfer.verify();
+        if is_valid {
+            self.verifier.precheck(&self.transfer);
+        }
+        is_valid
+    }
+
+    fn execute(&self, fork: &mut Fork) -> Result<(), ExecutionError> {
+        self.transfer.execute_with(fork, Some(&self.verifier))
+    }
+}
+
+#[test]
+fn cache_eviction_and_config_changes() {
+    use exonum::crypto::hash;
+
+    let bounds = |min, max| AmountBounds { min, max };
+    let mut cache = VerificationCache::new(2);
+    let hashes: Vec<_> = (0_u8..3).map(|i| hash(&[i])).collect();
+    cache.insert(hashes[0], bounds(10, None));
+    cache.insert(hashes[1], bounds(10, None));
+    assert!(cache.is_verified(&hashes[0], bounds(10, None)));
+    assert!(!cache.is_verified(&hashes[0], bounds(20, None)));
+    assert!(!cache.is_verified(&hashes[0], bounds(10, Some(100))));
+
+    cache.insert(hashes[2], bounds(10, None));
+    assert!(!cache.is_verified(&hashes[0], bounds(10, None)));
+    assert!(cache.is_verified(&hashes[1], bounds(10, None)));
+    assert!(cache.is_verified(&hashes[2], bounds(10, None)));
+}
+
+#[test]
+fn verifiers_do_not_share_cache() {
+    use exonum::crypto::gen_keypair;
+    use {SecretState, CONFIG};
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let transfer = state.create_transfer(100, &gen_keypair().0, 10);
+    let bounds = AmountBounds::new(&CONFIG);
+
+    let (verifier, other_verifier) = (Verifier::default(), Verifier::default());
+    // The configuration is not known yet, so the transfer is not verified.
+    verifier.precheck(&transfer);
+    assert!(!verifier.cache().is_verified(&transfer.hash(), bounds));
+
+    verifier.set_amount_bounds(&CONFIG);
+    other_verifier.set_amount_bounds(&CONFIG);
+    verifier.precheck(&transfer);
+    assert!(verifier.cache().is_verified(&transfer.hash(), bounds));
+    assert!(verifier
+        .clone()
+        .cache()
+        .is_verified(&transfer.hash(), bounds));
+    assert!(!other_verifier.cache().is_verified(&transfer.hash(), bounds));
+}
+
+#[test]
+fn transfers_are_prechecked_in_background() {
+    use exonum::crypto::gen_keypair;
+    use {SecretState, CONFIG};
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let transfers: Vec<_> = (0..3)
+        .map(|_| state.create_transfer(100, &gen_keypair().0, 10))
+        .collect();
+    let bounds = AmountBounds::new(&CONFIG);
+
+    let verifier = Verifier::with_threads(2);
+    verifier.set_amount_bounds(&CONFIG);
+    for transfer in &transfers {
+        verifier.precheck(transfer);
+    }
+    let cache = Arc::clone(&verifier.cache);
+    // Dropping the verifier waits until all submitted transfers are prechecked.
+    drop(verifier);
+    for transfer in &transfers {
+        assert!(lock(&cache).is_verified(&transfer.hash(), bounds));
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Webhook notifications about wallet events.
+//!
+//! Wallets hosted on a server (e.g., by a merchant) would otherwise need to poll
+//! the `v1/wallet` endpoint to find out about incoming transfers. Instead, the node operator
+//! may register HTTP webhooks for such wallets via the private API of the node:
+//!
+//! - `POST v1/webhooks/register` with a [`WebhookQuery`] registers a webhook
+//! - `POST v1/webhooks/unregister` with a [`WebhookQuery`] removes a webhook
+//! - `GET v1/webhooks` returns all registered webhooks
+//!
+//! After each block changing the history or the unaccepted transfers of a wallet,
+//! the node posts a [`Notification`] to all webhooks of the wallet. The notification
+//! is serialized to JSON and signed with the service key of the node; the signature
+//! and the key are passed in the [`SIGNATURE_HEADER`] and [`KEY_HEADER`] headers,
+//! and can be checked with [`verify_notification()`].
+//!
+//! Delivery is best-effort: notifications are posted from a background thread without
+//! retries, and registrations are kept in memory, so they need to be restored after
+//! the node restarts. Receivers should treat notifications as hints and read the wallet
+//! state from the blockchain.
+//!
+//! This module requires the `webhooks` crate feature.
+//!
+//! [`WebhookQuery`]: struct.WebhookQuery.html
+//! [`Notification`]: struct.Notification.html
+//! [`SIGNATURE_HEADER`]: constant.SIGNATURE_HEADER.html
+//! [`KEY_HEADER`]: constant.KEY_HEADER.html
+//! [`verify_notification()`]: fn.verify_notification.html
+
+use exonum::{
+    api::{self, ServiceApiState},
+    crypto::{self, Hash, PublicKey, SecretKey, Signature},
+    encoding::serialize::encode_hex,
+    helpers::Height,
+    storage::Snapshot,
+};
+use reqwest::Client as HttpClient;
+use serde_json;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+use storage::{Event, Schema};
+
+/// Name of the HTTP header with the hex-encoded Ed25519 signature of the notification body.
+pub const SIGNATURE_HEADER: &str = "X-Private-Currency-Signature";
+/// Name of the HTTP header with the hex-encoded service key of the node.
+pub const KEY_HEADER: &str = "X-Private-Currency-Key";
+
+/// Timeout for delivering a single notification.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Notification about changes in a wallet posted to webhooks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    /// Public key of the wallet.
+    pub wallet: PublicKey,
+    /// Height of the block after which the notification is sent.
+    pub height: Height,
+    /// Number of entries in the wallet history after the block.
+    pub history_len: u64,
+    /// Events appended to the wallet history since the previous notification,
+    /// oldest first.
+    pub events: Vec<Event>,
+    /// Hashes of all transfers to the wallet awaiting acceptance.
+    pub pending_transfers: Vec<Hash>,
+}
+
+/// Checks that the notification `body` is signed by the node with the specified service key.
+pub fn verify_notification(body: &[u8], signature: &Signature, node_key: &PublicKey) -> bool {
+    crypto::verify(signature, body, node_key)
+}
+
+/// Query to register or remove a webhook.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookQuery {
+    /// Public key of the wallet.
+    pub wallet: PublicKey,
+    /// URL to post notifications to. Only `http` and `https` URLs are supported.
+    pub url: String,
+}
+
+/// Webhooks registered for a wallet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletWebhooks {
+    /// Public key of the wallet.
+    pub wallet: PublicKey,
+    /// Registered URLs.
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Registration {
+    urls: BTreeSet<String>,
+    history_len: u64,
+    pending_transfers: BTreeSet<Hash>,
+}
+
+#[derive(Debug)]
+struct Delivery {
+    url: String,
+    body: Vec<u8>,
+    signature: Signature,
+    node_key: PublicKey,
+}
+
+/// Registry of webhooks of the node, which posts notifications after committed blocks.
+///
+/// The registry is created with [`ServiceBuilder::webhooks()`] and is available via
+/// [`Service::webhooks()`]. Cloned registries share webhooks.
+///
+/// [`ServiceBuilder::webhooks()`]: ::ServiceBuilder::webhooks()
+/// [`Service::webhooks()`]: ::Service::webhooks()
+#[derive(Debug, Clone)]
+pub struct Webhooks {
+    registrations: Arc<Mutex<HashMap<PublicKey, Registration>>>,
+    deliveries: Arc<Mutex<Sender<Delivery>>>,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("private-currency-webhooks".to_owned())
+            .spawn(move || deliver(&rx))
+            .expect("cannot spawn webhook delivery thread");
+        Webhooks {
+            registrations: Arc::new(Mutex::new(HashMap::new())),
+            deliveries: Arc::new(Mutex::new(tx)),
+        }
+    }
+}
+
+impl Webhooks {
+    fn lock(&self) -> MutexGuard<HashMap<PublicKey, Registration>> {
+        self.registrations
+            .lock()
+            .expect("webhook registrations are poisoned")
+    }
+
+    /// Registers a webhook for the wallet. Only changes after the state in `snapshot`
+    /// are notified about.
+    ///
+    /// Returns `false` if the URL is not an `http` or `https` URL.
+    pub fn register(&self, snapshot: &dyn Snapshot, wallet: &PublicKey, url: &str) -> bool {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return false;
+        }
+
+        let mut registrations = self.lock();
+        let registration = registrations.entry(*wallet).or_insert_with(|| {
+            let schema = Schema::new(snapshot);
+            Registration {
+                urls: BTreeSet::new(),
+                history_len: schema.wallet(wallet).map_or(0, |w| w.history_len()),
+                pending_transfers: schema.unaccepted_transfers(wallet).into_iter().collect(),
+            }
+        });
+        registration.urls.insert(url.to_owned());
+        true
+    }
+
+    /// Removes a webhook. Returns `false` if the webhook is not registered.
+    pub fn unregister(&self, wallet: &PublicKey, url: &str) -> bool {
+        let mut registrations = self.lock();
+        let removed = registrations
+            .get_mut(wallet)
+            .map_or(false, |registration| registration.urls.remove(url));
+        if registrations
+            .get(wallet)
+            .map_or(false, |registration| registration.urls.is_empty())
+        {
+            registrations.remove(wallet);
+        }
+        removed
+    }
+
+    /// Returns all registered webhooks.
+    pub fn list(&self) -> Vec<WalletWebhooks> {
+        let mut list: Vec<_> = self
+            .lock()
+            .iter()
+            .map(|(wallet, registration)| WalletWebhooks {
+                wallet: *wallet,
+                urls: registration.urls.iter().cloned().collect(),
+            })
+            .collect();
+        list.sort_by(|x, y| x.wallet.cmp(&y.wallet));
+        list
+    }
+
+    /// Sends notifications about wallets changed by the block at the specified height.
+    pub(crate) fn on_commit(
+        &self,
+        snapshot: &dyn Snapshot,
+        height: Height,
+        node_key: &PublicKey,
+        secret_key: &SecretKey,
+    ) {
+        let schema = Schema::new(snapshot);
+        let deliveries = self
+            .deliveries
+            .lock()
+            .expect("webhook deliveries are poisoned");
+
+        for (wallet, registration) in self.lock().iter_mut() {
+            let history_len = schema.wallet(wallet).map_or(0, |w| w.history_len());
+            let pending_transfers: BTreeSet<_> =
+                schema.unaccepted_transfers(wallet).into_iter().collect();
+            if history_len == registration.history_len
+                && pending_transfers == registration.pending_transfers
+            {
+                continue;
+            }
+
+            let history = schema.history_index(wallet);
+            let notification = Notification {
+                wallet: *wallet,
+                height,
+                history_len,
+                events: (registration.history_len..history_len)
+                    .map(|i| history.get(i).expect("event"))
+                    .collect(),
+                pending_transfers: pending_transfers.iter().cloned().collect(),
+            };
+            registration.history_len = history_len;
+            registration.pending_transfers = pending_transfers;
+
+            let body = serde_json::to_vec(&notification).expect("cannot serialize notification");
+            let signature = crypto::sign(&body, secret_key);
+            for url in &registration.urls {
+                let delivery = Delivery {
+                    url: url.clone(),
+                    body: body.clone(),
+                    signature,
+                    node_key: *node_key,
+                };
+                // The delivery thread only terminates if it panics.
+                deliveries.send(delivery).ok();
+            }
+        }
+    }
+
+    /// HTTP API handler listing registered webhooks.
+    pub(crate) fn list_endpoint(
+        &self,
+    ) -> impl Fn(&ServiceApiState, ()) -> api::Result<Vec<WalletWebhooks>> + Clone + Send + Sync + 'static
+    {
+        let webhooks = self.clone();
+        move |_: &ServiceApiState, _: ()| Ok(webhooks.list())
+    }
+
+    /// HTTP API handler registering a webhook.
+    pub(crate) fn register_endpoint(
+        &self,
+    ) -> impl Fn(&ServiceApiState, WebhookQuery) -> api::Result<()> + Clone + Send + Sync + 'static
+    {
+        let webhooks = self.clone();
+        move |state: &ServiceApiState, query: WebhookQuery| {
+            if webhooks.register(state.snapshot().as_ref(), &query.wallet, &query.url) {
+                Ok(())
+            } else {
+                Err(api::Error::BadRequest("unsupported webhook URL".to_owned()))
+            }
+        }
+    }
+
+    /// HTTP API handler removing a webhook.
+    pub(crate) fn unregister_endpoint(
+        &self,
+    ) -> impl Fn(&ServiceApiState, WebhookQuery) -> api::Result<()> + Clone + Send + Sync + 'static
+    {
+        let webhooks = self.clone();
+        move |_: &ServiceApiState, query: WebhookQuery| {
+            if webhooks.unregister(&query.wallet, &query.url) {
+                Ok(())
+            } else {
+                Err(api::Error::NotFound("webhook is not registered".to_owned()))
+            }
+        }
+    }
+}
+
+/// Posts notifications until all senders are dropped.
+fn deliver(deliveries: &Receiver<Delivery>) {
+    let http = HttpClient::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .expect("cannot create HTTP client");
+    for delivery in deliveries {
+        let response = http
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, encode_hex(delivery.signature.as_ref()))
+            .header(KEY_HEADER, encode_hex(delivery.node_key.as_ref()))
+            .body(delivery.body)
+            .send();
+        // Delivery is best-effort; unreachable webhooks are not retried.
+        drop(response);
+    }
+}
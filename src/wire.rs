@@ -0,0 +1,580 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stable wire types for third-party clients.
+//!
+//! Types in this module are plain `serde` structures mirroring the JSON forms
+//! of transactions and wallet proofs accepted and produced by the HTTP API of the service.
+//! Unlike the types generated by `encoding_struct!` and `transactions!`, they do not depend
+//! on the binary layout of messages, and are the normative description of the JSON format
+//! (the [JSON schema](::api::schema()) is derived from the same conventions).
+//!
+//! # Conventions
+//!
+//! - Keys, hashes, signatures, commitments and other byte strings are encoded
+//!   as lowercase hex strings.
+//! - `u64` values within transactions and storage values are encoded as decimal strings,
+//!   so that they can be handled by JavaScript clients without loss of precision.
+//!   Smaller integers are encoded as JSON numbers.
+//! - Fields are declared in the order of the binary layout of transactions. Parsers
+//!   must not depend on the order of fields in JSON objects.
+//!
+//! Wire types can be converted to and from their internal counterparts; a conversion
+//! to an internal type fails if the wire value is malformed (e.g., contains invalid hex
+//! or a non-canonical commitment).
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{self, Value};
+
+use api;
+use transactions::{self, CryptoTransactions, WireError};
+
+/// Signed transaction of the service.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message<B> {
+    /// Version of the Exonum protocol; always `0`.
+    pub protocol_version: u8,
+    /// Identifier of the service ([`SERVICE_ID`](::SERVICE_ID)).
+    pub service_id: u16,
+    /// Identifier of the transaction type within the service.
+    pub message_id: u16,
+    /// Transaction payload.
+    pub body: B,
+    /// Hex-encoded Ed25519 signature of the binary transaction message.
+    pub signature: String,
+}
+
+/// Payload of a `CreateWallet` transaction (message ID `0`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateWalletBody {
+    /// Hex-encoded Ed25519 key of the wallet.
+    pub key: String,
+}
+
+/// Payload of a `Transfer` transaction (message ID `1`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferBody {
+    /// Hex-encoded Ed25519 key of the sender.
+    pub from: String,
+    /// Hex-encoded Ed25519 key of the receiver.
+    pub to: String,
+    /// Rollback delay.
+    pub rollback_delay: u32,
+    /// Length of the sender’s wallet history, as a decimal string.
+    pub history_len: String,
+    /// Hex-encoded commitment to the transferred amount.
+    pub amount: String,
+    /// Range proofs for the amount, as hex-encoded 32-byte scalars and points. The proof
+    /// for the upper bound on the amount (if any) follows the proof for the lower bound.
+    pub amount_proof: Vec<String>,
+    /// Range proof for the sufficient balance, as hex-encoded 32-byte scalars and points.
+    pub sufficient_balance_proof: Vec<String>,
+    /// Encrypted opening for the amount.
+    pub encrypted_data: EncryptedData,
+}
+
+/// Encrypted data embedded into transfers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedData {
+    /// Hex-encoded 24-byte nonce.
+    pub nonce: String,
+    /// Hex-encoded ciphertext.
+    pub encrypted_data: String,
+}
+
+/// Payload of an `Accept` transaction (message ID `2`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AcceptBody {
+    /// Hex-encoded Ed25519 key of the receiver.
+    pub receiver: String,
+    /// Hex-encoded hash of the accepted transfer.
+    pub transfer_id: String,
+}
+
+/// Payload of a `LockForBridge` transaction (message ID `3`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockForBridgeBody {
+    /// Hex-encoded Ed25519 key of the sender.
+    pub from: String,
+    /// Hex-encoded Ed25519 key of the receiver in the destination deployment.
+    pub to: String,
+    /// Hex-encoded network identifier of the destination deployment.
+    pub destination: String,
+    /// Length of the sender’s wallet history, as a decimal string.
+    pub history_len: String,
+    /// Hex-encoded commitment to the locked amount.
+    pub amount: String,
+    /// Range proof for the amount, as hex-encoded 32-byte scalars and points.
+    pub amount_proof: Vec<String>,
+    /// Range proof for the sufficient balance, as hex-encoded 32-byte scalars and points.
+    pub sufficient_balance_proof: Vec<String>,
+    /// Encrypted opening for the amount.
+    pub encrypted_data: EncryptedData,
+}
+
+/// Payload of a `ReleaseFromBridge` transaction (message ID `4`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReleaseFromBridgeBody {
+    /// Hex-encoded Ed25519 key of the relayer.
+    pub relayer: String,
+    /// Hex-encoded binary message of the released `LockForBridge` transaction.
+    pub lock: String,
+}
+
+/// Payload of an `AcceptWithNote` transaction (message ID `5`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AcceptWithNoteBody {
+    /// Hex-encoded Ed25519 key of the receiver of the transfer.
+    pub receiver: String,
+    /// Hex-encoded hash of the accepted transfer.
+    pub transfer_id: String,
+    /// Acknowledgment encrypted to the sender of the transfer.
+    pub note: EncryptedData,
+}
+
+/// Payload of an `UpdateSettings` transaction (message ID `6`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpdateSettingsBody {
+    /// Hex-encoded Ed25519 key of the wallet.
+    pub key: String,
+    /// Minimum rollback delay of incoming transfers preferred by the wallet owner.
+    pub min_rollback_delay: u32,
+    /// Arbitrary number making the transaction unique, as a decimal string.
+    pub seed: String,
+}
+
+/// Payload of an `OpenDispute` transaction (message ID `7`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenDisputeBody {
+    /// Hex-encoded Ed25519 key of the sender of the disputed transfer.
+    pub sender: String,
+    /// Hex-encoded binary message of the disputed `Transfer` transaction.
+    pub transfer: String,
+    /// Arbitrary number making the transaction unique, as a decimal string.
+    pub seed: String,
+}
+
+/// Payload of a `ResolveDispute` transaction (message ID `8`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolveDisputeBody {
+    /// Hex-encoded Ed25519 key of the arbiter.
+    pub arbiter: String,
+    /// Hex-encoded binary message of the resolved `OpenDispute` transaction.
+    pub dispute: String,
+    /// Whether the escrowed amount is refunded to the sender of the disputed transfer.
+    pub refund: bool,
+}
+
+/// Payload of a `StreamTransfer` transaction (message ID `9`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamTransferBody {
+    /// Hex-encoded Ed25519 key of the sender.
+    pub from: String,
+    /// Hex-encoded Ed25519 key of the receiver.
+    pub to: String,
+    /// Number of payments in the stream.
+    pub duration: u32,
+    /// Length of the sender’s wallet history, as a decimal string.
+    pub history_len: String,
+    /// Hex-encoded commitment to the amount paid in each block.
+    pub rate: String,
+    /// Range proof for the rate, as hex-encoded 32-byte scalars and points.
+    pub rate_proof: Vec<String>,
+    /// Range proof for the sufficient balance, as hex-encoded 32-byte scalars and points.
+    pub sufficient_balance_proof: Vec<String>,
+    /// Encrypted opening for the rate.
+    pub encrypted_data: EncryptedData,
+}
+
+/// Payload of a `CloseStream` transaction (message ID `10`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloseStreamBody {
+    /// Hex-encoded Ed25519 key of the sender or the receiver of the stream.
+    pub author: String,
+    /// Hex-encoded binary message of the closed `StreamTransfer` transaction.
+    pub stream: String,
+    /// New number of payments in the stream.
+    pub duration: u32,
+}
+
+/// Payload of a `Lock` transaction (message ID `11`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockBody {
+    /// Hex-encoded Ed25519 key of the wallet owner.
+    pub owner: String,
+    /// Height starting from which the funds can be unlocked, as a decimal string.
+    pub unlock_height: String,
+    /// Length of the owner’s wallet history, as a decimal string.
+    pub history_len: String,
+    /// Hex-encoded commitment to the locked amount.
+    pub amount: String,
+    /// Range proof for the amount, as hex-encoded 32-byte scalars and points.
+    pub amount_proof: Vec<String>,
+    /// Range proof for the sufficient balance, as hex-encoded 32-byte scalars and points.
+    pub sufficient_balance_proof: Vec<String>,
+    /// Encrypted opening for the amount.
+    pub encrypted_data: EncryptedData,
+}
+
+/// Payload of an `Unlock` transaction (message ID `12`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnlockBody {
+    /// Hex-encoded Ed25519 key of the wallet owner.
+    pub owner: String,
+    /// Hex-encoded binary message of the `Lock` transaction.
+    pub lock: String,
+    /// Arbitrary number making the transaction unique, as a decimal string.
+    pub seed: String,
+}
+
+/// Payload of a `SetProfile` transaction (message ID `13`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetProfileBody {
+    /// Hex-encoded Ed25519 key of the wallet.
+    pub key: String,
+    /// Profile encrypted by the wallet owner to itself.
+    pub profile: EncryptedData,
+    /// Arbitrary number making the transaction unique, as a decimal string.
+    pub seed: String,
+}
+
+/// Payload of a `RequestTransfer` transaction (message ID `14`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestTransferBody {
+    /// Hex-encoded Ed25519 key of the requester.
+    pub requester: String,
+    /// Hex-encoded Ed25519 key of the payer.
+    pub payer: String,
+    /// Hex-encoded commitment to the requested amount.
+    pub amount: String,
+    /// Encrypted opening for the amount.
+    pub encrypted_data: EncryptedData,
+    /// Arbitrary number making the transaction unique, as a decimal string.
+    pub seed: String,
+}
+
+/// Payload of a `Rerandomize` transaction (message ID `15`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RerandomizeBody {
+    /// Hex-encoded Ed25519 key of the wallet owner.
+    pub owner: String,
+    /// Hex-encoded commitment to zero added to the balance.
+    pub blinding: String,
+    /// Hex-encoded proof that the balance commitment is rerandomized to the same value.
+    pub equality_proof: String,
+    /// Encrypted opening for the blinding.
+    pub encrypted_data: EncryptedData,
+    /// Arbitrary number making the transaction unique, as a decimal string.
+    pub seed: String,
+}
+
+/// Payload of a `BackUpTransfer` transaction (message ID `16`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackUpTransferBody {
+    /// Hex-encoded Ed25519 key of the sender of the transfer.
+    pub from: String,
+    /// Hex-encoded Ed25519 key of the holder of the backup key.
+    pub holder: String,
+    /// Hex-encoded hash of the backed up transfer.
+    pub transfer_id: String,
+    /// Opening for the transfer amount encrypted for the holder.
+    pub encrypted_data: EncryptedData,
+    /// Arbitrary number making the transaction unique, as a decimal string.
+    pub seed: String,
+}
+
+/// `CreateWallet` transaction.
+pub type CreateWallet = Message<CreateWalletBody>;
+/// `Transfer` transaction.
+pub type Transfer = Message<TransferBody>;
+/// `Accept` transaction.
+pub type Accept = Message<AcceptBody>;
+/// `LockForBridge` transaction.
+pub type LockForBridge = Message<LockForBridgeBody>;
+/// `ReleaseFromBridge` transaction.
+pub type ReleaseFromBridge = Message<ReleaseFromBridgeBody>;
+/// `AcceptWithNote` transaction.
+pub type AcceptWithNote = Message<AcceptWithNoteBody>;
+/// `UpdateSettings` transaction.
+pub type UpdateSettings = Message<UpdateSettingsBody>;
+/// `OpenDispute` transaction.
+pub type OpenDispute = Message<OpenDisputeBody>;
+/// `ResolveDispute` transaction.
+pub type ResolveDispute = Message<ResolveDisputeBody>;
+/// `StreamTransfer` transaction.
+pub type StreamTransfer = Message<StreamTransferBody>;
+/// `CloseStream` transaction.
+pub type CloseStream = Message<CloseStreamBody>;
+/// `Lock` transaction.
+pub type Lock = Message<LockBody>;
+/// `Unlock` transaction.
+pub type Unlock = Message<UnlockBody>;
+/// `SetProfile` transaction.
+pub type SetProfile = Message<SetProfileBody>;
+/// `RequestTransfer` transaction.
+pub type RequestTransfer = Message<RequestTransferBody>;
+/// `Rerandomize` transaction.
+pub type Rerandomize = Message<RerandomizeBody>;
+/// `BackUpTransfer` transaction.
+pub type BackUpTransfer = Message<BackUpTransferBody>;
+
+/// Event in the wallet history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FullEvent {
+    /// Wallet creation.
+    CreateWallet(CreateWallet),
+    /// Transfer to or from the wallet.
+    Transfer(Transfer),
+    /// Rolled-back transfer from the wallet.
+    Rollback(Transfer),
+    /// Funds locked for the bridge to another deployment.
+    BridgeLock(LockForBridge),
+    /// Funds released from the bridge.
+    BridgeRelease(ReleaseFromBridge),
+    /// Acknowledgment of an outgoing transfer by its receiver.
+    Acknowledgment(AcceptWithNote),
+    /// Funds moved into the disputed escrow.
+    DisputeOpened(OpenDispute),
+    /// Funds released from the disputed escrow.
+    DisputeResolved(ResolveDispute),
+    /// Funds locked for an outgoing payment stream.
+    StreamOpened(StreamTransfer),
+    /// Payment of an incoming stream.
+    StreamPayment(StreamTransfer),
+    /// Cancelled payments of an outgoing stream refunded to the sender.
+    StreamClosed(CloseStream),
+    /// Funds moved to the locked balance.
+    Lock(Lock),
+    /// Locked funds returned to the balance.
+    Unlock(Unlock),
+    /// Transfer requested from the wallet.
+    TransferRequest(RequestTransfer),
+    /// Balance commitment replaced with a fresh commitment to the same value.
+    Rerandomization(Rerandomize),
+}
+
+/// Response of the `v1/wallet` endpoint.
+///
+/// Merkle proofs and the block proof are kept as raw JSON values; their format is defined
+/// by the Exonum core.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletProof {
+    /// Block header with precommits.
+    pub block_proof: Value,
+    /// Proof from the block state hash to the wallets table.
+    pub wallet_table_proof: Value,
+    /// Proof from the wallets table to the wallet.
+    pub wallet_proof: Value,
+    /// Proof from the block state hash to the profiles table.
+    pub profile_table_proof: Value,
+    /// Proof from the profiles table to the encrypted profile of the wallet.
+    pub profile_proof: Value,
+    /// History and unaccepted transfers of the wallet; absent if the wallet does not exist.
+    #[serde(flatten)]
+    pub contents: Option<WalletContents>,
+}
+
+/// Part of a `WalletProof` related to the wallet history and unaccepted transfers.
+/// The fields are inlined into the `WalletProof` object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletContents {
+    /// Requested events in the wallet history.
+    pub history: Vec<FullEvent>,
+    /// Unaccepted incoming transfers.
+    pub unaccepted_transfers: Vec<Transfer>,
+    /// Proof for the history events; `null` if there are no events.
+    pub history_proof: Option<Value>,
+    /// Proof for the unaccepted transfers.
+    pub unaccepted_transfers_proof: Value,
+}
+
+/// Converts between types with the same JSON form.
+fn convert<T: Serialize, U: DeserializeOwned>(value: &T) -> Result<U, WireError> {
+    serde_json::to_value(value)
+        .and_then(serde_json::from_value)
+        .map_err(WireError::Json)
+}
+
+impl<B: Serialize + DeserializeOwned> Message<B> {
+    /// Converts a transaction of the service into its wire form.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body type does not correspond to the transaction type.
+    pub fn from_transaction(tx: &CryptoTransactions) -> Self {
+        serde_json::from_str(&tx.to_json()).expect("transaction has a different JSON form")
+    }
+
+    /// Converts the wire form into a transaction of the service.
+    /// The signature of the transaction is not verified.
+    pub fn to_transaction(&self) -> Result<CryptoTransactions, WireError> {
+        let json = serde_json::to_string(self).map_err(WireError::Json)?;
+        CryptoTransactions::from_json(&json)
+    }
+}
+
+impl<'a> From<&'a transactions::CreateWallet> for CreateWallet {
+    fn from(tx: &'a transactions::CreateWallet) -> Self {
+        Self::from_transaction(&CryptoTransactions::CreateWallet(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::Transfer> for Transfer {
+    fn from(tx: &'a transactions::Transfer) -> Self {
+        Self::from_transaction(&CryptoTransactions::Transfer(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::Accept> for Accept {
+    fn from(tx: &'a transactions::Accept) -> Self {
+        Self::from_transaction(&CryptoTransactions::Accept(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::LockForBridge> for LockForBridge {
+    fn from(tx: &'a transactions::LockForBridge) -> Self {
+        Self::from_transaction(&CryptoTransactions::LockForBridge(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::ReleaseFromBridge> for ReleaseFromBridge {
+    fn from(tx: &'a transactions::ReleaseFromBridge) -> Self {
+        Self::from_transaction(&CryptoTransactions::ReleaseFromBridge(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::AcceptWithNote> for AcceptWithNote {
+    fn from(tx: &'a transactions::AcceptWithNote) -> Self {
+        Self::from_transaction(&CryptoTransactions::AcceptWithNote(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::UpdateSettings> for UpdateSettings {
+    fn from(tx: &'a transactions::UpdateSettings) -> Self {
+        Self::from_transaction(&CryptoTransactions::UpdateSettings(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::OpenDispute> for OpenDispute {
+    fn from(tx: &'a transactions::OpenDispute) -> Self {
+        Self::from_transaction(&CryptoTransactions::OpenDispute(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::ResolveDispute> for ResolveDispute {
+    fn from(tx: &'a transactions::ResolveDispute) -> Self {
+        Self::from_transaction(&CryptoTransactions::ResolveDispute(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::StreamTransfer> for StreamTransfer {
+    fn from(tx: &'a transactions::StreamTransfer) -> Self {
+        Self::from_transaction(&CryptoTransactions::StreamTransfer(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::CloseStream> for CloseStream {
+    fn from(tx: &'a transactions::CloseStream) -> Self {
+        Self::from_transaction(&CryptoTransactions::CloseStream(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::Lock> for Lock {
+    fn from(tx: &'a transactions::Lock) -> Self {
+        Self::from_transaction(&CryptoTransactions::Lock(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::Unlock> for Unlock {
+    fn from(tx: &'a transactions::Unlock) -> Self {
+        Self::from_transaction(&CryptoTransactions::Unlock(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::SetProfile> for SetProfile {
+    fn from(tx: &'a transactions::SetProfile) -> Self {
+        Self::from_transaction(&CryptoTransactions::SetProfile(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::RequestTransfer> for RequestTransfer {
+    fn from(tx: &'a transactions::RequestTransfer) -> Self {
+        Self::from_transaction(&CryptoTransactions::RequestTransfer(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::Rerandomize> for Rerandomize {
+    fn from(tx: &'a transactions::Rerandomize) -> Self {
+        Self::from_transaction(&CryptoTransactions::Rerandomize(tx.clone()))
+    }
+}
+
+impl<'a> From<&'a transactions::BackUpTransfer> for BackUpTransfer {
+    fn from(tx: &'a transactions::BackUpTransfer) -> Self {
+        Self::from_transaction(&CryptoTransactions::BackUpTransfer(tx.clone()))
+    }
+}
+
+impl Transfer {
+    /// Converts the wire form into a `Transfer` transaction.
+    pub fn to_transfer(&self) -> Result<transactions::Transfer, WireError> {
+        match self.to_transaction()? {
+            CryptoTransactions::Transfer(tx) => Ok(tx),
+            _ => Err(WireError::UnknownMessageType(self.message_id)),
+        }
+    }
+}
+
+impl<'a> From<&'a api::WalletProof> for WalletProof {
+    fn from(proof: &'a api::WalletProof) -> Self {
+        convert(proof).expect("wallet proof has a different JSON form")
+    }
+}
+
+impl WalletProof {
+    /// Converts the wire form into a `WalletProof`, which can be checked.
+    pub fn to_proof(&self) -> Result<api::WalletProof, WireError> {
+        convert(self)
+    }
+}
+
+#[test]
+fn transactions_roundtrip() {
+    use exonum::{crypto::gen_keypair, messages::Message as ExonumMessage};
+    use SecretState;
+
+    let mut state = SecretState::with_random_keypair();
+    state.initialize();
+    let (receiver, _) = gen_keypair();
+    let transfer = state.create_transfer(1_000, &receiver, 10);
+
+    let wire = Transfer::from(&transfer);
+    assert_eq!(wire.message_id, 1);
+    assert_eq!(wire.body.history_len, "1");
+    assert_eq!(
+        serde_json::to_value(&wire).unwrap(),
+        serde_json::to_value(&transfer).unwrap()
+    );
+    assert_eq!(wire.to_transfer().unwrap().hash(), transfer.hash());
+
+    let create_wallet = state.create_wallet();
+    let wire = CreateWallet::from(&create_wallet);
+    assert_eq!(wire.message_id, 0);
+    assert_eq!(wire.to_transaction().unwrap().hash(), create_wallet.hash());
+    let wire = Transfer {
+        message_id: 0,
+        ..Transfer::from(&transfer)
+    };
+    assert!(wire.to_transfer().is_err());
+}
@@ -18,15 +18,20 @@ extern crate exonum;
 #[macro_use]
 extern crate exonum_testkit;
 extern crate private_currency;
+extern crate serde_json;
 
-use exonum::crypto::{CryptoHash, PublicKey};
+use exonum::{
+    blockchain::Schema as CoreSchema,
+    crypto::{CryptoHash, PublicKey},
+};
 use exonum_testkit::{ApiKind, TestKit, TestKitBuilder};
 
 use std::{collections::HashSet, iter::FromIterator};
 
 use private_currency::{
     api::{CheckedWalletProof, FullEvent, TrustAnchor, WalletProof, WalletQuery},
-    SecretState, Service as Currency,
+    transactions::MAX_PROFILE_SIZE,
+    SecretState, Service as Currency, WatchWallet,
 };
 
 fn create_testkit() -> TestKit {
@@ -36,6 +41,10 @@ fn create_testkit() -> TestKit {
 }
 
 fn wallet(testkit: &TestKit, key: PublicKey, start_history_at: u64) -> CheckedWalletProof {
+    wallet_with_query(testkit, &WalletQuery::new(key, start_history_at))
+}
+
+fn wallet_with_query(testkit: &TestKit, query: &WalletQuery) -> CheckedWalletProof {
     let trust_anchor = TrustAnchor::new(
         testkit
             .network()
@@ -44,17 +53,13 @@ fn wallet(testkit: &TestKit, key: PublicKey, start_history_at: u64) -> CheckedWa
             .map(|node| node.public_keys().consensus_key),
     );
 
-    let query = WalletQuery {
-        key,
-        start_history_at,
-    };
     let wallet_proof: WalletProof = testkit
         .api()
         .public(ApiKind::Service("private_currency"))
-        .query(&query)
+        .query(query)
         .get("v1/wallet")
         .unwrap();
-    wallet_proof.check(&trust_anchor, &query).unwrap()
+    wallet_proof.check(&trust_anchor, query).unwrap()
 }
 
 #[test]
@@ -123,3 +128,455 @@ fn wallet_api() {
         alice_sec.to_public()
     );
 }
+
+#[test]
+fn sparse_history_queries() {
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let alice_pk = *alice_sec.public_key();
+    let bob_sec = SecretState::with_random_keypair();
+    let create_wallet = alice_sec.create_wallet();
+    testkit.create_block_with_transactions(txvec![create_wallet.clone(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+
+    let mut transfers = vec![];
+    for _ in 0..5 {
+        let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+        testkit.create_block_with_transaction(transfer.clone());
+        alice_sec.transfer(&transfer);
+        transfers.push(transfer);
+    }
+
+    // Indices are deduplicated and sorted; indices beyond the history length are ignored.
+    let query = WalletQuery::new(alice_pk, 0).with_history_indices(vec![4, 0, 9, 4, 2]);
+    let response = wallet_with_query(&testkit, &query);
+    assert_eq!(
+        response.history,
+        vec![
+            FullEvent::CreateWallet(create_wallet),
+            FullEvent::Transfer(transfers[1].clone()),
+            FullEvent::Transfer(transfers[3].clone()),
+        ]
+    );
+
+    let query = WalletQuery::new(alice_pk, 0).with_history_indices(vec![3, 4]);
+    let response = wallet_with_query(&testkit, &query);
+    assert_eq!(
+        response.history,
+        vec![
+            FullEvent::Transfer(transfers[2].clone()),
+            FullEvent::Transfer(transfers[3].clone()),
+        ]
+    );
+
+    let query = WalletQuery::new(alice_pk, 0).with_history_indices(vec![]);
+    let response = wallet_with_query(&testkit, &query);
+    assert!(response.history.is_empty());
+}
+
+#[test]
+fn history_queries_with_event_tags() {
+    use private_currency::storage::EventTag;
+
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let alice_pk = *alice_sec.public_key();
+    let mut bob_sec = SecretState::with_random_keypair();
+    let create_wallet = alice_sec.create_wallet();
+    testkit.create_block_with_transactions(txvec![create_wallet.clone(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    let outgoing = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(outgoing.clone());
+    alice_sec.transfer(&outgoing);
+    let incoming = bob_sec.create_transfer(200, &alice_pk, 10);
+    testkit.create_block_with_transaction(incoming.clone());
+    let accept = alice_sec.verify_transfer(&incoming).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+
+    let query = WalletQuery::new(alice_pk, 0).with_event_tags(vec![EventTag::Transfer]);
+    let response = wallet_with_query(&testkit, &query);
+    assert_eq!(
+        response.history,
+        vec![FullEvent::Transfer(outgoing), FullEvent::Transfer(incoming)]
+    );
+    assert_eq!(response.wallet.unwrap().history_len(), 3);
+
+    let query = WalletQuery::new(alice_pk, 0)
+        .with_history_indices(vec![0, 2])
+        .with_event_tags(vec![EventTag::CreateWallet, EventTag::Rollback]);
+    let response = wallet_with_query(&testkit, &query);
+    assert_eq!(
+        response.history,
+        vec![FullEvent::CreateWallet(create_wallet)]
+    );
+
+    let query = WalletQuery::new(alice_pk, 1).with_event_tags(vec![EventTag::Rollback]);
+    let response = wallet_with_query(&testkit, &query);
+    assert!(response.history.is_empty());
+
+    assert_eq!(EventTag::from_u8(2), Some(EventTag::Rollback));
+    assert_eq!(EventTag::from_u8(200), None);
+}
+
+#[test]
+fn streamed_wallet_proofs() {
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let alice_pk = *alice_sec.public_key();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    for _ in 0..3 {
+        let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+        testkit.create_block_with_transaction(transfer.clone());
+        alice_sec.transfer(&transfer);
+    }
+
+    let trust_anchor = TrustAnchor::new(
+        testkit
+            .network()
+            .validators()
+            .iter()
+            .map(|node| node.public_keys().consensus_key),
+    );
+    let (absent_pk, _) = exonum::crypto::gen_keypair();
+    for &(key, start_history_at) in &[(alice_pk, 0), (alice_pk, 2), (absent_pk, 0)] {
+        let query = WalletQuery::new(key, start_history_at);
+        let mut buffer = vec![];
+        WalletProof::write_json(testkit.snapshot(), &query, &mut buffer).unwrap();
+        let proof: WalletProof = serde_json::from_slice(&buffer).unwrap();
+        let checked = proof.check(&trust_anchor, &query).unwrap();
+
+        let expected = wallet_with_query(&testkit, &query);
+        assert_eq!(checked.wallet, expected.wallet);
+        assert_eq!(checked.history, expected.history);
+        assert_eq!(checked.unaccepted_transfers, expected.unaccepted_transfers);
+    }
+}
+
+#[test]
+fn wire_wallet_proofs() {
+    use private_currency::wire;
+
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let alice_pk = *alice_sec.public_key();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+
+    let trust_anchor = TrustAnchor::new(
+        testkit
+            .network()
+            .validators()
+            .iter()
+            .map(|node| node.public_keys().consensus_key),
+    );
+    let query = WalletQuery::new(alice_pk, 0);
+    let proof: wire::WalletProof = testkit
+        .api()
+        .public(ApiKind::Service("private_currency"))
+        .query(&query)
+        .get("v1/wallet")
+        .unwrap();
+    {
+        let contents = proof.contents.as_ref().expect("wallet contents");
+        assert_eq!(contents.history.len(), 2);
+        assert_eq!(
+            contents.history[1],
+            wire::FullEvent::Transfer(wire::Transfer::from(&transfer))
+        );
+    }
+
+    let checked = proof
+        .to_proof()
+        .unwrap()
+        .check(&trust_anchor, &query)
+        .unwrap();
+    assert_eq!(checked.history[1], FullEvent::Transfer(transfer));
+}
+
+#[test]
+fn signed_wallet_queries() {
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(Currency::default().require_signed_queries())
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+
+    let api = testkit.api();
+    let unsigned_query = WalletQuery::new(*alice_sec.public_key(), 0);
+    let response: Result<WalletProof, _> = api
+        .public(ApiKind::Service("private_currency"))
+        .query(&unsigned_query)
+        .get("v1/wallet");
+    assert!(response.is_err());
+
+    let challenge = CoreSchema::new(testkit.snapshot()).last_block().hash();
+    let signed_query = alice_sec.wallet_query(0, challenge);
+    let response: WalletProof = api
+        .public(ApiKind::Service("private_currency"))
+        .query(&signed_query)
+        .get("v1/wallet")
+        .unwrap();
+    let trust_anchor = TrustAnchor::new(
+        testkit
+            .network()
+            .validators()
+            .iter()
+            .map(|node| node.public_keys().consensus_key),
+    );
+    let checked = response.check(&trust_anchor, &signed_query).unwrap();
+    assert_eq!(
+        checked.wallet.expect("Alice's wallet").info(),
+        alice_sec.to_public()
+    );
+
+    // Bob cannot query Alice's wallet by signing the query with his own key.
+    let mut forged_query = bob_sec.wallet_query(0, challenge);
+    forged_query.key = *alice_sec.public_key();
+    let response: Result<WalletProof, _> = api
+        .public(ApiKind::Service("private_currency"))
+        .query(&forged_query)
+        .get("v1/wallet");
+    assert!(response.is_err());
+}
+
+#[test]
+fn schema_api() {
+    use exonum::encoding::serialize::json::reexport::{self as serde_json, Value};
+
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+
+    let schema: Value = testkit
+        .api()
+        .public(ApiKind::Service("private_currency"))
+        .get("v1/schema")
+        .unwrap();
+    let definitions = &schema["definitions"];
+
+    // Check that all fields of a real transfer are described by the schema.
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    let transfer = serde_json::to_value(&transfer).unwrap();
+    let body_schema = &definitions["TransferBody"]["properties"];
+    for (field, _) in transfer["body"].as_object().unwrap() {
+        assert!(
+            body_schema.get(field).is_some(),
+            "undescribed field {}",
+            field
+        );
+    }
+    for name in &[
+        "WalletQuery",
+        "WalletProof",
+        "FullEvent",
+        "Accept",
+        "CreateWallet",
+    ] {
+        assert!(definitions.get(name).is_some());
+    }
+}
+
+/// Checks a string against a `pattern` from the service schema. Only the patterns
+/// used in the schema are supported.
+fn matches_pattern(pattern: &str, s: &str) -> bool {
+    fn is_decimal(s: &str) -> bool {
+        !s.is_empty() && s.chars().all(|c| c.is_digit(10))
+    }
+
+    let is_hex = s.chars().all(|c| c.is_digit(16) && !c.is_uppercase());
+    match pattern {
+        "^([0-9a-f]{2})*$" => is_hex && s.len() % 2 == 0,
+        "^[0-9]{1,20}$" => is_decimal(s) && s.len() <= 20,
+        "^([0-9]+(,[0-9]+)*)?$" => s.is_empty() || s.split(',').all(is_decimal),
+        _ => {
+            let len = pattern
+                .trim_start_matches("^[0-9a-f]{")
+                .trim_end_matches("}$")
+                .parse::<usize>()
+                .unwrap_or_else(|_| panic!("unsupported pattern {}", pattern));
+            is_hex && s.len() == len
+        }
+    }
+}
+
+/// Validates `value` against `schema`, resolving `$ref`s in the `definitions` section
+/// of the service schema. Only the keywords used in the service schema are supported.
+fn validate(
+    definitions: &serde_json::Value,
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+) -> Result<(), String> {
+    if let Some(reference) = schema.get("$ref") {
+        let name = reference
+            .as_str()
+            .unwrap()
+            .trim_start_matches("#/definitions/");
+        return validate(definitions, &definitions[name], value)
+            .map_err(|e| format!("{}: {}", name, e));
+    }
+
+    if let Some(variants) = schema.get("oneOf") {
+        let matched = variants
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|variant| validate(definitions, variant, value).is_ok())
+            .count();
+        if matched != 1 {
+            return Err(format!("{} matches {} variants of `oneOf`", value, matched));
+        }
+    }
+
+    if let Some(ty) = schema.get("type") {
+        let type_matches = match ty.as_str().unwrap() {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            other => panic!("unsupported type {}", other),
+        };
+        if !type_matches {
+            return Err(format!("{} is not of type {}", value, ty));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            return Err(format!("{} is not equal to {}", value, expected));
+        }
+    }
+    if let Some(minimum) = schema.get("minimum") {
+        if value.as_f64() < minimum.as_f64() {
+            return Err(format!("{} is less than {}", value, minimum));
+        }
+    }
+    if let Some(pattern) = schema.get("pattern") {
+        let pattern = pattern.as_str().unwrap();
+        if !matches_pattern(pattern, value.as_str().unwrap()) {
+            return Err(format!("{} does not match pattern {}", value, pattern));
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        if let Some(min_items) = schema.get("minItems") {
+            if (items.len() as u64) < min_items.as_u64().unwrap() {
+                return Err(format!("too few items: {}", items.len()));
+            }
+        }
+        if let Some(max_items) = schema.get("maxItems") {
+            if items.len() as u64 > max_items.as_u64().unwrap() {
+                return Err(format!("too many items: {}", items.len()));
+            }
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                validate(definitions, item_schema, item).map_err(|e| format!("[{}]: {}", i, e))?;
+            }
+        }
+    }
+
+    if let Some(fields) = value.as_object() {
+        if let Some(required) = schema.get("required") {
+            for name in required.as_array().unwrap() {
+                let name = name.as_str().unwrap();
+                if !fields.contains_key(name) {
+                    return Err(format!("missing required field {}", name));
+                }
+            }
+        }
+        let properties = schema.get("properties");
+        let is_closed = schema.get("additionalProperties") == Some(&serde_json::Value::Bool(false));
+        for (name, field) in fields {
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(field_schema) => validate(definitions, field_schema, field)
+                    .map_err(|e| format!("{}: {}", name, e))?,
+                None if is_closed => return Err(format!("undescribed field {}", name)),
+                None => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn schema_describes_real_values() {
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    let mut carol_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        carol_sec.create_wallet(),
+    ]);
+    alice_sec.initialize();
+    bob_sec.initialize();
+    carol_sec.initialize();
+
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    let unaccepted_transfer = carol_sec.create_transfer(200, bob_sec.public_key(), 10);
+    testkit.create_block_with_transactions(txvec![
+        transfer.clone(),
+        unaccepted_transfer.clone(),
+    ]);
+    let accept = bob_sec.verify_transfer(&transfer).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+
+    let schema: serde_json::Value = testkit
+        .api()
+        .public(ApiKind::Service("private_currency"))
+        .get("v1/schema")
+        .unwrap();
+    let definitions = &schema["definitions"];
+
+    let transfer = serde_json::to_value(&transfer).unwrap();
+    validate(definitions, &definitions["Transfer"], &transfer).unwrap();
+    validate(definitions, &definitions["Transaction"], &transfer).unwrap();
+
+    let wallet_proof: serde_json::Value = testkit
+        .api()
+        .public(ApiKind::Service("private_currency"))
+        .query(&WalletQuery::new(*bob_sec.public_key(), 0))
+        .get("v1/wallet")
+        .unwrap();
+    validate(definitions, &definitions["WalletProof"], &wallet_proof).unwrap();
+
+    // Check that the proof actually contains the values described by nested definitions.
+    let history = wallet_proof["history"].as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    for event in history {
+        validate(definitions, &definitions["FullEvent"], event).unwrap();
+    }
+    let unaccepted_transfers = wallet_proof["unaccepted_transfers"].as_array().unwrap();
+    assert_eq!(unaccepted_transfers.len(), 1);
+    let transfer = &unaccepted_transfers[0];
+    validate(definitions, &definitions["Transfer"], transfer).unwrap();
+
+    // Proofs of absence lack the wallet contents.
+    let absent_sec = SecretState::with_random_keypair();
+    let absence_proof: serde_json::Value = testkit
+        .api()
+        .public(ApiKind::Service("private_currency"))
+        .query(&WalletQuery::new(*absent_sec.public_key(), 0))
+        .get("v1/wallet")
+        .unwrap();
+    assert!(absence_proof.get("history").is_none());
+    validate(definitions, &definitions["WalletProof"], &absence_proof).unwrap();
+}
@@ -0,0 +1,267 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Property tests for the transaction logic over random interleavings of transactions.
+//! Requires `test_utils` and `proptest` crate features.
+
+extern crate exonum;
+extern crate exonum_testkit;
+extern crate private_currency;
+#[macro_use]
+extern crate proptest;
+
+use exonum::{
+    blockchain::{Transaction, TransactionErrorType},
+    crypto::{self, CryptoHash, Hash},
+};
+use exonum_testkit::TestKit;
+use private_currency::{
+    storage::Schema,
+    strategies::{self, Action, TransferKind, TransferSpec},
+    test_utils::{self, assert_wallet_matches},
+    transactions::{Accept, Error, Transfer},
+    Invariants, SecretState, Transactions, CONFIG,
+};
+use proptest::test_runner::Config as ProptestConfig;
+
+use std::collections::HashMap;
+
+const WALLETS: usize = 3;
+
+/// Expected outcome of a transaction in the block being built.
+#[derive(Debug)]
+enum Outcome {
+    Success,
+    Failure(Error),
+    // Outcomes of `Accept`s depend on whether the transfer has been rolled back.
+    Any,
+}
+
+/// Transfer committed to the blockchain.
+#[derive(Debug)]
+struct CommittedTransfer {
+    spec: TransferSpec,
+    tx: Transfer,
+}
+
+/// Driver executing scenarios on top of a testkit.
+struct Driver {
+    testkit: TestKit,
+    wallets: Vec<SecretState>,
+    block: Vec<(Box<dyn Transaction>, Outcome)>,
+    // Transfers in the block being built, which are expected to be committed.
+    block_transfers: Vec<CommittedTransfer>,
+    // Amounts of committed transfers by their hashes.
+    amounts: HashMap<Hash, u64>,
+    // `Accept`s scheduled for submission, with the number of committed blocks
+    // after which they are submitted.
+    scheduled_accepts: Vec<(u64, Accept)>,
+    committed_blocks: u64,
+}
+
+impl Driver {
+    fn new() -> Self {
+        let mut testkit = test_utils::create_testkit();
+        let wallets = test_utils::fund_wallets(&mut testkit, WALLETS);
+        Driver {
+            testkit,
+            wallets,
+            block: vec![],
+            block_transfers: vec![],
+            amounts: HashMap::new(),
+            scheduled_accepts: vec![],
+            committed_blocks: 0,
+        }
+    }
+
+    fn has_transfer_from(&self, index: usize) -> bool {
+        self.block_transfers.iter().any(|t| t.spec.from == index)
+    }
+
+    fn add_transfer(&mut self, spec: TransferSpec) {
+        // Transfers from the same wallet in a single block reference the same history length,
+        // so all of them except for the first one would fail.
+        if self.has_transfer_from(spec.from) {
+            self.commit_block();
+        }
+
+        let sender_state = self.wallets[spec.from].clone();
+        let receiver = match spec.kind {
+            TransferKind::UnregisteredReceiver => crypto::gen_keypair().0,
+            _ => *self.wallets[spec.to].public_key(),
+        };
+        let transfer = match self.wallets[spec.from].try_create_transfer(
+            spec.amount,
+            &receiver,
+            spec.rollback_delay,
+        ) {
+            Ok(transfer) => transfer,
+            // The sender has insufficient balance.
+            Err(_) => return,
+        };
+
+        match spec.kind {
+            TransferKind::Valid => {
+                self.block
+                    .push((Box::new(transfer.clone()), Outcome::Success));
+                self.block_transfers
+                    .push(CommittedTransfer { spec, tx: transfer });
+            }
+            TransferKind::UnregisteredReceiver => {
+                self.wallets[spec.from].abort(&transfer.hash());
+                self.block.push((
+                    Box::new(transfer),
+                    Outcome::Failure(Error::UnregisteredReceiver),
+                ));
+            }
+            TransferKind::Stale => {
+                let mut stale_state = sender_state;
+                let stale_transfer = stale_state.create_transfer(
+                    spec.amount,
+                    self.wallets[spec.to].public_key(),
+                    spec.rollback_delay,
+                );
+                self.block
+                    .push((Box::new(transfer.clone()), Outcome::Success));
+                self.block.push((
+                    Box::new(stale_transfer),
+                    Outcome::Failure(Error::OutdatedHistory),
+                ));
+                self.block_transfers
+                    .push(CommittedTransfer { spec, tx: transfer });
+            }
+        }
+    }
+
+    fn commit_block(&mut self) {
+        let committed_blocks = self.committed_blocks;
+        let (due, scheduled): (Vec<_>, Vec<_>) = self
+            .scheduled_accepts
+            .drain(..)
+            .partition(|&(after, _)| after <= committed_blocks);
+        self.scheduled_accepts = scheduled;
+        for (_, accept) in due {
+            self.block.push((Box::new(accept), Outcome::Any));
+        }
+
+        let (transactions, outcomes): (Vec<_>, Vec<_>) = self.block.drain(..).unzip();
+        let block = self.testkit.create_block_with_transactions(transactions);
+        for (tx, outcome) in block.iter().zip(outcomes) {
+            let status = tx.status();
+            match outcome {
+                Outcome::Success => assert!(status.is_ok(), "{:?}", status),
+                Outcome::Failure(error) => assert_eq!(
+                    status.unwrap_err().error_type(),
+                    TransactionErrorType::Code(error as u8)
+                ),
+                Outcome::Any => {}
+            }
+        }
+        self.committed_blocks += 1;
+
+        for committed in self.block_transfers.drain(..) {
+            let hash = committed.tx.hash();
+            self.amounts.insert(hash, committed.spec.amount);
+            if let Some(after) = committed.spec.accept_after {
+                let verified = self.wallets[committed.spec.to]
+                    .verify_transfer(&committed.tx)
+                    .expect("cannot verify transfer");
+                assert_eq!(verified.value(), committed.spec.amount);
+                self.scheduled_accepts
+                    .push((self.committed_blocks + u64::from(after), verified.accept));
+            }
+        }
+        self.check();
+    }
+
+    /// Checks that secret states of all wallets correspond to the blockchain, storage
+    /// invariants hold, and the total amount of tokens is conserved.
+    fn check(&mut self) {
+        for wallet in &mut self.wallets {
+            test_utils::sync(&self.testkit, wallet);
+            assert_wallet_matches(&self.testkit, wallet);
+        }
+
+        let schema = Schema::new(self.testkit.snapshot());
+        let violations = schema.check_invariants(Invariants::all());
+        assert!(violations.is_empty(), "{:?}", violations);
+
+        let balances: u64 = self.wallets.iter().map(SecretState::balance).sum();
+        let in_flight: u64 = self
+            .wallets
+            .iter()
+            .flat_map(|wallet| schema.unaccepted_transfers(wallet.public_key()))
+            .map(|hash| self.amounts[&hash])
+            .sum();
+        assert_eq!(
+            balances + in_flight,
+            CONFIG.initial_balance * WALLETS as u64
+        );
+    }
+
+    fn run(&mut self, scenario: Vec<Action>) {
+        for action in scenario {
+            match action {
+                Action::Transfer(spec) => self.add_transfer(spec),
+                Action::CommitBlock => self.commit_block(),
+            }
+        }
+        self.commit_block();
+        while !self.scheduled_accepts.is_empty() {
+            self.commit_block();
+        }
+
+        // Wait until all unaccepted transfers are rolled back.
+        let max_delay = self
+            .amounts
+            .keys()
+            .filter_map(|hash| Schema::new(self.testkit.snapshot()).transfer_rollback_height(hash))
+            .max();
+        if let Some(height) = max_delay {
+            while self.testkit.height() <= height {
+                self.commit_block();
+            }
+        }
+
+        let schema = Schema::new(self.testkit.snapshot());
+        assert_eq!(schema.unaccepted_transfers_count(), 0);
+        let balances: u64 = self.wallets.iter().map(SecretState::balance).sum();
+        assert_eq!(balances, CONFIG.initial_balance * WALLETS as u64);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn transfers_decrypt_for_receivers(
+        (_, receiver, transfer) in strategies::transfer(CONFIG),
+    ) {
+        prop_assert!(transfer.verify());
+        let verified = receiver.verify_transfer(&transfer);
+        prop_assert!(verified.is_some());
+    }
+
+    #[test]
+    fn corrupted_transfers_do_not_verify(bytes in strategies::corrupted_transfer(CONFIG)) {
+        if let Ok(Transactions::Transfer(transfer)) = Transactions::from_bytes(bytes) {
+            prop_assert!(!transfer.verify());
+        }
+    }
+
+    #[test]
+    fn balances_are_conserved(scenario in strategies::scenario(WALLETS, 12, &CONFIG)) {
+        Driver::new().run(scenario);
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Regression tests for rollback and acceptance logic based on simulation traces.
+//! Requires the `test_utils` crate feature.
+
+extern crate exonum;
+extern crate private_currency;
+extern crate serde_json;
+
+use exonum::crypto::Hash;
+use private_currency::{
+    simulation::{AcceptPolicy, Behavior, Outcome, Simulation, TraceEvent},
+    transactions::Error,
+};
+
+use std::collections::HashMap;
+
+/// Minimum rollback delay in the default configuration, which is used by simulated clients.
+const ROLLBACK_DELAY: u64 = 5;
+
+/// Returns the height of the block rolling back a transfer committed at `height`.
+/// Rollbacks are performed after the transactions in the block are executed, so acceptance
+/// in the same block still succeeds.
+fn rollback_block(height: u64) -> u64 {
+    height + ROLLBACK_DELAY + 1
+}
+
+fn outcomes(trace: &[TraceEvent]) -> HashMap<Hash, Outcome> {
+    trace
+        .iter()
+        .filter_map(|event| match *event {
+            TraceEvent::Executed {
+                tx_hash, outcome, ..
+            } => Some((tx_hash, outcome)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns `(height, from, to)` for transfers committed in the simulation.
+fn committed_transfers(trace: &[TraceEvent]) -> HashMap<Hash, (u64, usize, usize)> {
+    let outcomes = outcomes(trace);
+    trace
+        .iter()
+        .filter_map(|event| match *event {
+            TraceEvent::TransferSubmitted {
+                height,
+                from,
+                to,
+                tx_hash,
+                ..
+            } if outcomes[&tx_hash] == Outcome::Success => Some((tx_hash, (height, from, to))),
+            _ => None,
+        })
+        .collect()
+}
+
+fn resolved(trace: &[TraceEvent]) -> (Vec<Hash>, Vec<Hash>) {
+    let accepted = trace
+        .iter()
+        .filter_map(|event| match *event {
+            TraceEvent::Accepted { transfer, .. } => Some(transfer),
+            _ => None,
+        })
+        .collect();
+    let rolled_back = trace
+        .iter()
+        .filter_map(|event| match *event {
+            TraceEvent::RolledBack { transfer, .. } => Some(transfer),
+            _ => None,
+        })
+        .collect();
+    (accepted, rolled_back)
+}
+
+fn mixed_simulation(seed: [u8; 32]) -> Simulation {
+    let mut simulation = Simulation::new(seed);
+    simulation.add_client(Behavior::honest(2));
+    simulation.add_client(Behavior::storm().offline_during(4..12));
+    simulation.add_client(Behavior::passive().accepting(AcceptPolicy::Delayed(3)));
+    simulation.add_client(Behavior::honest(3).accepting(AcceptPolicy::Foreign));
+    simulation.run(30);
+    simulation
+}
+
+#[test]
+fn traces_are_deterministic() {
+    let trace = mixed_simulation([1; 32]).trace().to_vec();
+    assert_eq!(trace, mixed_simulation([1; 32]).trace());
+    assert_ne!(trace, mixed_simulation([2; 32]).trace());
+
+    let json = serde_json::to_string(&trace).unwrap();
+    let restored: Vec<TraceEvent> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, trace);
+}
+
+#[test]
+fn withheld_transfers_are_rolled_back() {
+    let mut simulation = Simulation::new([3; 32]);
+    simulation.add_client(Behavior::honest(1));
+    simulation.add_client(Behavior::passive().accepting(AcceptPolicy::Withhold));
+    simulation.run(20);
+
+    let trace = simulation.trace();
+    let transfers = committed_transfers(trace);
+    let (accepted, rolled_back) = resolved(trace);
+    assert!(accepted.is_empty());
+    let last_height = simulation.testkit().height().0;
+    for (hash, &(height, ..)) in &transfers {
+        let expected = rollback_block(height) <= last_height;
+        assert_eq!(rolled_back.contains(hash), expected);
+    }
+    for event in trace {
+        if let TraceEvent::RolledBack { height, transfer } = *event {
+            assert_eq!(height, rollback_block(transfers[&transfer].0));
+        }
+    }
+}
+
+#[test]
+fn offline_receiver_misses_transfers() {
+    let mut simulation = Simulation::new([4; 32]);
+    simulation.add_client(Behavior::honest(1));
+    simulation.add_client(Behavior::passive().offline_during(3..15));
+    simulation.run(25);
+
+    let trace = simulation.trace();
+    assert!(trace.contains(&TraceEvent::WentOffline {
+        height: 3,
+        client: 1,
+    }));
+    assert!(trace.contains(&TraceEvent::CameOnline {
+        height: 15,
+        client: 1,
+    }));
+
+    let (accepted, rolled_back) = resolved(trace);
+    let last_height = simulation.testkit().height().0;
+    for (hash, &(height, ..)) in &committed_transfers(trace) {
+        if rollback_block(height) < 15 {
+            // The receiver is offline until the transfer is rolled back.
+            assert!(rolled_back.contains(hash));
+        } else if height < last_height {
+            // The receiver accepts the transfer in the next block, or once it is back online.
+            assert!(accepted.contains(hash));
+        }
+    }
+    assert!(!rolled_back.is_empty());
+    assert!(!accepted.is_empty());
+}
+
+#[test]
+fn adversarial_accepts_fail() {
+    let mut simulation = Simulation::new([5; 32]);
+    simulation.add_client(Behavior::honest(1));
+    simulation.add_client(Behavior::passive());
+    simulation.add_client(Behavior::passive().accepting(AcceptPolicy::Foreign));
+    simulation.add_client(Behavior::passive().accepting(AcceptPolicy::Delayed(ROLLBACK_DELAY + 1)));
+    simulation.run(20);
+
+    let trace = simulation.trace();
+    let outcomes = outcomes(trace);
+    let transfers = committed_transfers(trace);
+    let mut checked_accepts = 0;
+    for event in trace {
+        if let TraceEvent::AcceptSubmitted {
+            client,
+            transfer,
+            tx_hash,
+            ..
+        } = *event
+        {
+            let (_, _, to) = transfers[&transfer];
+            let expected = if client != to {
+                Outcome::Failure(Error::UnauthorizedAccept as u8)
+            } else if client == 3 {
+                // The transfer is rolled back by the time of acceptance.
+                Outcome::Failure(Error::UnknownTransfer as u8)
+            } else {
+                Outcome::Success
+            };
+            assert_eq!(outcomes[&tx_hash], expected);
+            checked_accepts += 1;
+        }
+    }
+    assert!(checked_accepts > 0);
+}
+
+#[test]
+fn transfer_storm() {
+    let mut simulation = Simulation::new([6; 32]);
+    for _ in 0..8 {
+        simulation.add_client(Behavior::storm());
+    }
+    simulation.run(15);
+
+    let trace = simulation.trace();
+    for (_, outcome) in outcomes(trace) {
+        assert_eq!(outcome, Outcome::Success);
+    }
+    let transfers = committed_transfers(trace);
+    // Each client sends a transfer in every block after the wallets are created.
+    assert_eq!(transfers.len(), 8 * 14);
+}
@@ -29,7 +29,7 @@ use private_currency::{
     crypto::Opening,
     storage::{Event, Schema},
     transactions::{Accept, Error},
-    SecretState, Service as Currency, CONFIG,
+    SecretState, Service as Currency, ServiceBuilder, CONFIG,
 };
 
 use std::{collections::HashSet, iter::FromIterator};
@@ -37,9 +37,8 @@ use std::{collections::HashSet, iter::FromIterator};
 const INITIAL_BALANCE: u64 = CONFIG.initial_balance;
 
 fn create_testkit() -> TestKit {
-    TestKitBuilder::validator()
-        .with_service(Currency::default())
-        .create()
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    TestKitBuilder::validator().with_service(service).create()
 }
 
 #[test]
@@ -146,6 +145,7 @@ fn answering_payment() {
     assert!(schema.unaccepted_transfers(bob_sec.public_key()).is_empty());
     // The transfer should no longer be in pending rollbacks.
     assert!(schema.rollback_transfers(rollback_height).is_empty());
+    assert!(schema.transfer_rollback_height(&transfer.hash()).is_none());
 
     // Seeing the `Accept` transaction confirmed, Bob can safely modify his state.
     bob_sec.transfer(&transfer);
@@ -178,6 +178,10 @@ fn automatic_rollback() {
     ]);
     alice_sec.transfer(&transfer);
     let rollback_height = Height(testkit.height().0 + u64::from(ROLLBACK_DELAY));
+    assert_eq!(
+        Schema::new(testkit.snapshot()).transfer_rollback_height(&transfer.hash()),
+        Some(rollback_height)
+    );
     testkit.create_blocks_until(rollback_height.next().next());
 
     let schema = Schema::new(testkit.snapshot());
@@ -188,6 +192,7 @@ fn automatic_rollback() {
     assert_eq!(alice_history[2], Event::rollback(&transfer.hash()));
 
     assert!(schema.rollback_transfers(rollback_height).is_empty());
+    assert!(schema.transfer_rollback_height(&transfer.hash()).is_none());
 
     // Seeing the rollback, Alice updates its state.
     alice_sec.rollback(&transfer);
@@ -199,6 +204,63 @@ fn automatic_rollback() {
     assert!(alice_sec.corresponds_to(&alice));
 }
 
+#[test]
+fn several_rollbacks_from_same_wallet_in_single_block() {
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    let mut carol_sec = SecretState::with_random_keypair();
+    alice_sec.initialize();
+    bob_sec.initialize();
+    carol_sec.initialize();
+
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        carol_sec.create_wallet(),
+    ]);
+
+    let transfer1 = alice_sec.create_transfer(100, bob_sec.public_key(), 6);
+    testkit.create_block_with_transaction(transfer1.clone());
+    alice_sec.transfer(&transfer1);
+    let transfer2 = alice_sec.create_transfer(200, carol_sec.public_key(), 5);
+    testkit.create_block_with_transaction(transfer2.clone());
+    alice_sec.transfer(&transfer2);
+
+    let rollback_height = {
+        let schema = Schema::new(testkit.snapshot());
+        let height = schema
+            .transfer_rollback_height(&transfer1.hash())
+            .expect("rollback height");
+        assert_eq!(
+            schema.transfer_rollback_height(&transfer2.hash()),
+            Some(height)
+        );
+        height
+    };
+    testkit.create_blocks_until(rollback_height.next());
+
+    let schema = Schema::new(testkit.snapshot());
+    let alice_history = schema.history(alice_sec.public_key());
+    assert_eq!(alice_history.len(), 5);
+    assert_eq!(alice_history[3], Event::rollback(&transfer1.hash()));
+    assert_eq!(alice_history[4], Event::rollback(&transfer2.hash()));
+    assert_eq!(schema.unaccepted_transfers_count(), 0);
+
+    alice_sec.rollback(&transfer1);
+    alice_sec.rollback(&transfer2);
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE);
+    let alice = schema
+        .wallet(alice_sec.public_key())
+        .expect("Alice's wallet")
+        .info();
+    assert!(alice_sec.corresponds_to(&alice));
+    for receiver in &[&bob_sec, &carol_sec] {
+        let wallet = schema.wallet(receiver.public_key()).expect("wallet");
+        assert_eq!(wallet.info(), receiver.to_public());
+    }
+}
+
 #[test]
 fn unauthorized_accept() {
     let mut testkit = create_testkit();
@@ -528,11 +590,45 @@ fn debugger() {
     testkit.create_block_with_transactions(txvec![alice_transfer.clone(), bob_transfer.clone(),]);
     testkit.create_blocks_until(Height(10)); // let both transfers expire
 
+    // Bob's acceptance is too late.
+    let late_accept = bob_sec
+        .verify_transfer(&alice_transfer)
+        .expect("verify_transfer")
+        .accept;
+    testkit.create_block_with_transaction(late_accept.clone()); // height 11
+
+    let alice_transfer2 = alice_sec.create_transfer(50, &bob_pk, 5);
+    testkit.create_block_with_transaction(alice_transfer2.clone()); // height 12
+    let accept = bob_sec
+        .verify_transfer(&alice_transfer2)
+        .expect("verify_transfer")
+        .accept;
+    testkit.create_block_with_transaction(accept.clone()); // height 13
+
+    drop(testkit);
+    handle.join().unwrap();
     let debug_events = debug_events.read().expect("read debug_events").clone();
 
+    assert_eq!(debug_events.len(), 9);
     assert_eq!(
-        debug_events,
-        vec![
+        debug_events[..6],
+        [
+            DebugEvent::WalletCreated {
+                tx: alice_sec.create_wallet(),
+                height: Height(1),
+            },
+            DebugEvent::WalletCreated {
+                tx: bob_sec.create_wallet(),
+                height: Height(1),
+            },
+            DebugEvent::TransferCommitted {
+                transfer: alice_transfer.clone(),
+                height: Height(2),
+            },
+            DebugEvent::TransferCommitted {
+                transfer: bob_transfer.clone(),
+                height: Height(2),
+            },
             DebugEvent::RolledBack {
                 transfer: alice_transfer,
                 height: Height(8)
@@ -543,7 +639,1852 @@ fn debugger() {
             },
         ]
     );
+    match debug_events[6] {
+        DebugEvent::ExecutionFailed {
+            ref tx_hash,
+            error: TransactionErrorType::Code(_),
+            height: Height(11),
+            ..
+        } => assert_eq!(*tx_hash, late_accept.hash()),
+        ref other => panic!("unexpected event: {:?}", other),
+    }
+    assert_eq!(
+        debug_events[7..],
+        [
+            DebugEvent::TransferCommitted {
+                transfer: alice_transfer2.clone(),
+                height: Height(12),
+            },
+            DebugEvent::TransferAccepted {
+                transfer: alice_transfer2,
+                accept,
+                height: Height(13),
+            },
+        ]
+    );
+}
+
+#[test]
+fn debugger_with_wallet_filter() {
+    use private_currency::{DebugEvent, DebuggerOptions};
+    use std::thread;
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    let mut carol_sec = SecretState::with_random_keypair();
+    let alice_pk = *alice_sec.public_key();
+    let bob_pk = *bob_sec.public_key();
+
+    let options = DebuggerOptions {
+        wallets: Some(HashSet::from_iter(vec![bob_pk])),
+        ..DebuggerOptions::default()
+    };
+    let (currency, debugger) = ServiceBuilder::new().debugger(options).build();
+    let debugger = debugger.expect("debugger");
+    let mut testkit = TestKitBuilder::validator().with_service(currency).create();
+    let handle = thread::spawn(move || debugger.collect::<Vec<_>>());
+
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        carol_sec.create_wallet(),
+    ]);
+    alice_sec.initialize();
+    carol_sec.initialize();
+
+    let to_bob = alice_sec.create_transfer(100, &bob_pk, 5);
+    let to_alice = carol_sec.create_transfer(100, &alice_pk, 5);
+    testkit.create_block_with_transactions(txvec![to_bob.clone(), to_alice]);
+    testkit.create_blocks_until(Height(8)); // let both transfers expire
+
+    drop(testkit);
+    let debug_events = handle.join().unwrap();
+    assert_eq!(
+        debug_events,
+        vec![
+            DebugEvent::WalletCreated {
+                tx: bob_sec.create_wallet(),
+                height: Height(1),
+            },
+            DebugEvent::TransferCommitted {
+                transfer: to_bob.clone(),
+                height: Height(2),
+            },
+            DebugEvent::RolledBack {
+                transfer: to_bob,
+                height: Height(8),
+            },
+        ]
+    );
+}
+
+#[test]
+fn debugger_reports_rejected_proofs() {
+    use private_currency::{Config, DebugEvent, DebuggerOptions, RejectedProof};
+    use std::thread;
+
+    let (currency, debugger) = Currency::debug(DebuggerOptions::default());
+    let mut testkit = TestKitBuilder::validator().with_service(currency).create();
+    let handle = thread::spawn(move || debugger.collect::<Vec<_>>());
+
+    let (alice_pk, alice_sk) = crypto::gen_keypair();
+    let alice_sec = SecretState::from_keypair(alice_pk, alice_sk.clone());
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+
+    // Alice's client has a wrong idea about the initial balance.
+    let mut confused_alice = SecretState::from_keypair(alice_pk, alice_sk).with_config(Config {
+        initial_balance: INITIAL_BALANCE * 10,
+        ..CONFIG
+    });
+    confused_alice.initialize();
+    let transfer = confused_alice.create_transfer(INITIAL_BALANCE * 5, bob_sec.public_key(), 10);
+    let block = testkit.create_block_with_transaction(transfer.clone());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::IncorrectProof as u8)
+    );
 
     drop(testkit);
-    handle.join().unwrap();
+    let debug_events = handle.join().unwrap();
+    assert_eq!(
+        debug_events.last(),
+        Some(&DebugEvent::ProofRejected {
+            transfer,
+            proof: RejectedProof::SufficientBalance,
+            past_balance_index: Some(0),
+            sender_history_len: 1,
+            sender_last_send_index: 0,
+            height: Height(2),
+        })
+    );
+}
+
+#[test]
+fn state_dump() {
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+
+    let schema = Schema::new(testkit.snapshot());
+    let dump = schema.dump_state();
+    assert_eq!(dump.height, Height(2));
+    assert_eq!(dump.state_hash, schema.state_hash());
+    assert_eq!(dump.wallets.len(), 2);
+    let bob_dump = dump
+        .wallets
+        .iter()
+        .find(|dump| dump.wallet.public_key() == bob_sec.public_key())
+        .expect("Bob's wallet");
+    assert_eq!(bob_dump.unaccepted_transfers, vec![transfer.hash()]);
+
+    assert_eq!(dump.pending_transfers.len(), 1);
+    let rollback_height = dump.pending_transfers[0].rollback_height.unwrap();
+    assert_eq!(dump.pending_transfers[0].transfer, transfer);
+    assert_eq!(
+        dump.rollback_queue.into_iter().collect::<Vec<_>>(),
+        vec![(rollback_height.0, vec![transfer.hash()])]
+    );
+}
+
+#[test]
+#[cfg(feature = "replay")]
+fn replay_of_recorded_blocks() {
+    use private_currency::{
+        replay::{record_blocks, Replayer},
+        DebugEvent,
+    };
+
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 3);
+    testkit.create_block_with_transaction(transfer.clone());
+    testkit.create_blocks_until(Height(10));
+
+    let blocks = record_blocks(testkit.snapshot());
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[1].height, Height(2));
+
+    let mut replayer = Replayer::new(CONFIG);
+    let replayed = replayer.replay_block(&blocks[0]).unwrap();
+    assert_eq!(replayed.events.len(), 2);
+    let replayed = replayer.replay_block(&blocks[1]).unwrap();
+    assert_eq!(replayed.transactions, vec![transfer.hash()]);
+    assert_eq!(
+        replayed.events,
+        vec![DebugEvent::TransferCommitted {
+            transfer,
+            height: Height(2),
+        }]
+    );
+    assert!(replayer.replay_block(&blocks[0]).is_err());
+
+    // Rollbacks are replayed as well once the replay blockchain catches up.
+    let expected_dump = Schema::new(testkit.snapshot()).dump_state();
+    while replayer.height() < Height(10) {
+        let next = private_currency::replay::RecordedBlock {
+            height: replayer.height().next(),
+            transactions: vec![],
+        };
+        replayer.replay_block(&next).unwrap();
+    }
+    assert_eq!(replayer.dump_state(), expected_dump);
+}
+
+#[test]
+fn custom_service_config() {
+    use private_currency::Config;
+
+    let config = Config {
+        initial_balance: 500,
+        min_transfer_amount: 10,
+        ..CONFIG
+    };
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(Currency::default().with_config(config.clone()))
+        .create();
+    assert_eq!(Schema::new(testkit.snapshot()).config(), config);
+
+    let (alice_pk, alice_sk) = crypto::gen_keypair();
+    let mut alice_sec =
+        SecretState::from_keypair(alice_pk, alice_sk.clone()).with_config(config.clone());
+    let bob_sec = SecretState::with_random_keypair().with_config(config);
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    assert_eq!(alice_sec.balance(), 500);
+    let schema = Schema::new(testkit.snapshot());
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+
+    // The transfer is valid with the default config, but not with the actual one.
+    let mut default_alice = SecretState::from_keypair(alice_pk, alice_sk);
+    default_alice.initialize();
+    let transfer = default_alice.create_transfer(5, bob_sec.public_key(), 10);
+    let block = testkit.create_block_with_transaction(transfer);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::IncorrectAmountProof as u8)
+    );
+}
+
+#[test]
+fn transfer_amounts_are_capped() {
+    use private_currency::{Config, TransferError};
+
+    let config = Config {
+        max_transfer_amount: Some(100),
+        ..CONFIG
+    };
+    assert!(config.is_valid());
+    assert!(!Config {
+        max_transfer_amount: Some(0),
+        ..config.clone()
+    }
+    .is_valid());
+
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(Currency::default().with_config(config.clone()))
+        .create();
+    let (alice_pk, alice_sk) = crypto::gen_keypair();
+    let mut alice_sec =
+        SecretState::from_keypair(alice_pk, alice_sk.clone()).with_config(config.clone());
+    let bob_sec = SecretState::with_random_keypair().with_config(config);
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+
+    assert_eq!(
+        alice_sec
+            .try_create_transfer(101, bob_sec.public_key(), 10)
+            .unwrap_err(),
+        TransferError::AmountTooLarge(101)
+    );
+    assert_eq!(
+        bob_sec.request_transfer(101, &alice_pk).unwrap_err(),
+        TransferError::AmountTooLarge(101)
+    );
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    assert!(transfer.amount_proof().upper_bound().is_some());
+    let block = testkit.create_block_with_transaction(transfer);
+    assert!(block.iter().all(|tx| tx.status().is_ok()));
+
+    // A transfer without the upper-bound proof is rejected.
+    let mut uncapped_alice = SecretState::from_keypair(alice_pk, alice_sk);
+    uncapped_alice.initialize();
+    let transfer = uncapped_alice.create_transfer(50, bob_sec.public_key(), 10);
+    assert!(transfer.amount_proof().upper_bound().is_none());
+    let block = testkit.create_block_with_transaction(transfer);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::IncorrectAmountProof as u8)
+    );
+}
+
+#[test]
+fn service_reconfiguration() {
+    use private_currency::{Config, SERVICE_NAME};
+
+    let mut testkit = create_testkit();
+    let new_config = Config {
+        min_transfer_amount: 100,
+        ..CONFIG
+    };
+    let mut proposal = testkit.configuration_change_proposal();
+    proposal.set_service_config(SERVICE_NAME, new_config.clone());
+    proposal.set_actual_from(Height(5));
+    testkit.commit_configuration_change(proposal);
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    assert_eq!(Schema::new(testkit.snapshot()).config(), CONFIG);
+
+    testkit.create_blocks_until(Height(6));
+    assert_eq!(Schema::new(testkit.snapshot()).config(), new_config);
+
+    // The transfer satisfies the old configuration, but not the new one.
+    let transfer = alice_sec.create_transfer(50, bob_sec.public_key(), 10);
+    let block = testkit.create_block_with_transaction(transfer);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::IncorrectAmountProof as u8)
+    );
+
+    // Invalid configurations are ignored.
+    let mut proposal = testkit.configuration_change_proposal();
+    let invalid_config = Config {
+        rollback_delay_bounds: 10..10,
+        ..CONFIG
+    };
+    proposal.set_service_config(SERVICE_NAME, invalid_config);
+    proposal.set_actual_from(Height(10));
+    testkit.commit_configuration_change(proposal);
+    testkit.create_blocks_until(Height(11));
+    assert_eq!(Schema::new(testkit.snapshot()).config(), new_config);
+}
+
+#[test]
+fn metrics_are_collected() {
+    use private_currency::{TransactionKind, TransactionOutcome};
+
+    let (service, _) = ServiceBuilder::new().metrics(true).build();
+    let metrics = service.metrics().expect("metrics").clone();
+    let mut testkit = TestKitBuilder::validator().with_service(service).create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    alice_sec.initialize();
+    bob_sec.initialize();
+    let accepted = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    let rolled_back = alice_sec.create_transfer(200, bob_sec.public_key(), 5);
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        accepted.clone(),
+        rolled_back.clone(),
+    ]);
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.height, Some(testkit.height()));
+    assert_eq!(snapshot.rollback_queue_depth, 1);
+
+    let accept = bob_sec.verify_transfer(&accepted).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+    testkit.create_blocks_until(Height(10));
+    // The transfer is already rolled back, so it cannot be accepted.
+    let late_accept = bob_sec.verify_transfer(&rolled_back).unwrap().accept;
+    testkit.create_block_with_transaction(late_accept);
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.rollback_queue_depth, 0);
+    assert_eq!(snapshot.rollbacks, 1);
+    let count = |kind, outcome| {
+        snapshot
+            .transactions
+            .iter()
+            .find(|count| count.kind == kind && count.outcome == outcome)
+            .map_or(0, |count| count.count)
+    };
+    assert_eq!(
+        count(TransactionKind::CreateWallet, TransactionOutcome::Success),
+        2
+    );
+    assert_eq!(
+        count(TransactionKind::Accept, TransactionOutcome::Success),
+        1
+    );
+    assert_eq!(
+        count(
+            TransactionKind::Accept,
+            TransactionOutcome::Error(Error::UnknownTransfer as u8)
+        ),
+        1
+    );
+    assert!(snapshot.proof_verification.count >= 4);
+}
+
+#[test]
+fn storage_migration_from_v1() {
+    use exonum::storage::{Entry, MapIndex};
+    use private_currency::migrations::{self, STORAGE_VERSION};
+
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    alice_sec.initialize();
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        transfer.clone(),
+    ]);
+    let rollback_height =
+        Schema::new(testkit.snapshot()).transfer_rollback_height(&transfer.hash());
+    assert_eq!(
+        migrations::storage_version(testkit.snapshot()),
+        STORAGE_VERSION
+    );
+
+    // Remove the data absent in the first version of the storage.
+    let mut fork = testkit.blockchain_mut().fork();
+    Entry::<_, u16>::new("private_currency.storage_version", &mut fork).remove();
+    Entry::<_, u64>::new("private_currency.unaccepted_count", &mut fork).remove();
+    MapIndex::<_, Hash, u64>::new("private_currency.rollback_heights", &mut fork).clear();
+    assert_eq!(migrations::storage_version(&fork), 1);
+    assert_eq!(Schema::new(&fork).unaccepted_transfers_count(), 0);
+
+    let report = migrations::migrate_fork(&mut fork).unwrap();
+    assert_eq!((report.from, report.to), (1, STORAGE_VERSION));
+    assert_eq!(
+        report.state_hash,
+        Schema::new(testkit.snapshot()).state_hash()
+    );
+    let schema = Schema::new(&fork);
+    assert_eq!(schema.unaccepted_transfers_count(), 1);
+    assert_eq!(
+        schema.transfer_rollback_height(&transfer.hash()),
+        rollback_height
+    );
+
+    let report = migrations::migrate_fork(&mut fork).unwrap();
+    assert!(report.is_noop());
+}
+
+#[test]
+fn storage_migration_from_v2() {
+    use exonum::storage::{Entry, MapIndex};
+    use private_currency::{
+        migrations::{self, STORAGE_VERSION},
+        TransferFilter,
+    };
+
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    alice_sec.initialize();
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        transfer.clone(),
+    ]);
+    let filter = Schema::new(testkit.snapshot()).transfer_filter(bob_sec.public_key());
+    assert!(filter.contains(&transfer.hash()));
+
+    // Remove the data absent in the second version of the storage.
+    let mut fork = testkit.blockchain_mut().fork();
+    Entry::<_, u16>::new("private_currency.storage_version", &mut fork).set(2);
+    MapIndex::<_, crypto::PublicKey, TransferFilter>::new(
+        "private_currency.transfer_filters",
+        &mut fork,
+    )
+    .clear();
+    assert!(Schema::new(&fork)
+        .transfer_filter(bob_sec.public_key())
+        .is_empty());
+
+    let report = migrations::migrate_fork(&mut fork).unwrap();
+    assert_eq!((report.from, report.to), (2, STORAGE_VERSION));
+    let schema = Schema::new(&fork);
+    assert_eq!(schema.transfer_filter(bob_sec.public_key()), filter);
+    assert!(schema.transfer_filter(alice_sec.public_key()).is_empty());
+}
+
+#[test]
+fn storage_migration_reencodes_legacy_wallets() {
+    use exonum::storage::{Entry, ProofMapIndex, StorageValue};
+    use private_currency::{
+        crypto::Commitment,
+        migrations::{self, STORAGE_VERSION},
+    };
+
+    let mut testkit = create_testkit();
+    let alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+    ]);
+    let wallet = Schema::new(testkit.snapshot())
+        .wallet(alice_sec.public_key())
+        .unwrap();
+    let state_hash = Schema::new(testkit.snapshot()).state_hash();
+
+    // Store Alice's wallet in the layout preceding the `locked_balance` field,
+    // which is the last field of the wallet.
+    let mut fork = testkit.blockchain_mut().fork();
+    Entry::<_, u16>::new("private_currency.storage_version", &mut fork).set(2);
+    let mut legacy_bytes = wallet.clone().into_bytes();
+    let legacy_len = legacy_bytes.len() - 32;
+    legacy_bytes.truncate(legacy_len);
+    ProofMapIndex::<_, crypto::PublicKey, Vec<u8>>::new("private_currency.wallets", &mut fork)
+        .put(alice_sec.public_key(), legacy_bytes);
+    let legacy_state_hash = Schema::new(&fork).state_hash();
+    assert_ne!(legacy_state_hash, state_hash);
+
+    let report = migrations::migrate_fork(&mut fork).unwrap();
+    assert_eq!((report.from, report.to), (2, STORAGE_VERSION));
+    assert_eq!(report.reencoded_wallets, 1);
+    assert!(report.changes_state_hash());
+    assert_eq!(report.prev_state_hash, legacy_state_hash);
+    assert_eq!(report.state_hash, state_hash);
+    assert_eq!(migrations::storage_version(&fork), STORAGE_VERSION);
+
+    let schema = Schema::new(&fork);
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice, wallet);
+    assert_eq!(alice.locked_balance(), Commitment::with_no_blinding(0));
+    assert!(schema.wallet(bob_sec.public_key()).is_some());
+}
+
+#[test]
+fn invariant_violations() {
+    use exonum::storage::Entry;
+    use private_currency::Invariants;
+
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    alice_sec.initialize();
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        transfer,
+    ]);
+    let snapshot = testkit.snapshot();
+    assert!(Schema::new(&snapshot)
+        .check_invariants(Invariants::all())
+        .is_empty());
+
+    let mut fork = testkit.blockchain_mut().fork();
+    Entry::<_, u64>::new("private_currency.unaccepted_count", &mut fork).set(5);
+    let schema = Schema::new(&fork);
+    assert!(schema
+        .check_invariants(Invariants::all() - Invariants::ROLLBACK_INDEX)
+        .is_empty());
+    let violations = schema.check_invariants(Invariants::all());
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].check, Invariants::ROLLBACK_INDEX);
+    assert_eq!(violations[0].wallet, None);
+    assert_eq!(violations[0].expected, "1");
+    assert_eq!(violations[0].actual, "5");
+}
+
+#[test]
+fn invariant_violations_do_not_stop_node() {
+    use exonum::storage::Entry;
+
+    let (service, _) = ServiceBuilder::new()
+        .check_invariants(true)
+        .metrics(true)
+        .build();
+    let metrics = service.metrics().expect("metrics").clone();
+    let mut testkit = TestKitBuilder::validator().with_service(service).create();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    alice_sec.initialize();
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        transfer,
+    ]);
+    assert_eq!(metrics.snapshot().invariant_violations, 0);
+
+    let mut fork = testkit.blockchain_mut().fork();
+    Entry::<_, u64>::new("private_currency.unaccepted_count", &mut fork).set(5);
+    testkit.blockchain_mut().merge(fork.into_patch()).unwrap();
+
+    // Violations are reported after each block, and blocks continue to be committed.
+    let height = testkit.height();
+    testkit.create_block();
+    testkit.create_block();
+    assert_eq!(testkit.height(), height.next().next());
+    assert_eq!(metrics.snapshot().invariant_violations, 2);
+}
+
+#[test]
+fn bridge_between_deployments() {
+    use private_currency::{
+        api::FullEvent, api::TrustAnchor, bridge::Relayer, BridgeConfig, Config,
+    };
+
+    let keypairs: Vec<_> = (0..3).map(|_| crypto::gen_keypair()).collect();
+    // Only the first two relayers are authorized in the deployments.
+    let authorized: Vec<_> = keypairs[..2].iter().map(|&(pk, _)| pk).collect();
+    let create_testkit = |network_id: Hash| {
+        let config = Config {
+            wire_version: 2,
+            bridge: Some(BridgeConfig {
+                network_id,
+                relayers: authorized.clone(),
+                threshold: 2,
+            }),
+            ..CONFIG
+        };
+        let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+        TestKitBuilder::validator()
+            .with_service(service.with_config(config))
+            .create()
+    };
+    let (source_id, destination_id) = (crypto::hash(b"source"), crypto::hash(b"destination"));
+    let mut source = create_testkit(source_id);
+    let mut destination = create_testkit(destination_id);
+    let relayers: Vec<_> = keypairs
+        .into_iter()
+        .map(|(pk, sk)| Relayer::new(pk, sk, TrustAnchor::new(vec![]), destination_id))
+        .collect();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    source.create_block_with_transaction(alice_sec.create_wallet());
+    destination.create_block_with_transaction(bob_sec.create_wallet());
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    // Locks addressed to the deployment they are submitted to are rejected.
+    let lock = alice_sec
+        .create_bridge_lock(1_000, bob_sec.public_key(), &source_id)
+        .unwrap();
+    let block = source.create_block_with_transaction(lock);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::InvalidBridgeLock as u8)
+    );
+
+    let lock = alice_sec
+        .create_bridge_lock(1_000, bob_sec.public_key(), &destination_id)
+        .unwrap();
+    let block = source.create_block_with_transaction(lock.clone());
+    assert!(block[0].status().is_ok());
+    assert!(alice_sec
+        .apply_event(1, &FullEvent::BridgeLock(lock.clone()))
+        .unwrap());
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE - 1_000);
+    let schema = Schema::new(source.snapshot());
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+
+    // The first vote does not release the lock.
+    let block = destination.create_block_with_transaction(relayers[0].vote(&lock));
+    assert!(block[0].status().is_ok());
+    let schema = Schema::new(destination.snapshot());
+    assert_eq!(
+        schema.bridge_release_votes(&lock.hash()),
+        vec![*relayers[0].public_key()]
+    );
+    let bob = schema.wallet(bob_sec.public_key()).unwrap();
+    assert_eq!(bob_sec.to_public(), bob.info());
+
+    let block = destination
+        .create_block_with_transactions(txvec![relayers[0].vote(&lock), relayers[2].vote(&lock),]);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::DuplicateBridgeVote as u8)
+    );
+    assert_eq!(
+        block[1].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnauthorizedRelayer as u8)
+    );
+
+    // The second vote releases the lock.
+    let release = relayers[1].vote(&lock);
+    let block = destination.create_block_with_transaction(release.clone());
+    assert!(block[0].status().is_ok());
+    let schema = Schema::new(destination.snapshot());
+    assert_eq!(schema.bridge_release(&lock.hash()), Some(release.hash()));
+    assert_eq!(
+        schema.history(bob_sec.public_key())[1],
+        Event::bridge_release(&release.hash())
+    );
+    assert!(bob_sec
+        .apply_event(1, &FullEvent::BridgeRelease(release))
+        .unwrap());
+    assert_eq!(bob_sec.balance(), INITIAL_BALANCE + 1_000);
+    let bob = schema.wallet(bob_sec.public_key()).unwrap();
+    assert_eq!(bob_sec.to_public(), bob.info());
+
+    // Released funds can be spent.
+    let transfer = bob_sec.create_transfer(INITIAL_BALANCE + 500, alice_sec.public_key(), 10);
+    let block =
+        destination.create_block_with_transactions(txvec![alice_sec.create_wallet(), transfer,]);
+    assert!(block.iter().all(|tx| tx.status().is_ok()));
+}
+
+#[test]
+fn bridge_transactions_require_wire_version() {
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    alice_sec.initialize();
+
+    let lock = alice_sec
+        .create_bridge_lock(1_000, bob_sec.public_key(), &Hash::zero())
+        .unwrap();
+    let block = testkit.create_block_with_transaction(lock);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnsupportedTransaction as u8)
+    );
+}
+
+#[test]
+fn unknown_transactions_fail_execution() {
+    use exonum::{
+        blockchain::Transaction,
+        crypto::SIGNATURE_LENGTH,
+        messages::{Message, MessageBuffer, RawMessage},
+    };
+    use private_currency::transactions::{CreateWallet, UnknownTransaction};
+
+    let mut testkit = create_testkit();
+    let (public_key, secret_key) = crypto::gen_keypair();
+    // Sign a message of a type unknown to the service, which has the author key
+    // as the first field.
+    let create_wallet = CreateWallet::new(&public_key, &secret_key);
+    let buffer: &MessageBuffer = create_wallet.raw();
+    let mut bytes = buffer.as_ref().to_vec();
+    bytes[2] = 100;
+    let unsigned_len = bytes.len() - SIGNATURE_LENGTH;
+    bytes.truncate(unsigned_len);
+    let signature = crypto::sign(&bytes, &secret_key);
+    bytes.extend_from_slice(signature.as_ref());
+    let tx = UnknownTransaction::from_raw(RawMessage::new(MessageBuffer::from_vec(bytes))).unwrap();
+    assert!(tx.verify());
+
+    let block = testkit.create_block_with_transactions(vec![Box::new(tx) as Box<Transaction>]);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnsupportedTransaction as u8)
+    );
+    assert!(Schema::new(testkit.snapshot())
+        .wallet(&public_key)
+        .is_none());
+}
+
+#[test]
+fn state_hash_commits_to_activated_tables() {
+    use private_currency::Config;
+
+    let mut testkit = create_testkit();
+    let alice_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    // Only wallets are committed to before the wire version 3 is activated.
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(schema.state_hash(), vec![schema.wallets().merkle_root()]);
+
+    let config = Config {
+        wire_version: 3,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(
+        schema.state_hash(),
+        vec![
+            schema.wallets().merkle_root(),
+            schema.profiles().merkle_root()
+        ]
+    );
+
+    let config = Config {
+        wire_version: 5,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(
+        schema.state_hash(),
+        vec![
+            schema.wallets().merkle_root(),
+            schema.profiles().merkle_root(),
+            schema.receipt_roots().merkle_root(),
+            schema.latest_checkpoints().merkle_root(),
+        ]
+    );
+}
+
+#[test]
+fn receipts_require_wire_version() {
+    use private_currency::{crypto::Commitment, Config};
+
+    let config = Config {
+        wire_version: 4,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+    ]);
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+    let accept = bob_sec.verify_transfer(&transfer).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+
+    // The transfer is accepted, but no receipts are recorded.
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(
+        schema.wallet(bob_sec.public_key()).unwrap().balance(),
+        Commitment::with_no_blinding(INITIAL_BALANCE + 100)
+    );
+    assert!(schema
+        .receipt(alice_sec.public_key(), &transfer.hash())
+        .is_none());
+    assert!(schema
+        .receipt(bob_sec.public_key(), &transfer.hash())
+        .is_none());
+    assert_eq!(schema.receipt_roots().keys().count(), 0);
+}
+
+#[test]
+fn checkpoints_require_wire_version() {
+    use private_currency::{storage::CHECKPOINT_INTERVAL, Config};
+
+    let config = Config {
+        wire_version: 4,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+    ]);
+    alice_sec.initialize();
+
+    // Together with the wallet creation, Alice's history reaches the checkpoint interval.
+    for _ in 1..CHECKPOINT_INTERVAL {
+        let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 100);
+        testkit.create_block_with_transaction(transfer.clone());
+        alice_sec.transfer(&transfer);
+    }
+
+    let schema = Schema::new(testkit.snapshot());
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice.history_len(), CHECKPOINT_INTERVAL);
+    assert!(schema
+        .latest_checkpoints()
+        .get(alice_sec.public_key())
+        .is_none());
+    assert!(schema
+        .history_checkpoints(alice_sec.public_key())
+        .is_empty());
+}
+
+#[test]
+fn accept_with_note() {
+    use private_currency::{api::FullEvent, Config};
+
+    let config = Config {
+        wire_version: 3,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    testkit.create_block_with_transaction(bob_sec.create_wallet());
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    let transfer = alice_sec.create_transfer(1_000, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+    let accept = bob_sec
+        .accept_with_note(&transfer, b"invoice #42")
+        .expect("accept_with_note");
+    // The sender cannot accept its own transfer.
+    assert!(alice_sec.accept_with_note(&transfer, b"note").is_none());
+    let block = testkit.create_block_with_transaction(accept.clone());
+    assert!(block[0].status().is_ok());
+
+    let schema = Schema::new(testkit.snapshot());
+    let bob = schema.wallet(bob_sec.public_key()).unwrap();
+    assert_eq!(bob.history_len(), 2);
+    assert!(schema.unaccepted_transfers(bob_sec.public_key()).is_empty());
+    let alice_history = schema.history(alice_sec.public_key());
+    assert_eq!(
+        alice_history,
+        vec![
+            Event::create_wallet(&alice_sec.create_wallet().hash()),
+            Event::transfer(&transfer.hash()),
+            Event::acknowledgment(&accept.hash()),
+        ]
+    );
+
+    assert!(alice_sec
+        .apply_event(1, &FullEvent::Transfer(transfer.clone()))
+        .unwrap());
+    assert!(alice_sec
+        .apply_event(2, &FullEvent::Acknowledgment(accept.clone()))
+        .unwrap());
+    assert!(bob_sec
+        .apply_event(2, &FullEvent::Acknowledgment(accept.clone()))
+        .is_err());
+    assert_eq!(alice_sec.read_note(&accept), Some(b"invoice #42".to_vec()));
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE - 1_000);
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+
+    // The acknowledgment is accounted for in the history referenced by later transfers.
+    let transfer = alice_sec.create_transfer(500, bob_sec.public_key(), 10);
+    assert_eq!(transfer.history_len(), 3);
+    let block = testkit.create_block_with_transaction(transfer);
+    assert!(block[0].status().is_ok());
+}
+
+#[test]
+fn accept_with_note_requires_wire_version() {
+    let mut testkit = create_testkit();
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    testkit.create_block_with_transaction(bob_sec.create_wallet());
+    alice_sec.initialize();
+
+    let transfer = alice_sec.create_transfer(1_000, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+    let accept = bob_sec.accept_with_note(&transfer, b"note").unwrap();
+    let block = testkit.create_block_with_transaction(accept);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnsupportedTransaction as u8)
+    );
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(schema.unaccepted_transfers(bob_sec.public_key()).len(), 1);
+}
+
+#[test]
+fn min_rollback_delay_preference() {
+    use private_currency::Config;
+
+    let config = Config {
+        wire_version: 3,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    let carol_sec = SecretState::with_random_keypair();
+    // Settings cannot be updated before the wallet is created.
+    let block = testkit.create_block_with_transaction(bob_sec.update_settings(20));
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnregisteredWallet as u8)
+    );
+
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    testkit.create_block_with_transaction(bob_sec.create_wallet());
+    alice_sec.initialize();
+    let max_delay = CONFIG.rollback_delay_bounds.end;
+    let block = testkit.create_block_with_transactions(txvec![
+        bob_sec.update_settings(max_delay),
+        bob_sec.update_settings(20),
+        carol_sec.update_settings(20),
+    ]);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::InvalidRollbackDelay as u8)
+    );
+    assert!(block[1].status().is_ok());
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(schema.min_rollback_delay(bob_sec.public_key()), 20);
+    assert_eq!(schema.min_rollback_delay(carol_sec.public_key()), 0);
+
+    let transfer = alice_sec.create_transfer(1_000, bob_sec.public_key(), 10);
+    let block = testkit.create_block_with_transaction(transfer.clone());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::RollbackDelayTooShort as u8)
+    );
+    alice_sec.abort(&transfer.hash());
+    let transfer = alice_sec.create_transfer(1_000, bob_sec.public_key(), 20);
+    let block = testkit.create_block_with_transaction(transfer);
+    assert!(block[0].status().is_ok());
+
+    // Zero removes the preference.
+    testkit.create_block_with_transaction(bob_sec.update_settings(0));
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(schema.min_rollback_delay(bob_sec.public_key()), 0);
+}
+
+#[test]
+fn disputes() {
+    use exonum::messages::{Message, MessageBuffer};
+    use private_currency::{
+        api::FullEvent, dispute::Arbiter, transactions::OpenDispute, Config, DisputeConfig,
+    };
+
+    let (arbiter_pk, arbiter_sk) = crypto::gen_keypair();
+    let arbiter = Arbiter::new(arbiter_pk, arbiter_sk);
+    let config = Config {
+        wire_version: 3,
+        dispute: Some(DisputeConfig {
+            arbiter: arbiter_pk,
+            window: 3,
+        }),
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transaction(alice_sec.create_wallet());
+    testkit.create_block_with_transaction(bob_sec.create_wallet());
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    let transfer = alice_sec.create_transfer(1_000, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+    // Unaccepted transfers cannot be disputed.
+    assert!(bob_sec.open_dispute(&transfer).is_none());
+    let block = testkit.create_block_with_transaction(alice_sec.open_dispute(&transfer).unwrap());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::InvalidDispute as u8)
+    );
+
+    let accept = bob_sec.verify_transfer(&transfer).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+    // Only the sender of the transfer may dispute it.
+    let (mallory_pk, mallory_sk) = crypto::gen_keypair();
+    let buffer: &MessageBuffer = transfer.raw();
+    let forged_dispute = OpenDispute::new(&mallory_pk, buffer.as_ref(), 0, &mallory_sk);
+    let block = testkit.create_block_with_transaction(forged_dispute);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnauthorizedDispute as u8)
+    );
+
+    let dispute = alice_sec.open_dispute(&transfer).unwrap();
+    let block = testkit.create_block_with_transactions(txvec![
+        dispute.clone(),
+        alice_sec.open_dispute(&transfer).unwrap(),
+    ]);
+    assert!(block[0].status().is_ok());
+    assert_eq!(
+        block[1].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::AlreadyDisputed as u8)
+    );
+
+    // The transferred amount is moved from Bob's balance into the escrow.
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(
+        schema.transfer_dispute(&transfer.hash()),
+        Some(dispute.hash())
+    );
+    assert_eq!(
+        schema.escrowed_amount(&dispute.hash()),
+        Some(transfer.amount())
+    );
+    let bob = schema.wallet(bob_sec.public_key()).unwrap();
+    assert_eq!(bob.last_send_index(), 2);
+    bob_sec
+        .apply_event(1, &FullEvent::Transfer(transfer.clone()))
+        .unwrap();
+    bob_sec
+        .apply_event(2, &FullEvent::DisputeOpened(dispute.clone()))
+        .unwrap();
+    assert_eq!(bob_sec.balance(), INITIAL_BALANCE);
+    assert_eq!(bob_sec.to_public(), bob.info());
+
+    // Only the arbiter may resolve the dispute.
+    let (pk, sk) = crypto::gen_keypair();
+    let block = testkit.create_block_with_transaction(Arbiter::new(pk, sk).resolve(&dispute, true));
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnauthorizedArbiter as u8)
+    );
+
+    let resolution = arbiter.resolve(&dispute, true);
+    let block = testkit.create_block_with_transactions(txvec![
+        resolution.clone(),
+        arbiter.resolve(&dispute, false),
+    ]);
+    assert!(block[0].status().is_ok());
+    assert_eq!(
+        block[1].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnknownDispute as u8)
+    );
+
+    // The escrowed amount is refunded to Alice.
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(schema.escrowed_amount(&dispute.hash()), None);
+    assert_eq!(
+        schema.history(alice_sec.public_key())[2],
+        Event::dispute_resolved(&resolution.hash())
+    );
+    alice_sec
+        .apply_event(1, &FullEvent::Transfer(transfer.clone()))
+        .unwrap();
+    alice_sec
+        .apply_event(2, &FullEvent::DisputeResolved(resolution))
+        .unwrap();
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE);
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+
+    // Transfers can only be disputed within the dispute window after acceptance.
+    let transfer = alice_sec.create_transfer(500, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+    let accept = bob_sec.verify_transfer(&transfer).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+    for _ in 0..3 {
+        testkit.create_block();
+    }
+    let block = testkit.create_block_with_transaction(alice_sec.open_dispute(&transfer).unwrap());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::DisputeWindowExpired as u8)
+    );
+}
+
+#[test]
+fn disputes_of_spent_funds() {
+    use private_currency::{
+        api::FullEvent, dispute::Arbiter, Config, DisputeConfig, TransferError, WatchWallet,
+    };
+
+    let (arbiter_pk, arbiter_sk) = crypto::gen_keypair();
+    let arbiter = Arbiter::new(arbiter_pk, arbiter_sk);
+    let config = Config {
+        wire_version: 3,
+        dispute: Some(DisputeConfig {
+            arbiter: arbiter_pk,
+            window: 3,
+        }),
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    let carol_sec = SecretState::with_random_keypair();
+    testkit.create_block_with_transactions(txvec![
+        alice_sec.create_wallet(),
+        bob_sec.create_wallet(),
+        carol_sec.create_wallet(),
+    ]);
+    alice_sec.initialize();
+    bob_sec.initialize();
+    let mut bob_watch = WatchWallet::with_view_key(*bob_sec.public_key(), bob_sec.view_key());
+
+    let transfer = alice_sec.create_transfer(1_000, bob_sec.public_key(), 10);
+    testkit.create_block_with_transaction(transfer.clone());
+    let accept = bob_sec.verify_transfer(&transfer).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+    bob_sec
+        .apply_event(1, &FullEvent::Transfer(transfer.clone()))
+        .unwrap();
+
+    // Bob spends the received funds before the dispute is opened.
+    let spending = bob_sec.create_transfer(INITIAL_BALANCE + 900, carol_sec.public_key(), 10);
+    testkit.create_block_with_transaction(spending.clone());
+    let dispute = alice_sec.open_dispute(&transfer).unwrap();
+    let block = testkit.create_block_with_transaction(dispute.clone());
+    assert!(block[0].status().is_ok());
+
+    let bob_events = vec![
+        FullEvent::CreateWallet(bob_sec.create_wallet()),
+        FullEvent::Transfer(transfer.clone()),
+        FullEvent::Transfer(spending),
+        FullEvent::DisputeOpened(dispute.clone()),
+    ];
+    for (i, event) in bob_events.iter().enumerate().skip(2) {
+        assert!(bob_sec.apply_event(i as u64, event).unwrap());
+    }
+    for (i, event) in bob_events.iter().enumerate() {
+        assert!(bob_watch.apply_event(i as u64, event).unwrap());
+    }
+
+    // The uncovered part of the disputed amount is tracked as a deficit, and the state
+    // still corresponds to the (negative) on-chain balance.
+    let schema = Schema::new(testkit.snapshot());
+    let bob = schema.wallet(bob_sec.public_key()).unwrap();
+    assert_eq!(bob.history_len(), 4);
+    assert_eq!(bob_sec.history_len(), 4);
+    assert_eq!(bob_sec.balance(), 0);
+    assert_eq!(bob_sec.deficit(), 900);
+    assert_eq!(bob_sec.to_public(), bob.info());
+    assert_eq!(bob_watch.balance(), Some(0));
+    assert_eq!(bob_watch.deficit(), Some(900));
+    assert_eq!(
+        bob_sec
+            .try_create_transfer(1, carol_sec.public_key(), 10)
+            .unwrap_err(),
+        TransferError::InsufficientBalance {
+            balance: 0,
+            amount: 1,
+        }
+    );
+
+    // The dispute is rejected, and the escrowed amount settles the deficit.
+    let resolution = arbiter.resolve(&dispute, false);
+    let block = testkit.create_block_with_transaction(resolution.clone());
+    assert!(block[0].status().is_ok());
+    let event = FullEvent::DisputeResolved(resolution);
+    assert!(bob_sec.apply_event(4, &event).unwrap());
+    assert!(bob_watch.apply_event(4, &event).unwrap());
+
+    let schema = Schema::new(testkit.snapshot());
+    let bob = schema.wallet(bob_sec.public_key()).unwrap();
+    assert_eq!(bob_sec.balance(), 100);
+    assert_eq!(bob_sec.deficit(), 0);
+    assert_eq!(bob_sec.to_public(), bob.info());
+    assert_eq!(bob_watch.balance(), Some(100));
+    assert_eq!(bob_watch.deficit(), Some(0));
+}
+
+#[test]
+fn payment_streams() {
+    use exonum::messages::{Message, MessageBuffer};
+    use private_currency::{api::FullEvent, transactions::CloseStream, Config, TransferError};
+
+    let config = Config {
+        wire_version: 3,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(
+            txvec![alice_sec.create_wallet(), bob_sec.create_wallet(),],
+        );
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    assert_eq!(
+        alice_sec
+            .create_stream(100, 0, bob_sec.public_key())
+            .unwrap_err(),
+        TransferError::EmptyStream
+    );
+    assert_eq!(
+        alice_sec
+            .create_stream(INITIAL_BALANCE, 2, bob_sec.public_key())
+            .unwrap_err(),
+        TransferError::InsufficientBalance {
+            balance: INITIAL_BALANCE,
+            amount: 2 * INITIAL_BALANCE,
+        }
+    );
+
+    // The first payment is made in the block containing the stream.
+    let stream = alice_sec
+        .create_stream(100, 5, bob_sec.public_key())
+        .unwrap();
+    let block = testkit.create_block_with_transaction(stream.clone());
+    assert!(block[0].status().is_ok());
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(schema.stream_status(&stream.hash()).unwrap().paid(), 1);
+    assert_eq!(
+        schema.history(bob_sec.public_key())[1],
+        Event::stream_payment(&stream.hash())
+    );
+    alice_sec
+        .apply_event(1, &FullEvent::StreamOpened(stream.clone()))
+        .unwrap();
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE - 500);
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+    testkit.create_block();
+
+    // Only the parties of the stream may close it, and the stream cannot be shortened
+    // below the number of payments already made.
+    let (mallory_pk, mallory_sk) = crypto::gen_keypair();
+    let buffer: &MessageBuffer = stream.raw();
+    let forged_close = CloseStream::new(&mallory_pk, buffer.as_ref(), 2, &mallory_sk);
+    let block = testkit.create_block_with_transactions(txvec![
+        forged_close,
+        bob_sec.close_stream(&stream, 1).unwrap(),
+    ]);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnauthorizedStreamClose as u8)
+    );
+    assert_eq!(
+        block[1].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::InvalidStreamClose as u8)
+    );
+    assert_eq!(
+        Schema::new(testkit.snapshot())
+            .stream_status(&stream.hash())
+            .unwrap()
+            .paid(),
+        3
+    );
+
+    // The stream can be closed only once. The fourth payment completes the closed stream.
+    let close = bob_sec.close_stream(&stream, 4).unwrap();
+    let block = testkit.create_block_with_transactions(txvec![
+        close.clone(),
+        alice_sec.close_stream(&stream, 4).unwrap(),
+    ]);
+    assert!(block[0].status().is_ok());
+    assert_eq!(
+        block[1].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::InvalidStreamClose as u8)
+    );
+    let schema = Schema::new(testkit.snapshot());
+    assert!(schema.stream_status(&stream.hash()).is_none());
+
+    // The cancelled payment is refunded to Alice.
+    assert_eq!(
+        schema.history(alice_sec.public_key())[2],
+        Event::stream_closed(&close.hash())
+    );
+    alice_sec
+        .apply_event(2, &FullEvent::StreamClosed(close))
+        .unwrap();
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE - 400);
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+
+    for index in 1..=4 {
+        bob_sec
+            .apply_event(index, &FullEvent::StreamPayment(stream.clone()))
+            .unwrap();
+    }
+    assert_eq!(bob_sec.balance(), INITIAL_BALANCE + 400);
+    let bob = schema.wallet(bob_sec.public_key()).unwrap();
+    assert_eq!(bob_sec.to_public(), bob.info());
+    assert_eq!(bob.history_len(), 5);
+
+    let block = testkit.create_block_with_transaction(alice_sec.close_stream(&stream, 3).unwrap());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnknownStream as u8)
+    );
+}
+
+#[test]
+fn locking_funds() {
+    use exonum::messages::{Message, MessageBuffer};
+    use private_currency::{
+        api::FullEvent, crypto::Commitment, transactions::Unlock, Config, TransferError,
+    };
+
+    let config = Config {
+        wire_version: 3,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    let unlock_height = testkit.height().0 + 5;
+    let lock = alice_sec.create_lock(300, unlock_height).unwrap();
+    let block = testkit.create_block_with_transaction(lock.clone());
+    assert!(block[0].status().is_ok());
+    let schema = Schema::new(testkit.snapshot());
+    assert!(schema.is_locked(&lock.hash()));
+    assert_eq!(
+        schema.history(alice_sec.public_key())[1],
+        Event::lock(&lock.hash())
+    );
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice.last_send_index(), 1);
+    assert_eq!(
+        &alice.balance() + &alice.locked_balance(),
+        Commitment::with_no_blinding(INITIAL_BALANCE)
+    );
+
+    // Locked funds cannot be spent.
+    alice_sec
+        .apply_event(1, &FullEvent::Lock(lock.clone()))
+        .unwrap();
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE - 300);
+    assert_eq!(alice_sec.to_public(), alice.info());
+    assert_eq!(
+        alice_sec
+            .try_create_transfer(INITIAL_BALANCE - 200, bob_sec.public_key(), 10)
+            .unwrap_err(),
+        TransferError::InsufficientBalance {
+            balance: INITIAL_BALANCE - 300,
+            amount: INITIAL_BALANCE - 200,
+        }
+    );
+
+    // Only the owner may unlock funds, and only after the unlock height.
+    assert!(bob_sec.unlock(&lock).is_none());
+    let (mallory_pk, mallory_sk) = crypto::gen_keypair();
+    let buffer: &MessageBuffer = lock.raw();
+    let forged_unlock = Unlock::new(&mallory_pk, buffer.as_ref(), 0, &mallory_sk);
+    let block = testkit
+        .create_block_with_transactions(txvec![forged_unlock, alice_sec.unlock(&lock).unwrap(),]);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnauthorizedUnlock as u8)
+    );
+    assert_eq!(
+        block[1].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::LockNotExpired as u8)
+    );
+
+    // The unlock can be resubmitted once the unlock height is reached.
+    testkit.create_blocks_until(Height(unlock_height - 1));
+    let unlock = alice_sec.unlock(&lock).unwrap();
+    let block = testkit.create_block_with_transaction(unlock.clone());
+    assert!(block[0].status().is_ok());
+    let schema = Schema::new(testkit.snapshot());
+    assert!(!schema.is_locked(&lock.hash()));
+    assert_eq!(
+        schema.history(alice_sec.public_key())[2],
+        Event::unlock(&unlock.hash())
+    );
+    alice_sec
+        .apply_event(2, &FullEvent::Unlock(unlock))
+        .unwrap();
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE);
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+    assert_eq!(alice.locked_balance(), Commitment::with_no_blinding(0));
+
+    // Funds can be unlocked only once.
+    let block = testkit.create_block_with_transaction(alice_sec.unlock(&lock).unwrap());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnknownLock as u8)
+    );
+}
+
+#[test]
+fn transfer_requests() {
+    use private_currency::{api::FullEvent, Config, TransferError};
+
+    let config = Config {
+        wire_version: 3,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    // Bob requests a transfer from Alice.
+    let request = bob_sec
+        .request_transfer(250, alice_sec.public_key())
+        .unwrap();
+    let block = testkit.create_block_with_transaction(request.clone());
+    assert!(block[0].status().is_ok());
+    let schema = Schema::new(testkit.snapshot());
+    assert!(schema.is_transfer_request_pending(&request.hash()));
+    assert_eq!(schema.transfer_request_payment(&request.hash()), None);
+    assert_eq!(
+        schema.history(alice_sec.public_key())[1],
+        Event::transfer_request(&request.hash())
+    );
+    // The request does not change Alice's balance.
+    alice_sec
+        .apply_event(1, &FullEvent::TransferRequest(request.clone()))
+        .unwrap();
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE);
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+    assert_eq!(alice_sec.transfer_request_amount(&request), Some(250));
+    assert_eq!(bob_sec.transfer_request_amount(&request), Some(250));
+
+    // Only the payer can pay the request.
+    assert_eq!(
+        bob_sec.pay_transfer_request(&request, 10).unwrap_err(),
+        TransferError::InvalidRequest
+    );
+
+    // Alice pays the request; the transfer is linked to it.
+    let transfer = alice_sec.pay_transfer_request(&request, 5).unwrap();
+    assert_eq!(transfer.amount(), request.amount());
+    let block = testkit.create_block_with_transaction(transfer.clone());
+    assert!(block[0].status().is_ok());
+    let schema = Schema::new(testkit.snapshot());
+    assert!(!schema.is_transfer_request_pending(&request.hash()));
+    assert_eq!(
+        schema.transfer_request_payment(&request.hash()),
+        Some(transfer.hash())
+    );
+    assert_eq!(
+        schema.paid_transfer_request(&transfer.hash()),
+        Some(request.hash())
+    );
+
+    // If the transfer is rolled back, the request becomes pending again.
+    let rollback_height = schema.transfer_rollback_height(&transfer.hash()).unwrap();
+    testkit.create_blocks_until(rollback_height.next());
+    let schema = Schema::new(testkit.snapshot());
+    assert!(schema.is_transfer_request_pending(&request.hash()));
+    assert_eq!(schema.transfer_request_payment(&request.hash()), None);
+    assert_eq!(schema.paid_transfer_request(&transfer.hash()), None);
+}
+
+#[test]
+fn transfer_requests_are_rate_limited() {
+    use private_currency::{Config, RateLimitConfig};
+
+    let config = Config {
+        wire_version: 3,
+        rate_limit: Some(RateLimitConfig {
+            max_transfers: 2,
+            window: 5,
+        }),
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+
+    // Requests are counted against the rate limit of the requester.
+    let requests: Vec<_> = (1..=3)
+        .map(|i| {
+            bob_sec
+                .request_transfer(100 * i, alice_sec.public_key())
+                .unwrap()
+        })
+        .collect();
+    let block = testkit.create_block_with_transactions(txvec![
+        requests[0].clone(),
+        requests[1].clone(),
+        requests[2].clone(),
+    ]);
+    assert!(block[0].status().is_ok());
+    assert!(block[1].status().is_ok());
+    assert_eq!(
+        block[2].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::RateLimited as u8)
+    );
+
+    let schema = Schema::new(testkit.snapshot());
+    assert!(!schema.is_transfer_request_pending(&requests[2].hash()));
+    assert_eq!(schema.history(alice_sec.public_key()).len(), 3);
+    assert_eq!(schema.outgoing_transfers_in_window(bob_sec.public_key()), 2);
+    assert_eq!(
+        schema.outgoing_transfers_in_window(alice_sec.public_key()),
+        0
+    );
+}
+
+#[test]
+fn rerandomizing_balance() {
+    use private_currency::{
+        api::FullEvent, crypto::Commitment, transactions::Rerandomize, Config, WatchWallet,
+    };
+
+    let config = Config {
+        wire_version: 3,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    let (mallory_pk, mallory_sk) = crypto::gen_keypair();
+    let mut mallory_sec = SecretState::from_keypair(mallory_pk, mallory_sk.clone());
+    let create_wallet = alice_sec.create_wallet();
+    testkit.create_block_with_transactions(txvec![
+        create_wallet.clone(),
+        bob_sec.create_wallet(),
+        mallory_sec.create_wallet(),
+    ]);
+    alice_sec.initialize();
+    mallory_sec.initialize();
+    let mut watch_wallet =
+        WatchWallet::with_view_key(*alice_sec.public_key(), alice_sec.view_key());
+    watch_wallet
+        .apply_event(0, &FullEvent::CreateWallet(create_wallet))
+        .unwrap();
+
+    // A transfer created before the rerandomization remains valid.
+    let transfer = alice_sec.create_transfer(100, bob_sec.public_key(), 10);
+    let rerandomize = alice_sec.rerandomize();
+    let block =
+        testkit.create_block_with_transactions(txvec![rerandomize.clone(), transfer.clone()]);
+    assert!(block[0].status().is_ok());
+    assert!(block[1].status().is_ok());
+
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(
+        schema.history(alice_sec.public_key())[1],
+        Event::rerandomization(&rerandomize.hash())
+    );
+    assert_eq!(
+        schema.rerandomizations().iter().collect::<Vec<_>>(),
+        vec![rerandomize.hash()]
+    );
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    let expected_balance = &(&Commitment::with_no_blinding(INITIAL_BALANCE)
+        + &rerandomize.blinding())
+        - &transfer.amount();
+    assert_eq!(alice.balance(), expected_balance);
+
+    for (index, event) in [
+        FullEvent::Rerandomization(rerandomize.clone()),
+        FullEvent::Transfer(transfer.clone()),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let index = index as u64 + 1;
+        alice_sec.apply_event(index, event).unwrap();
+        watch_wallet.apply_event(index, event).unwrap();
+    }
+    assert_eq!(alice_sec.balance(), INITIAL_BALANCE - 100);
+    assert_eq!(alice_sec.to_public(), alice.info());
+    assert_eq!(watch_wallet.balance(), Some(INITIAL_BALANCE - 100));
+
+    // The rerandomized balance can be spent.
+    let transfer = alice_sec.create_transfer(200, bob_sec.public_key(), 10);
+    let block = testkit.create_block_with_transaction(transfer);
+    assert!(block[0].status().is_ok());
+
+    // The blinding must commit to zero.
+    let valid = mallory_sec.rerandomize();
+    let (blinding, _) = Commitment::new(1_000);
+    let forged = Rerandomize::new(
+        &mallory_pk,
+        blinding,
+        valid.equality_proof(),
+        valid.encrypted_data(),
+        0,
+        &mallory_sk,
+    );
+    let block = testkit.create_block_with_transaction(forged);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::IncorrectEqualityProof as u8)
+    );
+}
+
+#[test]
+fn backing_up_transfers() {
+    use private_currency::{transactions::BackUpTransfer, Config};
+
+    let config = Config {
+        wire_version: 4,
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let (bob_pk, bob_sk) = crypto::gen_keypair();
+    let bob_sec = SecretState::from_keypair(bob_pk, bob_sk.clone());
+    let custodian_sec = SecretState::with_random_keypair();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    alice_sec.set_backup_key(Some(*custodian_sec.public_key()));
+
+    let transfer = alice_sec.create_transfer(100, &bob_pk, 10);
+    let backup = alice_sec.back_up_transfer(&transfer).unwrap();
+
+    // Backups are rejected until wire version 4 is activated.
+    let mut legacy_testkit = create_testkit();
+    let block = legacy_testkit.create_block_with_transaction(backup.clone());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnsupportedTransaction as u8)
+    );
+
+    // The backed up transfer must be committed.
+    let block = testkit.create_block_with_transaction(backup.clone());
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnknownTransfer as u8)
+    );
+
+    let backup = alice_sec.back_up_transfer(&transfer).unwrap();
+    let block = testkit.create_block_with_transactions(txvec![transfer.clone(), backup.clone()]);
+    assert!(block[0].status().is_ok());
+    assert!(block[1].status().is_ok());
+    assert_eq!(
+        Schema::new(testkit.snapshot()).transfer_backup(&transfer.hash()),
+        Some(backup.hash())
+    );
+    let opening = custodian_sec.backup_opening(&transfer, &backup).unwrap();
+    assert_eq!(opening.value, 100);
+
+    // Only the sender can back up a transfer, and only once.
+    let foreign_backup = BackUpTransfer::new(
+        &bob_pk,
+        custodian_sec.public_key(),
+        &transfer.hash(),
+        backup.encrypted_data(),
+        0,
+        &bob_sk,
+    );
+    let duplicate_backup = alice_sec.back_up_transfer(&transfer).unwrap();
+    let block = testkit.create_block_with_transactions(txvec![foreign_backup, duplicate_backup]);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::UnauthorizedBackup as u8)
+    );
+    assert_eq!(
+        block[1].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::DuplicateBackup as u8)
+    );
+    assert_eq!(
+        Schema::new(testkit.snapshot()).transfer_backup(&transfer.hash()),
+        Some(backup.hash())
+    );
+}
+
+#[test]
+fn transfers_are_rate_limited() {
+    use private_currency::{Config, RateLimitConfig};
+
+    let config = Config {
+        rate_limit: Some(RateLimitConfig {
+            max_transfers: 2,
+            window: 5,
+        }),
+        ..CONFIG
+    };
+    let (service, _) = ServiceBuilder::new().check_invariants(true).build();
+    let mut testkit = TestKitBuilder::validator()
+        .with_service(service.with_config(config))
+        .create();
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let bob_sec = SecretState::with_random_keypair();
+    let bob_pk = *bob_sec.public_key();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+
+    // Blocks at heights 2 and 3 belong to the window starting at height 0.
+    for _ in 0..2 {
+        let transfer = alice_sec.create_transfer(100, &bob_pk, 10);
+        let block = testkit.create_block_with_transaction(transfer.clone());
+        assert!(block[0].status().is_ok());
+        alice_sec.transfer(&transfer);
+    }
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(
+        schema.outgoing_transfers_in_window(alice_sec.public_key()),
+        2
+    );
+    assert_eq!(schema.outgoing_transfers_in_window(&bob_pk), 0);
+
+    let transfer = alice_sec.create_transfer(100, &bob_pk, 10);
+    let block = testkit.create_block_with_transaction(transfer);
+    assert_eq!(
+        block[0].status().unwrap_err().error_type(),
+        TransactionErrorType::Code(Error::RateLimited as u8)
+    );
+    let schema = Schema::new(testkit.snapshot());
+    let alice = schema.wallet(alice_sec.public_key()).unwrap();
+    assert_eq!(alice_sec.to_public(), alice.info());
+
+    // The next block starts a new window.
+    assert_eq!(testkit.height(), Height(4));
+    assert_eq!(
+        schema.outgoing_transfers_in_window(alice_sec.public_key()),
+        0
+    );
+    let transfer = alice_sec.create_transfer(50, &bob_pk, 10);
+    let block = testkit.create_block_with_transaction(transfer.clone());
+    assert!(block[0].status().is_ok());
+    alice_sec.transfer(&transfer);
+    let schema = Schema::new(testkit.snapshot());
+    assert_eq!(
+        schema.outgoing_transfers_in_window(alice_sec.public_key()),
+        1
+    );
 }
@@ -0,0 +1,126 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests for webhook notifications.
+
+extern crate exonum;
+#[macro_use]
+extern crate exonum_testkit;
+extern crate private_currency;
+extern crate serde_json;
+
+use exonum::{
+    crypto::{CryptoHash, Signature},
+    encoding::serialize::{encode_hex, FromHex},
+};
+use exonum_testkit::TestKitBuilder;
+use private_currency::{
+    storage::Event,
+    webhooks::{verify_notification, Notification, KEY_HEADER, SIGNATURE_HEADER},
+    SecretState, ServiceBuilder,
+};
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+};
+
+/// Accepts a single HTTP request and returns its headers (with lowercase names) and body.
+fn receive_request(listener: &TcpListener) -> (HashMap<String, String>, Vec<u8>) {
+    let (stream, _) = listener.accept().unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("POST / "));
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let header = line.trim_right();
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap().to_lowercase();
+        let value = parts.next().unwrap().trim().to_owned();
+        headers.insert(name, value);
+    }
+
+    let len: usize = headers["content-length"].parse().unwrap();
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+    let mut stream = stream;
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    (headers, body)
+}
+
+#[test]
+fn webhook_notifications() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+
+    let (service, _) = ServiceBuilder::new().webhooks(true).build();
+    let webhooks = service.webhooks().unwrap().clone();
+    let mut testkit = TestKitBuilder::validator().with_service(service).create();
+    let node_key = *testkit.us().service_keypair().0;
+
+    let mut alice_sec = SecretState::with_random_keypair();
+    let mut bob_sec = SecretState::with_random_keypair();
+    let alice_pk = *alice_sec.public_key();
+    testkit
+        .create_block_with_transactions(txvec![alice_sec.create_wallet(), bob_sec.create_wallet()]);
+    alice_sec.initialize();
+    bob_sec.initialize();
+
+    assert!(!webhooks.register(&*testkit.snapshot(), &alice_pk, "ftp://example.com"));
+    assert!(webhooks.register(&*testkit.snapshot(), &alice_pk, &url));
+    let list = webhooks.list();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].wallet, alice_pk);
+    assert_eq!(list[0].urls, vec![url.clone()]);
+
+    // An incoming transfer is reported as pending.
+    let transfer = bob_sec.create_transfer(100, &alice_pk, 10);
+    testkit.create_block_with_transaction(transfer.clone());
+    let (headers, body) = receive_request(&listener);
+    assert_eq!(
+        headers[&KEY_HEADER.to_lowercase()],
+        encode_hex(node_key.as_ref())
+    );
+    let signature = Signature::from_hex(&headers[&SIGNATURE_HEADER.to_lowercase()]).unwrap();
+    assert!(verify_notification(&body, &signature, &node_key));
+    let notification: Notification = serde_json::from_slice(&body).unwrap();
+    assert_eq!(notification.wallet, alice_pk);
+    assert_eq!(notification.height, testkit.height());
+    assert_eq!(notification.history_len, 1);
+    assert!(notification.events.is_empty());
+    assert_eq!(notification.pending_transfers, vec![transfer.hash()]);
+
+    // Acceptance of the transfer adds an event to the history.
+    let accept = alice_sec.verify_transfer(&transfer).unwrap().accept;
+    testkit.create_block_with_transaction(accept);
+    let (_, body) = receive_request(&listener);
+    let notification: Notification = serde_json::from_slice(&body).unwrap();
+    assert_eq!(notification.history_len, 2);
+    assert_eq!(notification.events, vec![Event::transfer(&transfer.hash())]);
+    assert!(notification.pending_transfers.is_empty());
+
+    assert!(webhooks.unregister(&alice_pk, &url));
+    assert!(!webhooks.unregister(&alice_pk, &url));
+    assert!(webhooks.list().is_empty());
+}